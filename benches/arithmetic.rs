@@ -0,0 +1,33 @@
+// cargo bench --bench arithmetic
+//
+// A loop of nothing but numbers and booleans.  Those, and nil, are all held in the Value itself, so
+// none of it should allocate: the allocation count printed is the thing to watch, and should
+// stay at 0 however long the loop runs.
+use std::time::Instant;
+use tree_walk::interpreter::Interpreter;
+use tree_walk::lexer::Scanner;
+use tree_walk::parser::Parser;
+
+const SOURCE: &str = "
+var total = 0;
+var odd = false;
+var i = 0;
+while (i < 1000000) {
+    total = total + i * 2 - 1;
+    odd = odd == false;
+    i = i + 1;
+}
+";
+
+fn main() {
+    let tokens = Scanner::new(SOURCE.to_owned()).collect();
+    let stmts = Parser::new(tokens).parse();
+    let mut interp = Interpreter::new();
+
+    let started = Instant::now();
+    interp.start(stmts).expect("benchmark script failed");
+    let elapsed = started.elapsed();
+
+    let report = interp.report();
+    println!("arithmetic: {:?}, {} steps, {} allocations", elapsed, report.steps, report.allocations);
+}
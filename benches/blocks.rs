@@ -0,0 +1,33 @@
+// cargo bench --bench blocks
+//
+// Enters three blocks per iteration, none of which declare anything, so none of them need a
+// scope of their own.
+use std::time::Instant;
+use tree_walk::interpreter::Interpreter;
+use tree_walk::lexer::Scanner;
+use tree_walk::parser::Parser;
+
+const SOURCE: &str = "
+var total = 0;
+var i = 0;
+while (i < 300000) {
+    {
+        {
+            total = total + 1;
+        }
+    }
+    i = i + 1;
+}
+";
+
+fn main() {
+    let tokens = Scanner::new(SOURCE.to_owned()).collect();
+    let stmts = Parser::new(tokens).parse();
+    let mut interp = Interpreter::new();
+
+    let started = Instant::now();
+    interp.start(stmts).expect("benchmark script failed");
+    let elapsed = started.elapsed();
+
+    println!("block entries: {:?}, {} steps", elapsed, interp.report().steps);
+}
@@ -0,0 +1,39 @@
+// cargo bench --bench globals
+//
+// Reads two globals from three scopes down, where every scope declares something of its own,
+// so an uncached read checks four scopes before it finds them.
+use std::time::Instant;
+use tree_walk::interpreter::Interpreter;
+use tree_walk::lexer::Scanner;
+use tree_walk::parser::Parser;
+
+const SOURCE: &str = "
+var total = 0;
+var step = 1;
+var i = 0;
+while (i < 200000) {
+    var a = 1;
+    {
+        var b = 2;
+        {
+            var c = 3;
+            total = total + step;
+            total = total + step;
+            total = total + step;
+        }
+    }
+    i = i + 1;
+}
+";
+
+fn main() {
+    let tokens = Scanner::new(SOURCE.to_owned()).collect();
+    let stmts = Parser::new(tokens).parse();
+    let mut interp = Interpreter::new();
+
+    let started = Instant::now();
+    interp.start(stmts).expect("benchmark script failed");
+    let elapsed = started.elapsed();
+
+    println!("global reads: {:?}, {} steps", elapsed, interp.report().steps);
+}
@@ -0,0 +1,30 @@
+// cargo bench --bench variables
+//
+// Sums a variable a million times from inside a block, so every read of `total` and `step`
+// walks one scope out before it finds them.
+use std::time::Instant;
+use tree_walk::interpreter::Interpreter;
+use tree_walk::lexer::Scanner;
+use tree_walk::parser::Parser;
+
+const SOURCE: &str = "
+var total = 0;
+var step = 1;
+var i = 0;
+while (i < 1000000) {
+    total = total + step;
+    i = i + 1;
+}
+";
+
+fn main() {
+    let tokens = Scanner::new(SOURCE.to_owned()).collect();
+    let stmts = Parser::new(tokens).parse();
+    let mut interp = Interpreter::new();
+
+    let started = Instant::now();
+    interp.start(stmts).expect("benchmark script failed");
+    let elapsed = started.elapsed();
+
+    println!("variable reads: {:?}, {} steps", elapsed, interp.report().steps);
+}
@@ -0,0 +1,5 @@
+// Backends that turn a parsed script into source for another language.  Each one mirrors the
+// interpreter's behavior for programs that run without a RuntimeError; what happens on the error
+// path is up to the target.
+pub mod js;
+pub mod rust;
@@ -0,0 +1,503 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use crate::interpreter;
+use crate::lexer::LexemeKind;
+use crate::parser::{Expr, Function, NodeId, Stmt, Value, TYPE_NAMES};
+use crate::visitor::{ExpressionVisitor, StatementVisitor};
+
+// Lox semantics JavaScript doesn't share: truthiness, and/or returning an operand, print's
+// formatting, and the natives.  The script itself goes in a block underneath so its names
+// can't shadow anything this needs.
+const PRELUDE: &str = r#""use strict";
+const $lox = {
+    truthy: (v) => v !== null && v !== false,
+    and: (a, b) => ($lox.truthy(a) ? b() : a),
+    or: (a, b) => ($lox.truthy(a) ? a : b()),
+    number: (n) => {
+        if (Number.isNaN(n)) return "NaN";
+        if (!Number.isFinite(n)) return n > 0 ? "Infinity" : "-Infinity";
+        if (Object.is(n, -0)) return "-0";
+        const m = Math.abs(n);
+        if (m !== 0 && (m >= 1e7 || m < 1e-3)) {
+            let [mantissa, exponent] = n.toExponential().split("e");
+            if (!mantissa.includes(".")) mantissa += ".0";
+            return mantissa + "E" + exponent.replace("+", "");
+        }
+        return String(n);
+    },
+    show: (v) => {
+        if (v === null) return "nil";
+        if (typeof v === "string") return '"' + v + '"';
+        if (typeof v === "number") return $lox.number(v);
+        if (typeof v === "function") return "<native fn>";
+        return String(v);
+    },
+    type: (v) => {
+        if (v === null) return "Nil";
+        if (typeof v === "number") return "Number";
+        if (typeof v === "string") return "String";
+        if (typeof v === "boolean") return "Boolean";
+        return "Function";
+    },
+    print: (...vs) => {
+        console.log(vs.map($lox.show).join(" "));
+        return vs[vs.length - 1];
+    },
+    // no newline, which only node can do, console.log everywhere else
+    write: (...vs) => {
+        const text = vs.map((v) => (typeof v === "string" ? v : $lox.show(v))).join(" ");
+        if (typeof process === "object") process.stdout.write(text);
+        else console.log(text);
+        return vs.length ? vs[vs.length - 1] : null;
+    },
+    printLn: (...vs) => {
+        console.log(vs.map($lox.show).join(" "));
+        return vs.length ? vs[vs.length - 1] : null;
+    },
+    eprint: (...vs) => {
+        console.error(vs.map($lox.show).join(" "));
+        return vs.length ? vs[vs.length - 1] : null;
+    },
+    exit: (code = 0) => {
+        if (typeof process === "object") process.exit(code);
+        throw new Error("exit(" + code + ")");
+    },
+    clock: () => Date.now() / 1000,
+    random: () => Math.random(),
+    readLine: () => (typeof prompt === "function" ? prompt() : null),
+    __file__: null,
+    NaN: NaN,
+    Infinity: Infinity,
+    isNan: (n) => Number.isNaN(n),
+    isFinite: (n) => Number.isFinite(n),
+    // what of the language made it across
+    hasFeature: (name) => ["is", "optional-chaining", "nil-coalescing", "timers"].includes(name),
+    // the host's event loop runs them once the program is done, same as run_until_idle
+    setTimeout: (f, ms, ...args) => setTimeout(f, ms, ...args),
+    setInterval: (f, ms, ...args) => setInterval(f, ms, ...args),
+    clearTimeout: (id) => clearTimeout(id),
+    clearInterval: (id) => clearInterval(id),
+    // the same bytes as the interpreter, a string's UTF-8
+    base64Encode: (s) => btoa(String.fromCharCode(...new TextEncoder().encode(s))),
+    base64Decode: (s) => new TextDecoder("utf-8", { fatal: true }).decode(Uint8Array.from(atob(s), (c) => c.charCodeAt(0))),
+    hexEncode: (s) => Array.from(new TextEncoder().encode(s), (b) => b.toString(16).padStart(2, "0")).join(""),
+    hexDecode: (s) => new TextDecoder("utf-8", { fatal: true }).decode(Uint8Array.from(s.match(/../g) || [], (h) => parseInt(h, 16))),
+    // only with the crypto feature, node has its own
+    sha256: (s) => require("crypto").createHash("sha256").update(s).digest("hex"),
+    md5: (s) => require("crypto").createHash("md5").update(s).digest("hex"),
+    constantTimeEquals: (a, b) => {
+        let diff = a.length ^ b.length;
+        for (let i = 0; i < Math.min(a.length, b.length); i++) diff |= a.charCodeAt(i) ^ b.charCodeAt(i);
+        return diff === 0;
+    },
+    // workers are the host's threads, there's nothing like them to translate to
+    spawn: () => { throw new Error("spawn isn't available in JavaScript"); },
+    send: () => { throw new Error("send isn't available in JavaScript"); },
+    recv: () => { throw new Error("recv isn't available in JavaScript"); },
+    join: () => { throw new Error("join isn't available in JavaScript"); },
+};
+"#;
+
+// Words a Lox identifier may be but a JavaScript one can't, or shouldn't
+const RESERVED: [&str; 45] = [
+    "arguments", "await", "break", "case", "catch", "class", "const", "continue", "debugger",
+    "default", "delete", "do", "else", "enum", "eval", "export", "extends", "false", "finally", "for",
+    "function", "if", "implements", "import", "in", "instanceof", "interface", "let", "new", "null",
+    "package", "private", "protected", "public", "return", "static", "super", "switch", "this",
+    "throw", "true", "try", "typeof", "var", "while",
+];
+
+// A standalone JavaScript program that does what `stmts` does.  Fails on the first parse error
+// in the tree.
+pub fn emit(stmts: &[Stmt]) -> Result<String, String> {
+    let mut js = Js {
+        out: String::new(),
+        indent: 1,
+        scopes: vec![HashMap::new()],
+        used: HashSet::new(),
+        natives: interpreter::natives().iter().map(|native| native.name).chain(interpreter::GLOBALS).collect(),
+        error: None,
+    };
+
+    for stmt in stmts {
+        stmt.accept(&mut js);
+    }
+
+    match js.error {
+        Some(error) => Err(error),
+        None => Ok(format!("{}{{\n{}}}\n", PRELUDE, js.out)),
+    }
+}
+
+struct Js {
+    out: String,
+    indent: usize,
+    // Lox name -> JavaScript name, innermost last
+    scopes: Vec<HashMap<String, String>>,
+    // every JavaScript name handed out, so shadowing gets a name of its own
+    used: HashSet<String>,
+    natives: HashSet<&'static str>,
+    error: Option<String>,
+}
+
+impl Js {
+    fn pad(&mut self) {
+        self.out.push_str(&"    ".repeat(self.indent));
+    }
+
+    fn line(&mut self, st: &str) {
+        self.pad();
+        self.out.push_str(st);
+        self.out.push('\n');
+    }
+
+    fn fail(&mut self, line: usize, message: &str) {
+        self.error.get_or_insert_with(|| format!("{} [line: {}]", message, line));
+    }
+
+    fn name(&self, name: &str) -> String {
+        match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(js) => js.clone(),
+            None if self.natives.contains(name) => format!("$lox.{}", name),
+            // unknown here means unknown at runtime too, JavaScript will throw a ReferenceError
+            None => mangle(name),
+        }
+    }
+
+    // The name to declare `name` under, or None when this scope already has it and Lox would
+    // simply overwrite it
+    fn declare(&mut self, name: &str) -> Option<String> {
+        if self.scopes.last().unwrap().contains_key(name) {
+            return None;
+        }
+
+        // JavaScript's `let` is hoisted to the top of its block, so a Lox read of the outer
+        // variable before an inner declaration would hit the inner one. Distinct names avoid that
+        let base = mangle(name);
+        let mut js = base.clone();
+        let mut n = 0;
+        while self.used.contains(&js) {
+            n += 1;
+            js = format!("{}${}", base, n);
+        }
+
+        self.used.insert(js.clone());
+        self.scopes.last_mut().unwrap().insert(name.to_string(), js.clone());
+        Some(js)
+    }
+
+    // `{ ... }` around an if or while body, whether or not it was a block in the source
+    fn body(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(stmts) => self.braces(stmts),
+            stmt => self.braces(std::slice::from_ref(stmt)),
+        }
+    }
+
+    fn braces(&mut self, stmts: &[Stmt]) {
+        self.out.push_str("{\n");
+        self.indent += 1;
+        self.scopes.push(HashMap::new());
+        stmts.iter().for_each(|stmt| stmt.accept(self));
+        self.scopes.pop();
+        self.indent -= 1;
+        self.pad();
+        self.out.push('}');
+    }
+}
+
+fn mangle(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}$", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// Rust would write inf, and the script's own variables could be called NaN
+fn number(n: f64) -> String {
+    if n.is_nan() {
+        "$lox.NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "$lox.Infinity" } else { "-$lox.Infinity" }.to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+fn string(st: &str) -> String {
+    let mut out = String::from("\"");
+    for c in st.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl ExpressionVisitor<String> for Js {
+    fn visit_assign(&mut self, name: &str, expr: &Expr, _line: &usize) -> String {
+        format!("{} = {}", self.name(name), expr.accept(self))
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &LexemeKind, right: &Expr) -> String {
+        let op = match operator {
+            LexemeKind::EqualEqual => "===".to_string(),
+            LexemeKind::BangEqual => "!==".to_string(),
+            op => op.source_text(),
+        };
+        format!("{} {} {}", left.accept(self), op, right.accept(self))
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], line: &usize) -> String {
+        // known by the time it's translated, and JavaScript has no way to find out
+        if let Expr::Variable { name, .. } = callee {
+            match name.as_str() {
+                "__line__" if self.name(name) == "$lox.__line__" => return line.to_string(),
+                "version" if self.name(name) == "$lox.version" => return string(env!("CARGO_PKG_VERSION")),
+                _ => {}
+            }
+        }
+        let args: Vec<String> = arguments.iter().map(|arg| arg.accept(self)).collect();
+        format!("{}({})", callee.accept(self), args.join(", "))
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &LexemeKind, right: &Expr) -> String {
+        // nil is null, so JavaScript's ?? already agrees with ours
+        if operator == &LexemeKind::QuestionQuestion {
+            return format!("({} ?? {})", left.accept(self), right.accept(self));
+        }
+        let helper = if operator == &LexemeKind::OR { "or" } else { "and" };
+        format!("$lox.{}({}, () => {})", helper, left.accept(self), right.accept(self))
+    }
+
+    fn visit_literal(&mut self, val: &Value) -> String {
+        match val {
+            Value::BOOLEAN(b) => b.to_string(),
+            Value::STRING(st) => string(st),
+            Value::NUMBER(n) => number(*n),
+            Value::Native(native) => format!("$lox.{}", native.name),
+            Value::Module(module) => format!("$lox.{}", module.name()),
+            // only ever made at runtime, never written in source
+            Value::RecordType(_) | Value::Record(_) => {
+                self.fail(0, "Records can't be translated to JavaScript");
+                String::new()
+            }
+            Value::Class(_) | Value::Instance(_) | Value::Method(_) => {
+                self.fail(0, "Classes can't be translated to JavaScript");
+                String::new()
+            }
+            Value::Null => "null".to_string(),
+        }
+    }
+
+    fn visit_unary(&mut self, operator: &LexemeKind, right: &Expr) -> String {
+        let right = right.accept(self);
+        match operator {
+            LexemeKind::Bang => format!("!$lox.truthy({})", right),
+            // keep - -a from becoming --a
+            op => format!("{}({})", op.source_text(), right),
+        }
+    }
+
+    fn visit_grouping(&mut self, val: &Expr) -> String {
+        format!("({})", val.accept(self))
+    }
+
+    fn visit_variable(&mut self, ident: &str, _id: NodeId, _line: &usize) -> String {
+        self.name(ident)
+    }
+
+    fn visit_this(&mut self, line: &usize) -> String {
+        self.fail(*line, "Methods can't be translated to JavaScript");
+        String::new()
+    }
+
+    fn visit_get(&mut self, _object: &Expr, name: &str, _optional: &bool, line: &usize) -> String {
+        // the runtime has no native modules to look members up in
+        self.fail(*line, &format!("Module member .{} can't be translated to JavaScript", name));
+        String::new()
+    }
+
+    fn visit_set(&mut self, _object: &Expr, name: &str, _value: &Expr, line: &usize) -> String {
+        self.fail(*line, &format!("Instance field .{} can't be translated to JavaScript", name));
+        String::new()
+    }
+
+    fn visit_is(&mut self, value: &Expr, type_name: &str, line: &usize) -> String {
+        if !TYPE_NAMES.contains(&type_name) {
+            self.fail(*line, &format!("Unknown type \"{}\"", type_name));
+        }
+        format!("($lox.type({}) === {})", value.accept(self), string(type_name))
+    }
+
+    // the natives it waits on are the host's, the program runs as a module where await works
+    fn visit_await(&mut self, expr: &Expr, _line: &usize) -> String {
+        format!("(await {})", expr.accept(self))
+    }
+
+    fn visit_if_expr(&mut self, _condition: &Expr, _then_branch: &Stmt, _else_branch: &Option<Stmt>, line: &usize) -> String {
+        // JavaScript's if has no value, and a ternary can't hold the statements a branch may have
+        self.fail(*line, "An if used as a value can't be translated to JavaScript");
+        String::new()
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) -> String {
+        self.fail(*line, message);
+        String::new()
+    }
+}
+
+impl StatementVisitor<()> for Js {
+    fn visit_block(&mut self, stmts: &[Stmt]) {
+        self.pad();
+        self.braces(stmts);
+        self.out.push('\n');
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) {
+        let condition = condition.accept(self);
+        self.pad();
+        self.out.push_str(&format!("if ($lox.truthy({})) ", condition));
+        self.body(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.out.push_str(" else ");
+            self.body(else_branch);
+        }
+        self.out.push('\n');
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, _line: &usize) {
+        let condition = condition.accept(self);
+        self.pad();
+        self.out.push_str(&format!("while ($lox.truthy({})) ", condition));
+        self.body(body);
+        self.out.push('\n');
+    }
+
+    fn visit_variable_def(&mut self, ident: &str, expr: &Option<Expr>, _line: &usize) {
+        // the initializer still sees the outer binding: var a = a;
+        let init = match expr {
+            Some(expr) => expr.accept(self),
+            None => "null".to_string(),
+        };
+
+        match self.declare(ident) {
+            Some(js) => self.line(&format!("let {} = {};", js, init)),
+            None => {
+                let js = self.name(ident);
+                self.line(&format!("{} = {};", js, init))
+            }
+        }
+    }
+
+    fn visit_record(&mut self, _name: &str, _fields: &[String], line: &usize) {
+        self.fail(*line, "Records can't be translated to JavaScript");
+    }
+
+    fn visit_class(&mut self, _name: &str, _methods: &Arc<[Function]>, line: &usize) {
+        self.fail(*line, "Classes can't be translated to JavaScript");
+    }
+
+    fn visit_print(&mut self, exprs: &[Expr]) {
+        if !exprs.is_empty() {
+            let exprs: Vec<String> = exprs.iter().map(|expr| expr.accept(self)).collect();
+            self.line(&format!("$lox.print({});", exprs.join(", ")));
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        let expr = expr.accept(self);
+        self.line(&format!("{};", expr));
+    }
+
+    fn visit_with(&mut self, _ident: &str, _init: &Expr, _body: &Stmt, line: &usize) {
+        self.fail(*line, "with can't be translated to JavaScript");
+    }
+
+    fn visit_defer(&mut self, _expr: &Expr, line: &usize) {
+        // would need every block wrapped in a try/finally, which nothing else here does yet
+        self.fail(*line, "defer can't be translated to JavaScript");
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) {
+        self.fail(*line, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    fn js(source: &str) -> String {
+        let tokens = Scanner::new(source.to_owned()).collect();
+        let out = emit(&Parser::new(tokens).parse()).unwrap();
+        // everything after the prelude
+        out[PRELUDE.len()..].to_string()
+    }
+
+    #[test]
+    fn it_emits_statements() {
+        assert_eq!(js("
+var a = 1;
+while (a < 3 and true) {
+    if (a == 1) print(\"one\"); else a = -a;
+    a = a + 1;
+}
+print(clock());
+"), "{
+    let a = 1;
+    while ($lox.truthy($lox.and(a < 3, () => true))) {
+        if ($lox.truthy(a === 1)) {
+            $lox.print(\"one\");
+        } else {
+            a = -(a);
+        }
+        a = a + 1;
+    }
+    $lox.print($lox.clock());
+}
+");
+    }
+
+    #[test]
+    fn it_renames_to_keep_lox_scoping() {
+        assert_eq!(js("
+var new = 1;
+var new = 2;
+{
+    print(new);
+    var new = 3;
+}
+"), "{
+    let new$ = 1;
+    new$ = 2;
+    {
+        $lox.print(new$);
+        let new$$1 = 3;
+    }
+}
+");
+    }
+
+    #[test]
+    fn it_fills_in_what_it_knows_already() {
+        assert_eq!(js("print(__file__);\nprint(__line__());"), "{\n    $lox.print($lox.__file__);\n    $lox.print(1);\n}\n");
+        assert_eq!(js("version()"), format!("{{\n    \"{}\";\n}}\n", env!("CARGO_PKG_VERSION")));
+        assert_eq!(js("isNan(NaN)"), "{\n    $lox.isNan($lox.NaN);\n}\n");
+    }
+
+    #[test]
+    fn it_refuses_parse_errors() {
+        let tokens = Scanner::new("print(1".to_owned()).collect();
+        assert!(emit(&Parser::new(tokens).parse()).is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::lexer::LexemeKind;
+use crate::parser::{Expr, Function, NodeId, Stmt, Value, TYPE_NAMES};
+use crate::visitor::{ExpressionVisitor, StatementVisitor};
+
+// Printing has to match the interpreter, so numbers go through the same Lox format
+const HELPERS: &str = r#"
+fn lox_number(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_string();
+    } else if n.is_infinite() {
+        return if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+
+    let magnitude = n.abs();
+    if magnitude != 0.0 && (magnitude >= 1e7 || magnitude < 1e-3) {
+        let st = format!("{:e}", n);
+        let (mantissa, exponent) = st.split_at(st.find('e').unwrap());
+        let point = if mantissa.contains('.') { "" } else { ".0" };
+        return format!("{}{}E{}", mantissa, point, &exponent[1..]);
+    }
+
+    n.to_string()
+}
+
+fn clock() -> f64 {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    now.as_secs_f64()
+}
+"#;
+
+// Rust keywords a Lox script might use as a name, written as raw identifiers
+const KEYWORDS: [&str; 37] = [
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "priv", "pub", "ref", "return", "self", "static", "struct", "trait", "true", "type", "unsafe",
+    "use", "while",
+];
+
+// The static type every variable and expression must settle on.  Scripts where one can't be
+// pinned down (a variable that holds a number and later a string, `if (1)`, nil) aren't
+// straightforward enough and are refused with the reason.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ty {
+    Number,
+    Str,
+    Bool,
+}
+
+impl Ty {
+    fn rust(self) -> &'static str {
+        match self {
+            Ty::Number => "f64",
+            Ty::Str => "String",
+            Ty::Bool => "bool",
+        }
+    }
+}
+
+type Typed = Result<(String, Ty), String>;
+
+// Experimental: a standalone Rust program with the script in `fn main`
+pub fn emit(stmts: &[Stmt]) -> Result<String, String> {
+    let mut rust = Rust { out: String::new(), indent: 1, scopes: vec![HashMap::new()] };
+
+    for stmt in stmts {
+        stmt.accept(&mut rust)?;
+    }
+
+    Ok(format!("#![allow(unused)]\n\nfn main() {{\n{}}}\n{}", rust.out, HELPERS))
+}
+
+struct Rust {
+    out: String,
+    indent: usize,
+    // Lox name -> type, innermost last
+    scopes: Vec<HashMap<String, Ty>>,
+}
+
+impl Rust {
+    fn line(&mut self, st: &str) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(st);
+        self.out.push('\n');
+    }
+
+    fn lookup(&self, name: &str, line: usize) -> Result<Ty, String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+            .ok_or_else(|| format!("Variable \"{}\" does not exist [line: {}]", name, line))
+    }
+
+    fn expect(&mut self, expr: &Expr, ty: Ty, what: &str) -> Result<String, String> {
+        match expr.accept(self)? {
+            (code, found) if found == ty => Ok(code),
+            (_, found) => Err(unsupported(&format!("{} of type {} where {} is needed", what, found.rust(), ty.rust()))),
+        }
+    }
+
+    // `{ ... }` around an if or while body, whether or not it was a block in the source
+    fn body(&mut self, stmt: &Stmt) -> Result<(), String> {
+        match stmt {
+            Stmt::Block(stmts) => self.braces(stmts),
+            stmt => self.braces(std::slice::from_ref(stmt)),
+        }
+    }
+
+    fn braces(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        self.indent += 1;
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            stmt.accept(self)?;
+        }
+        self.scopes.pop();
+        self.indent -= 1;
+        Ok(())
+    }
+}
+
+fn unsupported(what: &str) -> String {
+    format!("Can't compile to Rust: {}", what)
+}
+
+fn ident(name: &str) -> String {
+    match name {
+        // these can't be raw identifiers
+        "self" | "crate" | "Self" | "super" => format!("{}_", name),
+        name if KEYWORDS.contains(&name) => format!("r#{}", name),
+        name => name.to_string(),
+    }
+}
+
+impl ExpressionVisitor<Typed> for Rust {
+    fn visit_assign(&mut self, name: &str, _expr: &Expr, line: &usize) -> Typed {
+        Err(unsupported(&format!("assignment to \"{}\" used as a value [line: {}]", name, line)))
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &LexemeKind, right: &Expr) -> Typed {
+        let (l, lt) = left.accept(self)?;
+        let (r, rt) = right.accept(self)?;
+        let op = operator.source_text();
+
+        match (operator, lt, rt) {
+            (LexemeKind::Plus, Ty::Str, Ty::Str) => Ok((format!("format!(\"{{}}{{}}\", {}, {})", l, r), Ty::Str)),
+            (LexemeKind::EqualEqual, _, _) | (LexemeKind::BangEqual, _, _) if lt == rt => {
+                Ok((format!("({} {} {})", l, op, r), Ty::Bool))
+            }
+            (LexemeKind::Plus, Ty::Number, Ty::Number)
+            | (LexemeKind::Minus, Ty::Number, Ty::Number)
+            | (LexemeKind::Star, Ty::Number, Ty::Number)
+            | (LexemeKind::Slash, Ty::Number, Ty::Number) => Ok((format!("({} {} {})", l, op, r), Ty::Number)),
+            (LexemeKind::Greater, Ty::Number, Ty::Number)
+            | (LexemeKind::GreaterEqual, Ty::Number, Ty::Number)
+            | (LexemeKind::Less, Ty::Number, Ty::Number)
+            | (LexemeKind::LessEqual, Ty::Number, Ty::Number) => Ok((format!("({} {} {})", l, op, r), Ty::Bool)),
+            _ => Err(unsupported(&format!("{} {} {}", lt.rust(), op, rt.rust()))),
+        }
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], line: &usize) -> Typed {
+        match callee {
+            Expr::Variable { name, .. } if name == "clock" && arguments.is_empty() && self.lookup(name, *line).is_err() => {
+                Ok(("clock()".to_string(), Ty::Number))
+            }
+            _ => Err(unsupported(&format!("calls other than clock() [line: {}]", line))),
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &LexemeKind, right: &Expr) -> Typed {
+        // there's no nil on this side to coalesce
+        if operator == &LexemeKind::QuestionQuestion {
+            return Err(unsupported("??"));
+        }
+        // with booleans on both sides Lox's and/or are Rust's
+        let l = self.expect(left, Ty::Bool, "operand")?;
+        let r = self.expect(right, Ty::Bool, "operand")?;
+        let op = if operator == &LexemeKind::OR { "||" } else { "&&" };
+        Ok((format!("({} {} {})", l, op, r), Ty::Bool))
+    }
+
+    fn visit_literal(&mut self, val: &Value) -> Typed {
+        match val {
+            Value::BOOLEAN(b) => Ok((b.to_string(), Ty::Bool)),
+            Value::STRING(st) => Ok((format!("{:?}.to_string()", st), Ty::Str)),
+            // Debug always keeps a decimal point or exponent, so it stays an f64 literal
+            Value::NUMBER(n) if n.is_finite() => Ok((format!("{:?}", n), Ty::Number)),
+            Value::NUMBER(n) if n.is_nan() => Ok(("f64::NAN".to_string(), Ty::Number)),
+            Value::NUMBER(n) => Ok((if *n > 0.0 { "f64::INFINITY" } else { "f64::NEG_INFINITY" }.to_string(), Ty::Number)),
+            _ => Err(unsupported("nil and function values")),
+        }
+    }
+
+    fn visit_unary(&mut self, operator: &LexemeKind, right: &Expr) -> Typed {
+        match operator {
+            LexemeKind::Bang => Ok((format!("!{}", self.expect(right, Ty::Bool, "operand")?), Ty::Bool)),
+            op => Ok((format!("{}({})", op.source_text(), self.expect(right, Ty::Number, "operand")?), Ty::Number)),
+        }
+    }
+
+    fn visit_grouping(&mut self, val: &Expr) -> Typed {
+        val.accept(self)
+    }
+
+    fn visit_variable(&mut self, ident_: &str, _id: NodeId, line: &usize) -> Typed {
+        match ident_ {
+            "NaN" if self.lookup(ident_, *line).is_err() => return self.visit_literal(&Value::NUMBER(f64::NAN)),
+            "Infinity" if self.lookup(ident_, *line).is_err() => return self.visit_literal(&Value::NUMBER(f64::INFINITY)),
+            _ => {}
+        }
+        let ty = self.lookup(ident_, *line)?;
+        let name = ident(ident_);
+        match ty {
+            Ty::Str => Ok((format!("{}.clone()", name), ty)),
+            _ => Ok((name, ty)),
+        }
+    }
+
+    fn visit_this(&mut self, line: &usize) -> Typed {
+        Err(unsupported(&format!("this [line: {}]", line)))
+    }
+
+    fn visit_get(&mut self, _object: &Expr, name: &str, _optional: &bool, line: &usize) -> Typed {
+        Err(unsupported(&format!("module member .{} [line: {}]", name, line)))
+    }
+
+    fn visit_set(&mut self, _object: &Expr, name: &str, _value: &Expr, line: &usize) -> Typed {
+        Err(unsupported(&format!("instance field .{} [line: {}]", name, line)))
+    }
+
+    fn visit_is(&mut self, value: &Expr, type_name: &str, line: &usize) -> Typed {
+        if !TYPE_NAMES.contains(&type_name) {
+            return Err(unsupported(&format!("unknown type \"{}\" [line: {}]", type_name, line)));
+        }
+
+        // every expression has its type pinned down here, so the answer is known already
+        let (code, ty) = value.accept(self)?;
+        let name = match ty {
+            Ty::Number => "Number",
+            Ty::Str => "String",
+            Ty::Bool => "Boolean",
+        };
+        Ok((format!("{{ let _ = {}; {} }}", code, name == type_name), Ty::Bool))
+    }
+
+    fn visit_await(&mut self, _expr: &Expr, line: &usize) -> Typed {
+        Err(unsupported(&format!("await [line: {}]", line)))
+    }
+
+    fn visit_if_expr(&mut self, _condition: &Expr, _then_branch: &Stmt, _else_branch: &Option<Stmt>, line: &usize) -> Typed {
+        Err(unsupported(&format!("if used as a value [line: {}]", line)))
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) -> Typed {
+        Err(format!("{} [line: {}]", message, line))
+    }
+}
+
+impl StatementVisitor<Result<(), String>> for Rust {
+    fn visit_block(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        self.line("{");
+        self.braces(stmts)?;
+        self.line("}");
+        Ok(())
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) -> Result<(), String> {
+        let condition = self.expect(condition, Ty::Bool, "if condition")?;
+        self.line(&format!("if {} {{", condition));
+        self.body(then_branch)?;
+        if let Some(else_branch) = else_branch {
+            self.line("} else {");
+            self.body(else_branch)?;
+        }
+        self.line("}");
+        Ok(())
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, _line: &usize) -> Result<(), String> {
+        let condition = self.expect(condition, Ty::Bool, "while condition")?;
+        self.line(&format!("while {} {{", condition));
+        self.body(body)?;
+        self.line("}");
+        Ok(())
+    }
+
+    fn visit_variable_def(&mut self, name: &str, expr: &Option<Expr>, _line: &usize) -> Result<(), String> {
+        let expr = expr.as_ref().ok_or_else(|| unsupported(&format!("\"{}\" declared without a value", name)))?;
+        let (code, ty) = expr.accept(self)?;
+
+        // redeclaring shadows, which is what Lox does too
+        self.line(&format!("let mut {}: {} = {};", ident(name), ty.rust(), code));
+        self.scopes.last_mut().unwrap().insert(name.to_string(), ty);
+        Ok(())
+    }
+
+    fn visit_record(&mut self, _name: &str, _fields: &[String], line: &usize) -> Result<(), String> {
+        Err(unsupported(&format!("records [line: {}]", line)))
+    }
+
+    fn visit_class(&mut self, _name: &str, _methods: &Arc<[Function]>, line: &usize) -> Result<(), String> {
+        Err(unsupported(&format!("classes [line: {}]", line)))
+    }
+
+    fn visit_print(&mut self, exprs: &[Expr]) -> Result<(), String> {
+        if exprs.is_empty() {
+            return Ok(());
+        }
+
+        let mut formats = vec![];
+        let mut args = vec![];
+        for expr in exprs {
+            match expr.accept(self)? {
+                (code, Ty::Number) => {
+                    formats.push("{}");
+                    args.push(format!("lox_number({})", code));
+                }
+                (code, Ty::Str) => {
+                    formats.push("\\\"{}\\\"");
+                    args.push(code);
+                }
+                (code, Ty::Bool) => {
+                    formats.push("{}");
+                    args.push(code);
+                }
+            }
+        }
+        self.line(&format!("println!(\"{}\", {});", formats.join(" "), args.join(", ")));
+        Ok(())
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) -> Result<(), String> {
+        // assignment is only allowed as a statement of its own
+        if let Expr::Assign { name, expr, line } = expr {
+            let ty = self.lookup(name, *line)?;
+            let code = self.expect(expr, ty, &format!("value assigned to \"{}\"", name))?;
+            self.line(&format!("{} = {};", ident(name), code));
+            return Ok(());
+        }
+
+        let (code, _) = expr.accept(self)?;
+        self.line(&format!("{};", code));
+        Ok(())
+    }
+
+    fn visit_with(&mut self, _ident: &str, _init: &Expr, _body: &Stmt, line: &usize) -> Result<(), String> {
+        Err(unsupported(&format!("with [line: {}]", line)))
+    }
+
+    fn visit_defer(&mut self, _expr: &Expr, line: &usize) -> Result<(), String> {
+        Err(unsupported(&format!("defer [line: {}]", line)))
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) -> Result<(), String> {
+        Err(format!("{} [line: {}]", message, line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    fn rust(source: &str) -> Result<String, String> {
+        let tokens = Scanner::new(source.to_owned()).collect();
+        emit(&Parser::new(tokens).parse())
+    }
+
+    #[test]
+    fn it_emits_main() {
+        let out = rust(r#"
+var n = 0;
+var s = "a";
+while (n < 3 and true) {
+    s = s + "b";
+    n = n + 1;
+}
+print(s);
+print(n / 2);
+"#).unwrap();
+        assert!(out.starts_with(r#"#![allow(unused)]
+
+fn main() {
+    let mut n: f64 = 0.0;
+    let mut s: String = "a".to_string();
+    while ((n < 3.0) && true) {
+        s = format!("{}{}", s.clone(), "b".to_string());
+        n = (n + 1.0);
+    }
+    println!("\"{}\"", s.clone());
+    println!("{}", lox_number((n / 2.0)));
+}
+"#));
+    }
+
+    #[test]
+    fn it_refuses_dynamic_scripts() {
+        assert_eq!(
+            rust(r#"var a = 1; a = "s";"#),
+            Err(r#"Can't compile to Rust: value assigned to "a" of type String where f64 is needed"#.to_string())
+        );
+        assert!(rust("if (1) print(1);").is_err());
+        assert!(rust("var a;").is_err());
+        assert!(rust("print(b);").is_err());
+    }
+
+    #[test]
+    fn it_answers_is_from_the_static_type() {
+        let out = rust("var n = 1; print(n is Number); print(n + 1 is String);").unwrap();
+        assert!(out.contains("{ let _ = n; true }"));
+        assert!(out.contains("{ let _ = (n + 1.0); false }"));
+        assert!(rust("print(1 is Integer);").is_err());
+    }
+
+    #[test]
+    fn it_escapes_keywords() {
+        assert!(rust("var fn = 1; print(fn);").unwrap().contains("let mut r#fn: f64 = 1.0;"));
+        assert!(rust("var `while` = 1; print(`while`);").unwrap().contains("let mut r#while: f64 = 1.0;"));
+    }
+
+    #[test]
+    fn it_spells_out_nan_and_infinity() {
+        assert!(rust("var a = NaN; var b = -Infinity;").unwrap().contains("let mut a: f64 = f64::NAN;"));
+        assert!(rust("var a = NaN; var b = -Infinity;").unwrap().contains("let mut b: f64 = -(f64::INFINITY);"));
+        assert!(rust("var NaN = 1; print(NaN);").unwrap().contains("lox_number(NaN)"));
+    }
+}
@@ -0,0 +1,826 @@
+// A Debug Adapter Protocol server, what `tree-walk dap` runs so an editor can drive the debugger:
+// launch a script, set breakpoints, step, and look at scopes and variables while it's stopped.
+// Messages are JSON with a Content-Length header in front, requests in on stdin and responses
+// and events out on stdout.
+//
+// It's all one thread.  Requests are only read before the script starts and while it's stopped,
+// so a pause request sent while it runs isn't answered until something else stops it
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use crate::interpreter::{Capabilities, Debugger, Interpreter, Pause, RuntimeError, Step};
+use crate::parser::{Parser, Value};
+
+// Serve one debugging session, until the editor disconnects or goes away
+pub fn serve(input: impl BufRead + 'static, output: impl Write + 'static) -> io::Result<()> {
+    let session = Rc::new(RefCell::new(Session::new(input, output)));
+    let (mut launched, mut configured) = (false, false);
+
+    loop {
+        let Some(request) = session.borrow_mut().read()? else {
+            return Ok(());
+        };
+        let mut s = session.borrow_mut();
+        match request.command() {
+            "initialize" => {
+                let arguments = request.get("arguments");
+                let starts_at1 = |key| arguments.and_then(|args| args.get(key)).and_then(Json::as_bool).unwrap_or(true);
+                s.line_base = starts_at1("linesStartAt1") as usize;
+                s.column_base = starts_at1("columnsStartAt1") as usize;
+                s.respond(&request, object([
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsConditionalBreakpoints", true.into()),
+                    ("supportsEvaluateForHovers", true.into()),
+                ]));
+                s.event("initialized", object([]));
+            }
+            "launch" => {
+                let arguments = request.get("arguments");
+                let program = arguments.and_then(|args| args.get("program")).and_then(Json::as_str);
+                let Some(program) = program else {
+                    s.fail(&request, "launch needs the program to run");
+                    continue;
+                };
+                s.program = program.to_string();
+                s.entry = arguments.and_then(|args| args.get("stopOnEntry")).and_then(Json::as_bool).unwrap_or(false);
+                s.respond(&request, Json::Null);
+                launched = true;
+            }
+            "setBreakpoints" => {
+                s.set_breakpoints(&request);
+            }
+            "configurationDone" => {
+                s.respond(&request, Json::Null);
+                configured = true;
+            }
+            "threads" => s.threads(&request),
+            "disconnect" => {
+                s.respond(&request, Json::Null);
+                return Ok(());
+            }
+            _ => s.fail(&request, &format!("{} isn't supported", request.command())),
+        }
+        drop(s);
+
+        // the editor sends these two in either order, the script waits for both
+        if launched && configured {
+            run(&session);
+            if session.borrow().disconnected {
+                return Ok(());
+            }
+            (launched, configured) = (false, false);
+        }
+    }
+}
+
+// The launched program, start to finish, with the session as its debugger and its output
+fn run(session: &Rc<RefCell<Session>>) {
+    let program = session.borrow().program.clone();
+    let source = match fs::read_to_string(&program) {
+        Ok(source) => source,
+        Err(err) => return session.borrow_mut().finish(&format!("Can't read {}: {}\n", program, err), 66),
+    };
+
+    let mut interp = Interpreter::builder()
+        .capabilities(Capabilities::all())
+        .output(Output::new(session, "stdout"))
+        .error_output(Output::new(session, "stderr"))
+        .file(&program)
+        .build();
+    let mut parser = Parser::scanned(interp.scanner(source).scan());
+    if !parser.scan_errors().is_empty() {
+        let errors: String = parser.scan_errors().iter().map(|error| format!("{}\n", error)).collect();
+        return session.borrow_mut().finish(&errors, 65);
+    }
+
+    for (line, condition) in session.borrow().program_breakpoints() {
+        interp.set_breakpoint(line, condition.as_deref());
+    }
+    if session.borrow().entry {
+        interp.step(Step::In);
+    }
+    interp.set_debugger(Adapter(session.clone()));
+
+    // eval rather than start, start echoes trees to stdout where the protocol is
+    let result = interp.eval(parser.parse()).and_then(|_| interp.run_until_idle());
+    let exit_code = interp.exit_code().unwrap_or(0);
+    // its outputs send whatever's left of a line as they go
+    drop(interp);
+    let mut session = session.borrow_mut();
+    if session.disconnected {
+        return;
+    }
+    match result {
+        Ok(_) => session.finish("", exit_code),
+        Err(err) => session.finish(&format!("{}\n", err), 70),
+    }
+}
+
+struct Session {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    // of the next message out
+    seq: usize,
+    // 1 where the editor counts from 1, 0 where it counts from 0 like the interpreter
+    line_base: usize,
+    column_base: usize,
+    program: String,
+    // still to stop on entry, the first step the script takes is that
+    entry: bool,
+    // source path -> the breakpoints set in it, lines counted from 0
+    breakpoints: HashMap<String, Vec<(usize, Option<String>)>>,
+    disconnected: bool,
+}
+
+impl Session {
+    fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            input: Box::new(input),
+            output: Box::new(output),
+            seq: 1,
+            line_base: 1,
+            column_base: 1,
+            program: String::new(),
+            entry: false,
+            breakpoints: HashMap::new(),
+            disconnected: false,
+        }
+    }
+
+    // the next message, None once the editor has closed the stream
+    fn read(&mut self) -> io::Result<Option<Json>> {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if self.input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+
+        let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length"))?;
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body)?;
+        let body = String::from_utf8_lossy(&body);
+        Json::parse(&body).map(Some).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message that isn't JSON"))
+    }
+
+    // An editor that has gone away turns up as the end of the input, so a failed write is
+    // left for that to deal with
+    fn send(&mut self, mut message: Vec<(String, Json)>) {
+        message.insert(0, ("seq".to_string(), self.seq.into()));
+        self.seq += 1;
+        let body = Json::Object(message).to_string();
+        let _ = write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.output.flush();
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) {
+        let mut response = fields([
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", true.into()),
+            ("command", request.command().into()),
+        ]);
+        if body != Json::Null {
+            response.push(("body".to_string(), body));
+        }
+        self.send(response);
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) {
+        self.send(fields([
+            ("type", "response".into()),
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", false.into()),
+            ("command", request.command().into()),
+            ("message", message.into()),
+        ]));
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send(fields([("type", "event".into()), ("event", event.into()), ("body", body)]));
+    }
+
+    // whatever the script leaves unsaid, then how it exited
+    fn finish(&mut self, stderr: &str, exit_code: i32) {
+        if !stderr.is_empty() {
+            self.event("output", object([("category", "stderr".into()), ("output", stderr.into())]));
+        }
+        self.event("exited", object([("exitCode", Json::Number(exit_code as f64))]));
+        self.event("terminated", object([]));
+    }
+
+    fn threads(&mut self, request: &Json) {
+        let main = object([("id", 1.into()), ("name", "main".into())]);
+        self.respond(request, object([("threads", Json::Array(vec![main]))]));
+    }
+
+    // Replaces the breakpoints of the source the request is for, and hands back the ones it
+    // replaced and the new ones if that's the program
+    #[allow(clippy::type_complexity)]
+    fn set_breakpoints(&mut self, request: &Json) -> Option<(Vec<(usize, Option<String>)>, Vec<(usize, Option<String>)>)> {
+        let arguments = request.get("arguments");
+        let path = arguments.and_then(|args| args.get("source")).and_then(|source| source.get("path")).and_then(Json::as_str);
+        let Some(path) = path else {
+            self.fail(request, "setBreakpoints needs a source path");
+            return None;
+        };
+
+        let requested = arguments.and_then(|args| args.get("breakpoints")).and_then(Json::as_array).unwrap_or(&[]);
+        let breakpoints: Vec<(usize, Option<String>)> = requested
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint.get("line").and_then(Json::as_usize).unwrap_or(0);
+                let condition = breakpoint.get("condition").and_then(Json::as_str).filter(|condition| !condition.is_empty());
+                (line.saturating_sub(self.line_base), condition.map(str::to_string))
+            })
+            .collect();
+        let verified = breakpoints
+            .iter()
+            .map(|(line, _)| object([("verified", true.into()), ("line", (line + self.line_base).into())]))
+            .collect();
+        self.respond(request, object([("breakpoints", Json::Array(verified))]));
+
+        let replaced = self.breakpoints.insert(path.to_string(), breakpoints.clone()).unwrap_or_default();
+        same_file(path, &self.program).then_some((replaced, breakpoints))
+    }
+
+    // only the launched script can stop, there's no loading another
+    fn program_breakpoints(&self) -> Vec<(usize, Option<String>)> {
+        self.breakpoints
+            .iter()
+            .filter(|(path, _)| same_file(path, &self.program))
+            .flat_map(|(_, breakpoints)| breakpoints.iter().cloned())
+            .collect()
+    }
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => Path::new(a) == Path::new(b),
+    }
+}
+
+// The session as the interpreter's debugger.  Each pause tells the editor it stopped and why,
+// then answers what it asks about the stopped script until it says to go on
+struct Adapter(Rc<RefCell<Session>>);
+
+impl Debugger for Adapter {
+    fn pause(&mut self, interp: &mut Interpreter, pause: &Pause) -> Result<(), RuntimeError> {
+        let mut session = self.0.borrow_mut();
+        let (reason, text) = match pause {
+            Pause::Step { .. } if session.entry => {
+                session.entry = false;
+                ("entry", None)
+            }
+            Pause::Step { .. } => ("step", None),
+            Pause::Breakpoint { error: None, .. } => ("breakpoint", None),
+            Pause::Breakpoint { error: Some(error), .. } => ("breakpoint", Some(format!("its condition failed: {}", error))),
+            Pause::Watch { name, .. } => ("data breakpoint", Some(format!("{} was written", name))),
+            Pause::Error { message, .. } => ("exception", Some(message.clone())),
+        };
+        let mut stopped = fields([("reason", reason.into()), ("threadId", 1.into()), ("allThreadsStopped", true.into())]);
+        if let Some(text) = text {
+            stopped.push(("text".to_string(), text.into()));
+        }
+        session.event("stopped", Json::Object(stopped));
+
+        // scopes and variables refer to these by position, they stay put for as long as it's stopped
+        let frames: Vec<HashMap<String, Value>> = interp.environment().iter_frames().collect();
+        let disconnected = RuntimeError::new(pause.line(), "Stopped by the debugger");
+        loop {
+            let request = match session.read() {
+                Ok(Some(request)) => request,
+                _ => {
+                    session.disconnected = true;
+                    return Err(disconnected);
+                }
+            };
+            match request.command() {
+                "threads" => session.threads(&request),
+                "stackTrace" => {
+                    let source = object([("path", session.program.as_str().into())]);
+                    let frame = object([
+                        ("id", 1.into()),
+                        ("name", "script".into()),
+                        ("source", source),
+                        ("line", (pause.line() + session.line_base).into()),
+                        ("column", session.column_base.into()),
+                    ]);
+                    session.respond(&request, object([("stackFrames", Json::Array(vec![frame])), ("totalFrames", 1.into())]));
+                }
+                "scopes" => {
+                    let scopes = (0..frames.len())
+                        .map(|index| {
+                            let name = match index {
+                                _ if index + 1 == frames.len() => "Globals",
+                                0 => "Locals",
+                                _ => "Enclosing",
+                            };
+                            object([("name", name.into()), ("variablesReference", (index + 1).into()), ("expensive", false.into())])
+                        })
+                        .collect();
+                    session.respond(&request, object([("scopes", Json::Array(scopes))]));
+                }
+                "variables" => {
+                    let reference = request.get("arguments").and_then(|args| args.get("variablesReference")).and_then(Json::as_usize);
+                    let mut variables: Vec<(&String, &Value)> = match reference.and_then(|reference| frames.get(reference.wrapping_sub(1))) {
+                        Some(frame) => frame.iter().collect(),
+                        None => vec![],
+                    };
+                    variables.sort_by(|a, b| a.0.cmp(b.0));
+                    let variables = variables
+                        .into_iter()
+                        .map(|(name, value)| {
+                            object([
+                                ("name", name.as_str().into()),
+                                ("value", value.to_string().into()),
+                                ("type", value.type_name().into()),
+                                ("variablesReference", 0.into()),
+                            ])
+                        })
+                        .collect();
+                    session.respond(&request, object([("variables", Json::Array(variables))]));
+                }
+                "evaluate" => {
+                    let expression = request.get("arguments").and_then(|args| args.get("expression")).and_then(Json::as_str);
+                    match interp.eval_pure(expression.unwrap_or("")) {
+                        Ok(value) => session.respond(&request, object([("result", value.to_string().into()), ("variablesReference", 0.into())])),
+                        Err(err) => session.fail(&request, &err.to_string()),
+                    }
+                }
+                "setBreakpoints" => {
+                    if let Some((replaced, breakpoints)) = session.set_breakpoints(&request) {
+                        for (line, _) in replaced {
+                            interp.clear_breakpoint(line);
+                        }
+                        for (line, condition) in breakpoints {
+                            interp.set_breakpoint(line, condition.as_deref());
+                        }
+                    }
+                }
+                "continue" => {
+                    session.respond(&request, object([("allThreadsContinued", true.into())]));
+                    return Ok(());
+                }
+                command @ ("next" | "stepIn" | "stepOut") => {
+                    interp.step(match command {
+                        "next" => Step::Over,
+                        "stepIn" => Step::In,
+                        _ => Step::Out,
+                    });
+                    session.respond(&request, Json::Null);
+                    return Ok(());
+                }
+                "disconnect" => {
+                    session.respond(&request, Json::Null);
+                    session.disconnected = true;
+                    return Err(disconnected);
+                }
+                _ => {
+                    let message = format!("{} isn't supported", request.command());
+                    session.fail(&request, &message);
+                }
+            }
+        }
+    }
+}
+
+// Where the script's print and eprint go, output events in the category given.  A print writes
+// its newline separately, so it's held on to until a line is done rather than sent in pieces
+struct Output(Rc<RefCell<Session>>, &'static str, String);
+
+impl Output {
+    fn new(session: &Rc<RefCell<Session>>, category: &'static str) -> Self {
+        Self(session.clone(), category, String::new())
+    }
+
+    fn send(&mut self, output: String) {
+        if let Ok(mut session) = self.0.try_borrow_mut() {
+            session.event("output", object([("category", self.1.into()), ("output", output.into())]));
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.2.push_str(&String::from_utf8_lossy(buf));
+        if let Some(end) = self.2.rfind('\n') {
+            let lines = self.2.drain(..=end).collect();
+            self.send(lines);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.2.is_empty() {
+            let rest = std::mem::take(&mut self.2);
+            self.send(rest);
+        }
+        Ok(())
+    }
+}
+
+// the end of a script that doesn't end its last line
+impl Drop for Output {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+// As much JSON as the protocol needs.  Objects keep their keys in order, which is all a message
+// being written needs and reading one doesn't care about
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn fields<const N: usize>(fields: [(&str, Json); N]) -> Vec<(String, Json)> {
+    IntoIterator::into_iter(fields).map(|(key, value)| (key.to_string(), value)).collect()
+}
+
+fn object<const N: usize>(fields_: [(&str, Json); N]) -> Json {
+    Json::Object(fields(fields_))
+}
+
+impl Json {
+    fn parse(text: &str) -> Option<Json> {
+        let mut reader = JsonReader { bytes: text.as_bytes(), cursor: 0 };
+        let value = reader.value()?;
+        reader.whitespace();
+        (reader.cursor == reader.bytes.len()).then_some(value)
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // what a request asks for, "" if it doesn't say
+    fn command(&self) -> &str {
+        self.get("command").and_then(Json::as_str).unwrap_or("")
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(st) => Some(st),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(st: &str) -> Self {
+        Json::String(st.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(st: String) -> Self {
+        Json::String(st)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            // JSON has no way to write these
+            Json::Number(_) => write!(f, "null"),
+            Json::String(st) => write_string(f, st),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, st: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in st.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct JsonReader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl JsonReader<'_> {
+    fn whitespace(&mut self) {
+        while self.bytes.get(self.cursor).is_some_and(u8::is_ascii_whitespace) {
+            self.cursor += 1;
+        }
+    }
+
+    // `expected` next, past any whitespace
+    fn eat(&mut self, expected: u8) -> bool {
+        self.whitespace();
+        let found = self.bytes.get(self.cursor) == Some(&expected);
+        if found {
+            self.cursor += 1;
+        }
+        found
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Option<Json> {
+        let found = self.bytes[self.cursor..].starts_with(keyword.as_bytes());
+        self.cursor += keyword.len();
+        found.then_some(value)
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.whitespace();
+        match self.bytes.get(self.cursor)? {
+            b'n' => self.keyword("null", Json::Null),
+            b't' => self.keyword("true", Json::Bool(true)),
+            b'f' => self.keyword("false", Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => {
+                self.cursor += 1;
+                let mut items = vec![];
+                if self.eat(b']') {
+                    return Some(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.eat(b']') {
+                        return Some(Json::Array(items));
+                    }
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+            }
+            b'{' => {
+                self.cursor += 1;
+                let mut fields = vec![];
+                if self.eat(b'}') {
+                    return Some(Json::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    if !self.eat(b':') {
+                        return None;
+                    }
+                    fields.push((key, self.value()?));
+                    if self.eat(b'}') {
+                        return Some(Json::Object(fields));
+                    }
+                    if !self.eat(b',') {
+                        return None;
+                    }
+                }
+            }
+            _ => {
+                let start = self.cursor;
+                while self.bytes.get(self.cursor).is_some_and(|b| b"+-.eE".contains(b) || b.is_ascii_digit()) {
+                    self.cursor += 1;
+                }
+                let number = std::str::from_utf8(&self.bytes[start..self.cursor]).ok()?;
+                number.parse().ok().map(Json::Number)
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.bytes.get(self.cursor) != Some(&b'"') {
+            return None;
+        }
+        self.cursor += 1;
+
+        let mut st = String::new();
+        loop {
+            // copy over everything up to the next quote or escape in one go
+            let start = self.cursor;
+            while !matches!(self.bytes.get(self.cursor), Some(b'"') | Some(b'\\') | None) {
+                self.cursor += 1;
+            }
+            st.push_str(std::str::from_utf8(&self.bytes[start..self.cursor]).ok()?);
+
+            let b = *self.bytes.get(self.cursor)?;
+            self.cursor += 1;
+            if b == b'"' {
+                return Some(st);
+            }
+            let escaped = *self.bytes.get(self.cursor)?;
+            self.cursor += 1;
+            st.push(match escaped {
+                b'n' => '\n',
+                b'r' => '\r',
+                b't' => '\t',
+                b'b' => '\u{8}',
+                b'f' => '\u{c}',
+                b'u' => {
+                    let unit = self.hex4()?;
+                    // the second half of a surrogate pair follows as another \u
+                    if (0xd800..0xdc00).contains(&unit) && self.bytes[self.cursor..].starts_with(b"\\u") {
+                        self.cursor += 2;
+                        let low = self.hex4()?;
+                        char::from_u32(0x10000 + ((unit - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)).unwrap_or('\u{fffd}')
+                    } else {
+                        char::from_u32(unit).unwrap_or('\u{fffd}')
+                    }
+                }
+                other => other as char,
+            });
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = self.bytes.get(self.cursor..self.cursor + 4)?;
+        self.cursor += 4;
+        u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // the requests, each framed the way an editor sends them
+    fn requests(requests: &[&str]) -> io::Cursor<Vec<u8>> {
+        let mut input = String::new();
+        for (seq, request) in requests.iter().enumerate() {
+            let body = request.replacen('{', &format!("{{\"seq\":{},\"type\":\"request\",", seq + 1), 1);
+            input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+        }
+        io::Cursor::new(input.into_bytes())
+    }
+
+    // every message the server sent, back out of their frames
+    fn messages(output: &Shared) -> Vec<Json> {
+        let mut session = Session::new(io::Cursor::new(output.0.borrow().clone()), io::sink());
+        std::iter::from_fn(|| session.read().unwrap()).collect()
+    }
+
+    #[test]
+    fn it_reads_and_writes_json() {
+        let text = r#"{"a": [1, -2.5e1, true, null], "b": {"c": "q\"\\\né😀"}, "d": []}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("a"), Some(&Json::Array(vec![1.into(), Json::Number(-25.0), true.into(), Json::Null])));
+        assert_eq!(json.get("b").and_then(|b| b.get("c")), Some(&"q\"\\\né😀".into()));
+        assert_eq!(json.to_string(), r#"{"a":[1,-25,true,null],"b":{"c":"q\"\\\né😀"},"d":[]}"#);
+        assert_eq!(Json::parse("{\"a\": 1"), None);
+        assert_eq!(Json::parse("[1] 2"), None);
+    }
+
+    #[test]
+    fn it_debugs_a_script_for_an_editor() {
+        let program = env::temp_dir().join(format!("tree-walk-dap-test-{}.lox", std::process::id()));
+        fs::write(&program, "var i = 0;\nwhile (i < 3) {\n  i = i + 1;\n  print(i);\n}\n").unwrap();
+        let path = program.to_str().unwrap().replace('\\', "\\\\");
+
+        let output = Shared::default();
+        let input = requests(&[
+            r#"{"command":"initialize","arguments":{"adapterID":"lox"}}"#,
+            &format!(r#"{{"command":"launch","arguments":{{"program":"{}"}}}}"#, path),
+            &format!(r#"{{"command":"setBreakpoints","arguments":{{"source":{{"path":"{}"}},"breakpoints":[{{"line":3,"condition":"i == 1"}}]}}}}"#, path),
+            r#"{"command":"configurationDone"}"#,
+            // stopped at the breakpoint, the second time round
+            r#"{"command":"evaluate","arguments":{"expression":"i * 10"}}"#,
+            r#"{"command":"next","arguments":{"threadId":1}}"#,
+            // stopped after the step, at the print
+            r#"{"command":"stackTrace","arguments":{"threadId":1}}"#,
+            r#"{"command":"scopes","arguments":{"frameId":1}}"#,
+            r#"{"command":"variables","arguments":{"variablesReference":1}}"#,
+            r#"{"command":"continue","arguments":{"threadId":1}}"#,
+            r#"{"command":"disconnect"}"#,
+        ]);
+        serve(input, output.clone()).unwrap();
+        fs::remove_file(&program).unwrap();
+
+        let messages = messages(&output);
+        let events: Vec<String> = messages
+            .iter()
+            .filter_map(|message| {
+                let event = message.get("event")?.as_str()?;
+                let body = message.get("body")?;
+                let detail = body.get("reason").or(body.get("output")).or(body.get("exitCode"));
+                Some(format!("{} {}", event, detail.map(Json::to_string).unwrap_or_default()))
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "initialized ",
+                "output \"1\\n\"",
+                "stopped \"breakpoint\"",
+                "stopped \"step\"",
+                "output \"2\\n\"",
+                "output \"3\\n\"",
+                "exited 0",
+                "terminated ",
+            ]
+        );
+
+        let body = |command: &str| {
+            let response = messages.iter().find(|message| message.get("command").and_then(Json::as_str) == Some(command));
+            response.and_then(|response| response.get("body")).map(Json::to_string).unwrap_or_default()
+        };
+        assert_eq!(body("evaluate"), r#"{"result":"10","variablesReference":0}"#);
+        assert_eq!(
+            body("stackTrace"),
+            format!(r#"{{"stackFrames":[{{"id":1,"name":"script","source":{{"path":"{}"}},"line":4,"column":1}}],"totalFrames":1}}"#, path)
+        );
+        assert_eq!(body("scopes"), r#"{"scopes":[{"name":"Globals","variablesReference":1,"expensive":false}]}"#);
+        assert_eq!(body("variables"), r#"{"variables":[{"name":"i","value":"2","type":"Number","variablesReference":0}]}"#);
+    }
+}
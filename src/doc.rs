@@ -0,0 +1,178 @@
+use crate::lexer::{LexemeKind, Scanner, Token, TriviaKind};
+
+// A top level declaration along with the `///` comment lines written directly above it
+#[derive(Debug, PartialEq)]
+pub struct DocItem {
+    pub kind: &'static str,
+    pub name: String,
+    pub line: usize,
+    pub docs: Vec<String>,
+}
+
+// Doc comments live in trivia, so this works from the lossless token stream rather than the AST.
+// That way declarations the parser does not understand yet (fun, class) are still documented.
+pub fn extract(source: String) -> Vec<DocItem> {
+    let tokens: Vec<Token> = Scanner::lossless(source).collect();
+    let mut items = vec![];
+    let mut depth = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        let kind = match token.lexeme {
+            LexemeKind::LeftBrace => {
+                depth += 1;
+                continue;
+            }
+            LexemeKind::RightBrace => {
+                depth -= 1;
+                continue;
+            }
+            LexemeKind::VAR => "var",
+            LexemeKind::FUN => "fun",
+            LexemeKind::CLASS => "class",
+            _ => continue,
+        };
+
+        if depth > 0 {
+            continue;
+        }
+
+        if let Some(LexemeKind::IDENTIFIER(name)) = tokens.get(i + 1).map(|t| &t.lexeme) {
+            items.push(DocItem {
+                kind,
+                name: name.to_string(),
+                line: token.line,
+                docs: doc_lines(token),
+            });
+        }
+    }
+
+    items
+}
+
+// Only the run of `///` lines immediately above the declaration counts.  A blank line or an
+// ordinary comment in between starts the run over.
+fn doc_lines(token: &Token) -> Vec<String> {
+    let mut docs = vec![];
+    for trivia in &token.leading {
+        match trivia.kind {
+            TriviaKind::Comment => match trivia.text.strip_prefix("///") {
+                Some(text) => docs.push(text.strip_prefix(' ').unwrap_or(text).to_string()),
+                None => docs.clear(),
+            },
+            TriviaKind::Whitespace => {
+                if trivia.text.matches('\n').count() > 1 {
+                    docs.clear();
+                }
+            }
+        }
+    }
+
+    docs
+}
+
+pub fn to_markdown(file: &str, items: &[DocItem]) -> String {
+    let mut st = format!("# {}\n", file);
+    for item in items {
+        st.push_str(&format!("\n## {} `{}`\n", item.kind, item.name));
+        if !item.docs.is_empty() {
+            st.push('\n');
+            for line in &item.docs {
+                st.push_str(line);
+                st.push('\n');
+            }
+        }
+    }
+
+    st
+}
+
+pub fn to_json(file: &str, items: &[DocItem]) -> String {
+    let items: Vec<String> = items
+        .iter()
+        .map(|item| {
+            let docs: Vec<String> = item.docs.iter().map(|d| json_string(d)).collect();
+            format!(
+                "{{\"kind\":{},\"name\":{},\"line\":{},\"docs\":[{}]}}",
+                json_string(item.kind),
+                json_string(&item.name),
+                item.line,
+                docs.join(",")
+            )
+        })
+        .collect();
+
+    format!("{{\"file\":{},\"items\":[{}]}}", json_string(file), items.join(","))
+}
+
+fn json_string(st: &str) -> String {
+    let mut out = String::from("\"");
+    for c in st.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "/// The answer.
+/// Computed at great expense.
+var answer = 42;
+
+/// Not attached, there is a blank line below.
+
+var loose;
+
+// ordinary comment
+/// Greets someone.
+fun greet(name) {
+    /// inner declarations are not documented
+    var inner = 1;
+}
+
+class Empty {}
+";
+
+    #[test]
+    fn it_extracts_doc_comments() {
+        let items = extract(SOURCE.to_string());
+        assert_eq!(
+            items,
+            vec![
+                DocItem {
+                    kind: "var",
+                    name: "answer".to_string(),
+                    line: 2,
+                    docs: vec!["The answer.".to_string(), "Computed at great expense.".to_string()],
+                },
+                DocItem { kind: "var", name: "loose".to_string(), line: 6, docs: vec![] },
+                DocItem { kind: "fun", name: "greet".to_string(), line: 10, docs: vec!["Greets someone.".to_string()] },
+                DocItem { kind: "class", name: "Empty".to_string(), line: 15, docs: vec![] },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_renders_markdown() {
+        let items = extract("/// Doc\nvar a;\nvar b;".to_string());
+        assert_eq!(to_markdown("a.lox", &items), "# a.lox\n\n## var `a`\n\nDoc\n\n## var `b`\n");
+    }
+
+    #[test]
+    fn it_renders_json() {
+        let items = extract("/// Say \"hi\"\nvar a;".to_string());
+        assert_eq!(
+            to_json("a.lox", &items),
+            "{\"file\":\"a.lox\",\"items\":[{\"kind\":\"var\",\"name\":\"a\",\"line\":1,\"docs\":[\"Say \\\"hi\\\"\"]}]}"
+        );
+    }
+}
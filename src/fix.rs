@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use crate::interpreter;
+use crate::lexer::{self, LexemeKind, Scanner, Token};
+use crate::symbols::SymbolTable;
+
+// A change that makes a diagnostic go away.  `safe` ones keep the meaning the script obviously
+// had and can be applied without asking, the rest are suggestions.
+#[derive(Debug, PartialEq)]
+pub struct Fix {
+    pub line: usize,
+    pub message: String,
+    pub edits: Vec<Edit>,
+    pub safe: bool,
+}
+
+// Replace `delete` bytes at `offset` (a byte offset into the source, LineIndex turns it into a
+// line and column) with `insert`
+#[derive(Debug, PartialEq)]
+pub struct Edit {
+    pub offset: usize,
+    pub delete: usize,
+    pub insert: String,
+}
+
+impl Edit {
+    fn insert(offset: usize, text: &str) -> Self {
+        Self { offset, delete: 0, insert: text.to_string() }
+    }
+}
+
+type Span = Range<usize>;
+
+// Everything in `source` we know how to fix, in source order.  Works on tokens rather than the
+// tree so the fixes for things the parser chokes on (print without parentheses) are still found.
+// Declaring an assigned variable needs to know what's declared, so that one only turns up once
+// the script parses.
+pub fn fixes(source: &str) -> Result<Vec<Fix>, String> {
+    let (tokens, errors) = Scanner::lossless(source.to_string()).scan();
+    if let Some(error) = errors.first() {
+        return Err(error.to_string());
+    }
+
+    let spans = lexer::spans(&tokens);
+    let mut fixes = vec![];
+    print_parentheses(&tokens, &spans, &mut fixes);
+    semicolons(&tokens, &spans, &mut fixes);
+    blocks(&tokens, &spans, &mut fixes);
+    if let Ok(table) = SymbolTable::new(source.to_string()) {
+        declarations(&tokens, &spans, &table, &mut fixes);
+    }
+
+    fixes.sort_by_key(|fix| fix.edits[0].offset);
+    Ok(fixes)
+}
+
+// `source` with `fixes` applied.  Edits at the same offset go in the order the fixes are given
+pub fn apply(source: &str, fixes: &[&Fix]) -> String {
+    let mut edits: Vec<&Edit> = fixes.iter().flat_map(|fix| fix.edits.iter()).collect();
+    edits.sort_by_key(|edit| edit.offset);
+
+    let mut out = String::new();
+    let mut copied = 0;
+    for edit in edits {
+        // edits that overlap one already made lose whatever of theirs was already deleted
+        let start = edit.offset.max(copied);
+        out.push_str(&source[copied..start]);
+        out.push_str(&edit.insert);
+        copied = (edit.offset + edit.delete).max(start);
+    }
+    out.push_str(&source[copied..]);
+
+    out
+}
+
+fn is(token: Option<&Token>, kind: LexemeKind) -> bool {
+    token.is_some_and(|token| token.lexeme == kind)
+}
+
+// Index of the last token of the statement starting at `start`: up to its semicolon, or the
+// end of the line if it doesn't have one
+fn statement_end(tokens: &[Token], start: usize) -> usize {
+    let mut end = start;
+    while let Some(token) = tokens.get(end + 1) {
+        if token.lexeme == LexemeKind::EOF || token.line != tokens[start].line || tokens[end].lexeme == LexemeKind::Semicolon {
+            break;
+        }
+        end += 1;
+    }
+
+    if tokens[end].lexeme == LexemeKind::Semicolon && end > start {
+        end - 1
+    } else {
+        end
+    }
+}
+
+// print x;  ->  print(x);
+fn print_parentheses(tokens: &[Token], spans: &[Span], fixes: &mut Vec<Fix>) {
+    for (index, token) in tokens.iter().enumerate() {
+        let next = tokens.get(index + 1);
+        if token.lexeme != LexemeKind::PRINT || is(next, LexemeKind::LeftParen) || is(next, LexemeKind::EOF) || next.is_none() {
+            continue;
+        }
+
+        let end = statement_end(tokens, index + 1);
+        fixes.push(Fix {
+            line: token.line,
+            message: "print needs parentheses around what it prints".to_string(),
+            edits: vec![
+                // a space between print and ( confuses the parser, so it goes
+                Edit { offset: spans[index].end, delete: spans[index + 1].start - spans[index].end, insert: "(".to_string() },
+                Edit::insert(spans[end].end, ")"),
+            ],
+            safe: true,
+        });
+    }
+}
+
+// a = 1
+// b = 2  ->  a = 1; b = 2;
+fn semicolons(tokens: &[Token], spans: &[Span], fixes: &mut Vec<Fix>) {
+    let ends_expression = |token: &Token| {
+        matches!(
+            token.lexeme,
+            LexemeKind::IDENTIFIER(_) | LexemeKind::NUMBER(_) | LexemeKind::STRING(_) | LexemeKind::TRUE
+                | LexemeKind::FALSE | LexemeKind::NIL | LexemeKind::RightParen
+        )
+    };
+    let starts_statement = |token: &Token| {
+        matches!(
+            token.lexeme,
+            LexemeKind::IDENTIFIER(_) | LexemeKind::VAR | LexemeKind::PRINT | LexemeKind::IF | LexemeKind::WHILE
+                | LexemeKind::WITH | LexemeKind::DEFER | LexemeKind::LeftBrace | LexemeKind::RightBrace | LexemeKind::EOF
+        )
+    };
+
+    // one entry per open parenthesis, true for the condition of an if or while (or what a with
+    // holds), whose closing parenthesis is followed by a body rather than ending anything
+    let mut parens = vec![];
+    for (index, token) in tokens.iter().enumerate() {
+        let mut condition = false;
+        match token.lexeme {
+            LexemeKind::LeftParen => {
+                let keyword = index.checked_sub(1).map(|before| &tokens[before].lexeme);
+                parens.push(matches!(keyword, Some(LexemeKind::IF) | Some(LexemeKind::WHILE) | Some(LexemeKind::WITH)));
+            }
+            LexemeKind::RightParen => condition = parens.pop().unwrap_or(false),
+            _ => {}
+        }
+
+        if !parens.is_empty() || condition || !ends_expression(token) {
+            continue;
+        }
+
+        // the last statement, or one with the next on a later line
+        let ends_statement = match tokens.get(index + 1) {
+            None => true,
+            Some(next) => starts_statement(next) && (next.line > token.line || next.lexeme == LexemeKind::EOF),
+        };
+        if ends_statement {
+            fixes.push(Fix {
+                line: token.line,
+                message: "Missing semicolon".to_string(),
+                edits: vec![Edit::insert(spans[index].end, ";")],
+                safe: true,
+            });
+        }
+    }
+}
+
+// if (a) var b = 1;  ->  if (a) { var b = 1; }
+// Not safe: b stops being visible after the if
+fn blocks(tokens: &[Token], spans: &[Span], fixes: &mut Vec<Fix>) {
+    let mut parens = vec![];
+    for (index, token) in tokens.iter().enumerate() {
+        let body = match token.lexeme {
+            LexemeKind::LeftParen => {
+                let keyword = index.checked_sub(1).map(|before| tokens[before].lexeme.clone());
+                parens.push(keyword);
+                continue;
+            }
+            LexemeKind::RightParen => parens.pop().flatten(),
+            LexemeKind::ELSE => Some(LexemeKind::ELSE),
+            _ => continue,
+        };
+
+        let keyword = match body {
+            Some(LexemeKind::IF) => "if",
+            Some(LexemeKind::WHILE) => "while",
+            Some(LexemeKind::ELSE) => "else",
+            _ => continue,
+        };
+        if !is(tokens.get(index + 1), LexemeKind::VAR) {
+            continue;
+        }
+
+        let end = statement_end(tokens, index + 1);
+        let end = if is(tokens.get(end + 1), LexemeKind::Semicolon) { end + 1 } else { end };
+        fixes.push(Fix {
+            line: tokens[index + 1].line,
+            message: format!("Declaration as the body of {}, wrap it in a block", keyword),
+            edits: vec![Edit::insert(spans[index + 1].start, "{ "), Edit::insert(spans[end].end, " }")],
+            safe: false,
+        });
+    }
+}
+
+// a = 1;  ->  var a = 1;  at the top level, for a name nothing declares
+fn declarations(tokens: &[Token], spans: &[Span], table: &SymbolTable, fixes: &mut Vec<Fix>) {
+    let natives: HashSet<&str> = interpreter::natives().iter().map(|native| native.name).collect();
+
+    // brace depth before each token
+    let mut depth = vec![];
+    let mut open = 0usize;
+    for token in tokens.iter() {
+        if token.lexeme == LexemeKind::RightBrace {
+            open = open.saturating_sub(1);
+        }
+        depth.push(open);
+        if token.lexeme == LexemeKind::LeftBrace {
+            open += 1;
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for symbol in table.symbols() {
+        let first = seen.insert(symbol.name.as_str());
+        if !first || symbol.binding.is_some() || natives.contains(symbol.name.as_str()) {
+            continue;
+        }
+
+        let index = symbol.token;
+        let starts_statement = match index.checked_sub(1).map(|before| &tokens[before]) {
+            None => true,
+            Some(before) => {
+                matches!(before.lexeme, LexemeKind::Semicolon | LexemeKind::RightBrace) || before.line < tokens[index].line
+            }
+        };
+        if depth[index] == 0 && starts_statement && is(tokens.get(index + 1), LexemeKind::Equal) {
+            fixes.push(Fix {
+                line: symbol.line,
+                message: format!("\"{}\" is assigned but never declared", symbol.name),
+                edits: vec![Edit::insert(spans[index].start, "var ")],
+                safe: true,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every safe fix, applied until there are none left
+    fn fix(source: &str) -> String {
+        let mut source = source.to_string();
+        loop {
+            let fixes = fixes(&source).unwrap();
+            let safe: Vec<&Fix> = fixes.iter().filter(|fix| fix.safe).collect();
+            if safe.is_empty() {
+                return source;
+            }
+            source = apply(&source, &safe);
+        }
+    }
+
+    #[test]
+    fn it_adds_semicolons() {
+        assert_eq!(fix("var a = 1\nprint(a)\na = a + 1 // again\n"), "var a = 1;\nprint(a);\na = a + 1; // again\n");
+        assert_eq!(fix("if (a)\n  print(a);\nwhile (a)\n  a = false"), "if (a)\n  print(a);\nwhile (a)\n  a = false;");
+        assert_eq!(fix("var a = 1 +\n  2;"), "var a = 1 +\n  2;");
+    }
+
+    #[test]
+    fn it_adds_print_parentheses() {
+        assert_eq!(fix("print 1 + 2;\nprint \"a\""), "print(1 + 2);\nprint(\"a\");");
+    }
+
+    #[test]
+    fn it_declares_top_level_assignments() {
+        assert_eq!(fix("total = 1;\ntotal = total + 1;\n{ inner = 2; }"), "var total = 1;\ntotal = total + 1;\n{ inner = 2; }");
+        // the fix for the print comes first, then the script parses and the assignment is found
+        assert_eq!(fix("count = 1\nprint count"), "var count = 1;\nprint(count);");
+    }
+
+    #[test]
+    fn it_suggests_blocks() {
+        let source = "if (true) var a = 1;\nelse var a = 2;";
+        let fixes = fixes(source).unwrap();
+        assert_eq!(fixes.len(), 2);
+        assert!(fixes.iter().all(|fix| !fix.safe));
+        assert_eq!(fixes[1].message, "Declaration as the body of else, wrap it in a block");
+
+        let all: Vec<&Fix> = fixes.iter().collect();
+        assert_eq!(apply(source, &all), "if (true) { var a = 1; }\nelse { var a = 2; }");
+    }
+}
@@ -0,0 +1,453 @@
+use crate::lexer::LexemeKind;
+use crate::parser::{Associativity, Precedence, OPERATORS, PREFIX_OPERATORS};
+
+// The grammar as data, for `tree-walk grammar`.  Expressions are built from the parser's own
+// tables, OPERATORS and PREFIX_OPERATORS, so a new operator shows up here without anyone
+// remembering to.  Statements are hand written by the parser, so they're written out again
+// below, following parser/statement.rs
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    // source text exactly as written, "var"
+    Token(String),
+    // a kind of token rather than one token, IDENTIFIER
+    Class(&'static str),
+    // another rule
+    Rule(&'static str),
+    Sequence(Vec<Node>),
+    Choice(Vec<Node>),
+    Optional(Box<Node>),
+    // any number of times, none included
+    Repeat(Box<Node>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub name: &'static str,
+    pub body: Node,
+}
+
+fn token(text: &str) -> Node {
+    Node::Token(text.to_string())
+}
+
+fn seq(nodes: Vec<Node>) -> Node {
+    Node::Sequence(nodes)
+}
+
+fn optional(node: Node) -> Node {
+    Node::Optional(Box::new(node))
+}
+
+fn repeat(node: Node) -> Node {
+    Node::Repeat(Box::new(node))
+}
+
+const IDENTIFIER: Node = Node::Class("IDENTIFIER");
+
+// a Choice only when there's a choice
+fn choice(mut nodes: Vec<Node>) -> Node {
+    if nodes.len() == 1 {
+        nodes.remove(0)
+    } else {
+        Node::Choice(nodes)
+    }
+}
+
+// what a level's rule is called, its Precedence in snake case
+fn level_name(level: Precedence) -> &'static str {
+    match level {
+        Precedence::Assignment => "assignment",
+        Precedence::Pipe => "pipe",
+        Precedence::Coalesce => "coalesce",
+        Precedence::Or => "or",
+        Precedence::And => "and",
+        Precedence::Equality => "equality",
+        Precedence::Comparison => "comparison",
+        Precedence::Term => "term",
+        Precedence::Factor => "factor",
+        Precedence::Unary => "unary",
+        Precedence::Call => "call",
+    }
+}
+
+// Every rule, the whole program first
+pub fn rules() -> Vec<Rule> {
+    let semicolon = || optional(token(";"));
+    let mut rules = vec![
+        Rule { name: "program", body: repeat(Node::Rule("declaration")) },
+        Rule {
+            name: "declaration",
+            body: choice(vec![
+                Node::Rule("var_declaration"),
+                Node::Rule("record_declaration"),
+                Node::Rule("class_declaration"),
+                Node::Rule("statement"),
+            ]),
+        },
+        Rule {
+            name: "var_declaration",
+            body: seq(vec![token("var"), IDENTIFIER, optional(seq(vec![token("="), Node::Rule("expression")])), semicolon()]),
+        },
+        Rule {
+            name: "record_declaration",
+            body: seq(vec![
+                token("record"),
+                IDENTIFIER,
+                token("{"),
+                optional(seq(vec![IDENTIFIER, repeat(seq(vec![token(","), IDENTIFIER])), optional(token(","))])),
+                token("}"),
+            ]),
+        },
+        Rule { name: "class_declaration", body: seq(vec![token("class"), IDENTIFIER, token("{"), repeat(Node::Rule("method")), token("}")]) },
+        Rule {
+            name: "method",
+            body: seq(vec![
+                IDENTIFIER,
+                token("("),
+                optional(seq(vec![IDENTIFIER, repeat(seq(vec![token(","), IDENTIFIER]))])),
+                token(")"),
+                Node::Rule("block"),
+            ]),
+        },
+        Rule {
+            name: "statement",
+            body: choice(vec![
+                Node::Rule("if_statement"),
+                Node::Rule("while_statement"),
+                Node::Rule("with_statement"),
+                Node::Rule("block"),
+                Node::Rule("print_statement"),
+                Node::Rule("defer_statement"),
+                seq(vec![Node::Rule("expression"), semicolon()]),
+            ]),
+        },
+        Rule {
+            name: "if_statement",
+            body: seq(vec![
+                token("if"),
+                token("("),
+                Node::Rule("expression"),
+                token(")"),
+                Node::Rule("declaration"),
+                optional(seq(vec![token("else"), Node::Rule("declaration")])),
+            ]),
+        },
+        Rule {
+            name: "while_statement",
+            body: seq(vec![token("while"), token("("), Node::Rule("expression"), token(")"), Node::Rule("declaration")]),
+        },
+        Rule {
+            name: "with_statement",
+            body: seq(vec![
+                token("with"),
+                token("("),
+                token("var"),
+                IDENTIFIER,
+                token("="),
+                Node::Rule("expression"),
+                token(")"),
+                Node::Rule("declaration"),
+            ]),
+        },
+        Rule { name: "block", body: seq(vec![token("{"), repeat(Node::Rule("declaration")), token("}")]) },
+        Rule {
+            name: "print_statement",
+            body: seq(vec![token("print"), token("("), optional(Node::Rule("arguments")), token(")"), semicolon()]),
+        },
+        Rule { name: "defer_statement", body: seq(vec![token("defer"), Node::Rule("expression"), semicolon()]) },
+        Rule { name: "expression", body: Node::Rule(level_name(Precedence::Assignment)) },
+    ];
+
+    // one rule per level that has operators between operands, loosest first, each made of the
+    // next tighter one
+    let mut level = Precedence::Assignment;
+    while level < Precedence::Unary {
+        let next = Node::Rule(level_name(level.tighter()));
+        let operators: Vec<_> = OPERATORS.iter().filter(|(_, precedence, _)| *precedence == level).collect();
+
+        // `is` names a type on its right, it isn't an expression
+        let mut operands: Vec<Node> = vec![];
+        let plain: Vec<Node> = operators
+            .iter()
+            .filter(|(operator, _, _)| *operator != LexemeKind::IS)
+            .map(|(operator, _, _)| token(&operator.source_text()))
+            .collect();
+        if !plain.is_empty() {
+            operands.push(seq(vec![choice(plain), next.clone()]));
+        }
+        if operators.iter().any(|(operator, _, _)| *operator == LexemeKind::IS) {
+            operands.push(seq(vec![token("is"), IDENTIFIER]));
+        }
+
+        let body = match operators.first().map(|(_, _, associativity)| *associativity) {
+            // a = b = c is a = (b = c), so the right is this level again
+            Some(Associativity::Right) => {
+                let operators = operators.iter().map(|(operator, _, _)| token(&operator.source_text())).collect();
+                seq(vec![next, optional(seq(vec![choice(operators), Node::Rule(level_name(level))]))])
+            }
+            Some(Associativity::Left) => seq(vec![next, repeat(choice(operands))]),
+            None => next,
+        };
+        rules.push(Rule { name: level_name(level), body });
+        level = level.tighter();
+    }
+
+    let mut prefixes: Vec<Node> = PREFIX_OPERATORS.iter().map(|operator| token(&operator.source_text())).collect();
+    prefixes.push(token("await"));
+    rules.push(Rule {
+        name: level_name(Precedence::Unary),
+        body: choice(vec![seq(vec![choice(prefixes), Node::Rule("unary")]), Node::Rule("call")]),
+    });
+
+    // calls and property reads, which follow their operand
+    let suffixes = OPERATORS
+        .iter()
+        .filter(|(_, precedence, _)| *precedence == Precedence::Call)
+        .map(|(operator, _, _)| match operator {
+            LexemeKind::LeftParen => seq(vec![token("("), optional(Node::Rule("arguments")), token(")")]),
+            operator => seq(vec![token(&operator.source_text()), IDENTIFIER]),
+        })
+        .collect();
+    rules.push(Rule { name: level_name(Precedence::Call), body: seq(vec![Node::Rule("primary"), repeat(choice(suffixes))]) });
+
+    rules.push(Rule {
+        name: "arguments",
+        body: seq(vec![Node::Rule("expression"), repeat(seq(vec![token(","), Node::Rule("expression")]))]),
+    });
+    rules.push(Rule {
+        name: "primary",
+        body: choice(vec![
+            token("true"),
+            token("false"),
+            Node::Class("NUMBER"),
+            Node::Class("STRING"),
+            IDENTIFIER,
+            // the same if as the statement, only somewhere a value is wanted
+            Node::Rule("if_statement"),
+            // () is nil
+            seq(vec![token("("), optional(Node::Rule("expression")), token(")")]),
+        ]),
+    });
+
+    rules
+}
+
+// ISO 14977 style: `,` between what comes in order, [ ] for optional, { } for any number of
+pub fn to_ebnf(rules: &[Rule]) -> String {
+    let width = rules.iter().map(|rule| rule.name.len()).max().unwrap_or(0);
+    rules.iter().map(|rule| format!("{:width$} = {} ;\n", rule.name, ebnf(&rule.body, false), width = width)).collect()
+}
+
+// `nested` when it's part of a sequence, where a choice needs parens
+fn ebnf(node: &Node, nested: bool) -> String {
+    match node {
+        // there's no escaping in EBNF, a " is quoted the other way
+        Node::Token(text) if text.contains('"') => format!("'{}'", text),
+        Node::Token(text) => format!("\"{}\"", text),
+        Node::Class(name) | Node::Rule(name) => name.to_string(),
+        Node::Sequence(nodes) => nodes.iter().map(|node| ebnf(node, true)).collect::<Vec<_>>().join(" , "),
+        Node::Choice(nodes) => {
+            let choices = nodes.iter().map(|node| ebnf(node, false)).collect::<Vec<_>>().join(" | ");
+            if nested {
+                format!("( {} )", choices)
+            } else {
+                choices
+            }
+        }
+        Node::Optional(node) => format!("[ {} ]", ebnf(node, false)),
+        Node::Repeat(node) => format!("{{ {} }}", ebnf(node, false)),
+    }
+}
+
+// One page with a railroad diagram per rule, drawn as inline SVG so it needs nothing else to
+// open.  Rules in a diagram link to their own
+pub fn to_railroad_html(rules: &[Rule]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Lox grammar</title>\n<style>\n\
+         body { font-family: sans-serif; }\n\
+         svg path { fill: none; stroke: #333; stroke-width: 1.5; }\n\
+         svg rect { fill: #f5f5dc; stroke: #333; stroke-width: 1.5; }\n\
+         svg .rule rect { fill: #dbe9f9; }\n\
+         svg .class rect { fill: #eee; }\n\
+         svg text { font: 13px monospace; text-anchor: middle; dominant-baseline: central; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    for rule in rules {
+        let diagram = layout(&rule.body);
+        let (width, height) = (diagram.width + 2 * RAIL, diagram.height + 2 * GAP);
+        let y = GAP + diagram.baseline;
+        html.push_str(&format!("<h2 id=\"{0}\">{0}</h2>\n", rule.name));
+        html.push_str(&format!("<svg width=\"{}\" height=\"{}\">\n", width, height));
+        // a bar at either end, the way railroad diagrams start and stop
+        html.push_str(&format!("<path d=\"M2 {} v12 M2 {} h{}\"/>\n", y - 6, y, RAIL - 2));
+        html.push_str(&format!("<g transform=\"translate({},{})\">{}</g>\n", RAIL, GAP, diagram.svg));
+        html.push_str(&format!("<path d=\"M{} {} h{} M{} {} v12\"/>\n", RAIL + diagram.width, y, RAIL - 2, width - 2, y - 6));
+        html.push_str("</svg>\n");
+        html.push_str(&format!("<pre>{}</pre>\n", escape(&to_ebnf(std::slice::from_ref(rule)))));
+    }
+    html.push_str("</body>\n</html>\n");
+
+    html
+}
+
+// room either side of a diagram, and between the rows of a choice
+const RAIL: i32 = 20;
+const GAP: i32 = 10;
+const BOX_HEIGHT: i32 = 24;
+
+// A piece of diagram drawn from (0, 0), with the line in at (0, baseline) and out at
+// (width, baseline)
+struct Diagram {
+    width: i32,
+    height: i32,
+    baseline: i32,
+    svg: String,
+}
+
+fn layout(node: &Node) -> Diagram {
+    match node {
+        Node::Token(text) => boxed(text, "token", 12, None),
+        Node::Class(name) => boxed(name, "class", 4, None),
+        Node::Rule(name) => boxed(name, "rule", 0, Some(name)),
+        Node::Sequence(nodes) => sequence(nodes.iter().map(layout).collect()),
+        Node::Choice(nodes) => branches(nodes.iter().map(layout).collect()),
+        Node::Optional(node) => branches(vec![line(), layout(node)]),
+        Node::Repeat(node) => branches(vec![line(), loop_back(layout(node))]),
+    }
+}
+
+fn boxed(text: &str, class: &str, radius: i32, link: Option<&str>) -> Diagram {
+    let width = text.chars().count() as i32 * 8 + 20;
+    let mut svg = format!(
+        "<g class=\"{}\"><rect width=\"{}\" height=\"{}\" rx=\"{}\"/><text x=\"{}\" y=\"{}\">{}</text></g>",
+        class,
+        width,
+        BOX_HEIGHT,
+        radius,
+        width / 2,
+        BOX_HEIGHT / 2,
+        escape(text)
+    );
+    if let Some(name) = link {
+        svg = format!("<a href=\"#{}\">{}</a>", name, svg);
+    }
+
+    Diagram { width, height: BOX_HEIGHT, baseline: BOX_HEIGHT / 2, svg }
+}
+
+// straight through, the way past something optional
+fn line() -> Diagram {
+    Diagram { width: 0, height: 0, baseline: 0, svg: String::new() }
+}
+
+// one after the other along the same line
+fn sequence(parts: Vec<Diagram>) -> Diagram {
+    let baseline = parts.iter().map(|part| part.baseline).max().unwrap_or(0);
+    let below = parts.iter().map(|part| part.height - part.baseline).max().unwrap_or(0);
+
+    let mut svg = String::new();
+    let mut x = 0;
+    for (i, part) in parts.iter().enumerate() {
+        if i > 0 {
+            svg.push_str(&format!("<path d=\"M{} {} h{}\"/>", x, baseline, GAP));
+            x += GAP;
+        }
+        svg.push_str(&format!("<g transform=\"translate({},{})\">{}</g>", x, baseline - part.baseline, part.svg));
+        x += part.width;
+    }
+
+    Diagram { width: x, height: baseline + below, baseline, svg }
+}
+
+// Stacked, the first on the line through and the rest below it, with rails either side to get
+// to each
+fn branches(parts: Vec<Diagram>) -> Diagram {
+    let inner = parts.iter().map(|part| part.width).max().unwrap_or(0);
+    let width = inner + 2 * RAIL;
+    let baseline = parts.first().map_or(0, |part| part.baseline);
+
+    let mut svg = String::new();
+    let mut top = 0;
+    for (i, part) in parts.iter().enumerate() {
+        let y = top + part.baseline;
+        svg.push_str(&format!("<g transform=\"translate({},{})\">{}</g>", RAIL, top, part.svg));
+        if i == 0 {
+            svg.push_str(&format!("<path d=\"M0 {0} h{1} M{2} {0} H{3}\"/>", y, RAIL, RAIL + part.width, width));
+        } else {
+            // down the rail on the way in, back up it on the way out
+            svg.push_str(&format!(
+                "<path d=\"M{0} {1} V{2} h{0} M{3} {2} H{4} V{1}\"/>",
+                RAIL / 2,
+                baseline,
+                y,
+                RAIL + part.width,
+                width - RAIL / 2
+            ));
+        }
+        top += part.height.max(0) + GAP;
+    }
+
+    Diagram { width, height: top - GAP, baseline, svg }
+}
+
+// `part` with a way from its end back round underneath to its start, for once or more
+fn loop_back(part: Diagram) -> Diagram {
+    let width = part.width + 2 * RAIL;
+    let below = part.height + GAP;
+    let svg = format!(
+        "<path d=\"M0 {0} h{1} M{2} {0} H{3} M{4} {0} V{5} H{6} V{0}\"/><g transform=\"translate({1},0)\">{7}</g>",
+        part.baseline,
+        RAIL,
+        RAIL + part.width,
+        width,
+        width - RAIL / 2,
+        below,
+        RAIL / 2,
+        part.svg
+    );
+
+    Diagram { width, height: below, baseline: part.baseline, svg }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every rule a diagram links to
+    fn referenced(node: &Node, names: &mut Vec<&'static str>) {
+        match node {
+            Node::Rule(name) => names.push(name),
+            Node::Sequence(nodes) | Node::Choice(nodes) => nodes.iter().for_each(|node| referenced(node, names)),
+            Node::Optional(node) | Node::Repeat(node) => referenced(node, names),
+            Node::Token(_) | Node::Class(_) => {}
+        }
+    }
+
+    #[test]
+    fn it_writes_the_grammar_from_the_parser_tables() {
+        let rules = rules();
+        let ebnf = to_ebnf(&rules);
+
+        // nothing refers to a rule that isn't there
+        let mut names = vec![];
+        rules.iter().for_each(|rule| referenced(&rule.body, &mut names));
+        assert!(names.iter().all(|name| rules.iter().any(|rule| rule.name == *name)), "{}", ebnf);
+
+        // and every operator the parser knows is in it
+        for (operator, _, _) in OPERATORS.iter() {
+            assert!(ebnf.contains(&format!("\"{}\"", operator.source_text())), "{:?} is missing", operator);
+        }
+        assert!(ebnf.contains("term               = factor , { ( \"-\" | \"+\" ) , factor } ;\n"), "{}", ebnf);
+        assert!(ebnf.contains("assignment         = pipe , [ \"=\" , assignment ] ;\n"), "{}", ebnf);
+        assert!(ebnf.contains("comparison         = term , { ( \">\" | \">=\" | \"<\" | \"<=\" ) , term | \"is\" , IDENTIFIER } ;\n"), "{}", ebnf);
+
+        let html = to_railroad_html(&rules);
+        assert_eq!(html.matches("<svg ").count(), rules.len());
+        assert!(html.contains("<a href=\"#expression\">"));
+        assert!(html.contains("&lt;="));
+    }
+}
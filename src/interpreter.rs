@@ -1,13 +1,55 @@
+#[cfg(feature = "async")]
+mod asynchronous;
+mod builder;
+mod callable;
+mod cancel;
+mod class;
+mod config;
+#[cfg(feature = "crypto")]
+mod crypto;
+mod debugger;
+mod encoding;
 mod environment;
+mod hamt;
+mod natives;
+mod record;
+mod report;
+mod resume;
+mod scope;
+mod timers;
+mod trace;
+mod workers;
 
 use std::fmt;
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
 use std::rc::Rc;
-use crate::parser::{Expr, Stmt, Value};
-use crate::lexer::LexemeKind;
-use crate::parser;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::parser::{Expr, Function, NodeId, Stmt, Value, TYPE_NAMES};
+use crate::lexer::{LexemeKind, Scanner};
+use crate::parser::{self, Parser};
+use crate::values;
 use crate::visitor::{ExpressionVisitor, StatementVisitor};
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncInterpreter, AsyncNative, NativeFuture};
+pub use builder::InterpreterBuilder;
+pub use callable::{callable, check_arity, Callable};
+pub use cancel::CancelToken;
+pub use class::{Class, Instance, Method};
+pub use debugger::{parse_breakpoint, Console, Debugger, Pause, Step};
+pub use config::{Capabilities, InterpreterConfig, StepHook, Yield, DEFAULT_MAX_CALL_DEPTH, DEFAULT_STACK_SIZE};
 pub use environment::Environment;
+pub use natives::{NativeFunction, NativeModule};
+pub use record::{Record, RecordType};
+pub use report::{ExecutionReport, ProgramOutcome};
+pub use resume::RunState;
+use resume::Paused;
+pub use trace::{read_trace, write_trace, Trace};
+use scope::ScopeGuard;
+use timers::Timers;
+use workers::{Parent, Workers};
 
 // Error strategy
 // Lexer - captures all tokens. UNEXPECTED(String) enum variant for unknown
@@ -20,6 +62,13 @@ pub struct RuntimeError {
     message: String,
 }
 
+impl RuntimeError {
+    // for the rest of the crate, which has to stop a run too now and again
+    pub(crate) fn new(line: usize, message: &str) -> Self {
+        Self { line, message: message.to_string() }
+    }
+}
+
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} [line: {}]", self.message, self.line)
@@ -30,56 +79,742 @@ type InterpreterResult = Result<Value, RuntimeError>;
 
 pub struct Interpreter {
     environment: Rc<RefCell<Environment>>,
+    // natives, consulted once a name is not found in any scope
+    builtins: HashMap<String, Value>,
+    // globals that are only built when a script first uses them, see register_lazy_global
+    lazy_globals: HashMap<String, Box<dyn FnOnce() -> Value>>,
+    // `defer`red expressions of every scope still running, innermost last.  Each scope runs
+    // its own on the way out, see ScopeGuard::finish
+    deferred: Vec<Expr>,
+    // waiting for the program to finish, see run_until_idle
+    timers: Timers,
+    // scripts this one spawned, and the one that spawned it if it's a worker itself
+    workers: Workers,
+    parent: Option<Parent>,
+    number_format: values::NumberFormat,
+    trace: Trace,
+    random_state: u64,
+    // where print goes and readLine reads from, stdin when there's no input
+    output: Box<dyn Write>,
+    // where eprint goes, so warnings stay out of whatever reads the output
+    error_output: Box<dyn Write>,
+    input: Option<Box<dyn BufRead>>,
+    config: InterpreterConfig,
+    report: ExecutionReport,
+    call_depth: usize,
+    // how many statements and expressions the tree walk is inside of, see enter
+    depth: usize,
+    // what hasFeature says yes to
+    features: HashSet<String>,
+    // where the native being called was called from, for __line__()
+    call_line: usize,
+    // the step the step hook is next due at, 0 until the run has scheduled it
+    next_yield: u64,
+    // Inline cache for variable reads: the node's id -> how many scopes out its name was found,
+    // None for a native.  Entries are only trusted while `shadowing` hasn't moved since, see
+    // define.  No two nodes share an id, so nothing has to be forgotten when a tree is freed
+    lookups: HashMap<usize, (u64, Option<usize>)>,
+    shadowing: u64,
+    // set for the duration of `start`, so a native can't start another run in the middle of one
+    running: bool,
+    // a program run with eval_resumable that ran out of steps, see resume
+    paused: Option<Paused>,
+    // what the script passed to exit(), which stops the run like an error does.  See exited
+    exit_code: Option<i32>,
+    cancel: CancelToken,
+    // what a watched write or a breakpoint stops for, taken out while it's paused so it can be
+    // handed the interpreter
+    debugger: Option<Box<dyn Debugger>>,
+    // names a write to pauses the run, see watched
+    watches: HashSet<String>,
+    // line -> the condition it only stops when, see breakpoint
+    breakpoints: HashMap<usize, Option<String>>,
+    // the scope the innermost statement to fail ran in, kept for the debugger until the error
+    // gets out of the script's statement.  See post_mortem
+    failed: Option<Rc<RefCell<Environment>>>,
+    // pause at the next statement run with no more calls than this under way, see step
+    stepping: Option<usize>,
+    // the debugger stopped the run, which ends it like exit() would instead of moving on to the
+    // next statement
+    stopped: bool,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self {
-            environment: Rc::new(RefCell::new(Environment::new()))
+        Self::with_config(InterpreterConfig::default())
+    }
+
+    pub fn with_config(config: InterpreterConfig) -> Self {
+        Self::builder().config(config).build()
+    }
+
+    // Make `module`'s functions available to scripts as name.function, in place of any native
+    // or module already called `name`
+    pub fn register_module(&mut self, name: &str, module: NativeModule) {
+        self.builtins.insert(name.to_string(), Value::Module(Arc::new(module.named(name))));
+    }
+
+    // A global called `name` whose value is made by `init` the first time a script reads it, so
+    // expensive host objects are only built for the scripts that use them.  Assigning to it
+    // first replaces it without ever calling `init`.
+    pub fn register_lazy_global(&mut self, name: &str, init: impl FnOnce() -> Value + 'static) {
+        self.lazy_globals.insert(name.to_string(), Box::new(init));
+    }
+
+    // Build the lazy global `name`, if there is one, into the global scope
+    fn force_lazy(&mut self, name: &str) -> Option<Value> {
+        let value = self.lazy_globals.remove(name)?();
+        self.define_global(name, value.clone());
+        Some(value)
+    }
+
+    // Let scripts know `name` is there with hasFeature, for whatever the host adds to the language
+    pub fn add_feature(&mut self, name: &str) {
+        self.features.insert(name.to_string());
+    }
+
+    // What __file__ reads as, the path of the script about to run
+    pub fn set_file(&mut self, name: &str) {
+        self.builtins.insert("__file__".to_string(), Value::STRING(name.into()));
+    }
+
+    // in the innermost scope, by whatever's on `line`
+    fn define(&mut self, name: &str, value: Value, line: usize) -> Result<(), RuntimeError> {
+        // a cached read of this name may now resolve somewhere closer. Fresh names can't have
+        // been cached through this scope, so only shadowing invalidates
+        if self.environment.borrow().depth_of(name).is_some() || self.builtins.contains_key(name) {
+            self.shadowing += 1;
+        }
+        let old = self.environment.borrow_mut().define(name.to_string(), value.clone());
+        self.watched(name, old, value, line)
+    }
+
+    fn define_global(&mut self, name: &str, value: Value) {
+        self.globals().borrow_mut().define(name.to_string(), value);
+        // a read of the name may have been cached as a native
+        self.shadowing += 1;
+    }
+
+    fn globals(&self) -> Rc<RefCell<Environment>> {
+        let mut environment = self.environment.clone();
+        loop {
+            let enclosing = environment.borrow().enclosing().cloned();
+            match enclosing {
+                Some(env) => environment = env,
+                None => return environment,
+            }
+        }
+    }
+
+    // What pauses the run when a watched variable is written, see watch
+    pub fn set_debugger(&mut self, debugger: impl Debugger + 'static) {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    // Pause whenever `name` is declared or assigned, in any scope.  Only does anything once
+    // there's a debugger to pause into
+    pub fn watch(&mut self, name: &str) {
+        self.watches.insert(name.to_string());
+    }
+
+    // false if `name` wasn't being watched
+    pub fn unwatch(&mut self, name: &str) -> bool {
+        self.watches.remove(name)
+    }
+
+    // Pause before running a statement on `line`, every time or only when `condition` is true
+    // then.  Replaces whatever breakpoint was on the line before
+    pub fn set_breakpoint(&mut self, line: usize, condition: Option<&str>) {
+        self.breakpoints.insert(line, condition.map(str::to_string));
+    }
+
+    // false if there wasn't one on `line`
+    pub fn clear_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line).is_some()
+    }
+
+    // Pause at the next statement that `step` goes as far as, for a debugger that's paused now, or
+    // before a run to stop at its first statement.  Whatever pauses first, a breakpoint in a call
+    // stepped over say, calls the step off, and so does the end of the run
+    pub fn step(&mut self, step: Step) {
+        self.stepping = Some(match step {
+            Step::In => usize::MAX,
+            Step::Over => self.call_depth,
+            Step::Out => self.call_depth.saturating_sub(1),
+        });
+    }
+
+    // Hand over to the debugger if `stmt` is where a step stops, or on a breakpoint's line and
+    // its condition holds.  The condition goes through eval_pure, so checking it can't change
+    // what the script sees
+    fn breakpoint(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        let Some(line) = stmt.line() else {
+            return Ok(());
+        };
+        if self.stepping.is_some_and(|depth| self.call_depth <= depth) {
+            return self.pause(&Pause::Step { line });
+        }
+        let error = match self.breakpoints.get(&line) {
+            None => return Ok(()),
+            Some(None) => None,
+            Some(Some(condition)) => match self.eval_pure(&condition.clone()) {
+                Err(err) => Some(err.message),
+                value if is_truthy(&value) => None,
+                _ => return Ok(()),
+            },
+        };
+
+        self.pause(&Pause::Breakpoint { line, error })
+    }
+
+    // An error made it out of one of the script's statements.  The debugger gets to look around
+    // the scope it happened in, with the variables as they were then, before the run moves on.
+    // Not for exit(), that's the script finishing
+    fn post_mortem(&mut self, error: &RuntimeError) {
+        let Some(failed) = self.failed.take() else {
+            return;
+        };
+        if self.exit_code.is_some() || self.stopped {
+            return;
+        }
+
+        let environment = std::mem::replace(&mut self.environment, failed);
+        let pause = Pause::Error { message: error.message.clone(), line: error.line };
+        // it's going to stop or carry on with the error either way
+        let _ = self.pause(&pause);
+        self.environment = environment;
+    }
+
+    // Hand over to the debugger if `name` is being watched
+    fn watched(&mut self, name: &str, old: Option<Value>, new: Value, line: usize) -> Result<(), RuntimeError> {
+        if self.debugger.is_none() || !self.watches.contains(name) {
+            return Ok(());
+        }
+
+        let pause = Pause::Watch { name: name.to_string(), old, new, line };
+        self.pause(&pause)
+    }
+
+    fn pause(&mut self, pause: &Pause) -> Result<(), RuntimeError> {
+        let Some(mut debugger) = self.debugger.take() else {
+            return Ok(());
+        };
+        self.stepping = None;
+        let result = debugger.pause(self, pause);
+        // unless it installed another one while it was paused
+        self.debugger.get_or_insert(debugger);
+        self.stopped |= result.is_err();
+        result
+    }
+
+    // For anything more than a config, see InterpreterBuilder
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+
+    // Natives call this before doing anything on the host
+    fn require(&self, capability: Capabilities, native: &str) -> Result<(), RuntimeError> {
+        if self.config.capabilities.contains(capability) {
+            Ok(())
+        } else {
+            Err(RuntimeError {
+                line: 0,
+                message: format!("{}() needs the {} capability", native, capability.name()),
+            })
+        }
+    }
+
+    // applied to every number written by print
+    pub fn set_number_format(&mut self, number_format: values::NumberFormat) {
+        self.number_format = number_format;
+    }
+
+    // Keep every nondeterministic value the script sees, see `recorded`
+    pub fn record(&mut self) {
+        self.trace = Trace::Record(vec![]);
+    }
+
+    // Feed previously recorded values back instead of asking the clock, rng or stdin
+    pub fn replay(&mut self, values: Vec<Value>) {
+        self.trace = Trace::Replay(values.into());
+    }
+
+    pub fn recorded(&self) -> &[Value] {
+        match &self.trace {
+            Trace::Record(values) => values,
+            _ => &[],
+        }
+    }
+
+    // Natives that read the outside world produce their value through here
+    fn nondeterministic(&mut self, produce: impl FnOnce(&mut Self) -> Value) -> InterpreterResult {
+        if let Trace::Replay(values) = &mut self.trace {
+            return values.pop_front().ok_or(RuntimeError {
+                line: 0,
+                message: "Replay trace is exhausted".to_string(),
+            });
+        }
+
+        let value = produce(self);
+        if let Trace::Record(values) = &mut self.trace {
+            values.push(value.clone());
+        }
+
+        Ok(value)
+    }
+
+    // Everything print, write and printLn put out goes through here, to the host's output.  What
+    // doesn't end a line is flushed, or a prompt wouldn't show up before readLine waits
+    fn emit(&mut self, text: &str, newline: bool) -> Result<(), RuntimeError> {
+        self.report.printed_lines += text.matches('\n').count() as u64 + newline as u64;
+        let written = if newline {
+            writeln!(self.output, "{}", text)
+        } else {
+            write!(self.output, "{}", text).and_then(|_| self.output.flush())
+        };
+        written.map_err(|err| RuntimeError {
+            line: 0,
+            message: format!("Couldn't print: {}", err),
+        })
+    }
+
+    // emit for the error output.  Nothing there counts as a printed line
+    fn emit_error(&mut self, text: &str) -> Result<(), RuntimeError> {
+        writeln!(self.error_output, "{}", text).map_err(|err| RuntimeError {
+            line: 0,
+            message: format!("Couldn't print: {}", err),
+        })
+    }
+
+    fn concat(&mut self, a: &str, b: &str) -> InterpreterResult {
+        let len = a.len() + b.len();
+        if let Some(max) = self.config.max_string_length {
+            if len > max {
+                return Err(RuntimeError {
+                    line: 0,
+                    message: format!("String of {} bytes exceeds the limit of {}", len, max),
+                });
+            }
+        }
+
+        self.report.allocations += 1;
+        Ok(Value::STRING(format!("{}{}", a, b).into()))
+    }
+
+    // The innermost scope, which is the global one unless a native is asking mid-block
+    pub fn environment(&self) -> Ref<'_, Environment> {
+        self.environment.borrow()
+    }
+
+    // A scanner set up the way this interpreter's config asks, for source it's about to run
+    pub fn scanner(&self, source: String) -> Scanner {
+        let mut scanner = Scanner::new(source);
+        if self.config.case_insensitive_keywords {
+            scanner = scanner.ignoring_keyword_case();
+        }
+        if self.config.implicit_semicolons {
+            scanner = scanner.implicit_semicolons();
         }
+        scanner
+    }
+
+    // Resource usage of the most recent `start`
+    pub fn report(&self) -> &ExecutionReport {
+        &self.report
     }
 
-    pub fn start(&mut self, stmts: Vec<Stmt>) -> InterpreterResult {
+    // Back to the global scope with nothing in flight, keeping the globals themselves.  Errors
+    // already unwind cleanly, this is for an embedder that caught a panic out of `start`
+    pub fn reset(&mut self) {
+        self.environment = self.globals();
+
+        self.call_depth = 0;
+        self.depth = 0;
+        self.lookups.clear();
+        self.timers.clear();
+        self.deferred.clear();
+        self.paused = None;
+        self.exit_code = None;
+        self.stepping = None;
+        self.stopped = false;
+        self.failed = None;
+        self.running = false;
+    }
+
+    // Call whatever the program left on timers, soonest first, sleeping until each is due.
+    // Returns once none are left, so an interval keeps it going until something clears it.  It
+    // stops at the first error, and whatever hadn't fired yet is still there for another go
+    pub fn run_until_idle(&mut self) -> Result<(), RuntimeError> {
+        if self.running {
+            return Err(RuntimeError {
+                line: 0,
+                message: "Interpreter is already running".to_string(),
+            });
+        }
+        self.running = true;
+
         let mut result = Ok(Value::Null);
-        let mut iter_stmts = stmts.into_iter();
+        while let Some(timer) = self.timers.next() {
+            result = self.interrupted(0).and_then(|_| self.call_value(timer.callee, &timer.args, 0));
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.running = false;
+        self.exited(result).map(|_| ())
+    }
+
+    // For stopping a run from another thread, see CancelToken
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    // what a loop or timer does before going round again
+    fn interrupted(&self, line: usize) -> Result<(), RuntimeError> {
+        if !self.cancel.is_cancelled() {
+            return Ok(());
+        }
+        Err(RuntimeError {
+            line,
+            message: "Interrupted".to_string(),
+        })
+    }
+
+    // The status the last run passed to exit(), None if it didn't call it
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    // exit() unwinds like an error, this is where a run turns it back into a finished program.
+    // Whatever was left on timers goes too, the script is done
+    fn exited(&mut self, result: InterpreterResult) -> InterpreterResult {
+        if self.exit_code.is_none() {
+            return result;
+        }
+        self.timers.clear();
+        Ok(Value::Null)
+    }
+
+    // Drop every timer still waiting, for a native that decides the script is done
+    pub fn clear_timers(&mut self) {
+        self.timers.clear();
+    }
+
+    // Takes a borrowed tree as well, so one from a parse cache can be run more than once
+    pub fn start<S: AsRef<[Stmt]>>(&mut self, stmts: S) -> InterpreterResult {
+        self.run(stmts.as_ref(), false)
+    }
+
+    // Run a program for its result, the entry point for embedders.  Unlike `start` it stops at
+    // the first error rather than carrying on with the next statement, and doesn't echo the
+    // tree.  See ProgramOutcome for what counts as the result.
+    pub fn eval<S: AsRef<[Stmt]>>(&mut self, stmts: S) -> Result<ProgramOutcome, RuntimeError> {
+        let stmts = stmts.as_ref();
+        let value = self.run(stmts, true)?;
+        Ok(self.outcome(stmts, value))
+    }
+
+    // what a program that finished with `value` has to show for it
+    fn outcome(&self, stmts: &[Stmt], value: Value) -> ProgramOutcome {
+        let value = match stmts.last() {
+            _ if self.exit_code.is_some() => None,
+            Some(Stmt::Expr(_)) | Some(Stmt::Block(_)) | Some(Stmt::If { .. }) => Some(value),
+            _ => None,
+        };
+        ProgramOutcome {
+            value,
+            printed_lines: self.report.printed_lines,
+            duration: self.report.duration,
+            exit_code: self.exit_code,
+        }
+    }
+
+    fn run(&mut self, stmts: &[Stmt], strict: bool) -> InterpreterResult {
+        if self.running {
+            return Err(RuntimeError {
+                line: 0,
+                message: "Interpreter is already running".to_string(),
+            });
+        }
+        self.running = true;
 
-        while let Some(stmt) = iter_stmts.next() {
-            println!("{:?}", parser::debug_tree(&stmt));
+        let mut result = Ok(Value::Null);
+        self.report = ExecutionReport::default();
+        self.next_yield = 0;
+        self.stopped = false;
+        self.failed = None;
+        self.exit_code = None;
+        self.cancel.clear();
+        let started = Instant::now();
+
+        // what the script defers outside any block waits for the end of the script
+        let mut script = ScopeGuard::deferring(self);
+        for stmt in stmts {
+            if !strict {
+                println!("{:?}", parser::debug_tree(stmt));
+            }
 
             // keep reassigning assuming the last one is an expression
-            result = self.execute(&stmt);
+            result = script.execute(stmt);
+            if let Err(err) = &result {
+                script.post_mortem(err);
+            }
+            if (strict || script.exit_code.is_some() || script.stopped) && result.is_err() {
+                break;
+            }
+        }
+        let result = script.finish(result);
+
+        self.report.duration = started.elapsed();
+        // a step the run didn't get to take isn't the next run's to take
+        self.stepping = None;
+        self.running = false;
+        self.exited(result)
+    }
+
+    // Evaluate a single expression without being able to change anything, for a watch window or
+    // a hover.  Assignments are refused up front, and so are calls since every native either
+    // reads the outside world or moves the rng along.  What's left runs against a snapshot of the
+    // environment, and the report and lookup cache are put back afterwards
+    pub fn eval_pure(&mut self, source: &str) -> InterpreterResult {
+        let mut parser = Parser::scanned(self.scanner(source.to_string()).scan()).with_stack_size(self.config.stack_size);
+        if let Some(error) = parser.scan_errors().first() {
+            return Err(RuntimeError { line: error.line, message: error.message.clone() });
+        }
+
+        let mut stmts = parser.parse();
+        let expr = match stmts.pop() {
+            Some(Stmt::Expr(expr)) if stmts.is_empty() => expr,
+            // an if on its own parses as the statement, but it's just as much a value
+            Some(Stmt::If { condition, then_branch, else_branch }) if stmts.is_empty() => {
+                Expr::If { condition: Box::new(condition), then_branch, else_branch, line: 0 }
+            }
+            _ => {
+                return Err(RuntimeError {
+                    line: 0,
+                    message: "Only a single expression can be evaluated".to_string(),
+                })
+            }
+        };
+        if let Some(error) = side_effect(&expr) {
+            return Err(error);
         }
 
+        let snapshot = Rc::new(RefCell::new(self.environment().snapshot()));
+        let environment = std::mem::replace(&mut self.environment, snapshot);
+        let report = self.report.clone();
+        // this tree is gone once it's evaluated, its entries needn't outlive it
+        let lookups = std::mem::take(&mut self.lookups);
+        // building a lazy global is a side effect too, they stay undefined until a script runs
+        let lazy_globals = std::mem::take(&mut self.lazy_globals);
+
+        let mut scope = ScopeGuard::deferring(self);
+        let result = scope.evaluate(&expr);
+        let result = scope.finish(result);
+
+        self.environment = environment;
+        self.report = report;
+        self.lookups = lookups;
+        self.lazy_globals = lazy_globals;
         result
     }
 
     pub fn execute(&mut self, stmt: &Stmt) -> InterpreterResult {
-        stmt.accept(self)
+        self.report.steps += 1;
+        self.report.statements += 1;
+        if self.report.steps >= self.next_yield {
+            self.step_hook()?;
+        }
+        if self.debugger.is_some() && (self.stepping.is_some() || !self.breakpoints.is_empty()) {
+            self.breakpoint(stmt)?;
+        }
+        self.enter()?;
+        let result = stmt.accept(self);
+        self.depth -= 1;
+        // the scopes are about to be unwound, hold on to the one it failed in
+        if result.is_err() && self.failed.is_none() && self.debugger.is_some() && !self.stopped {
+            self.failed = Some(self.environment.clone());
+        }
+        result
+    }
+
+    // One level further into the tree, unless that's more than the stack has room for.  Rust
+    // can't catch running out, it aborts the process, so this gives up first
+    fn enter(&mut self) -> Result<(), RuntimeError> {
+        let max = self.config.stack_size / config::STACK_PER_LEVEL;
+        if self.depth >= max {
+            return Err(RuntimeError {
+                line: 0,
+                message: format!("Nested more than {} deep, out of stack (see InterpreterConfig::stack_size)", max),
+            });
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // Time for the host's step hook, if there is one
+    fn step_hook(&mut self) -> Result<(), RuntimeError> {
+        let hook = match &self.config.step_hook {
+            Some(hook) => hook.clone(),
+            None => {
+                self.next_yield = u64::MAX;
+                return Ok(());
+            }
+        };
+        // the first statement of a run only schedules it
+        if self.next_yield == 0 {
+            self.next_yield = self.report.steps + hook.every;
+            return Ok(());
+        }
+
+        let every = match (hook.callback)(&self.report) {
+            Yield::Continue => hook.every,
+            Yield::Reschedule(steps) => steps.max(1),
+            Yield::Abort => {
+                return Err(RuntimeError {
+                    line: 0,
+                    message: format!("Stopped by the host after {} steps", self.report.steps),
+                })
+            }
+        };
+        self.next_yield = self.report.steps + every;
+        Ok(())
+    }
+
+    fn call_value(&mut self, callee: Value, args: &[Value], line: usize) -> InterpreterResult {
+        let callee = callable(&callee).ok_or(RuntimeError {
+            line,
+            message: "Can only call functions".to_string(),
+        })?;
+        check_arity(callee, args, line)?;
+
+        let mut call = ScopeGuard::call(self, line)?;
+        callee.call(&mut call, args, line)
+    }
+
+    // A method body runs in a scope of its own straight over the globals, with `this` and its
+    // parameters declared in it by the call on `line`, like any other declaration so watches and
+    // the lookup cache see them.  Scopes can't be kept around once left, so it sees nothing of
+    // where its class was declared, and nothing of where it's called from.  Like a block, the
+    // call is worth what the body's last statement is, except for init, which is always the
+    // instance whether the class called it or the script did again
+    fn call_method(&mut self, method: &Method, args: &[Value], line: usize) -> InterpreterResult {
+        let function = method.function();
+        let environment = Environment::new_with_scope(&self.globals());
+        let mut frame = ScopeGuard::frame(self, environment);
+        let bound = std::iter::once(("this", Value::Instance(method.receiver().clone())))
+            .chain(function.params.iter().map(String::as_str).zip(args.iter().cloned()))
+            .try_for_each(|(name, value)| frame.define(name, value, line));
+        let value = bound.and_then(|_| frame.execute_all(&function.body)).map(|value| match function.name.as_str() {
+            "init" => Value::Instance(method.receiver().clone()),
+            _ => block_value(&function.body, value),
+        });
+        frame.finish(value)
+    }
+
+    // `ident` found the long way round, skipping the lookup cache
+    fn resolve(&mut self, ident: &str) -> InterpreterResult {
+        let found = self.environment.borrow().with_value(ident, Value::clone);
+        found.or_else(|err| match self.force_lazy(ident) {
+            Some(value) => Ok(value),
+            None => self.builtins.get(ident).cloned().ok_or(err),
+        })
+    }
+
+    // the value of the last one, stopping at the first error
+    fn execute_all(&mut self, stmts: &[Stmt]) -> InterpreterResult {
+        let mut value = Value::Null;
+        for stmt in stmts {
+            value = self.execute(stmt)?;
+        }
+
+        Ok(value)
     }
 
     pub fn evaluate(&mut self, expr: &Expr) -> InterpreterResult {
-        expr.accept(self)
+        self.report.steps += 1;
+        self.enter()?;
+        let result = expr.accept(self);
+        self.depth -= 1;
+        result
     }
 }
 
-impl ExpressionVisitor<InterpreterResult> for Interpreter {
-    fn visit_assign(&mut self, name: &str, expr: &Expr) -> InterpreterResult {
-        let val = self.evaluate(&expr)?;
+// Everything a script can call without defining it first
+pub fn natives() -> Vec<NativeFunction> {
+    natives::builtins()
+}
+
+// The values a script can read without defining them first.  __file__ is nil until the host
+// says otherwise, NaN and Infinity are the numbers there's no literal for
+pub const GLOBALS: [&str; 3] = ["__file__", "NaN", "Infinity"];
+
+// What hasFeature says yes to out of the box.  Hosts add their own, see InterpreterBuilder::feature
+pub const FEATURES: [&str; 9] = [
+    "defer",
+    "with",
+    "records",
+    "is",
+    "optional-chaining",
+    "nil-coalescing",
+    "if-expressions",
+    "timers",
+    "workers",
+];
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        self.environment.borrow_mut().assign(name.to_string(), val.clone())?;
+impl ExpressionVisitor<InterpreterResult> for Interpreter {
+    fn visit_assign(&mut self, name: &str, expr: &Expr, line: &usize) -> InterpreterResult {
+        let val = self.evaluate(expr)?;
+
+        let assigned = self.environment.borrow_mut().assign(name, val.clone());
+        let old = match assigned {
+            Ok(old) => Some(old),
+            Err(err) => {
+                if self.lazy_globals.remove(name).is_none() {
+                    return Err(RuntimeError { line: *line, ..err });
+                }
+                self.define_global(name, val.clone());
+                None
+            }
+        };
+        self.watched(name, old, val.clone(), *line)?;
 
         Ok(val)
     }
 
     fn visit_binary(&mut self, l: &Expr, op: &LexemeKind, r: &Expr) -> InterpreterResult {
-        let num = unwrap_number(self.evaluate(l))?;
-        let num2 = unwrap_number(self.evaluate(r))?;
+        let left = self.evaluate(l)?;
+        let right = self.evaluate(r)?;
+
+        // Numbers are IEEE doubles all the way down and nothing here papers over that: NaN isn't
+        // equal to anything, itself included, and every comparison with it is false; -0 == 0;
+        // 1 / 0 is Infinity and 0 / 0 is NaN rather than an error.  isNan() is the way to ask
+        match (op, &left, &right) {
+            (LexemeKind::Plus, Value::STRING(a), Value::STRING(b)) => return self.concat(a, b),
+            (LexemeKind::EqualEqual, _, _) => return Ok(Value::BOOLEAN(left == right)),
+            (LexemeKind::BangEqual, _, _) => return Ok(Value::BOOLEAN(left != right)),
+            _ => {}
+        }
+
+        let num = unwrap_number(Ok(left))?;
+        let num2 = unwrap_number(Ok(right))?;
 
         match op {
             LexemeKind::Minus => Ok(Value::NUMBER(num - num2)),
             LexemeKind::Plus => Ok(Value::NUMBER(num + num2)),
             LexemeKind::Slash => Ok(Value::NUMBER(num / num2)),
             LexemeKind::Star => Ok(Value::NUMBER(num * num2)),
+            LexemeKind::Greater => Ok(Value::BOOLEAN(num > num2)),
+            LexemeKind::GreaterEqual => Ok(Value::BOOLEAN(num >= num2)),
+            LexemeKind::Less => Ok(Value::BOOLEAN(num < num2)),
+            LexemeKind::LessEqual => Ok(Value::BOOLEAN(num <= num2)),
             _ => Err(RuntimeError {
                 line: 0,
                 message: "Invalid".to_string(),
@@ -87,7 +822,29 @@ impl ExpressionVisitor<InterpreterResult> for Interpreter {
         }
     }
 
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], line: &usize) -> InterpreterResult {
+        let value = self.evaluate(callee)?;
+        // fs?.read(a) is nil without reading anything when fs is
+        if value == Value::Null && optional_chain(callee) {
+            return Ok(Value::Null);
+        }
+
+        let mut args = vec![];
+        for arg in arguments {
+            args.push(self.evaluate(arg)?);
+        }
+
+        self.call_value(value, &args, *line)
+    }
+
     fn visit_logical(&mut self, l: &Expr, op: &LexemeKind, r: &Expr) -> InterpreterResult {
+        if op == &LexemeKind::QuestionQuestion {
+            return match self.evaluate(l)? {
+                Value::Null => self.evaluate(r),
+                left => Ok(left),
+            };
+        }
+
         let left_result = self.evaluate(l);
 
         if op == &LexemeKind::OR {
@@ -120,18 +877,122 @@ impl ExpressionVisitor<InterpreterResult> for Interpreter {
         }
     }
 
+    // through evaluate like any other operand, so ((((1)))) counts against the stack the same
     fn visit_grouping(&mut self, expr: &Expr) -> InterpreterResult {
-        let value = expr.accept(self)?;
+        self.evaluate(expr)
+    }
+
+    fn visit_variable(&mut self, ident: &str, id: NodeId, line: &usize) -> InterpreterResult {
+        let env = self.environment.borrow();
+
+        let depth = match self.lookups.get(&id.get()) {
+            Some((shadowing, depth)) if *shadowing == self.shadowing => *depth,
+            _ => {
+                let depth = env.depth_of(ident);
+                if depth.is_some() || self.builtins.contains_key(ident) {
+                    self.lookups.insert(id.get(), (self.shadowing, depth));
+                }
+                depth
+            }
+        };
+
+        // one clone for the caller, cheap since strings are shared
+        match depth.and_then(|depth| env.with_value_at(depth, ident, Value::clone)) {
+            Some(val) => Ok(val),
+            None => {
+                // building a lazy global defines it, which needs the environment back
+                drop(env);
+                self.resolve(ident).map_err(|err| RuntimeError { line: *line, ..err })
+            }
+        }
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &str, optional: &bool, line: &usize) -> InterpreterResult {
+        match self.evaluate(object)? {
+            Value::Null if *optional || optional_chain(object) => Ok(Value::Null),
+            Value::Record(record) => record.get(name).cloned().ok_or_else(|| RuntimeError {
+                line: *line,
+                message: format!("{} has no field \"{}\"", record.kind().name(), name),
+            }),
+            // fields first, so one can hide a method of the same name
+            Value::Instance(instance) => match instance.get(name) {
+                Some(value) => Ok(value),
+                None => Method::bind(&instance, name).map(|method| Value::Method(Arc::new(method))).ok_or_else(|| RuntimeError {
+                    line: *line,
+                    message: format!("{} has no field or method \"{}\"", instance, name),
+                }),
+            },
+            Value::Module(module) => match module.get(name) {
+                Some(native) => Ok(Value::Native(native.clone())),
+                None => Err(RuntimeError {
+                    line: *line,
+                    message: format!("Module {} has no member \"{}\"", module.name(), name),
+                }),
+            },
+            _ => Err(RuntimeError {
+                line: *line,
+                message: format!("Only modules, records and instances have members, can't look up \"{}\"", name),
+            }),
+        }
+    }
+
+    fn visit_this(&mut self, line: &usize) -> InterpreterResult {
+        // no script can declare it, only call_method
+        self.environment.borrow().with_value("this", Value::clone).map_err(|_| RuntimeError {
+            line: *line,
+            message: "Can't use this outside of a method".to_string(),
+        })
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &str, value: &Expr, line: &usize) -> InterpreterResult {
+        let Value::Instance(instance) = self.evaluate(object)? else {
+            return Err(RuntimeError {
+                line: *line,
+                message: format!("Only instances have fields, can't set \"{}\"", name),
+            });
+        };
+        let value = self.evaluate(value)?;
+        instance.set(name, value.clone());
+
         Ok(value)
     }
 
-    fn visit_variable(&mut self, ident: &str) -> InterpreterResult {
-        match self.environment.borrow().retrieve(ident) {
-            Ok(val) => Ok(val.clone()),
-            m => m
+    fn visit_is(&mut self, value: &Expr, type_name: &str, line: &usize) -> InterpreterResult {
+        if TYPE_NAMES.contains(&type_name) {
+            let value = self.evaluate(value)?;
+            return Ok(Value::BOOLEAN(value.type_name() == type_name));
+        }
+
+        // otherwise it has to be a record type or class in scope
+        match self.resolve(type_name) {
+            Ok(Value::RecordType(kind)) => match self.evaluate(value)? {
+                Value::Record(record) => Ok(Value::BOOLEAN(*record.kind() == *kind)),
+                _ => Ok(Value::BOOLEAN(false)),
+            },
+            Ok(Value::Class(class)) => match self.evaluate(value)? {
+                Value::Instance(instance) => Ok(Value::BOOLEAN(*instance.class() == *class)),
+                _ => Ok(Value::BOOLEAN(false)),
+            },
+            _ => Err(RuntimeError {
+                line: *line,
+                message: format!("Unknown type \"{}\", it can be one of {}, a record or a class", type_name, TYPE_NAMES.join(", ")),
+            }),
         }
     }
 
+    // Only AsyncInterpreter can wait, and it takes care of the awaits it can handle before the
+    // statement gets here
+    fn visit_await(&mut self, _expr: &Expr, line: &usize) -> InterpreterResult {
+        Err(RuntimeError {
+            line: *line,
+            message: "Can't await here, only an AsyncInterpreter can, and only for a whole statement at the top of the script".to_string(),
+        })
+    }
+
+    fn visit_if_expr(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>, _line: &usize) -> InterpreterResult {
+        StatementVisitor::visit_if(self, condition, then_branch, else_branch)
+    }
+
     fn visit_error(&mut self, line: &usize, message: &str) -> InterpreterResult {
         Err(RuntimeError {
             line: *line,
@@ -151,391 +1012,1485 @@ fn unwrap_number(v: InterpreterResult) -> Result<f64, RuntimeError> {
 }
 
 fn is_truthy(expr: &Result<Value, RuntimeError>) -> bool {
+    !matches!(expr, Ok(Value::Null) | Ok(Value::BOOLEAN(false)))
+}
+
+// A block is worth what its last statement is if that's an expression (or a block or if that
+// ends in one), so `{ var a = 1; a + 1 }` is 2.  Anything else leaves it nil, a print included
+fn block_value(stmts: &[Stmt], last: Value) -> Value {
+    match stmts.last() {
+        Some(Stmt::Expr(_)) | Some(Stmt::Block(_)) | Some(Stmt::If { .. }) => last,
+        _ => Value::Null,
+    }
+}
+
+// Whether `expr` is a chain with a ?. somewhere in it.  Once a nil turns up in one, the rest of
+// the chain is nil too instead of failing on it
+fn optional_chain(expr: &Expr) -> bool {
     match expr {
-        Ok(Value::Null) => false,
-        Ok(Value::BOOLEAN(false)) => false,
-        _ => true,
+        Expr::Get { object, optional, .. } => *optional || optional_chain(object),
+        Expr::Call { callee, .. } => optional_chain(callee),
+        _ => false,
+    }
+}
+
+// The first thing in `expr` that could change state, as the error eval_pure reports for it
+fn side_effect(expr: &Expr) -> Option<RuntimeError> {
+    match expr {
+        Expr::Assign { name, line, .. } => Some(RuntimeError {
+            line: *line,
+            message: format!("Can't assign to \"{}\" here, evaluation has to be side effect free", name),
+        }),
+        Expr::Call { line, .. } => Some(RuntimeError {
+            line: *line,
+            message: "Can't call functions here, evaluation has to be side effect free".to_string(),
+        }),
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+            side_effect(left).or_else(|| side_effect(right))
+        }
+        Expr::Unary { right, .. } => side_effect(right),
+        Expr::Grouping(inner) => side_effect(inner),
+        Expr::Get { object, .. } => side_effect(object),
+        Expr::Set { name, line, .. } => Some(RuntimeError {
+            line: *line,
+            message: format!("Can't set the field \"{}\" here, evaluation has to be side effect free", name),
+        }),
+        Expr::Is { value, .. } => side_effect(value),
+        Expr::Await { line, .. } => Some(RuntimeError {
+            line: *line,
+            message: "Can't await here, evaluation has to be side effect free".to_string(),
+        }),
+        Expr::If { condition, then_branch, else_branch, .. } => side_effect(condition)
+            .or_else(|| stmt_side_effect(then_branch))
+            .or_else(|| else_branch.as_ref().as_ref().and_then(stmt_side_effect)),
+        Expr::Literal(_) | Expr::Variable { .. } | Expr::This { .. } | Expr::Error { .. } => None,
+    }
+}
+
+// The same for the branches of an if expression.  Declarations are fine, they land in a scope
+// of the snapshot; printing isn't, and neither is a loop that may never end
+fn stmt_side_effect(stmt: &Stmt) -> Option<RuntimeError> {
+    match stmt {
+        Stmt::Expr(expr) => side_effect(expr),
+        Stmt::Block(stmts) => stmts.iter().find_map(stmt_side_effect),
+        Stmt::If { condition, then_branch, else_branch } => side_effect(condition)
+            .or_else(|| stmt_side_effect(then_branch))
+            .or_else(|| else_branch.as_ref().as_ref().and_then(stmt_side_effect)),
+        Stmt::VariableDef { expr, .. } => expr.as_ref().and_then(side_effect),
+        Stmt::Record { .. } | Stmt::Class { .. } => None,
+        Stmt::Print(_) => Some(RuntimeError {
+            line: 0,
+            message: "Can't print here, evaluation has to be side effect free".to_string(),
+        }),
+        Stmt::While { line, .. } => Some(RuntimeError {
+            line: *line,
+            message: "Can't loop here, evaluation has to be side effect free".to_string(),
+        }),
+        Stmt::Defer { expr, .. } => side_effect(expr),
+        Stmt::With { line, .. } => Some(RuntimeError {
+            line: *line,
+            message: "Can't use with here, closing is a side effect".to_string(),
+        }),
+        Stmt::Error { .. } => None,
     }
 }
 
 impl StatementVisitor<InterpreterResult> for Interpreter {
-    fn visit_block(&mut self, stmts: &Vec<Stmt>) -> InterpreterResult {
-        // make new inner environment
-        // unable to have mutable copy as we descend down the tree :(
-        let new_env = Environment::new_with_scope(&self.environment);
+    fn visit_block(&mut self, stmts: &[Stmt]) -> InterpreterResult {
+        // nothing can end up in a scope nobody declares into, so skip making one. Loop bodies
+        // are usually like this and would otherwise allocate on every iteration
+        let mut scope = if stmts.iter().any(Stmt::is_declaration) {
+            // make new inner environment, dropping `scope` puts the old one back even when a
+            // statement errors out part way through
+            ScopeGuard::block(self)
+        } else {
+            ScopeGuard::deferring(self)
+        };
 
-        let tmp = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(new_env)));
+        let value = scope.execute_all(stmts).map(|value| block_value(stmts, value));
+        scope.finish(value)
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) -> InterpreterResult {
+        let condition = self.evaluate(condition)?;
+
+        if is_truthy(&Ok(condition)) {
+            self.execute(then_branch)
+        } else if let Some(e) = else_branch {
+            self.execute(e)
+        } else {
+            Ok(Value::Null)
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, line: &usize) -> InterpreterResult {
+        while is_truthy(&self.evaluate(condition)) {
+            self.interrupted(*line)?;
+            self.execute(body)?;
+        }
+
+        Ok(Value::Null)
+    }
+
+    fn visit_variable_def(&mut self, ident: &str, initializer: &Option<Expr>, line: &usize) -> InterpreterResult {
+        if let Some(expr) = initializer {
+            match self.evaluate(expr) {
+                Ok(val) => {
+                    self.define(ident, val, *line)?;
+                    Ok(Value::Null)
+                }
+                err => {
+                    // var a = b;
+                    err
+                }
+            }
+        } else {
+            // var a;
+            Ok(Value::Null)
+        }
+    }
+
+    fn visit_record(&mut self, name: &str, fields: &[String], line: &usize) -> InterpreterResult {
+        // `is` would never get as far as looking for it
+        if TYPE_NAMES.contains(&name) {
+            return Err(RuntimeError {
+                line: *line,
+                message: format!("{} is already a type, a record needs another name", name),
+            });
+        }
+
+        self.define(name, Value::RecordType(Arc::new(RecordType::new(name, fields))), *line)?;
+        Ok(Value::Null)
+    }
+
+    fn visit_class(&mut self, name: &str, methods: &Arc<[Function]>, line: &usize) -> InterpreterResult {
+        if TYPE_NAMES.contains(&name) {
+            return Err(RuntimeError {
+                line: *line,
+                message: format!("{} is already a type, a class needs another name", name),
+            });
+        }
+
+        self.define(name, Value::Class(Arc::new(Class::new(name, methods))), *line)?;
+        Ok(Value::Null)
+    }
+
+    // the value of the last one, like an expression statement
+    fn visit_print(&mut self, exprs: &[Expr]) -> InterpreterResult {
+        if exprs.is_empty() {
+            return Ok(Value::Null);
+        }
+
+        let mut value = Value::Null;
+        let mut texts = vec![];
+        for expr in exprs {
+            value = self.evaluate(expr)?;
+            texts.push(values::pretty(&value, values::DEFAULT_DEPTH, &self.number_format));
+        }
+
+        self.emit(&texts.join(" "), true)?;
+        Ok(value)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) -> InterpreterResult {
+        self.evaluate(expr)
+    }
+
+    fn visit_with(&mut self, ident: &str, init: &Expr, body: &Stmt, line: &usize) -> InterpreterResult {
+        // whatever does the closing has to be there before anything is opened
+        let close = self.resolve("close").map_err(|_| RuntimeError {
+            line: *line,
+            message: format!("Nothing to close \"{}\" with, with needs a close function", ident),
+        })?;
+        let resource = self.evaluate(init)?;
+
+        // the resource is declared in a scope of its own, the same as a block starting with it
+        let mut scope = ScopeGuard::block(self);
+        let result = scope.define(ident, resource.clone(), *line);
+        let result = result.and_then(|_| scope.execute(body)).map(|_| Value::Null);
+        let result = scope.finish(result);
+        // closed last, after anything the body deferred, and only its error if nothing else failed
+        let closed = self.call_value(close, &[resource], *line);
+        result.and(closed.map(|_| Value::Null))
+    }
+
+    fn visit_defer(&mut self, expr: &Expr, _line: &usize) -> InterpreterResult {
+        self.deferred.push(expr.clone());
+        Ok(Value::Null)
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) -> InterpreterResult {
+        Err(RuntimeError {
+            line: *line,
+            message: message.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    #[test]
+    fn it_works() {
+        let tokens = Scanner::new("-1".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res.unwrap(), Value::NUMBER(-1.0));
+    }
+
+    #[test]
+    fn it_adds() {
+        let tokens = Scanner::new("-1+1".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res.unwrap(), Value::NUMBER(0.0));
+    }
+
+    #[test]
+    fn it_unary_works() {
+        let tokens = Scanner::new("+1".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res.unwrap(), Value::NUMBER(1.0));
+    }
+
+    #[test]
+    fn it_errors() {
+        // () on its own is nil, a stray ) still isn't anything
+        let tokens = Scanner::new(")".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Err(RuntimeError { line: 0, message: "Parsing error at RightParen".to_string() }));
+    }
+
+    #[test]
+    fn it_does_not_error_prefix_number() {
+        let tokens = Scanner::new("*1".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::NUMBER(1.0)));
+    }
+
+    // #[test]
+    // fn it_errors_invalid_operator() {
+    //     let tokens = Scanner::new("1&1".to_owned()).collect();
+    //     let stmts = Parser::new(tokens).parse();
+    //     let mut interp = Interpreter::new();
+    //     let res = interp.start(stmts);
+    //     assert_eq!(res, Err(RuntimeError { line: 0, message: "Parsing error at &".to_string() }));
+    // }
+
+    #[test]
+    fn it_works_stmts() {
+        let tokens = Scanner::new("print(\"foo\")".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::STRING("foo".into())));
+
+        let tokens = Scanner::new("print(2)".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::NUMBER(2.0)));
+
+        let tokens = Scanner::new("print(2+1)".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::NUMBER(3.0)));
+
+        let tokens = Scanner::new("print()".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::Null));
+    }
+
+    #[test]
+    fn it_works_variables() {
+        let tokens = Scanner::new("var a;".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::Null));
+        assert_eq!(interp.environment().flatten().len(), 0);
+        assert_eq!(interp.environment().flatten().get("a"), None);
+
+        let tokens = Scanner::new("var a = \"foo\";".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::Null));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::STRING("foo".into())));
+    }
+
+    #[test]
+    fn it_works_multiline() {
+        let tokens = Scanner::new("var a = 4;
+print(a);".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::NUMBER(4.0)));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(4.0)));
+    }
+
+    #[test]
+    fn it_errors_variable() {
+        let tokens = Scanner::new("var a = b;".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Err(RuntimeError { line: 0, message: "Variable \"b\" does not exist".to_string() }));
+    }
+
+    #[test]
+    fn it_works_block_ss() {
+        let tokens = Scanner::new("{
+var a = 4;
+print(a);
+}".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::Null));
+        assert_eq!(interp.environment().flatten().len(), 0);
+        assert_eq!(interp.environment().flatten().get("a"), None);
+        assert_eq!(interp.environment().depth(), 0);
+    }
+
+    #[test]
+    fn it_works_shadow_block() {
+        let tokens = Scanner::new("
+var a = 4;
+{
+    var a = 4;
+    print(a);
+}
+".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::Null));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(4.0)));
+        // assert_eq!(interp.environment().depth(), 0);
+    }
+
+    #[test]
+    fn it_works_replace_inner_block() {
+        let tokens = Scanner::new("
+var a = 4;
+{
+    a = 5;
+    var b = 10.1;
+    print(a);
+}
+".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::Null));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(5.0)));
+        assert_eq!(interp.environment().depth(), 0);
+    }
+
+    #[test]
+    fn it_block_errors() {
+        let tokens = Scanner::new("
+var a = 4;
+{
+    b = 5;
+    print(b);
+}
+".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Err(RuntimeError { line: 3, message: "Variable \"b\" does not exist".to_string() }));
+        assert_eq!(interp.environment().depth(), 0);
+    }
+
+    #[test]
+    fn it_runs_later_statements_in_the_outer_scope_after_a_block_errors() {
+        let tokens = Scanner::new("
+var a = 4;
+{
+    var a = 5;
+    b = 5;
+}
+a;
+".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.start(stmts), Ok(Value::NUMBER(4.0)));
+    }
+
+    #[test]
+    fn it_refuses_to_start_while_running() {
+        fn reenter(interp: &mut Interpreter, _: &[Value]) -> InterpreterResult {
+            interp.start(vec![])
+        }
+
+        let mut interp = Interpreter::new();
+        let native = NativeFunction { name: "reenter", func: reenter, must_use: false };
+        interp.builtins.insert("reenter".to_string(), Value::Native(native));
+
+        let tokens = Scanner::new("reenter();".to_owned()).collect();
+        let res = interp.start(Parser::new(tokens).parse());
+        assert_eq!(res, Err(RuntimeError { line: 0, message: "Interpreter is already running".to_string() }));
+
+        // and the failed attempt didn't leave it stuck
+        assert_eq!(interp.start(vec![]), Ok(Value::Null));
+    }
+
+    #[test]
+    fn it_unwinds_call_depth_when_a_native_errors() {
+        // no capabilities, so clock() fails inside the call
+        let tokens = Scanner::new("{ var a = clock(); }".to_owned()).collect();
+        let mut interp = Interpreter::new();
+        assert!(interp.start(Parser::new(tokens).parse()).is_err());
+        assert_eq!(interp.call_depth, 0);
+        assert_eq!(interp.report().max_call_depth, 1);
+        assert_eq!(interp.environment().depth(), 0);
+    }
+
+    #[test]
+    fn it_stops_calls_past_the_depth_limit() {
+        let mut interp = Interpreter::builder().capabilities(Capabilities::TIME).max_call_depth(Some(0)).build();
+        let tokens = Scanner::new("var a = 1;\nvar b = clock();".to_owned()).collect();
+        assert_eq!(interp.start(Parser::new(tokens).parse()), Err(RuntimeError { line: 1, message: "stack overflow".to_string() }));
+        assert_eq!(interp.call_depth, 0);
+
+        // one deep is all clock() needs
+        let mut interp = Interpreter::builder().capabilities(Capabilities::TIME).max_call_depth(Some(1)).build();
+        let tokens = Scanner::new("isNan(clock())".to_owned()).collect();
+        assert_eq!(interp.start(Parser::new(tokens).parse()), Ok(Value::BOOLEAN(false)));
+        assert_eq!(interp.report().max_call_depth, 1);
+    }
+
+    #[test]
+    fn it_runs_with_a_persistent_environment() {
+        let tokens = Scanner::new("
+var a = 1;
+var i = 0;
+while (i < 3) {
+    var b = i;
+    a = a + b;
+    i = i + 1;
+}
+".to_owned()).collect();
+        let config = InterpreterConfig { persistent_environment: true, ..InterpreterConfig::default() };
+        let mut interp = Interpreter::with_config(config);
+        interp.start(Parser::new(tokens).parse()).unwrap();
+
+        let snapshot = interp.environment().snapshot();
+        interp.environment.borrow_mut().assign("a", Value::NUMBER(0.0)).unwrap();
+        assert_eq!(snapshot.retrieve("a"), Ok(Value::NUMBER(4.0)));
+    }
+
+    #[test]
+    fn it_evaluates_pure_expressions() {
+        let tokens = Scanner::new("var a = 2;".to_owned()).collect();
+        let mut interp = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all(), ..InterpreterConfig::default() });
+        interp.start(Parser::new(tokens).parse()).unwrap();
+        let steps = interp.report().steps;
+
+        assert_eq!(interp.eval_pure("a * 3 > 5"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(interp.eval_pure("a == 2 and \"ye\" + \"s\""), Ok(Value::STRING("yes".into())));
+        assert_eq!(interp.eval_pure("b"), Err(RuntimeError { line: 0, message: "Variable \"b\" does not exist".to_string() }));
+        assert_eq!(interp.report().steps, steps);
+    }
+
+    #[test]
+    fn it_refuses_side_effects_in_pure_evaluation() {
+        let tokens = Scanner::new("var a = 2;".to_owned()).collect();
+        let mut interp = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all(), ..InterpreterConfig::default() });
+        interp.start(Parser::new(tokens).parse()).unwrap();
+
+        let message = |res: InterpreterResult| res.unwrap_err().message;
+        assert_eq!(message(interp.eval_pure("a = a + 1")), "Can't assign to \"a\" here, evaluation has to be side effect free");
+        assert_eq!(message(interp.eval_pure("random()")), "Can't call functions here, evaluation has to be side effect free");
+        assert_eq!(message(interp.eval_pure("print(a);")), "Only a single expression can be evaluated");
+        assert_eq!(message(interp.eval_pure("a; a")), "Only a single expression can be evaluated");
+        assert_eq!(message(interp.eval_pure("if (a) { print(a); }")), "Can't print here, evaluation has to be side effect free");
+        assert_eq!(message(interp.eval_pure("if (a) { a = 1 }")), "Can't assign to \"a\" here, evaluation has to be side effect free");
+        assert_eq!(interp.eval_pure("if (a) { var b = a; b + 1 }"), Ok(Value::NUMBER(3.0)));
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(2.0)));
+    }
+
+    #[test]
+    fn it_scans_with_the_configured_keyword_case() {
+        let mut interp = Interpreter::with_config(InterpreterConfig { case_insensitive_keywords: true, ..InterpreterConfig::default() });
+        let tokens = interp.scanner("VAR Total = 2; If (TRUE) Total = Total + 1; Total".to_owned()).collect();
+        assert_eq!(interp.start(Parser::new(tokens).parse()), Ok(Value::NUMBER(3.0)));
+        assert_eq!(interp.eval_pure("total"), Err(RuntimeError { line: 0, message: "Variable \"total\" does not exist".to_string() }));
+    }
+
+    #[test]
+    fn it_scans_with_implicit_semicolons_when_asked() {
+        // without them the second line is a call of what the first left
+        let source = "var f = 1\n(2)";
+        let mut interp = Interpreter::with_config(InterpreterConfig { implicit_semicolons: true, ..InterpreterConfig::default() });
+        let tokens = interp.scanner(source.to_owned()).collect();
+        assert_eq!(interp.start(Parser::new(tokens).parse()), Ok(Value::NUMBER(2.0)));
+
+        let mut interp = Interpreter::new();
+        let tokens = interp.scanner(source.to_owned()).collect();
+        assert_eq!(interp.start(Parser::new(tokens).parse()), Err(RuntimeError { line: 1, message: "Can only call functions".to_string() }));
+    }
+
+    #[test]
+    fn it_evaluates_programs_to_an_outcome() {
+        let eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            Interpreter::new().eval(Parser::new(tokens).parse())
+        };
+
+        let outcome = eval("var a = 2; print(a); print(\"x\ny\"); a * 2").unwrap();
+        assert_eq!(outcome.value, Some(Value::NUMBER(4.0)));
+        assert_eq!(outcome.printed_lines, 3);
+
+        assert_eq!(eval("var a = 2; print(a);").unwrap().value, None);
+        assert_eq!(eval("var a = 2; { a }").unwrap().value, Some(Value::NUMBER(2.0)));
+        // start carries on past the error, eval doesn't
+        assert_eq!(eval("b; 1").map(|outcome| outcome.value), Err(RuntimeError { line: 0, message: "Variable \"b\" does not exist".to_string() }));
+    }
+
+    #[test]
+    fn it_calls_module_members() {
+        fn twice(_interp: &mut Interpreter, args: &[Value]) -> InterpreterResult {
+            match args {
+                [Value::NUMBER(n)] => Ok(Value::NUMBER(n * 2.0)),
+                _ => Ok(Value::Null),
+            }
+        }
+
+        let mut interp = Interpreter::new();
+        interp.register_module("math", NativeModule::new().function(NativeFunction { name: "twice", func: twice, must_use: true }));
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        assert_eq!(eval("math.twice(21)"), Ok(Value::NUMBER(42.0)));
+        assert_eq!(eval("var m = math; m.twice(m.twice(1))"), Ok(Value::NUMBER(4.0)));
+        assert_eq!(eval("math").map(|value| value.to_string()), Ok("<module math>".to_string()));
+        assert_eq!(eval("math.thrice(1)"), Err(RuntimeError { line: 0, message: "Module math has no member \"thrice\"".to_string() }));
+        assert_eq!(eval("clock.twice"), Err(RuntimeError { line: 0, message: "Only modules, records and instances have members, can't look up \"twice\"".to_string() }));
+    }
+
+    #[test]
+    fn it_gets_past_nil_with_optional_chains_and_coalescing() {
+        fn twice(_interp: &mut Interpreter, args: &[Value]) -> InterpreterResult {
+            match args {
+                [Value::NUMBER(n)] => Ok(Value::NUMBER(n * 2.0)),
+                _ => Ok(Value::Null),
+            }
+        }
+
+        let mut interp = Interpreter::new();
+        interp.register_module("math", NativeModule::new().function(NativeFunction { name: "twice", func: twice, must_use: true }));
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(format!("var none = if (false) {{ 1 }}; {}", source)).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        assert_eq!(eval("math?.twice(2)"), Ok(Value::NUMBER(4.0)));
+        assert_eq!(eval("none?.twice(2)"), Ok(Value::Null));
+        // the rest of the chain is skipped, arguments included
+        assert_eq!(eval("none?.twice.more(missing)(1)"), Ok(Value::Null));
+        assert_eq!(eval("none.twice"), Err(RuntimeError { line: 0, message: "Only modules, records and instances have members, can't look up \"twice\"".to_string() }));
+
+        assert_eq!(eval("none ?? 1"), Ok(Value::NUMBER(1.0)));
+        assert_eq!(eval("none?.twice(2) ?? none ?? \"last\""), Ok(Value::STRING("last".into())));
+        // only nil is replaced, and the right side isn't looked at otherwise
+        assert_eq!(eval("0 ?? missing"), Ok(Value::NUMBER(0.0)));
+        assert_eq!(eval("false ?? 1"), Ok(Value::BOOLEAN(false)));
+        assert_eq!(eval("none ?? false or true"), Ok(Value::BOOLEAN(true)));
+    }
+
+    #[test]
+    fn it_tests_types_with_is() {
+        let mut interp = Interpreter::new();
+        interp.register_module("math", NativeModule::new());
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        assert_eq!(eval("1 is Number"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("\"1\" is Number"), Ok(Value::BOOLEAN(false)));
+        assert_eq!(eval("var none = if (false) { 1 }; none is Nil"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("clock is Function and math is Module"), Ok(Value::BOOLEAN(true)));
+        // as tight as <, so both sides of the == are tests
+        assert_eq!(eval("1 + 1 is Number == true is Boolean"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(
+            eval("1 is Integer"),
+            Err(RuntimeError {
+                line: 0,
+                message: "Unknown type \"Integer\", it can be one of Boolean, String, Number, Function, Module, Record, Instance, Nil, a record or a class".to_string(),
+            })
+        );
+        assert_eq!(eval("1 is 2"), Err(RuntimeError { line: 0, message: "Expected a type name after is".to_string() }));
+    }
+
+    #[test]
+    fn it_pipes_values_into_calls() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(format!("record Point {{ x, y }} {}", source)).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        // the piped value goes in front of the arguments already there
+        assert_eq!(eval("(1 + 1 |> Point(3)).x"), Ok(Value::NUMBER(2.0)));
+        assert_eq!(eval("\"hi\" |> hexEncode |> hexDecode"), Ok(Value::STRING("hi".into())));
+        assert_eq!(eval("1 |> 2"), Err(RuntimeError { line: 0, message: "Can only call functions".to_string() }));
+    }
+
+    #[test]
+    fn it_keeps_to_ieee_for_zero_infinity_and_nan() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+        let shown = |value: Result<Value, RuntimeError>| value.unwrap().to_string();
+
+        // printing is Java's, whatever Rust would have written
+        assert_eq!(shown(eval("-0")), "-0");
+        assert_eq!(shown(eval("0 * -1")), "-0");
+        assert_eq!(shown(eval("1 / 0")), "Infinity");
+        assert_eq!(shown(eval("-1 / 0")), "-Infinity");
+        assert_eq!(shown(eval("0 / 0")), "NaN");
+        assert_eq!(shown(eval("-Infinity")), "-Infinity");
+
+        assert_eq!(eval("-0 == 0"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("NaN == NaN"), Ok(Value::BOOLEAN(false)));
+        assert_eq!(eval("NaN != NaN"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("NaN < 1 or NaN >= 1"), Ok(Value::BOOLEAN(false)));
+        assert_eq!(eval("Infinity > 1e308 and -Infinity < -1e308"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("Infinity - Infinity == Infinity - Infinity"), Ok(Value::BOOLEAN(false)));
+
+        assert_eq!(eval("isNan(0 / 0) and isNan(NaN) and isNan(Infinity) == false"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("isFinite(-0) and isFinite(Infinity) == false and isFinite(NaN) == false"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("isNan(\"NaN\")"), Err(RuntimeError { line: 0, message: "isNan() takes a number".to_string() }));
+    }
+
+    #[test]
+    fn it_builds_records() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(format!("record Point {{ x, y }} var p = Point(1, 2); {}", source)).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        assert_eq!(eval("p.x + p.y"), Ok(Value::NUMBER(3.0)));
+        // equal when every field is, no matter which call built them
+        assert_eq!(eval("p == Point(1, 2)"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("p == Point(2, 1)"), Ok(Value::BOOLEAN(false)));
+        assert_eq!(eval("p is Point and p is Record and Point is Function"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("1 is Point"), Ok(Value::BOOLEAN(false)));
+        assert_eq!(eval("Point(p, \"far\")").map(|value| value.to_string()), Ok("Point { x: Point { x: 1, y: 2 }, y: \"far\" }".to_string()));
+        assert_eq!(eval("Point(1)"), Err(RuntimeError { line: 0, message: "Expected 2 arguments but got 1".to_string() }));
+        assert_eq!(eval("p.z"), Err(RuntimeError { line: 0, message: "Point has no field \"z\"".to_string() }));
+        assert_eq!(eval("record Number { n }"), Err(RuntimeError { line: 0, message: "Number is already a type, a record needs another name".to_string() }));
+    }
+
+    #[test]
+    fn it_makes_instances_of_classes() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(format!("class Foo {{}} class Bar {{ hello(name) {{ print(name); }} }} var f = Foo(); {}", source)).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        assert_eq!(eval("f").map(|value| value.to_string()), Ok("<Foo instance>".to_string()));
+        assert_eq!(eval("Bar").map(|value| value.to_string()), Ok("<class Bar>".to_string()));
+        assert_eq!(eval("f is Foo and f is Instance and Foo is Function"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("f is Bar or Bar() is Foo"), Ok(Value::BOOLEAN(false)));
+        // every call makes a new one
+        assert_eq!(eval("f == f and f != Foo()"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("Foo(1)"), Err(RuntimeError { line: 0, message: "Expected 0 arguments but got 1".to_string() }));
+        assert_eq!(eval("class String {}"), Err(RuntimeError { line: 0, message: "String is already a type, a class needs another name".to_string() }));
+    }
+
+    #[test]
+    fn it_sets_fields_on_instances() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        assert_eq!(eval("class Point {} var p = Point(); p.x = 1; p.y = p.x + 1;"), Ok(Value::NUMBER(2.0)));
+        assert_eq!(eval("p.x + p.y"), Ok(Value::NUMBER(3.0)));
+        // every copy of it is the same instance, and fields are its own
+        assert_eq!(eval("var q = p; q.x = p.y = 5; p.x + q.y"), Ok(Value::NUMBER(10.0)));
+        assert_eq!(eval("Point().x"), Err(RuntimeError { line: 0, message: "<Point instance> has no field or method \"x\"".to_string() }));
+        assert_eq!(eval("var n = 1; n.x = 2;"), Err(RuntimeError { line: 0, message: "Only instances have fields, can't set \"x\"".to_string() }));
+        assert_eq!(
+            interp.eval_pure("p.x = 2"),
+            Err(RuntimeError { line: 0, message: "Can't set the field \"x\" here, evaluation has to be side effect free".to_string() })
+        );
+    }
+
+    #[test]
+    fn it_calls_methods_with_this() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+        let source = "class Counter {\n  add(by) { this.count = this.count + by; this }\n  total() { this.count }\n  peek() { secret }\n}\n\
+                      var c = Counter(); c.count = 0;";
+        eval(source).unwrap();
+
+        assert_eq!(eval("c.add(2).add(3).total()"), Ok(Value::NUMBER(5.0)));
+        // the method keeps hold of its instance once it's a value of its own
+        assert_eq!(eval("var add = c.add; add(1); c.total()"), Ok(Value::NUMBER(6.0)));
+        assert_eq!(eval("add").map(|value| value.to_string()), Ok("<method Counter.add>".to_string()));
+        assert_eq!(eval("add is Function and add == c.add and add != Counter().add"), Ok(Value::BOOLEAN(true)));
+        // a field of the same name comes first
+        assert_eq!(eval("c.total = 1; c.total"), Ok(Value::NUMBER(1.0)));
+        // the body sees the globals, but nothing of where it's called from
+        assert_eq!(eval("{ var secret = 1; c.peek() }"), Err(RuntimeError { line: 3, message: "Variable \"secret\" does not exist".to_string() }));
+        assert_eq!(eval("add()"), Err(RuntimeError { line: 0, message: "Expected 1 argument but got 0".to_string() }));
+        assert_eq!(eval("this"), Err(RuntimeError { line: 0, message: "Can't use this outside of a method".to_string() }));
+    }
+
+    #[test]
+    fn it_initialises_instances_with_init() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+        assert_eq!(eval("class Point {\n  init(x, y) { this.x = x; this.y = y; 0 }\n  sum() { this.x + this.y }\n}\nvar p = Point(1, 2);\np.x"), Ok(Value::NUMBER(1.0)));
+
+        assert_eq!(eval("p.sum()"), Ok(Value::NUMBER(3.0)));
+        // the instance whatever the body ends with, and again when it's called to start over
+        assert_eq!(eval("p.init(3, 4) == p"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("p.sum()"), Ok(Value::NUMBER(7.0)));
+        assert_eq!(eval("Point(1)"), Err(RuntimeError { line: 0, message: "Expected 2 arguments but got 1".to_string() }));
+    }
+
+    #[test]
+    fn it_runs_timers_once_the_program_is_done() {
+        // adds its argument to the global notes, and stops everything at three of them
+        fn note(interp: &mut Interpreter, args: &[Value]) -> InterpreterResult {
+            let notes = match (interp.resolve("notes")?, args) {
+                (Value::STRING(notes), [Value::STRING(note)]) => format!("{}{}", notes, note),
+                _ => return Ok(Value::Null),
+            };
+            if notes.len() == 3 {
+                interp.clear_timers();
+            }
+            interp.define_global("notes", Value::STRING(notes.into()));
+            Ok(Value::Null)
+        }
+
+        let mut interp = Interpreter::builder().native(NativeFunction { name: "note", func: note, must_use: false }).build();
+        let eval = |interp: &mut Interpreter, source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value)
+        };
+
+        eval(&mut interp, "var notes = \"\"; setTimeout(note, 2, \"c\"); setTimeout(note, 0, \"b\"); var x = setTimeout(note, 1, \"x\"); clearTimeout(x); note(\"a\");").unwrap();
+        // nothing fires until the program is done
+        assert_eq!(interp.resolve("notes"), Ok(Value::STRING("a".into())));
+        assert_eq!(interp.run_until_idle(), Ok(()));
+        assert_eq!(interp.resolve("notes"), Ok(Value::STRING("abc".into())));
+
+        eval(&mut interp, "notes = \"\"; setInterval(note, 1, \"i\");").unwrap();
+        assert_eq!(interp.run_until_idle(), Ok(()));
+        assert_eq!(interp.resolve("notes"), Ok(Value::STRING("iii".into())));
+
+        assert_eq!(eval(&mut interp, "clearTimeout(1)"), Ok(Some(Value::BOOLEAN(false))));
+        assert_eq!(
+            eval(&mut interp, "setTimeout(1, 2)"),
+            Err(RuntimeError { line: 0, message: "setTimeout() takes a function to call and a delay in milliseconds".to_string() })
+        );
+    }
+
+    #[test]
+    fn it_stops_at_exit() {
+        let mut interp = Interpreter::new();
+        let parse = |source: &str| Parser::new(Scanner::new(source.to_owned()).collect()).parse();
+
+        let outcome = interp.eval(parse("var a = 1; { defer a = 10; exit(3); a = 2; } a = 4; a")).unwrap();
+        assert_eq!((outcome.exit_code, outcome.value), (Some(3), None));
+        // the defer still ran on the way out, nothing after the exit did
+        assert_eq!(interp.resolve("a"), Ok(Value::NUMBER(10.0)));
+
+        // start doesn't carry on past it either, and it isn't an error
+        assert_eq!(interp.start(parse("exit(); a = 5;")), Ok(Value::Null));
+        assert_eq!(interp.exit_code(), Some(0));
+        assert_eq!(interp.resolve("a"), Ok(Value::NUMBER(10.0)));
+
+        // from a timer, which takes the rest of them with it
+        interp.eval(parse("setTimeout(exit, 0, 4); setTimeout(exit, 1, 5);")).unwrap();
+        assert_eq!(interp.exit_code(), None);
+        assert_eq!(interp.run_until_idle(), Ok(()));
+        assert_eq!(interp.exit_code(), Some(4));
+
+        assert_eq!(interp.eval(parse("exit(1.5);")), Err(RuntimeError { line: 0, message: "exit() takes a whole number status code".to_string() }));
+        assert_eq!(interp.exit_code(), None);
+    }
+
+    #[test]
+    fn it_stops_loops_when_cancelled() {
+        let mut interp = Interpreter::new();
+        let parse = |source: &str| Parser::new(Scanner::new(source.to_owned()).collect()).parse();
+
+        let token = interp.cancel_token();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            token.cancel();
+        });
+        let res = interp.eval(parse("var i = 0;\nwhile (true) {\n    i = i + 1;\n}"));
+        canceller.join().unwrap();
+        assert_eq!(res, Err(RuntimeError { line: 1, message: "Interrupted".to_string() }));
+        assert!(interp.cancel_token().is_cancelled());
+
+        // the next run starts afresh
+        assert_eq!(interp.eval(parse("i > 0")).map(|outcome| outcome.value), Ok(Some(Value::BOOLEAN(true))));
+        assert!(!interp.cancel_token().is_cancelled());
+    }
+
+    #[test]
+    fn it_runs_out_of_stack_with_an_error() {
+        // built by hand, the parser would need the stack itself
+        let nested = |depth: usize| {
+            let mut stmt = Stmt::Expr(Expr::Literal(Value::NUMBER(1.0)));
+            for _ in 0..depth {
+                stmt = Stmt::Block(vec![stmt]);
+            }
+            vec![stmt]
+        };
+
+        let mut interp = Interpreter::new();
+        assert_eq!(
+            interp.eval(nested(5000)),
+            Err(RuntimeError { line: 0, message: "Nested more than 1024 deep, out of stack (see InterpreterConfig::stack_size)".to_string() })
+        );
+        assert_eq!(interp.eval(nested(1000)).map(|outcome| outcome.value), Ok(Some(Value::NUMBER(1.0))));
+
+        // given a thread with the room for it
+        let deeper = std::thread::Builder::new()
+            .stack_size(16 * 1024 * 1024)
+            .spawn(move || Interpreter::builder().stack_size(16 * 1024 * 1024).build().eval(nested(5000)).map(|outcome| outcome.value))
+            .unwrap();
+        assert_eq!(deeper.join().unwrap(), Ok(Some(Value::NUMBER(1.0))));
+    }
+
+    #[test]
+    fn it_passes_messages_to_workers() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        let source = "var w = spawn(\"var n = recv(); send(n * 2); n + 1\"); send(w, 20); recv(w) + join(w)";
+        assert_eq!(eval(source), Ok(Value::NUMBER(61.0)));
+        // nothing more to come once it's done
+        assert_eq!(eval("var w = spawn(\"1\"); recv(w) is Nil and join(w) == 1"), Ok(Value::BOOLEAN(true)));
+
+        // a worker's error is its joiner's
+        assert_eq!(eval("join(spawn(\"missing\"))"), Err(RuntimeError { line: 0, message: "Variable \"missing\" does not exist".to_string() }));
+        assert_eq!(
+            eval("var w = spawn(\"1\"); join(w); recv(w)"),
+            Err(RuntimeError { line: 0, message: "No worker 4, it was never spawned or has been joined".to_string() })
+        );
+        assert_eq!(eval("recv()"), Err(RuntimeError { line: 0, message: "recv() needs a worker id outside a spawned script".to_string() }));
+    }
 
-        for stmt in stmts {
-            self.execute(stmt)?;
+    #[test]
+    fn it_hands_control_back_every_few_steps() {
+        use std::sync::{Arc, Mutex};
+
+        let source = "var i = 0; while (i < 100) i = i + 1; i";
+        let calls = Arc::new(Mutex::new(vec![]));
+        let seen = calls.clone();
+        let mut interp = Interpreter::builder()
+            .yield_every_n_steps(10, move |report| {
+                seen.lock().unwrap().push(report.steps);
+                // more often once it's warmed up
+                if report.steps > 100 { Yield::Reschedule(5) } else { Yield::Continue }
+            })
+            .build();
+        let outcome = interp.eval(Parser::new(Scanner::new(source.to_owned()).collect()).parse()).unwrap();
+        assert_eq!(outcome.value, Some(Value::NUMBER(100.0)));
+
+        // steps count expressions too, so a statement can land a little past when it was due
+        let calls = calls.lock().unwrap();
+        assert!(calls.len() > 10);
+        for pair in calls.windows(2) {
+            let due = if pair[0] > 100 { 5 } else { 10 };
+            assert!(pair[1] - pair[0] >= due, "{:?}", pair);
         }
 
-        self.environment = tmp;
+        let config = InterpreterConfig::default().yield_every_n_steps(10, |report| if report.steps > 50 { Yield::Abort } else { Yield::Continue });
+        let mut interp = Interpreter::with_config(config);
+        assert_eq!(
+            interp.eval(Parser::new(Scanner::new(source.to_owned()).collect()).parse()),
+            Err(RuntimeError { line: 0, message: "Stopped by the host after 63 steps".to_string() })
+        );
+    }
 
-        Ok(Value::Null)
+    #[test]
+    fn it_tells_scripts_where_they_are() {
+        let mut interp = Interpreter::new();
+        let eval = |interp: &mut Interpreter, source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        assert_eq!(eval(&mut interp, "__file__ is Nil"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval(&mut interp, "var a = 1;\n\n__line__()"), Ok(Value::NUMBER(2.0)));
+        assert_eq!(eval(&mut interp, "version()"), Ok(Value::STRING(env!("CARGO_PKG_VERSION").into())));
+
+        interp.set_file("scripts/main.lox");
+        assert_eq!(eval(&mut interp, "__file__"), Ok(Value::STRING("scripts/main.lox".into())));
+        let mut built = Interpreter::builder().file("other.lox").build();
+        assert_eq!(eval(&mut built, "__file__"), Ok(Value::STRING("other.lox".into())));
     }
 
-    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) -> InterpreterResult {
-        match self.evaluate(condition) {
-            Ok(Value::BOOLEAN(true)) => self.execute(then_branch),
-            Ok(Value::BOOLEAN(false)) => {
-                if let Some(e) = else_branch {
-                    self.execute(e)
-                } else {
-                    Ok(Value::Null)
-                }
-            }
-            _ => Ok(Value::Null)
-        }
+    #[test]
+    fn it_answers_has_feature() {
+        let mut interp = Interpreter::builder().feature("sql").build();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+
+        assert_eq!(eval("hasFeature(\"records\") and hasFeature(\"sql\")"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("hasFeature(\"classes\")"), Ok(Value::BOOLEAN(false)));
+        // workers get the same
+        assert_eq!(eval("var w = spawn(\"hasFeature(recv())\"); send(w, \"sql\"); join(w)"), Ok(Value::BOOLEAN(true)));
+        assert_eq!(eval("hasFeature(1)"), Err(RuntimeError { line: 0, message: "hasFeature() takes the name of a feature".to_string() }));
     }
 
-    fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> InterpreterResult {
-        while is_truthy(&self.evaluate(condition)) {
-            let _ = self.execute(body);
+    #[test]
+    fn it_builds_lazy_globals_on_first_use() {
+        let built = Rc::new(RefCell::new(0));
+        let mut interp = Interpreter::new();
+        for name in ["db", "cache", "unused"] {
+            let built = built.clone();
+            interp.register_lazy_global(name, move || {
+                *built.borrow_mut() += 1;
+                Value::STRING(name.into())
+            });
         }
 
-        Ok(Value::Null)
-    }
+        let eval = |interp: &mut Interpreter, source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value)
+        };
 
-    fn visit_variable_def(&mut self, ident: &str, initializer: &Option<Expr>) -> InterpreterResult {
-        if let Some(expr) = initializer {
-            match self.evaluate(&expr) {
-                Ok(val) => {
-                    self.environment.borrow_mut().define(ident.to_string(), val);
-                    Ok(Value::Null)
-                }
-                err => {
-                    // var a = b;
-                    err
-                }
-            }
-        } else {
-            // var a;
-            Ok(Value::Null)
-        }
+        assert_eq!(interp.eval_pure("db"), Err(RuntimeError { line: 0, message: "Variable \"db\" does not exist".to_string() }));
+        assert_eq!(*built.borrow(), 0);
+
+        assert_eq!(eval(&mut interp, "{ db; db }"), Ok(Some(Value::STRING("db".into()))));
+        assert_eq!(eval(&mut interp, "cache = 1; cache"), Ok(Some(Value::NUMBER(1.0))));
+        // db once, cache never since it was assigned before anything read it
+        assert_eq!(*built.borrow(), 1);
+        assert_eq!(interp.environment().flatten().get("db"), Some(&Value::STRING("db".into())));
+        assert_eq!(interp.environment().flatten().get("unused"), None);
     }
 
-    fn visit_print(&mut self, expr: &Option<Expr>) -> InterpreterResult {
-        match expr {
-            Some(expr) => {
-                let value = self.evaluate(expr)?;
+    #[test]
+    fn it_resets_to_the_global_scope() {
+        let tokens = Scanner::new("var a = 1;".to_owned()).collect();
+        let mut interp = Interpreter::new();
+        interp.start(Parser::new(tokens).parse()).unwrap();
 
-                println!("{}", value);
+        // as if a panic had escaped from inside a block
+        let inner = Environment::new_with_scope(&interp.environment);
+        interp.environment = Rc::new(RefCell::new(inner));
+        interp.running = true;
+        interp.call_depth = 3;
 
-                Ok(value)
-            }
-            _ => Ok(Value::Null)
-        }
+        interp.reset();
+        assert_eq!(interp.environment().depth(), 0);
+        assert_eq!(interp.call_depth, 0);
+
+        let tokens = Scanner::new("a;".to_owned()).collect();
+        assert_eq!(interp.start(Parser::new(tokens).parse()), Ok(Value::NUMBER(1.0)));
     }
 
-    fn visit_expr(&mut self, expr: &Expr) -> InterpreterResult {
-        self.evaluate(expr)
+    #[test]
+    fn it_if_stmt_works() {
+        let tokens = Scanner::new("
+var a = 5;
+if (true)
+{
+    var b = 10.1;
+    print(a);
+}
+".to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut interp = Interpreter::new();
+        let res = interp.start(stmts);
+        assert_eq!(res, Ok(Value::Null));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(5.0)));
+        assert_eq!(interp.environment().depth(), 0);
     }
 
-    fn visit_error(&mut self, line: &usize, message: &str) -> InterpreterResult {
-        Err(RuntimeError {
-            line: *line,
-            message: message.to_string(),
-        })
+    #[test]
+    fn it_gives_blocks_and_ifs_values() {
+        let run = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            Interpreter::new().start(Parser::new(tokens).parse())
+        };
+
+        assert_eq!(run("{ var a = 4; a * 2 }"), Ok(Value::NUMBER(8.0)));
+        assert_eq!(run("{ var a = 4; }"), Ok(Value::Null));
+        assert_eq!(run("{ 1; { 2 } }"), Ok(Value::NUMBER(2.0)));
+        assert_eq!(run("var c = true; var x = if (c) { 1 } else { 2 }; x"), Ok(Value::NUMBER(1.0)));
+        assert_eq!(run("var x = if (false) { 1 }; x"), Ok(Value::Null));
+        assert_eq!(run("1 + if (false) 1 else { var b = 10; b * 2 }"), Ok(Value::NUMBER(21.0)));
+        assert_eq!(run("var x = if (true) { print(1); }; x"), Ok(Value::Null));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::lexer::Scanner;
-    use crate::parser::Parser;
+    #[test]
+    fn it_runs_deferred_expressions_on_the_way_out() {
+        let run = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            let mut interp = Interpreter::new();
+            let outcome = interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value);
+            let logged = interp.environment().flatten().get("log").cloned();
+            (outcome, logged)
+        };
+        let log = |st: &str| Some(Value::STRING(st.into()));
+
+        let (outcome, logged) = run("
+var log = \"\";
+{
+    defer log = log + \"1\";
+    defer log = log + \"2\";
+    if (true) defer log = log + \"3\";
+    log = log + \"body\";
+}
+log");
+        assert_eq!(outcome, Ok(log("body321")));
+        assert_eq!(logged, log("body321"));
+
+        // a deferred expression sees the block's variables and doesn't change its value
+        let (outcome, logged) = run("var log = 0; var i = 0; while (i < 3) { var j = i; defer log = log + j; i = i + 1 }");
+        assert_eq!(outcome, Ok(None));
+        assert_eq!(logged, Some(Value::NUMBER(3.0)));
+        assert_eq!(run("var log = 1; { defer log = 2; log }").0, Ok(Some(Value::NUMBER(1.0))));
+
+        // the script's own run once it's done
+        let (outcome, logged) = run("var log = \"end\"; defer log = \"deferred\"; log");
+        assert_eq!(outcome, Ok(log("end")));
+        assert_eq!(logged, log("deferred"));
+
+        // they still run when the block errors, and the block's error is the one reported
+        let (outcome, logged) = run("var log = \"\"; { defer log = \"ran\"; defer nope; missing }");
+        assert_eq!(outcome, Err(RuntimeError { line: 0, message: "Variable \"missing\" does not exist".to_string() }));
+        assert_eq!(logged, log("ran"));
+        let (outcome, _) = run("{ defer nope; 1 }");
+        assert_eq!(outcome, Err(RuntimeError { line: 0, message: "Variable \"nope\" does not exist".to_string() }));
+    }
 
     #[test]
-    fn it_works() {
-        let tokens = Scanner::new("-1".to_owned()).collect();
-        let stmts = Parser::new(tokens).parse();
-        let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res.unwrap(), Value::NUMBER(-1.0));
+    fn it_closes_what_with_holds() {
+        // remembers what it closed in a global the test can look at
+        fn close(interp: &mut Interpreter, args: &[Value]) -> InterpreterResult {
+            interp.define_global("closed", args[0].clone());
+            Ok(Value::Null)
+        }
+
+        let run = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            let mut interp = Interpreter::builder()
+                .native(NativeFunction { name: "close", func: close, must_use: false })
+                .build();
+            let result = interp.start(Parser::new(tokens).parse());
+            let closed = interp.environment().flatten().get("closed").cloned();
+            (result, closed)
+        };
+        let file = Some(Value::STRING("file".into()));
+
+        assert_eq!(run("var a = 0; with (var f = \"file\") { a = f; }"), (Ok(Value::Null), file.clone()));
+        // after anything the body deferred
+        assert_eq!(run("var closed = 0; with (var f = \"file\") defer closed = 1;"), (Ok(Value::Null), file.clone()));
+        // even when the body fails
+        assert_eq!(
+            run("with (var f = \"file\") { missing }"),
+            (Err(RuntimeError { line: 0, message: "Variable \"missing\" does not exist".to_string() }), file)
+        );
+        assert_eq!(
+            run("with (var f = 1) {}\nf").0,
+            Err(RuntimeError { line: 1, message: "Variable \"f\" does not exist".to_string() })
+        );
+
+        // nothing is opened without a way to close it
+        let tokens = Scanner::new("with (var f = open()) {}".to_owned()).collect();
+        assert_eq!(
+            Interpreter::new().start(Parser::new(tokens).parse()),
+            Err(RuntimeError { line: 0, message: "Nothing to close \"f\" with, with needs a close function".to_string() })
+        );
     }
 
     #[test]
-    fn it_adds() {
-        let tokens = Scanner::new("-1+1".to_owned()).collect();
+    fn it_and_operator_works() {
+        let tokens = Scanner::new("
+var a = true and 5;
+if (a)
+{
+    print(a);
+}
+".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
-        assert_eq!(res.unwrap(), Value::NUMBER(0.0));
+        assert_eq!(res, Ok(Value::Null));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(5.0)));
+        assert_eq!(interp.environment().depth(), 0);
     }
 
     #[test]
-    fn it_unary_works() {
-        let tokens = Scanner::new("+1".to_owned()).collect();
+    fn it_or_operator_works() {
+        let tokens = Scanner::new("
+var a = \"hi\" or 5;
+if (a)
+{
+    print(a);
+}
+".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
-        assert_eq!(res.unwrap(), Value::NUMBER(1.0));
+        assert_eq!(res, Ok(Value::Null));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::STRING("hi".into())));
+        assert_eq!(interp.environment().depth(), 0);
     }
 
     #[test]
-    fn it_errors() {
-        let tokens = Scanner::new("()".to_owned()).collect();
+    fn it_or_falsey_operator_works() {
+        let tokens = Scanner::new("
+var a = false or 5;
+print(a);
+".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
-        assert_eq!(res, Err(RuntimeError { line: 0, message: "Parsing error at RightParen".to_string() }));
+        assert_eq!(res, Ok(Value::NUMBER(5.0)));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(5.0)));
+        assert_eq!(interp.environment().depth(), 0);
     }
 
     #[test]
-    fn it_does_not_error_prefix_number() {
-        let tokens = Scanner::new("*1".to_owned()).collect();
+    fn it_and_falsey_operator_works() {
+        let tokens = Scanner::new("
+var a = false and false;
+print(a);
+".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::NUMBER(1.0)));
+        assert_eq!(res, Ok(Value::BOOLEAN(false)));
+        assert_eq!(interp.environment().flatten().len(), 1);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::BOOLEAN(false)));
+        assert_eq!(interp.environment().depth(), 0);
     }
 
-    // #[test]
-    // fn it_errors_invalid_operator() {
-    //     let tokens = Scanner::new("1&1".to_owned()).collect();
-    //     let stmts = Parser::new(tokens).parse();
-    //     let mut interp = Interpreter::new();
-    //     let res = interp.start(stmts);
-    //     assert_eq!(res, Err(RuntimeError { line: 0, message: "Parsing error at &".to_string() }));
-    // }
-
     #[test]
-    fn it_works_stmts() {
-        let tokens = Scanner::new("print(\"foo\")".to_owned()).collect();
+    fn it_calls_natives() {
+        let tokens = Scanner::new("var a = clock();".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
-        let mut interp = Interpreter::new();
+        let mut interp = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all(), ..InterpreterConfig::default() });
         let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::STRING("foo".to_string())));
+        assert_eq!(res, Ok(Value::Null));
+        assert!(matches!(interp.environment().flatten().get("a"), Some(Value::NUMBER(n)) if *n > 0.0));
 
-        let tokens = Scanner::new("print(2)".to_owned()).collect();
+        let tokens = Scanner::new("random".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
-        let mut interp = Interpreter::new();
         let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::NUMBER(2.0)));
+        assert!(matches!(res, Ok(Value::Native(_))));
+    }
 
-        let tokens = Scanner::new("print(2+1)".to_owned()).collect();
+    #[test]
+    fn it_reports_execution_stats() {
+        let tokens = Scanner::new("var a = \"foo\"; print(a); clock()".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
-        let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::NUMBER(3.0)));
+        let mut interp = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all(), ..InterpreterConfig::default() });
+        interp.start(stmts).unwrap();
+
+        let report = interp.report().clone();
+        // 3 statements, the literal, the printed variable, and clock + its callee
+        assert_eq!(report.steps, 7);
+        assert_eq!(report.max_call_depth, 1);
+        // reading a string shares it rather than copying
+        assert_eq!(report.allocations, 0);
+
+        interp.start(vec![]).unwrap();
+        assert_eq!(interp.report().steps, 0);
+    }
 
-        let tokens = Scanner::new("print()".to_owned()).collect();
+    #[test]
+    fn it_summarises_what_a_run_did() {
+        let tokens = Scanner::new("var a = 1; { var b = 2; { var c = isNan(b) } } clock()".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
-        let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::Null));
+        let mut interp = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all(), ..InterpreterConfig::default() });
+        interp.start(stmts).unwrap();
+
+        let report = interp.report();
+        // var, the outer block, its var, the inner block, its var and the clock call
+        assert_eq!((report.statements, report.calls, report.max_scope_depth), (6, 2, 2));
+        assert_eq!(
+            report.summary(),
+            "statements executed: 6\nfunctions called: 2\nallocations: 0\npeak environment depth: 2"
+        );
     }
 
     #[test]
-    fn it_works_variables() {
-        let tokens = Scanner::new("var a;".to_owned()).collect();
+    fn it_enforces_capabilities() {
+        let tokens = Scanner::new("clock()".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 0);
-        assert_eq!(interp.environment.borrow().variables.get("a"), None);
+        assert_eq!(res, Err(RuntimeError { line: 0, message: "clock() needs the time capability".to_string() }));
 
-        let tokens = Scanner::new("var a = \"foo\";".to_owned()).collect();
+        let tokens = Scanner::new("readLine()".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
-        let mut interp = Interpreter::new();
+        let mut interp = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all() - Capabilities::STDIN, ..InterpreterConfig::default() });
         let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::STRING("foo".to_string())));
+        assert_eq!(res, Err(RuntimeError { line: 0, message: "readLine() needs the stdin capability".to_string() }));
     }
 
     #[test]
-    fn it_works_multiline() {
-        let tokens = Scanner::new("var a = 4;
-print(a);".to_owned()).collect();
+    fn it_seeds_random_without_capability() {
+        let source = "var a = random(); var b = random();";
+        let run = || {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            let stmts = Parser::new(tokens).parse();
+            let mut interp = Interpreter::new();
+            interp.start(stmts).unwrap();
+            let vars = interp.environment().flatten();
+            (vars.get("a").cloned(), vars.get("b").cloned())
+        };
+
+        let first = run();
+        assert_eq!(first, run());
+        assert_ne!(first.0, first.1);
+        assert!(matches!(first.0, Some(Value::NUMBER(n)) if (0.0..1.0).contains(&n)));
+    }
+
+    #[test]
+    fn it_shadows_natives() {
+        let tokens = Scanner::new("var clock = 1; clock".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::NUMBER(4.0)));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::NUMBER(4.0)));
+        assert_eq!(res, Ok(Value::NUMBER(1.0)));
     }
 
     #[test]
-    fn it_errors_variable() {
-        let tokens = Scanner::new("var a = b;".to_owned()).collect();
+    fn it_errors_calling_non_function() {
+        let tokens = Scanner::new("var a = 1;\na()".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
-        assert_eq!(res, Err(RuntimeError { line: 0, message: "Variable \"b\" does not exist".to_string() }));
+        assert_eq!(res, Err(RuntimeError { line: 1, message: "Can only call functions".to_string() }));
     }
 
     #[test]
-    fn it_works_block_ss() {
-        let tokens = Scanner::new("{
-var a = 4;
-print(a);
-}".to_owned()).collect();
+    fn it_records_and_replays() {
+        let source = "var a = random(); var b = clock(); var c = random();";
+
+        let tokens = Scanner::new(source.to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
-        let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 0);
-        assert_eq!(interp.environment.borrow().variables.get("a"), None);
-        assert_eq!(interp.environment.borrow().enclosing, None);
+        let mut interp = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all(), ..InterpreterConfig::default() });
+        interp.record();
+        interp.start(stmts).unwrap();
+        let recorded = interp.recorded().to_vec();
+        assert_eq!(recorded.len(), 3);
+
+        let tokens = Scanner::new(source.to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut replayed = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all(), ..InterpreterConfig::default() });
+        replayed.replay(recorded.clone());
+        replayed.start(stmts).unwrap();
+        assert_eq!(replayed.environment().flatten().get("a"), Some(&recorded[0]));
+        assert_eq!(replayed.environment().flatten().get("b"), Some(&recorded[1]));
+        assert_eq!(replayed.environment().flatten().get("c"), Some(&recorded[2]));
+
+        let tokens = Scanner::new(source.to_owned()).collect();
+        let stmts = Parser::new(tokens).parse();
+        let mut exhausted = Interpreter::with_config(InterpreterConfig { capabilities: Capabilities::all(), ..InterpreterConfig::default() });
+        exhausted.replay(vec![Value::NUMBER(0.5)]);
+        let res = exhausted.start(stmts);
+        assert_eq!(res, Err(RuntimeError { line: 0, message: "Replay trace is exhausted".to_string() }));
     }
 
     #[test]
-    fn it_works_shadow_block() {
+    fn it_while_works() {
         let tokens = Scanner::new("
-var a = 4;
-{
-    var a = 4;
-    print(a);
+var a = 1;
+var b = true;
+while (b) {
+    b = false;
+    a = 2;
 }
 ".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
         assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::NUMBER(4.0)));
-        // assert_eq!(interp.environment.borrow().enclosing, None);
+        assert_eq!(interp.environment().flatten().len(), 2);
+        assert_eq!(interp.environment().flatten().get("b"), Some(&Value::BOOLEAN(false)));
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(2.0)));
+        assert_eq!(interp.environment().depth(), 0);
     }
 
     #[test]
-    fn it_works_replace_inner_block() {
-        let tokens = Scanner::new("
-var a = 4;
-{
-    a = 5;
-    var b = 10.1;
-    print(a);
-}
-".to_owned()).collect();
+    fn it_concatenates_strings() {
+        let tokens = Scanner::new("var a = \"foo\" + \"bar\";".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::NUMBER(5.0)));
-        assert_eq!(interp.environment.borrow().enclosing, None);
+        interp.start(stmts).unwrap();
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::STRING("foobar".into())));
     }
 
     #[test]
-    fn it_block_errors() {
+    fn it_limits_string_length() {
         let tokens = Scanner::new("
-var a = 4;
-{
-    b = 5;
-    print(b);
+var s = \"ab\";
+while (true) {
+    s = s + s;
 }
 ".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
-        let mut interp = Interpreter::new();
+        let mut interp = Interpreter::with_config(InterpreterConfig { max_string_length: Some(10), ..InterpreterConfig::default() });
         let res = interp.start(stmts);
-        assert_eq!(res, Err(RuntimeError { line: 0, message: "Variable \"b\" does not exist".to_string() }));
+        assert_eq!(res, Err(RuntimeError { line: 0, message: "String of 16 bytes exceeds the limit of 10".to_string() }));
     }
 
     #[test]
-    fn it_if_stmt_works() {
+    fn it_skips_scope_for_blocks_without_declarations() {
         let tokens = Scanner::new("
-var a = 5;
-if (true)
+var a = 1;
 {
-    var b = 10.1;
-    print(a);
+    a = 2;
+    {
+        var a = 3;
+    }
 }
 ".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
         assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::NUMBER(5.0)));
-        assert_eq!(interp.environment.borrow().enclosing, None);
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::NUMBER(2.0)));
+        assert_eq!(interp.environment().depth(), 0);
     }
 
     #[test]
-    fn it_and_operator_works() {
+    fn it_keeps_declarations_in_branches_inside_the_block() {
         let tokens = Scanner::new("
-var a = true and 5;
-if (a)
-{
-    print(a);
-}
+{ if (true) var x = 1; }
+{ if (false) 0; else var y = 2; }
+{ var i = 0; while (i < 1) i = i + 1; }
 ".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::NUMBER(5.0)));
-        assert_eq!(interp.environment.borrow().enclosing, None);
+        interp.start(stmts).unwrap();
+        let globals = interp.environment().flatten();
+        assert_eq!((globals.get("x"), globals.get("y"), globals.get("i")), (None, None, None));
+        assert_eq!(interp.environment().depth(), 0);
     }
 
     #[test]
-    fn it_or_operator_works() {
+    fn it_invalidates_cached_lookups_on_shadowing() {
         let tokens = Scanner::new("
-var a = \"hi\" or 5;
-if (a)
+var a = 1;
+var seen = 0;
 {
-    print(a);
+    var i = 0;
+    // not a block, so the declaration lands in the scope around the loop and outlives it
+    while ((i = i + 1) < 4)
+        if (i == 2) var a = 10; else seen = seen + a;
 }
 ".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
         let res = interp.start(stmts);
         assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::STRING("hi".to_string())));
-        assert_eq!(interp.environment.borrow().enclosing, None);
+        // the second read of `a` has to see the declaration the second time round made
+        assert_eq!(interp.environment().flatten().get("seen"), Some(&Value::NUMBER(11.0)));
     }
 
     #[test]
-    fn it_or_falsey_operator_works() {
+    fn it_keeps_cached_lookups_to_their_own_node() {
+        // the two methods' reads of y are different nodes, one of a global and one of a parameter,
+        // and running a class declaration over and over mustn't let one stand in for the other
         let tokens = Scanner::new("
-var a = false or 5;
-print(a);
+var y = \"global\";
+var wrong = 0;
+var i = 0;
+while (i < 200) {
+    { class A { f() { y } } A().f(); }
+    {
+        class B { f(y) { y } }
+        var r = B().f(\"param\");
+        if (r != \"param\") wrong = wrong + 1;
+    }
+    i = i + 1;
+}
 ".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::NUMBER(5.0)));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::NUMBER(5.0)));
-        assert_eq!(interp.environment.borrow().enclosing, None);
-    }
+        interp.start(stmts).unwrap();
+        assert_eq!(interp.environment().flatten().get("wrong"), Some(&Value::NUMBER(0.0)));
 
-    #[test]
-    fn it_and_falsey_operator_works() {
-        let tokens = Scanner::new("
-var a = false and false;
-print(a);
-".to_owned()).collect();
-        let stmts = Parser::new(tokens).parse();
+        // nor do runs of trees that are freed in between, the same shape each time so their
+        // nodes tend to land where the last tree's were
+        let parse = |source: &str| Parser::new(Scanner::new(source.to_owned()).collect()).parse();
         let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::BOOLEAN(false)));
-        assert_eq!(interp.environment.borrow().variables.len(), 1);
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::BOOLEAN(false)));
-        assert_eq!(interp.environment.borrow().enclosing, None);
+        interp.eval(parse("var y = \"global\"; var r = 0;")).unwrap();
+        for _ in 0..200 {
+            interp.eval(parse("{ class B { f(x) { r = y; } } B().f(\"param\"); }")).unwrap();
+            assert_eq!(interp.environment().flatten().get("r"), Some(&Value::STRING("global".into())));
+            interp.eval(parse("{ class B { f(y) { r = y; } } B().f(\"param\"); }")).unwrap();
+            assert_eq!(interp.environment().flatten().get("r"), Some(&Value::STRING("param".into())));
+        }
     }
 
     #[test]
-    fn it_while_works() {
+    fn it_if_uses_truthiness() {
         let tokens = Scanner::new("
-var a = 1;
-var b = true;
-while (b) {
-    b = false;
-    a = 2;
-}
+var a = 0;
+if (a) a = \"zero is truthy\"; else a = 1;
 ".to_owned()).collect();
         let stmts = Parser::new(tokens).parse();
         let mut interp = Interpreter::new();
-        let res = interp.start(stmts);
-        assert_eq!(res, Ok(Value::Null));
-        assert_eq!(interp.environment.borrow().variables.len(), 2);
-        assert_eq!(interp.environment.borrow().variables.get("b"), Some(&Value::BOOLEAN(false)));
-        assert_eq!(interp.environment.borrow().variables.get("a"), Some(&Value::NUMBER(2.0)));
-        assert_eq!(interp.environment.borrow().enclosing, None);
+        interp.start(stmts).unwrap();
+        assert_eq!(interp.environment().flatten().get("a"), Some(&Value::STRING("zero is truthy".into())));
+
+        let tokens = Scanner::new("if (b) print(1);".to_owned()).collect();
+        let res = Interpreter::new().start(Parser::new(tokens).parse());
+        assert_eq!(res, Err(RuntimeError { line: 0, message: "Variable \"b\" does not exist".to_string() }));
     }
 }
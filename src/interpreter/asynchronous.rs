@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+use crate::parser::{Expr, Stmt, Value};
+use super::{ExecutionReport, Interpreter, ProgramOutcome, RuntimeError, ScopeGuard};
+
+// What an async native hands back.  It can't borrow the interpreter, whatever it needs from the
+// arguments has to be moved in
+pub type NativeFuture = Pin<Box<dyn Future<Output = Result<Value, RuntimeError>>>>;
+
+// A native that starts something on the host and lets the script carry on once it's done, e.g.
+// `var body = await fetch(url);`.  Called like any other native, but only through await
+#[derive(Clone)]
+pub struct AsyncNative {
+    pub name: &'static str,
+    pub func: fn(&mut Interpreter, &[Value]) -> NativeFuture,
+}
+
+impl fmt::Debug for AsyncNative {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<async fn {}>", self.name)
+    }
+}
+
+// An Interpreter for embedders already running on an executor.  Evaluation gives the executor
+// its thread back whenever the script awaits one of the async natives, and picks up from there
+// once the future is ready.
+//
+// The tree is walked by plain recursion, which can't be stopped halfway, so an await has to be
+// a whole statement at the top of the script: `await f(a);`, `var x = await f(a);`,
+// `x = await f(a);` or `print(await f(a));`.  Anywhere else is a runtime error, as it is in a
+// plain Interpreter.
+pub struct AsyncInterpreter {
+    interp: Interpreter,
+    natives: HashMap<&'static str, AsyncNative>,
+}
+
+impl AsyncInterpreter {
+    pub fn new(mut interp: Interpreter) -> Self {
+        interp.add_feature("await");
+        Self { interp, natives: HashMap::new() }
+    }
+
+    // Add an async native, or replace the one with the same name
+    pub fn native(mut self, native: AsyncNative) -> Self {
+        self.natives.insert(native.name, native);
+        self
+    }
+
+    // the one doing the work between awaits, for everything that isn't async
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interp
+    }
+
+    // Interpreter::eval, waiting on the host where the script awaits
+    pub async fn eval<S: AsRef<[Stmt]>>(&mut self, stmts: S) -> Result<ProgramOutcome, RuntimeError> {
+        let stmts = stmts.as_ref();
+        if self.interp.running {
+            return Err(RuntimeError {
+                line: 0,
+                message: "Interpreter is already running".to_string(),
+            });
+        }
+        self.interp.running = true;
+
+        let mut result = Ok(Value::Null);
+        self.interp.report = ExecutionReport::default();
+        self.interp.next_yield = 0;
+        self.interp.exit_code = None;
+        self.interp.cancel.clear();
+        let started = Instant::now();
+
+        // the same as Interpreter::run, the guard just lives across the awaits
+        let natives = &self.natives;
+        let mut script = ScopeGuard::deferring(&mut self.interp);
+        for stmt in stmts {
+            result = match awaited(stmt) {
+                Some((callee, arguments, line)) => match start(&mut script, natives, callee, arguments, line) {
+                    Ok(future) => match future.await {
+                        Ok(value) => script.execute(&with_value(stmt, value)),
+                        Err(err) => Err(err),
+                    },
+                    Err(err) => Err(err),
+                },
+                None => script.execute(stmt),
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+        let result = script.finish(result);
+
+        self.interp.report.duration = started.elapsed();
+        self.interp.running = false;
+
+        let value = self.interp.exited(result)?;
+        Ok(self.interp.outcome(stmts, value))
+    }
+}
+
+// The call `stmt` waits on, if it's one of the shapes that can
+fn awaited(stmt: &Stmt) -> Option<(&Expr, &[Expr], usize)> {
+    let expr = match stmt {
+        Stmt::Expr(Expr::Assign { expr, .. }) => expr,
+        Stmt::Print(exprs) if exprs.len() == 1 => &exprs[0],
+        Stmt::Expr(expr) | Stmt::VariableDef { expr: Some(expr), .. } => expr,
+        _ => return None,
+    };
+    match expr {
+        Expr::Await { expr, line } => match expr.as_ref() {
+            Expr::Call { callee, arguments, .. } => Some((callee, arguments, *line)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// `stmt` with what its await finished with in place of the await
+fn with_value(stmt: &Stmt, value: Value) -> Stmt {
+    let value = Expr::Literal(value);
+    match stmt.clone() {
+        Stmt::Expr(Expr::Assign { name, line, .. }) => Stmt::Expr(Expr::Assign { name, expr: Box::new(value), line }),
+        Stmt::Expr(_) => Stmt::Expr(value),
+        Stmt::Print(_) => Stmt::Print(vec![value]),
+        Stmt::VariableDef { ident, line, .. } => Stmt::VariableDef { ident, expr: Some(value), line },
+        stmt => stmt,
+    }
+}
+
+// Look the callee up and call it with the arguments, which are evaluated like any others
+fn start(
+    interp: &mut Interpreter,
+    natives: &HashMap<&'static str, AsyncNative>,
+    callee: &Expr,
+    arguments: &[Expr],
+    line: usize,
+) -> Result<NativeFuture, RuntimeError> {
+    // a script's own variable of the same name wins, as it does over a native
+    let native = match callee {
+        Expr::Variable { name, .. } if interp.environment().depth_of(name).is_none() => natives.get(name.as_str()),
+        _ => None,
+    };
+    let native = native.ok_or(RuntimeError {
+        line,
+        message: "Can only await a call to an async native".to_string(),
+    })?;
+
+    let mut args = vec![];
+    for argument in arguments {
+        args.push(interp.evaluate(argument)?);
+    }
+
+    let mut call = ScopeGuard::call(interp, line)?;
+    Ok((native.func)(&mut call, &args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    // just enough of an executor to drive one future to the end, counting how often it had to
+    // wait
+    fn block_on<T>(future: impl Future<Output = T>) -> (T, usize) {
+        struct Noop;
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let waker = Waker::from(Arc::new(Noop));
+        let mut context = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        let mut pending = 0;
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(value) => return (value, pending),
+                Poll::Pending => pending += 1,
+            }
+        }
+    }
+
+    // doubles its argument, but not the first time it's asked
+    struct Later {
+        value: Value,
+        asked: bool,
+    }
+
+    impl Future for Later {
+        type Output = Result<Value, RuntimeError>;
+
+        fn poll(mut self: Pin<&mut Self>, _context: &mut Context) -> Poll<Self::Output> {
+            if !self.asked {
+                self.asked = true;
+                return Poll::Pending;
+            }
+            match self.value {
+                Value::NUMBER(n) => Poll::Ready(Ok(Value::NUMBER(n * 2.0))),
+                _ => Poll::Ready(Err(RuntimeError { line: 0, message: "Can only double numbers".to_string() })),
+            }
+        }
+    }
+
+    fn double(_interp: &mut Interpreter, args: &[Value]) -> NativeFuture {
+        let value = args.first().cloned().unwrap_or(Value::Null);
+        Box::pin(Later { value, asked: false })
+    }
+
+    fn eval(source: &str) -> (Result<ProgramOutcome, RuntimeError>, usize) {
+        let mut interp = AsyncInterpreter::new(Interpreter::new()).native(AsyncNative { name: "double", func: double });
+        let stmts = Parser::new(Scanner::new(source.to_owned()).collect()).parse();
+        block_on(interp.eval(stmts))
+    }
+
+    #[test]
+    fn it_waits_on_async_natives() {
+        let (outcome, pending) = eval("var a = await double(1); a = await double(a + 1); await double(a)");
+        assert_eq!(outcome.unwrap().value, Some(Value::NUMBER(12.0)));
+        // it gave the executor back once for each await
+        assert_eq!(pending, 3);
+
+        let (outcome, _) = eval("await double(\"a\");");
+        assert_eq!(outcome, Err(RuntimeError { line: 0, message: "Can only double numbers".to_string() }));
+        let (outcome, _) = eval("var double = clock; await double();");
+        assert_eq!(outcome, Err(RuntimeError { line: 0, message: "Can only await a call to an async native".to_string() }));
+        let (outcome, _) = eval("var a = 1 + await double(1);");
+        assert_eq!(
+            outcome,
+            Err(RuntimeError {
+                line: 0,
+                message: "Can't await here, only an AsyncInterpreter can, and only for a whole statement at the top of the script".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use crate::parser::Value;
+use crate::values::NumberFormat;
+use super::{natives, FEATURES, GLOBALS, Capabilities, Environment, ExecutionReport, Interpreter, InterpreterConfig, NativeFunction, NativeModule, Trace, Yield};
+
+// Everything an embedder can set up before a script runs, in one place.  Anything not mentioned
+// keeps the default, which is the same interpreter `Interpreter::new` gives: no capabilities,
+// stdout, stderr and stdin, the usual natives and no globals.
+//
+//     let interp = Interpreter::builder()
+//         .capabilities(Capabilities::TIME)
+//         .output(buffer)
+//         .global("answer", Value::NUMBER(42.0))
+//         .build();
+pub struct InterpreterBuilder {
+    config: InterpreterConfig,
+    number_format: NumberFormat,
+    output: Option<Box<dyn Write>>,
+    error_output: Option<Box<dyn Write>>,
+    input: Option<Box<dyn BufRead>>,
+    natives: Vec<NativeFunction>,
+    modules: Vec<(String, NativeModule)>,
+    globals: Vec<(String, Value)>,
+    lazy_globals: HashMap<String, Box<dyn FnOnce() -> Value>>,
+    file: Option<String>,
+    features: Vec<String>,
+}
+
+impl InterpreterBuilder {
+    pub(super) fn new() -> Self {
+        Self {
+            config: InterpreterConfig::default(),
+            number_format: NumberFormat::default(),
+            output: None,
+            error_output: None,
+            input: None,
+            natives: natives::builtins(),
+            modules: vec![],
+            globals: vec![],
+            lazy_globals: HashMap::new(),
+            file: None,
+            features: vec![],
+        }
+    }
+
+    // replaces everything set through the config methods below so far
+    pub fn config(mut self, config: InterpreterConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.config.capabilities = capabilities;
+        self
+    }
+
+    pub fn max_string_length(mut self, max: Option<usize>) -> Self {
+        self.config.max_string_length = max;
+        self
+    }
+
+    pub fn persistent_environment(mut self, persistent: bool) -> Self {
+        self.config.persistent_environment = persistent;
+        self
+    }
+
+    pub fn case_insensitive_keywords(mut self, insensitive: bool) -> Self {
+        self.config.case_insensitive_keywords = insensitive;
+        self
+    }
+
+    pub fn implicit_semicolons(mut self, implicit: bool) -> Self {
+        self.config.implicit_semicolons = implicit;
+        self
+    }
+
+    // see InterpreterConfig::stack_size
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.config.stack_size = bytes;
+        self
+    }
+
+    // see InterpreterConfig::max_call_depth
+    pub fn max_call_depth(mut self, max: Option<usize>) -> Self {
+        self.config.max_call_depth = max;
+        self
+    }
+
+    // see InterpreterConfig::yield_every_n_steps
+    pub fn yield_every_n_steps(mut self, n: u64, callback: impl Fn(&ExecutionReport) -> Yield + Send + Sync + 'static) -> Self {
+        self.config = self.config.yield_every_n_steps(n, callback);
+        self
+    }
+
+    pub fn number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
+        self
+    }
+
+    // where print writes, stdout otherwise
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    // where eprint writes, stderr otherwise
+    pub fn error_output(mut self, output: impl Write + 'static) -> Self {
+        self.error_output = Some(Box::new(output));
+        self
+    }
+
+    // where readLine reads from, stdin otherwise.  It still needs the stdin capability
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    // Add a native, or replace the one with the same name
+    pub fn native(mut self, native: NativeFunction) -> Self {
+        self.natives.retain(|existing| existing.name != native.name);
+        self.natives.push(native);
+        self
+    }
+
+    // see Interpreter::register_module
+    pub fn module(mut self, name: &str, module: NativeModule) -> Self {
+        self.modules.push((name.to_string(), module));
+        self
+    }
+
+    // A global the script starts with, as if it had declared it itself
+    pub fn global(mut self, name: &str, value: Value) -> Self {
+        self.globals.push((name.to_string(), value));
+        self
+    }
+
+    // see Interpreter::register_lazy_global
+    pub fn lazy_global(mut self, name: &str, init: impl FnOnce() -> Value + 'static) -> Self {
+        self.lazy_globals.insert(name.to_string(), Box::new(init));
+        self
+    }
+
+    // see Interpreter::add_feature
+    pub fn feature(mut self, name: &str) -> Self {
+        self.features.push(name.to_string());
+        self
+    }
+
+    // see Interpreter::set_file
+    pub fn file(mut self, name: &str) -> Self {
+        self.file = Some(name.to_string());
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        let mut builtins: HashMap<String, Value> = self
+            .natives
+            .into_iter()
+            .map(|native| (native.name.to_string(), Value::Native(native)))
+            .collect();
+        for name in GLOBALS {
+            builtins.insert(name.to_string(), Value::Null);
+        }
+        builtins.insert("NaN".to_string(), Value::NUMBER(f64::NAN));
+        builtins.insert("Infinity".to_string(), Value::NUMBER(f64::INFINITY));
+
+        let mut environment = if self.config.persistent_environment {
+            Environment::persistent()
+        } else {
+            Environment::new()
+        };
+        for (name, value) in self.globals {
+            environment.define(name, value);
+        }
+
+        let mut interp = Interpreter {
+            environment: Rc::new(RefCell::new(environment)),
+            builtins,
+            lazy_globals: self.lazy_globals,
+            deferred: vec![],
+            timers: Default::default(),
+            workers: Default::default(),
+            parent: None,
+            number_format: self.number_format,
+            trace: Trace::Off,
+            random_state: natives::seed(self.config.capabilities.contains(Capabilities::RANDOM)),
+            output: self.output.unwrap_or_else(|| Box::new(io::stdout())),
+            error_output: self.error_output.unwrap_or_else(|| Box::new(io::stderr())),
+            input: self.input,
+            config: self.config,
+            report: Default::default(),
+            call_depth: 0,
+            depth: 0,
+            features: FEATURES.iter().map(|name| name.to_string()).collect(),
+            call_line: 0,
+            next_yield: 0,
+            lookups: HashMap::new(),
+            shadowing: 0,
+            running: false,
+            paused: None,
+            exit_code: None,
+            cancel: Default::default(),
+            debugger: None,
+            watches: HashSet::new(),
+            breakpoints: HashMap::new(),
+            failed: None,
+            stepping: None,
+            stopped: false,
+        };
+        for (name, module) in self.modules {
+            interp.register_module(&name, module);
+        }
+        #[cfg(feature = "crypto")]
+        interp.add_feature("crypto");
+        for name in self.features {
+            interp.add_feature(&name);
+        }
+        if let Some(file) = self.file {
+            interp.set_file(&file);
+        }
+
+        interp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::RuntimeError;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    // a writer the test can still read after handing it over
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn answer(_interp: &mut Interpreter, _args: &[Value]) -> Result<Value, RuntimeError> {
+        Ok(Value::NUMBER(42.0))
+    }
+
+    #[test]
+    fn it_builds_a_configured_interpreter() {
+        let output = Shared::default();
+        let mut interp = Interpreter::builder()
+            .capabilities(Capabilities::STDIN)
+            .output(output.clone())
+            .input(io::Cursor::new("first\nsecond\n"))
+            .native(NativeFunction { name: "clock", func: answer, must_use: true })
+            .global("greeting", Value::STRING("hi".into()))
+            .build();
+
+        let tokens = Scanner::new("print(greeting); print(readLine()); print(clock()); readLine()".to_owned()).collect();
+        let outcome = interp.eval(Parser::new(tokens).parse()).unwrap();
+
+        assert_eq!(outcome.value, Some(Value::STRING("second".into())));
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "\"hi\"\n\"first\"\n42\n");
+    }
+
+    #[test]
+    fn it_prints_several_values_on_a_line() {
+        let output = Shared::default();
+        let mut interp = Interpreter::builder().output(output.clone()).build();
+
+        let tokens = Scanner::new("var a = 1; print(\"a\", a + 1, true); print(); print(a);".to_owned()).collect();
+        interp.eval(Parser::new(tokens).parse()).unwrap();
+
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "\"a\" 2 true\n1\n");
+    }
+
+    #[test]
+    fn it_writes_without_a_newline() {
+        let output = Shared::default();
+        let mut interp = Interpreter::builder().output(output.clone()).build();
+
+        let source = "write(\"[\"); write(\"=\", 1); write(\"]\"); printLn(); printLn(\"done\", true);";
+        let outcome = interp.eval(Parser::new(Scanner::new(source.to_owned()).collect()).parse()).unwrap();
+
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "[= 1]\n\"done\" true\n");
+        assert_eq!(outcome.printed_lines, 2);
+    }
+
+    #[test]
+    fn it_prints_errors_separately() {
+        let output = Shared::default();
+        let errors = Shared::default();
+        let mut interp = Interpreter::builder().output(output.clone()).error_output(errors.clone()).build();
+
+        let source = "print(1); eprint(\"careful\", 2); print(3);";
+        let outcome = interp.eval(Parser::new(Scanner::new(source.to_owned()).collect()).parse()).unwrap();
+
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "1\n3\n");
+        assert_eq!(String::from_utf8(errors.0.borrow().clone()).unwrap(), "\"careful\" 2\n");
+        assert_eq!(outcome.printed_lines, 2);
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+use crate::parser::Value;
+use super::{Class, Instance, Interpreter, Method, NativeFunction, Record, RecordType, RuntimeError};
+
+// Anything a script can call.  The interpreter only calls through this, so a new kind of
+// function (or a host's own) is one impl rather than another arm everywhere calls happen.
+// `interp` is already in a scope of the call's own, see ScopeGuard::call
+pub trait Callable {
+    fn name(&self) -> &str;
+
+    // how many arguments it takes, None when it takes any number
+    fn arity(&self) -> Option<usize>;
+
+    fn call(&self, interp: &mut Interpreter, args: &[Value], line: usize) -> Result<Value, RuntimeError>;
+}
+
+impl Callable for NativeFunction {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    // natives check their own arguments, most take a few shapes
+    fn arity(&self) -> Option<usize> {
+        None
+    }
+
+    fn call(&self, interp: &mut Interpreter, args: &[Value], line: usize) -> Result<Value, RuntimeError> {
+        interp.call_line = line;
+        (self.func)(interp, args)
+    }
+}
+
+// calling a record type builds one
+impl Callable for Arc<RecordType> {
+    fn name(&self) -> &str {
+        RecordType::name(self)
+    }
+
+    fn arity(&self) -> Option<usize> {
+        Some(self.fields().len())
+    }
+
+    fn call(&self, _interp: &mut Interpreter, args: &[Value], line: usize) -> Result<Value, RuntimeError> {
+        // the interpreter has checked already, a host calling it directly may not have
+        check_arity(self, args, line)?;
+        Ok(Value::Record(Arc::new(Record::new(self.clone(), args.to_vec()))))
+    }
+}
+
+// calling a class makes an instance of it, and runs its init method on it with the arguments
+// if it has one
+impl Callable for Arc<Class> {
+    fn name(&self) -> &str {
+        Class::name(self)
+    }
+
+    fn arity(&self) -> Option<usize> {
+        Some(self.method("init").map_or(0, |init| init.params.len()))
+    }
+
+    fn call(&self, interp: &mut Interpreter, args: &[Value], line: usize) -> Result<Value, RuntimeError> {
+        check_arity(self, args, line)?;
+        let instance = Arc::new(Instance::new(self.clone()));
+        if let Some(init) = Method::bind(&instance, "init") {
+            interp.call_method(&init, args, line)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+// calling a method runs its body with `this` as the instance it was looked up on
+impl Callable for Arc<Method> {
+    fn name(&self) -> &str {
+        &self.function().name
+    }
+
+    fn arity(&self) -> Option<usize> {
+        Some(self.function().params.len())
+    }
+
+    fn call(&self, interp: &mut Interpreter, args: &[Value], line: usize) -> Result<Value, RuntimeError> {
+        check_arity(self, args, line)?;
+        interp.call_method(self, args, line)
+    }
+}
+
+// The same count of arguments as `callee` declares, for the ones that declare one.  Done at the
+// call site, before anything runs, so a wrong count is the caller's error on the caller's line
+pub fn check_arity(callee: &dyn Callable, args: &[Value], line: usize) -> Result<(), RuntimeError> {
+    match callee.arity() {
+        Some(arity) if arity != args.len() => Err(RuntimeError {
+            line,
+            message: format!("Expected {} {} but got {}", arity, if arity == 1 { "argument" } else { "arguments" }, args.len()),
+        }),
+        _ => Ok(()),
+    }
+}
+
+// `value` as something to call, if it's one of the values that can be
+pub fn callable(value: &Value) -> Option<&dyn Callable> {
+    match value {
+        Value::Native(native) => Some(native),
+        Value::RecordType(kind) => Some(kind),
+        Value::Class(class) => Some(class),
+        Value::Method(method) => Some(method),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+    use crate::interpreter::Capabilities;
+
+    #[test]
+    fn it_calls_natives_and_records_alike() {
+        let mut interp = Interpreter::builder().capabilities(Capabilities::TIME).build();
+        let tokens = Scanner::new("record Point { x, y }".to_owned()).collect();
+        interp.start(Parser::new(tokens).parse()).unwrap();
+
+        let point = interp.eval_pure("Point").unwrap();
+        let point = callable(&point).unwrap();
+        assert_eq!((point.name(), point.arity()), ("Point", Some(2)));
+        let made = point.call(&mut interp, &[Value::NUMBER(1.0), Value::NUMBER(2.0)], 0).unwrap();
+        assert_eq!(made.to_string(), "Point { x: 1, y: 2 }");
+
+        // clock is there from the start, for scripts timing themselves
+        let clock = interp.eval_pure("clock").unwrap();
+        let clock = callable(&clock).unwrap();
+        assert_eq!((clock.name(), clock.arity()), ("clock", None));
+        assert!(matches!(clock.call(&mut interp, &[], 0), Ok(Value::NUMBER(n)) if n > 0.0));
+
+        assert!(callable(&Value::NUMBER(1.0)).is_none());
+    }
+
+    #[test]
+    fn it_treats_functions_as_values() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value)
+        };
+        eval("record Point { x, y }\nvar f = isNan;\nvar g = f;\nf = isFinite;\nvar make = Point;").unwrap();
+
+        // g kept the function f held when it was copied
+        assert_eq!(eval("f(NaN) == g(NaN)"), Ok(Some(Value::BOOLEAN(false))));
+        assert_eq!(eval("make(1, 2).y"), Ok(Some(Value::NUMBER(2.0))));
+        assert_eq!(eval("g is Function and make is Function"), Ok(Some(Value::BOOLEAN(true))));
+        // handed to another function, here by the pipe
+        assert_eq!(eval("NaN |> g"), Ok(Some(Value::BOOLEAN(true))));
+    }
+
+    #[test]
+    fn it_checks_arity_on_the_calls_line() {
+        let mut interp = Interpreter::new();
+        let tokens = Scanner::new("record Point { x, y }\nrecord Box { inside }\n\nPoint(1, 2, 3)".to_owned()).collect();
+        assert_eq!(
+            interp.start(Parser::new(tokens).parse()),
+            Err(RuntimeError { line: 3, message: "Expected 2 arguments but got 3".to_string() })
+        );
+
+        let tokens = Scanner::new("Box()".to_owned()).collect();
+        assert_eq!(
+            interp.start(Parser::new(tokens).parse()),
+            Err(RuntimeError { line: 0, message: "Expected 1 argument but got 0".to_string() })
+        );
+        // nothing's called when the count is wrong
+        assert_eq!(interp.report().calls, 0);
+    }
+}
@@ -0,0 +1,25 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// A handle for stopping a script from outside it, another thread or a Ctrl-C handler.  Loops
+// and timers check it before going round again, so even `while (true) {}` stops, with an
+// "Interrupted" error on the loop's line.  Every run starts out not cancelled, a cancel from
+// before it began doesn't count.  See Interpreter::cancel_token
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    // only an atomic store, so a signal handler can call it
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    // stays true after the run it stopped, until the next one starts
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub(super) fn clear(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
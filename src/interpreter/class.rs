@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use crate::parser::{Function, Value};
+
+// What `class Counter { ... }` declares.  Calling it makes a new instance
+#[derive(Debug, PartialEq)]
+pub struct Class {
+    name: String,
+    methods: Arc<[Function]>,
+}
+
+// A value made by calling a class.  Unlike a record it has an identity of its own: two instances
+// are only equal when they're the same one.  Its fields are whatever has been assigned to it so
+// far, behind a lock since every copy of the value is the same instance
+#[derive(Debug)]
+pub struct Instance {
+    class: Arc<Class>,
+    fields: Mutex<HashMap<String, Value>>,
+}
+
+// `receiver.name`, a method of the instance's class that remembers which instance it came from,
+// so calling it later still has the right `this`
+#[derive(Debug)]
+pub struct Method {
+    receiver: Arc<Instance>,
+    name: String,
+}
+
+impl Class {
+    pub(super) fn new(name: &str, methods: &Arc<[Function]>) -> Self {
+        Self { name: name.to_string(), methods: methods.clone() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn method(&self, name: &str) -> Option<&Function> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+}
+
+impl Instance {
+    pub(super) fn new(class: Arc<Class>) -> Self {
+        Self { class, fields: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn class(&self) -> &Class {
+        &self.class
+    }
+
+    pub fn get(&self, field: &str) -> Option<Value> {
+        self.lock().get(field).cloned()
+    }
+
+    // adds the field if it isn't there yet
+    pub fn set(&self, field: &str, value: Value) {
+        self.lock().insert(field.to_string(), value);
+    }
+
+    // Every field there is so far, by name.  A copy, so going through it doesn't hold the lock
+    // when a field leads back to this instance
+    pub fn fields(&self) -> Vec<(String, Value)> {
+        let mut fields: Vec<(String, Value)> = self.lock().iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        fields
+    }
+
+    // a panic can't leave the map half written, so a poisoned lock is as good as any
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Value>> {
+        self.fields.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Method {
+    // None when the class has no method by that name
+    pub(super) fn bind(receiver: &Arc<Instance>, name: &str) -> Option<Self> {
+        receiver.class.method(name)?;
+        Some(Self { receiver: receiver.clone(), name: name.to_string() })
+    }
+
+    pub fn receiver(&self) -> &Arc<Instance> {
+        &self.receiver
+    }
+
+    pub fn function(&self) -> &Function {
+        // bind made sure it's there, and a class's methods never change
+        self.receiver.class.method(&self.name).unwrap()
+    }
+}
+
+impl PartialEq for Method {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.receiver, &other.receiver) && self.name == other.name
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<method {}.{}>", self.receiver.class.name, self.name)
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
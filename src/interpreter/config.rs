@@ -0,0 +1,204 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign, Sub};
+use std::sync::Arc;
+use super::ExecutionReport;
+
+// Everything a script can reach outside the interpreter.  Natives check for the capability they
+// need before touching the host, so an embedder running untrusted code can tell exactly what
+// that code is able to do by looking at one value.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const FS: Self = Self(1 << 0);
+    pub const ENV: Self = Self(1 << 1);
+    pub const PROCESS: Self = Self(1 << 2);
+    pub const NET: Self = Self(1 << 3);
+    pub const TIME: Self = Self(1 << 4);
+    // seed random() from the host; without it the sequence is the same every run
+    pub const RANDOM: Self = Self(1 << 5);
+    pub const STDIN: Self = Self(1 << 6);
+
+    const NAMES: [(Self, &'static str); 7] = [
+        (Self::FS, "fs"),
+        (Self::ENV, "env"),
+        (Self::PROCESS, "process"),
+        (Self::NET, "net"),
+        (Self::TIME, "time"),
+        (Self::RANDOM, "random"),
+        (Self::STDIN, "stdin"),
+    ];
+
+    pub const fn none() -> Self {
+        Self(0)
+    }
+
+    pub const fn all() -> Self {
+        Self((1 << 7) - 1)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(cap, _)| *cap == self)
+            .map_or("<multiple>", |(_, name)| *name)
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl Sub for Capabilities {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = Self::NAMES
+            .iter()
+            .filter(|(cap, _)| self.contains(*cap))
+            .map(|(_, name)| *name)
+            .collect();
+        write!(f, "Capabilities({})", names.join(" | "))
+    }
+}
+
+// What a step hook wants done with the script, see InterpreterConfig::yield_every_n_steps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Yield {
+    // carry on until the next time round
+    Continue,
+    // carry on, and come back after this many steps instead of the usual
+    Reschedule(u64),
+    // stop the script with an error
+    Abort,
+}
+
+// Called every `every` statements a script runs, with the report so far.  Pausing is up to the
+// callback itself: the script waits for as long as it takes to return, e.g. until the next frame
+#[derive(Clone)]
+pub struct StepHook {
+    pub every: u64,
+    pub callback: Arc<dyn Fn(&ExecutionReport) -> Yield + Send + Sync>,
+}
+
+impl fmt::Debug for StepHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StepHook(every {} steps)", self.every)
+    }
+}
+
+// the same hook if it's the same callback
+impl PartialEq for StepHook {
+    fn eq(&self, other: &Self) -> bool {
+        self.every == other.every && Arc::ptr_eq(&self.callback, &other.callback)
+    }
+}
+
+// Strings longer than this (in bytes) are refused unless the embedder says otherwise
+pub const DEFAULT_MAX_STRING_LENGTH: usize = 16 * 1024 * 1024;
+
+// The stack a script is assumed to run on unless the embedder says otherwise, what std gives a
+// thread it spawns
+pub const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+// How many calls deep a script can get unless the embedder says otherwise
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+
+// How much of it each statement or expression the tree walk is inside of may take.  A block is
+// about 1.4KB in a debug build, this leaves room for whatever a native at the bottom needs
+pub(super) const STACK_PER_LEVEL: usize = 2048;
+
+// How an Interpreter is set up by whoever is embedding it.  The default is the safe one: a
+// script can compute and print but cannot touch anything else on the host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterpreterConfig {
+    pub capabilities: Capabilities,
+    // longest string concatenation may build, None for no limit. Keeps `s = s + s` in a loop
+    // from taking the host down with it
+    pub max_string_length: Option<usize>,
+    // keep scopes in persistent maps, so Environment::snapshot is a pointer copy per scope.
+    // Lookups get slower, only worth it when something snapshots a lot
+    pub persistent_environment: bool,
+    // scan PRINT and Print as print, see Scanner::ignoring_keyword_case
+    pub case_insensitive_keywords: bool,
+    // a newline can end a statement, see Scanner::implicit_semicolons
+    pub implicit_semicolons: bool,
+    // lets the host run scripts a slice at a time, see yield_every_n_steps
+    pub step_hook: Option<StepHook>,
+    // Bytes of stack the thread running the script has.  Nesting deep enough to use it up is a
+    // RuntimeError instead of the process aborting, and spawned workers get a stack this big.
+    // Raise it for deeply nested scripts, and run them on a thread with that much, e.g. from
+    // std::thread::Builder::stack_size, the way the CLI does
+    pub stack_size: usize,
+    // Calls a script can have going at once, None for no limit but the stack's.  Running out is
+    // a "stack overflow" RuntimeError on the call that went one too deep, so runaway recursion
+    // ends well before the stack itself would
+    pub max_call_depth: Option<usize>,
+}
+
+impl InterpreterConfig {
+    // Hand control back to `callback` every `n` statements, so a game engine or UI can run a
+    // script a little per frame, or give up on it
+    pub fn yield_every_n_steps(mut self, n: u64, callback: impl Fn(&ExecutionReport) -> Yield + Send + Sync + 'static) -> Self {
+        self.step_hook = Some(StepHook { every: n.max(1), callback: Arc::new(callback) });
+        self
+    }
+}
+
+impl Default for InterpreterConfig {
+    fn default() -> Self {
+        Self {
+            capabilities: Capabilities::none(),
+            max_string_length: Some(DEFAULT_MAX_STRING_LENGTH),
+            persistent_environment: false,
+            case_insensitive_keywords: false,
+            implicit_semicolons: false,
+            step_hook: None,
+            stack_size: DEFAULT_STACK_SIZE,
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_combines_capabilities() {
+        let caps = Capabilities::TIME | Capabilities::RANDOM;
+        assert!(caps.contains(Capabilities::TIME));
+        assert!(!caps.contains(Capabilities::FS));
+        assert!(!caps.contains(Capabilities::TIME | Capabilities::FS));
+        assert!(!(caps - Capabilities::TIME).contains(Capabilities::TIME));
+        assert!(Capabilities::all().contains(caps | Capabilities::STDIN));
+        assert_eq!(InterpreterConfig::default().capabilities, Capabilities::none());
+        assert_eq!(format!("{:?}", caps), "Capabilities(time | random)");
+    }
+}
@@ -0,0 +1,368 @@
+use std::io::{BufRead, Write};
+use crate::parser::Value;
+use super::{Interpreter, RuntimeError};
+
+// Why a run stopped to hand over to the debugger
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pause {
+    // A watched variable was written, by a declaration or an assignment on `line`.  `old` is None
+    // when the declaration is the first this scope has seen of the name
+    Watch { name: String, old: Option<Value>, new: Value, line: usize },
+    // The run got to a breakpoint's line and its condition, if it has one, held.  A condition
+    // that couldn't be evaluated stops it too, with why in `error`
+    Breakpoint { line: usize, error: Option<String> },
+    // A runtime error got out of a statement of the script, and the interpreter is in the scope
+    // it happened in.  The run goes on however the pause ends, the statement has failed
+    Error { message: String, line: usize },
+    // The statement a step asked for, see Interpreter::step
+    Step { line: usize },
+}
+
+// How far Interpreter::step runs before pausing again
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    // the next statement, inside a call if that's where it is
+    In,
+    // the next statement that isn't inside a call made from here
+    Over,
+    // the next statement once the method running now has returned
+    Out,
+}
+
+impl Pause {
+    // the line of the script it stopped at
+    pub fn line(&self) -> usize {
+        match self {
+            Pause::Watch { line, .. } | Pause::Breakpoint { line, .. } | Pause::Error { line, .. } | Pause::Step { line } => *line,
+        }
+    }
+}
+
+// A breakpoint as it's written at the console or given to --break, `[file:]line [if condition]`.
+// Scripts can't load other files, so the file is only there to read like the usual file:line
+pub fn parse_breakpoint(spec: &str) -> Option<(usize, Option<String>)> {
+    let (location, condition) = match spec.split_once(" if ") {
+        Some((location, condition)) => (location, Some(condition.trim().to_string())),
+        None => (spec, None),
+    };
+    let line = location.trim().rsplit(':').next()?.parse().ok()?;
+    Some((line, condition.filter(|condition| !condition.is_empty())))
+}
+
+// What the interpreter calls back into when the run stops, see Interpreter::set_debugger.  The
+// run carries on once pause returns, and an error stops it there like a runtime error would.
+// While paused the interpreter is free to be looked at, eval_pure for one, or told what to watch
+pub trait Debugger {
+    fn pause(&mut self, interp: &mut Interpreter, pause: &Pause) -> Result<(), RuntimeError>;
+}
+
+// A debugger at a prompt, what --debug runs scripts under.  Says why it stopped along with the
+// line of the script responsible, then takes commands until told to carry on
+pub struct Console {
+    lines: Vec<String>,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+const HELP: &str = "\
+watch NAME     stop whenever NAME is written
+unwatch NAME   stop watching NAME
+break LINE     stop on reaching LINE, or only when it holds with break LINE if CONDITION
+delete LINE    remove the breakpoint on LINE
+step           run to the next statement, into a method if it calls one
+next           run to the next statement here, over any calls
+finish         run until the method running now returns
+print EXPR     evaluate EXPR without changing anything
+continue       carry on running (also c, or end of input)
+quit           stop the script here";
+
+impl Console {
+    pub fn new(source: &str, input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            lines: source.lines().map(str::to_string).collect(),
+            input: Box::new(input),
+            output: Box::new(output),
+        }
+    }
+
+    fn describe(&self, pause: &Pause) -> String {
+        match pause {
+            Pause::Watch { name, old: Some(old), new, line } => {
+                format!("{} changed from {} to {}\n{}", name, old, new, self.source_line(*line))
+            }
+            Pause::Watch { name, old: None, new, line } => {
+                format!("{} declared as {}\n{}", name, new, self.source_line(*line))
+            }
+            Pause::Breakpoint { line, error: None } => format!("breakpoint\n{}", self.source_line(*line)),
+            Pause::Breakpoint { line, error: Some(error) } => {
+                format!("breakpoint, its condition failed: {}\n{}", error, self.source_line(*line))
+            }
+            Pause::Error { message, line } => format!("error: {}\n{}", message, self.source_line(*line)),
+            Pause::Step { line } => self.source_line(*line),
+        }
+    }
+
+    fn source_line(&self, line: usize) -> String {
+        match self.lines.get(line) {
+            Some(text) => format!("  line {}: {}", line, text.trim()),
+            None => format!("  line {}", line),
+        }
+    }
+
+    // one command's worth of reply, None to carry on running
+    fn command(&mut self, interp: &mut Interpreter, command: &str, line: usize) -> Result<Option<String>, RuntimeError> {
+        let (verb, rest) = command.split_once(' ').unwrap_or((command, ""));
+        let rest = rest.trim();
+        let reply = match verb {
+            "" | "c" | "continue" => return Ok(None),
+            "s" | "step" | "n" | "next" | "finish" => {
+                interp.step(match verb {
+                    "s" | "step" => Step::In,
+                    "n" | "next" => Step::Over,
+                    _ => Step::Out,
+                });
+                return Ok(None);
+            }
+            "q" | "quit" => {
+                return Err(RuntimeError { line, message: "Stopped by the debugger".to_string() });
+            }
+            "watch" if !rest.is_empty() => {
+                interp.watch(rest);
+                format!("watching {}", rest)
+            }
+            "unwatch" if !rest.is_empty() => {
+                if interp.unwatch(rest) {
+                    format!("no longer watching {}", rest)
+                } else {
+                    format!("{} wasn't being watched", rest)
+                }
+            }
+            "b" | "break" => match parse_breakpoint(rest) {
+                Some((line, condition)) => {
+                    let reply = match &condition {
+                        Some(condition) => format!("breakpoint on line {} if {}", line, condition),
+                        None => format!("breakpoint on line {}", line),
+                    };
+                    interp.set_breakpoint(line, condition.as_deref());
+                    reply
+                }
+                None => "Expected break [file:]line [if condition]".to_string(),
+            },
+            "delete" => match rest.parse() {
+                Ok(line) if interp.clear_breakpoint(line) => format!("deleted the breakpoint on line {}", line),
+                Ok(line) => format!("no breakpoint on line {}", line),
+                Err(_) => "Expected delete line".to_string(),
+            },
+            "p" | "print" if !rest.is_empty() => match interp.eval_pure(rest) {
+                Ok(value) => value.to_string(),
+                Err(err) => err.message,
+            },
+            "help" => HELP.to_string(),
+            _ => format!("Unknown command \"{}\", try help", command),
+        };
+
+        Ok(Some(reply))
+    }
+}
+
+impl Debugger for Console {
+    fn pause(&mut self, interp: &mut Interpreter, pause: &Pause) -> Result<(), RuntimeError> {
+        let line = pause.line();
+        // the console going away mid-session is no reason to fail the script
+        let _ = writeln!(self.output, "{}", self.describe(pause));
+        loop {
+            let _ = write!(self.output, "(debug) ");
+            let _ = self.output.flush();
+
+            let mut command = String::new();
+            match self.input.read_line(&mut command) {
+                Ok(0) | Err(_) => return Ok(()),
+                Ok(_) => {}
+            }
+            match self.command(interp, command.trim(), line)? {
+                Some(reply) => {
+                    let _ = writeln!(self.output, "{}", reply);
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    // every pause, and what `x` read as at the time
+    #[derive(Clone, Default)]
+    struct Recorder(Rc<RefCell<Vec<(Pause, Value)>>>);
+
+    impl Debugger for Recorder {
+        fn pause(&mut self, interp: &mut Interpreter, pause: &Pause) -> Result<(), RuntimeError> {
+            let x = interp.eval_pure("x")?;
+            self.0.borrow_mut().push((pause.clone(), x));
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn eval(interp: &mut Interpreter, source: &str) -> Result<(), RuntimeError> {
+        interp.eval(Parser::new(Scanner::new(source.to_owned()).collect()).parse()).map(|_| ())
+    }
+
+    #[test]
+    fn it_pauses_when_a_watched_variable_is_written() {
+        let recorder = Recorder::default();
+        let mut interp = Interpreter::new();
+        interp.set_debugger(recorder.clone());
+        interp.watch("x");
+
+        eval(&mut interp, "var x = 1;\nvar y = 2;\ny = 3;\nx = x + y;").unwrap();
+
+        assert_eq!(
+            *recorder.0.borrow(),
+            vec![
+                (Pause::Watch { name: "x".to_string(), old: None, new: Value::NUMBER(1.0), line: 0 }, Value::NUMBER(1.0)),
+                (Pause::Watch { name: "x".to_string(), old: Some(Value::NUMBER(1.0)), new: Value::NUMBER(4.0), line: 3 }, Value::NUMBER(4.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_pauses_when_a_method_parameter_is_watched() {
+        let recorder = Recorder::default();
+        let mut interp = Interpreter::new();
+        interp.set_debugger(recorder.clone());
+        interp.watch("x");
+
+        eval(&mut interp, "class A {\n  f(x) { x }\n}\nA().f(5);").unwrap();
+
+        assert_eq!(
+            *recorder.0.borrow(),
+            vec![(Pause::Watch { name: "x".to_string(), old: None, new: Value::NUMBER(5.0), line: 3 }, Value::NUMBER(5.0))]
+        );
+    }
+
+    #[test]
+    fn it_forgets_steps_a_run_left_over() {
+        let recorder = Recorder::default();
+        let mut interp = Interpreter::new();
+
+        // nothing to take the step without a debugger, so it's still pending when the run ends
+        interp.step(Step::In);
+        eval(&mut interp, "var x = 1;").unwrap();
+        interp.set_debugger(recorder.clone());
+        eval(&mut interp, "x = 2;").unwrap();
+
+        interp.step(Step::In);
+        interp.reset();
+        eval(&mut interp, "x = 3;").unwrap();
+
+        assert_eq!(*recorder.0.borrow(), vec![]);
+    }
+
+    #[test]
+    fn it_stops_at_breakpoints_whose_condition_holds() {
+        let recorder = Recorder::default();
+        let mut interp = Interpreter::new();
+        interp.set_debugger(recorder.clone());
+        interp.set_breakpoint(2, Some("x > 1"));
+        interp.set_breakpoint(4, Some("nope"));
+
+        let source = "var x = 0;\nwhile (x < 3) {\n  x = x + 1;\n}\nx;";
+        eval(&mut interp, source).unwrap();
+
+        let error = Some("Variable \"nope\" does not exist".to_string());
+        assert_eq!(
+            *recorder.0.borrow(),
+            vec![
+                // before the statement runs, so only the last time round
+                (Pause::Breakpoint { line: 2, error: None }, Value::NUMBER(2.0)),
+                (Pause::Breakpoint { line: 4, error }, Value::NUMBER(3.0)),
+            ]
+        );
+        assert_eq!(parse_breakpoint("loop.lox:12 if i > 100"), Some((12, Some("i > 100".to_string()))));
+        assert_eq!(parse_breakpoint("12"), Some((12, None)));
+        assert_eq!(parse_breakpoint("loop.lox"), None);
+    }
+
+    #[test]
+    fn it_looks_around_where_an_error_happened() {
+        let recorder = Recorder::default();
+        let mut interp = Interpreter::new();
+        interp.set_debugger(recorder.clone());
+
+        let source = "var x = 1;\n{\n  var x = 2;\n  x = x + nope;\n}\nx;";
+        let err = eval(&mut interp, source).unwrap_err();
+
+        // the inner x, whose block had been left by the time the error got out
+        let message = "Variable \"nope\" does not exist".to_string();
+        assert_eq!(err, RuntimeError { line: 3, message: message.clone() });
+        assert_eq!(*recorder.0.borrow(), vec![(Pause::Error { message, line: 3 }, Value::NUMBER(2.0))]);
+        assert_eq!(interp.eval_pure("x"), Ok(Value::NUMBER(1.0)));
+    }
+
+    // takes the same step every time it's paused, and notes the line
+    struct Stepper(Step, Rc<RefCell<Vec<usize>>>);
+
+    impl Debugger for Stepper {
+        fn pause(&mut self, interp: &mut Interpreter, pause: &Pause) -> Result<(), RuntimeError> {
+            self.1.borrow_mut().push(pause.line());
+            interp.step(self.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_steps_into_over_and_out_of_methods() {
+        let source = "class A {\n  f() {\n    var b = 1;\n    b = 2;\n  }\n}\nvar a = A();\na.f();\na = 3;";
+        let steps = |step: Step, first: usize| {
+            let lines = Rc::new(RefCell::new(vec![]));
+            let mut interp = Interpreter::new();
+            interp.set_debugger(Stepper(step, lines.clone()));
+            interp.set_breakpoint(first, None);
+            eval(&mut interp, source).unwrap();
+            lines.take()
+        };
+
+        assert_eq!(steps(Step::In, 6), vec![6, 7, 2, 3, 8]);
+        assert_eq!(steps(Step::Over, 6), vec![6, 7, 8]);
+        assert_eq!(steps(Step::Out, 2), vec![2, 8]);
+    }
+
+    #[test]
+    fn it_takes_commands_at_the_console() {
+        let output = Shared::default();
+        let source = "var x = 1;\nvar y = 2;\nx = 5;\ny = x;";
+        let commands = "print x + 1\nwatch y\nc\nunwatch y\nquit\n";
+        let mut interp = Interpreter::new();
+        interp.set_debugger(Console::new(source, io::Cursor::new(commands), output.clone()));
+        interp.watch("x");
+
+        let err = eval(&mut interp, source).unwrap_err();
+
+        assert_eq!(err, RuntimeError { line: 1, message: "Stopped by the debugger".to_string() });
+        assert_eq!(
+            String::from_utf8(output.0.borrow().clone()).unwrap(),
+            "x declared as 1\n  line 0: var x = 1;\n(debug) 2\n(debug) watching y\n(debug) \
+             y declared as 2\n  line 1: var y = 2;\n(debug) no longer watching y\n(debug) "
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use crate::parser::Value;
+use super::{Interpreter, NativeFunction, RuntimeError};
+
+// Base64 and hex, both ways.  There are no arrays, so bytes are a string: encoding takes the
+// string's UTF-8 bytes, and decoding has to come back to valid UTF-8 or it's an error rather
+// than a string that isn't one.  Binary data that isn't text stays encoded
+pub(super) fn natives() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction { name: "base64Encode", func: base64_encode, must_use: true },
+        NativeFunction { name: "base64Decode", func: base64_decode, must_use: true },
+        NativeFunction { name: "hexEncode", func: hex_encode, must_use: true },
+        NativeFunction { name: "hexDecode", func: hex_decode, must_use: true },
+    ]
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let bytes = string(args, "base64Encode")?.as_bytes();
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            // a short last chunk pads out with =
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    interp.report.allocations += 1;
+    Ok(Value::STRING(out.into()))
+}
+
+// The standard alphabet, and the padding can be left off
+fn base64_decode(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let text = string(args, "base64Decode")?.trim_end_matches('=');
+
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes() {
+        let digit = BASE64.iter().position(|&b| b == c).ok_or_else(|| invalid("base64"))?;
+        bits = bits << 6 | digit as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    // a single character left over doesn't make a byte
+    if text.len() % 4 == 1 {
+        return Err(invalid("base64"));
+    }
+
+    interp.report.allocations += 1;
+    text_of(bytes)
+}
+
+fn hex_encode(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let bytes = string(args, "hexEncode")?.as_bytes();
+    interp.report.allocations += 1;
+    Ok(Value::STRING(bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>().into()))
+}
+
+// either case
+fn hex_decode(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let text = string(args, "hexDecode")?;
+    if text.len() % 2 != 0 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid("hex"));
+    }
+
+    let bytes = (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| invalid("hex")))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    interp.report.allocations += 1;
+    text_of(bytes)
+}
+
+fn string<'a>(args: &'a [Value], native: &str) -> Result<&'a str, RuntimeError> {
+    match args {
+        [Value::STRING(s)] => Ok(s),
+        _ => Err(RuntimeError {
+            line: 0,
+            message: format!("{}() takes a string", native),
+        }),
+    }
+}
+
+fn text_of(bytes: Vec<u8>) -> Result<Value, RuntimeError> {
+    String::from_utf8(bytes).map(|s| Value::STRING(s.into())).map_err(|_| RuntimeError {
+        line: 0,
+        message: "Decoded bytes aren't UTF-8 text, and a string can only hold text".to_string(),
+    })
+}
+
+fn invalid(encoding: &str) -> RuntimeError {
+    RuntimeError {
+        line: 0,
+        message: format!("Not valid {}", encoding),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    #[test]
+    fn it_encodes_and_decodes() {
+        let mut interp = Interpreter::new();
+        let mut eval = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).collect();
+            interp.eval(Parser::new(tokens).parse()).map(|outcome| outcome.value.unwrap())
+        };
+        let string = |s: &str| Ok(Value::STRING(s.into()));
+
+        assert_eq!(eval("base64Encode(\"\")"), string(""));
+        assert_eq!(eval("base64Encode(\"f\")"), string("Zg=="));
+        assert_eq!(eval("base64Encode(\"fo\")"), string("Zm8="));
+        assert_eq!(eval("base64Encode(\"foobar\")"), string("Zm9vYmFy"));
+        assert_eq!(eval("base64Decode(\"Zm9vYg==\")"), string("foob"));
+        assert_eq!(eval("base64Decode(\"Zm9vYg\")"), string("foob"));
+        assert_eq!(eval("base64Decode(base64Encode(\"héllo\"))"), string("héllo"));
+
+        assert_eq!(eval("hexEncode(\"hi!\")"), string("686921"));
+        assert_eq!(eval("hexDecode(\"68692A\")"), string("hi*"));
+
+        assert_eq!(eval("base64Decode(\"Zm9v!\")"), Err(RuntimeError { line: 0, message: "Not valid base64".to_string() }));
+        assert_eq!(eval("base64Decode(\"Zm9vY\")"), Err(RuntimeError { line: 0, message: "Not valid base64".to_string() }));
+        assert_eq!(eval("hexDecode(\"686\")"), Err(RuntimeError { line: 0, message: "Not valid hex".to_string() }));
+        assert_eq!(
+            eval("hexDecode(\"ff\")"),
+            Err(RuntimeError { line: 0, message: "Decoded bytes aren't UTF-8 text, and a string can only hold text".to_string() })
+        );
+        assert_eq!(eval("hexEncode(1)"), Err(RuntimeError { line: 0, message: "hexEncode() takes a string".to_string() }));
+    }
+}
@@ -1,14 +1,73 @@
 use std::cell::RefCell;
-use std::collections;
 use std::rc::Rc;
 use std::collections::HashMap;
 use crate::parser::Value;
+use super::hamt;
 use super::RuntimeError;
 
+// Where one scope keeps its variables.  Persistent maps make `Environment::snapshot` cheap at
+// the price of slower lookups, so they're opt in, see InterpreterConfig::persistent_environment
+#[derive(Clone, Debug, PartialEq)]
+enum Variables {
+    Hashed(HashMap<String, Value>),
+    Persistent(hamt::Map<Value>),
+}
+
+impl Variables {
+    // same kind as `self`, but empty
+    fn empty_like(&self) -> Self {
+        match self {
+            Self::Hashed(_) => Self::Hashed(HashMap::new()),
+            Self::Persistent(_) => Self::Persistent(hamt::Map::new()),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Self::Hashed(map) => map.get(name),
+            Self::Persistent(map) => map.get(name),
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        match self {
+            Self::Hashed(map) => map.get_mut(name),
+            Self::Persistent(map) => map.get_mut(name),
+        }
+    }
+
+    fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // what `name` held before, if it was already here
+    fn insert(&mut self, name: String, value: Value) -> Option<Value> {
+        match self {
+            Self::Hashed(map) => map.insert(name, value),
+            Self::Persistent(map) => match map.get_mut(&name) {
+                Some(slot) => Some(std::mem::replace(slot, value)),
+                None => {
+                    map.insert(name, value);
+                    None
+                }
+            },
+        }
+    }
+
+    fn to_map(&self) -> HashMap<String, Value> {
+        match self {
+            Self::Hashed(map) => map.clone(),
+            Self::Persistent(map) => map.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        }
+    }
+}
+
+// Fields stay private so how scopes are stored can change without breaking anyone poking at
+// them, use depth, iter_frames and flatten to look around instead
 #[derive(Clone, Debug, PartialEq)]
 pub struct Environment {
-    pub variables: collections::HashMap<String, Value>,
-    pub enclosing: Option<Rc<RefCell<Environment>>>, // pattern especially useful when a function will cannot borrow a field as mutable. Once something already has a reference, you can't then borrow as mutable
+    variables: Variables,
+    enclosing: Option<Rc<RefCell<Environment>>>, // pattern especially useful when a function will cannot borrow a field as mutable. Once something already has a reference, you can't then borrow as mutable
     // place to mutate and read from enclosing.  But b/c cloned, the original Environment does not
     // inherit values after mutation
 }
@@ -16,70 +75,190 @@ pub struct Environment {
 impl Environment {
     pub fn new() -> Self {
         Self {
-            variables: HashMap::new(),
+            variables: Variables::Hashed(HashMap::new()),
+            enclosing: None,
+        }
+    }
+
+    // Globals whose scopes (and every scope nested in them) can be snapshotted cheaply
+    pub fn persistent() -> Self {
+        Self {
+            variables: Variables::Persistent(hamt::Map::new()),
             enclosing: None,
         }
     }
 
     pub fn new_with_scope(env: &Rc<RefCell<Environment>>) -> Self {
-        // create a new inner scope
+        // create a new inner scope, stored the same way as the one it's in
         Self {
-            variables: HashMap::new(), // empty b/c retrieve will look up enclosing chain for variables if need be
+            variables: env.borrow().variables.empty_like(), // empty b/c retrieve will look up enclosing chain for variables if need be
             enclosing: Some(env.clone()),
         }
     }
 
-    pub fn define(&mut self, name: String, value: Value) {
-        self.variables.insert(name, value);
+    // A copy of the whole chain that can be changed without touching this one.  For persistent
+    // environments that's a pointer copy per scope, the copies share everything until written to
+    pub fn snapshot(&self) -> Environment {
+        Self {
+            variables: self.variables.clone(),
+            enclosing: self.enclosing.as_ref().map(|env| Rc::new(RefCell::new(env.borrow().snapshot()))),
+        }
     }
 
-    pub fn assign(&mut self, name: String, value: Value) -> Result<(), RuntimeError> {
-        if !self.variables.contains_key(&name) {
-            // if inner most scope self.variables does not contain variable, check outer for variable
-            if let Some(ref encl) = self.enclosing {
-                // Rc<RefCell> - pointer with shared ownership with interior mutability
-                // need a ref b/c enclosing value does not implement the Copy trait
-                return encl.borrow_mut().assign(name.clone(), value.clone());
-            } else {
-                // if can never find, then error
-                // for key in self.variables.keys() {
-                //     eprintln!("{:?}", key);
-                // }
-                return Err(RuntimeError {
-                    line: 0,
-                    message: format!("Variable \"{}\" does not exist", name),
-                });
+    // How many scopes enclose this one, 0 for the globals
+    pub fn depth(&self) -> usize {
+        match self.enclosing {
+            Some(ref enclosing) => enclosing.borrow().depth() + 1,
+            None => 0,
+        }
+    }
+
+    // Every scope's variables, innermost first.  Copies, so holding on to them doesn't hold a
+    // borrow of the environment
+    pub fn iter_frames(&self) -> impl Iterator<Item = HashMap<String, Value>> {
+        let mut frames = vec![self.variables.to_map()];
+        let mut next = self.enclosing.clone();
+        while let Some(env) = next {
+            frames.push(env.borrow().variables.to_map());
+            next = env.borrow().enclosing.clone();
+        }
+
+        frames.into_iter()
+    }
+
+    // Everything visible from here, inner scopes shadowing outer ones
+    pub fn flatten(&self) -> HashMap<String, Value> {
+        let mut visible = HashMap::new();
+        for frame in self.iter_frames() {
+            for (name, value) in frame {
+                visible.entry(name).or_insert(value);
             }
         }
 
-        self.variables.insert(name, value);
+        visible
+    }
 
-        Ok(())
+    pub(super) fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.enclosing.as_ref()
     }
 
-    pub fn retrieve(&self, name: &str) -> Result<Value, RuntimeError> {
-        let val = self.variables.get(name);
-        if val.is_some() {
-            Ok(val.unwrap().clone())
+    // Hands back what a redeclaration in the same scope replaced, for watchpoints
+    pub fn define(&mut self, name: String, value: Value) -> Option<Value> {
+        self.variables.insert(name, value)
+    }
+
+    // Overwrites in place, so a name is only allocated when it is first defined.  Hands back the
+    // value it overwrote, for watchpoints
+    pub fn assign(&mut self, name: &str, value: Value) -> Result<Value, RuntimeError> {
+        if let Some(slot) = self.variables.get_mut(name) {
+            return Ok(std::mem::replace(slot, value));
+        }
+
+        // if inner most scope self.variables does not contain variable, check outer for variable
+        if let Some(ref encl) = self.enclosing {
+            // Rc<RefCell> - pointer with shared ownership with interior mutability
+            encl.borrow_mut().assign(name, value)
         } else {
-            // check enclosing scope recursively. Variables are lexically scoped so we need to do this
-            if let Some(ref enclosing) = self.enclosing {
-                let enc = enclosing.borrow();
-                let val = enc.retrieve(name);
-                match val {
-                    Ok(val) => Ok(val.clone()),
-                    Err(RuntimeError { line, message }) => Err(RuntimeError { line, message })
-                }
-            } else {
-                // if can never find, then error
-                // for key in self.variables.keys() {
-                //     eprintln!("{:?}", key);
-                // }
-                Err(RuntimeError {
-                    line: 0,
-                    message: format!("Variable \"{}\" does not exist", name),
-                })
-            }
+            // if can never find, then error
+            Err(RuntimeError {
+                line: 0,
+                message: format!("Variable \"{}\" does not exist", name),
+            })
+        }
+    }
+
+    // How many scopes out `name` lives, 0 being this one
+    pub fn depth_of(&self, name: &str) -> Option<usize> {
+        if self.variables.contains_key(name) {
+            return Some(0);
+        }
+
+        let enclosing = self.enclosing.as_ref()?;
+        enclosing.borrow().depth_of(name).map(|depth| depth + 1)
+    }
+
+    // Like with_value, but straight to the scope `depth_of` found instead of searching for it
+    pub fn with_value_at<R>(&self, depth: usize, name: &str, f: impl FnOnce(&Value) -> R) -> Option<R> {
+        if depth == 0 {
+            return self.variables.get(name).map(f);
+        }
+
+        self.enclosing.as_ref()?.borrow().with_value_at(depth - 1, name, f)
+    }
+
+    pub fn retrieve(&self, name: &str) -> Result<Value, RuntimeError> {
+        self.with_value(name, Value::clone)
+    }
+
+    // Look at a variable without copying it out.  The enclosing chain is walked by reference, so
+    // the only clone is whatever `f` decides to make
+    pub fn with_value<R>(&self, name: &str, f: impl FnOnce(&Value) -> R) -> Result<R, RuntimeError> {
+        if let Some(val) = self.variables.get(name) {
+            return Ok(f(val));
+        }
+
+        // check enclosing scope recursively. Variables are lexically scoped so we need to do this
+        match self.enclosing {
+            Some(ref enclosing) => enclosing.borrow().with_value(name, f),
+            // if can never find, then error
+            None => Err(RuntimeError {
+                line: 0,
+                message: format!("Variable \"{}\" does not exist", name),
+            }),
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_walks_frames_innermost_first() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().define("a".to_string(), Value::NUMBER(1.0));
+        globals.borrow_mut().define("b".to_string(), Value::NUMBER(2.0));
+        let mut inner = Environment::new_with_scope(&globals);
+        inner.define("a".to_string(), Value::NUMBER(3.0));
+
+        assert_eq!(globals.borrow().depth(), 0);
+        assert_eq!(inner.depth(), 1);
+
+        let frames: Vec<_> = inner.iter_frames().collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].get("a"), Some(&Value::NUMBER(3.0)));
+        assert_eq!(frames[1].get("a"), Some(&Value::NUMBER(1.0)));
+
+        let visible = inner.flatten();
+        assert_eq!(visible.len(), 2);
+        assert_eq!(visible.get("a"), Some(&Value::NUMBER(3.0)));
+        assert_eq!(visible.get("b"), Some(&Value::NUMBER(2.0)));
+    }
+
+    #[test]
+    fn it_snapshots_without_sharing_writes() {
+        for globals in [Environment::new(), Environment::persistent()] {
+            let globals = Rc::new(RefCell::new(globals));
+            globals.borrow_mut().define("a".to_string(), Value::NUMBER(1.0));
+            let mut inner = Environment::new_with_scope(&globals);
+            inner.define("b".to_string(), Value::NUMBER(2.0));
+
+            let mut copy = inner.snapshot();
+            copy.assign("a", Value::NUMBER(10.0)).unwrap();
+            copy.assign("b", Value::NUMBER(20.0)).unwrap();
+            copy.define("c".to_string(), Value::NUMBER(30.0));
+
+            assert_eq!(inner.retrieve("a"), Ok(Value::NUMBER(1.0)));
+            assert_eq!(inner.retrieve("b"), Ok(Value::NUMBER(2.0)));
+            assert!(inner.retrieve("c").is_err());
+            assert_eq!(copy.retrieve("a"), Ok(Value::NUMBER(10.0)));
+            assert_eq!(copy.depth(), 1);
         }
     }
 }
@@ -0,0 +1,257 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+// A persistent hash map (hash array mapped trie) from names to V.  Cloning one is a pointer copy,
+// and the two copies share every node until one of them writes, at which point only the path
+// down to the changed entry is copied.  That makes snapshots of an environment O(1) instead of a
+// copy of every variable.
+//
+// Each level of the trie eats 5 bits of the key's hash to pick one of up to 32 children.  The
+// bitmap says which of the 32 are present, so a branch only stores the ones that are.
+#[derive(Clone)]
+pub struct Map<V> {
+    root: Rc<Node<V>>,
+    len: usize,
+}
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+#[derive(Clone)]
+enum Node<V> {
+    Branch { bitmap: u32, children: Vec<Entry<V>> },
+    // keys whose whole hash is the same, once there are no bits left to tell them apart
+    Collision(Vec<(String, V)>),
+}
+
+#[derive(Clone)]
+enum Entry<V> {
+    Leaf(u64, String, V),
+    Node(Rc<Node<V>>),
+}
+
+fn hash(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<V: Clone> Map<V> {
+    pub fn new() -> Self {
+        Self {
+            root: Rc::new(Node::empty()),
+            len: 0,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.root.get(hash(key), 0, key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    // Only copies nodes shared with another map, and only if the key is there to be changed
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        Rc::make_mut(&mut self.root).get_mut(hash(key), 0, key)
+    }
+
+    pub fn insert(&mut self, key: String, value: V) {
+        if Rc::make_mut(&mut self.root).insert(hash(&key), 0, key, value) {
+            self.len += 1;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        let mut entries = Vec::with_capacity(self.len);
+        self.root.collect(&mut entries);
+        entries.into_iter()
+    }
+}
+
+impl<V: Clone> Node<V> {
+    fn empty() -> Self {
+        Node::Branch { bitmap: 0, children: vec![] }
+    }
+
+    // which of the 32 children `hash` goes to at this level, and where that child sits in
+    // `children` if it's there
+    fn slot(bitmap: u32, hash: u64, shift: u32) -> (u32, usize) {
+        let bit = 1 << ((hash >> shift) & MASK);
+        (bit, (bitmap & (bit - 1)).count_ones() as usize)
+    }
+
+    fn get(&self, hash: u64, shift: u32, key: &str) -> Option<&V> {
+        match self {
+            Node::Collision(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            Node::Branch { bitmap, children } => {
+                let (bit, index) = Self::slot(*bitmap, hash, shift);
+                if bitmap & bit == 0 {
+                    return None;
+                }
+
+                match &children[index] {
+                    Entry::Leaf(_, k, v) => if k == key { Some(v) } else { None },
+                    Entry::Node(child) => child.get(hash, shift + BITS, key),
+                }
+            }
+        }
+    }
+
+    fn get_mut(&mut self, hash: u64, shift: u32, key: &str) -> Option<&mut V> {
+        match self {
+            Node::Collision(entries) => entries.iter_mut().find(|(k, _)| k == key).map(|(_, v)| v),
+            Node::Branch { bitmap, children } => {
+                let (bit, index) = Self::slot(*bitmap, hash, shift);
+                if *bitmap & bit == 0 {
+                    return None;
+                }
+
+                match &mut children[index] {
+                    Entry::Leaf(_, k, v) => if k == key { Some(v) } else { None },
+                    Entry::Node(child) => Rc::make_mut(child).get_mut(hash, shift + BITS, key),
+                }
+            }
+        }
+    }
+
+    // true when `key` is new rather than replaced
+    fn insert(&mut self, hash: u64, shift: u32, key: String, value: V) -> bool {
+        match self {
+            Node::Collision(entries) => {
+                if let Some(entry) = entries.iter_mut().find(|(k, _)| *k == key) {
+                    entry.1 = value;
+                    return false;
+                }
+
+                entries.push((key, value));
+                true
+            }
+            Node::Branch { bitmap, children } => {
+                let (bit, index) = Self::slot(*bitmap, hash, shift);
+                if *bitmap & bit == 0 {
+                    children.insert(index, Entry::Leaf(hash, key, value));
+                    *bitmap |= bit;
+                    return true;
+                }
+
+                match &mut children[index] {
+                    Entry::Node(child) => return Rc::make_mut(child).insert(hash, shift + BITS, key, value),
+                    Entry::Leaf(_, k, v) if *k == key => {
+                        *v = value;
+                        return false;
+                    }
+                    Entry::Leaf(..) => {}
+                }
+
+                // two keys want the same slot, push the one already here down a level with the
+                // new one beside it
+                let (other_hash, other_key, other_value) = match children.remove(index) {
+                    Entry::Leaf(h, k, v) => (h, k, v),
+                    Entry::Node(_) => unreachable!(),
+                };
+
+                let next = shift + BITS;
+                let node = if next >= u64::BITS {
+                    Node::Collision(vec![(other_key, other_value), (key, value)])
+                } else {
+                    let mut node = Node::empty();
+                    node.insert(other_hash, next, other_key, other_value);
+                    node.insert(hash, next, key, value);
+                    node
+                };
+                children.insert(index, Entry::Node(Rc::new(node)));
+                true
+            }
+        }
+    }
+
+    fn collect<'a>(&'a self, out: &mut Vec<(&'a String, &'a V)>) {
+        match self {
+            Node::Collision(entries) => out.extend(entries.iter().map(|(k, v)| (k, v))),
+            Node::Branch { children, .. } => {
+                for child in children {
+                    match child {
+                        Entry::Leaf(_, k, v) => out.push((k, v)),
+                        Entry::Node(node) => node.collect(out),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<V: Clone> Default for Map<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Clone + PartialEq> PartialEq for Map<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<V: Clone + fmt::Debug> fmt::Debug for Map<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_inserts_and_gets() {
+        let mut map = Map::new();
+        for i in 0..1000 {
+            map.insert(format!("v{}", i), i);
+        }
+        map.insert("v10".to_string(), -10);
+
+        assert_eq!(map.len, 1000);
+        assert_eq!(map.get("v10"), Some(&-10));
+        assert_eq!(map.get("v999"), Some(&999));
+        assert_eq!(map.get("v1000"), None);
+        assert_eq!(map.iter().count(), 1000);
+    }
+
+    #[test]
+    fn it_leaves_clones_alone() {
+        let mut map = Map::new();
+        for i in 0..100 {
+            map.insert(format!("v{}", i), i);
+        }
+
+        let snapshot = map.clone();
+        map.insert("new".to_string(), 1);
+        *map.get_mut("v5").unwrap() = 50;
+
+        assert_eq!(snapshot.len, 100);
+        assert_eq!(snapshot.get("new"), None);
+        assert_eq!(snapshot.get("v5"), Some(&5));
+        assert_eq!(map.get("v5"), Some(&50));
+        assert!(map != snapshot);
+    }
+
+    #[test]
+    fn it_keeps_keys_with_the_same_hash_apart() {
+        let mut node = Node::empty();
+        assert!(node.insert(42, 0, "a".to_string(), 1));
+        assert!(node.insert(42, 0, "b".to_string(), 2));
+        assert!(!node.insert(42, 0, "a".to_string(), 3));
+
+        assert_eq!(node.get(42, 0, "a"), Some(&3));
+        assert_eq!(node.get(42, 0, "b"), Some(&2));
+        assert_eq!(node.get(42, 0, "c"), None);
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+use std::io::{self, BufRead};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::parser::Value;
+use crate::values;
+use super::{Capabilities, Interpreter, Parent, RuntimeError};
+
+// A function implemented in Rust and callable from a script.  Natives are looked up after every
+// user scope, so a script is free to shadow them with its own variables.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub func: fn(&mut Interpreter, &[Value]) -> Result<Value, RuntimeError>,
+    // calling it is pointless unless the result is used, see resolver::resolve
+    pub must_use: bool,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+// two natives are the same function if they were registered under the same name
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+// Natives a script reaches through one name, `fs.readFile(path)`, rather than each taking up a
+// global of its own.  The name is whatever the module was registered under, see
+// Interpreter::register_module
+#[derive(Clone, Debug, Default)]
+pub struct NativeModule {
+    name: String,
+    functions: Vec<NativeFunction>,
+}
+
+impl NativeModule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add a function, or replace the one with the same name
+    pub fn function(mut self, native: NativeFunction) -> Self {
+        self.functions.retain(|existing| existing.name != native.name);
+        self.functions.push(native);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NativeFunction> {
+        self.functions.iter().find(|native| native.name == name)
+    }
+
+    pub(super) fn named(self, name: &str) -> Self {
+        Self { name: name.to_string(), ..self }
+    }
+}
+
+// like natives, modules are the same if they were registered under the same name
+impl PartialEq for NativeModule {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+pub(super) fn builtins() -> Vec<NativeFunction> {
+    let natives = vec![
+        NativeFunction { name: "clock", func: clock, must_use: true },
+        NativeFunction { name: "random", func: random, must_use: true },
+        // throwing away a line of input is a reasonable thing to do
+        NativeFunction { name: "readLine", func: read_line, must_use: false },
+        NativeFunction { name: "write", func: write, must_use: false },
+        NativeFunction { name: "printLn", func: print_ln, must_use: false },
+        NativeFunction { name: "eprint", func: eprint, must_use: false },
+        NativeFunction { name: "exit", func: exit, must_use: false },
+        NativeFunction { name: "setTimeout", func: set_timeout, must_use: false },
+        NativeFunction { name: "setInterval", func: set_interval, must_use: false },
+        NativeFunction { name: "clearTimeout", func: clear_timer, must_use: false },
+        NativeFunction { name: "clearInterval", func: clear_timer, must_use: false },
+        NativeFunction { name: "__line__", func: line, must_use: true },
+        NativeFunction { name: "version", func: version, must_use: true },
+        NativeFunction { name: "hasFeature", func: has_feature, must_use: true },
+        NativeFunction { name: "isNan", func: is_nan, must_use: true },
+        NativeFunction { name: "isFinite", func: is_finite, must_use: true },
+        NativeFunction { name: "spawn", func: spawn, must_use: false },
+        NativeFunction { name: "send", func: send, must_use: false },
+        NativeFunction { name: "recv", func: recv, must_use: false },
+        NativeFunction { name: "join", func: join, must_use: false },
+    ];
+    let natives = [natives, super::encoding::natives()].concat();
+    #[cfg(feature = "crypto")]
+    let natives = [natives, super::crypto::natives()].concat();
+    natives
+}
+
+// seconds since the epoch
+fn clock(interp: &mut Interpreter, _args: &[Value]) -> Result<Value, RuntimeError> {
+    interp.require(Capabilities::TIME, "clock")?;
+    interp.nondeterministic(|_| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Value::NUMBER(now.as_secs_f64())
+    })
+}
+
+// uniformly distributed in [0, 1)
+fn random(interp: &mut Interpreter, _args: &[Value]) -> Result<Value, RuntimeError> {
+    interp.nondeterministic(|interp| {
+        // xorshift64*, plenty for scripts and no dependency
+        let mut x = interp.random_state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        interp.random_state = x;
+        let bits = x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        Value::NUMBER(bits as f64 / (1u64 << 53) as f64)
+    })
+}
+
+// the next line of stdin without its newline, nil at end of input
+fn read_line(interp: &mut Interpreter, _args: &[Value]) -> Result<Value, RuntimeError> {
+    interp.require(Capabilities::STDIN, "readLine")?;
+    interp.nondeterministic(|interp| {
+        let mut line = String::new();
+        let read = match &mut interp.input {
+            Some(input) => input.read_line(&mut line),
+            None => io::stdin().read_line(&mut line),
+        };
+        match read {
+            Ok(0) | Err(_) => Value::Null,
+            Ok(_) => {
+                interp.report.allocations += 1;
+                Value::STRING(line.trim_end_matches(&['\r', '\n'][..]).into())
+            }
+        }
+    })
+}
+
+// write(a, b) is print without the newline, so a script can put a line together a piece at a
+// time.  Strings go out as they are, without print's quotes, so write("=") grows a progress bar
+fn write(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let texts: Vec<String> = args
+        .iter()
+        .map(|value| match value {
+            Value::STRING(s) => s.to_string(),
+            value => values::pretty(value, values::DEFAULT_DEPTH, &interp.number_format),
+        })
+        .collect();
+    interp.emit(&texts.join(" "), false)?;
+    Ok(args.last().cloned().unwrap_or(Value::Null))
+}
+
+// printLn(a, b) is print, except printLn() still ends the line, e.g. the one write started
+fn print_ln(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let texts: Vec<String> = args.iter().map(|value| values::pretty(value, values::DEFAULT_DEPTH, &interp.number_format)).collect();
+    interp.emit(&texts.join(" "), true)?;
+    Ok(args.last().cloned().unwrap_or(Value::Null))
+}
+
+// eprint(a, b) is print for the error output, for warnings that shouldn't end up in whatever
+// reads a script's output
+fn eprint(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let texts: Vec<String> = args.iter().map(|value| values::pretty(value, values::DEFAULT_DEPTH, &interp.number_format)).collect();
+    interp.emit_error(&texts.join(" "))?;
+    Ok(args.last().cloned().unwrap_or(Value::Null))
+}
+
+// exit(code) stops the script there, with `code` for the CLI's exit status, 0 when it's left
+// out.  It unwinds like an error, so what's been deferred still runs on the way out
+fn exit(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let code = match args {
+        [] => 0,
+        [Value::NUMBER(n)] if n.fract() == 0.0 && *n >= i32::MIN as f64 && *n <= i32::MAX as f64 => *n as i32,
+        _ => {
+            return Err(RuntimeError {
+                line: 0,
+                message: "exit() takes a whole number status code".to_string(),
+            })
+        }
+    };
+    interp.exit_code = Some(code);
+    Err(RuntimeError {
+        line: 0,
+        message: format!("Exited with status {}", code),
+    })
+}
+
+// NaN == NaN is false, so this is the only way to spot one
+fn is_nan(_interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::BOOLEAN(number(args, "isNan")?.is_nan()))
+}
+
+// neither NaN nor +/-Infinity
+fn is_finite(_interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::BOOLEAN(number(args, "isFinite")?.is_finite()))
+}
+
+fn number(args: &[Value], native: &str) -> Result<f64, RuntimeError> {
+    match args {
+        [Value::NUMBER(n)] => Ok(*n),
+        _ => Err(RuntimeError {
+            line: 0,
+            message: format!("{}() takes a number", native),
+        }),
+    }
+}
+
+// the line __line__() is called on, counted the way error messages count them
+fn line(interp: &mut Interpreter, _args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::NUMBER(interp.call_line as f64))
+}
+
+// the interpreter's version, "0.1.0"
+fn version(_interp: &mut Interpreter, _args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::STRING(env!("CARGO_PKG_VERSION").into()))
+}
+
+// hasFeature("records"), so a script can make do without what an older interpreter is missing
+fn has_feature(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    match args {
+        [Value::STRING(name)] => Ok(Value::BOOLEAN(interp.features.contains(&**name))),
+        _ => Err(RuntimeError {
+            line: 0,
+            message: "hasFeature() takes the name of a feature".to_string(),
+        }),
+    }
+}
+
+// setTimeout(f, ms, args...) calls f(args...) once the program is done and ms have passed, see
+// Interpreter::run_until_idle.  The id it returns is for clearTimeout
+fn set_timeout(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    schedule(interp, args, "setTimeout", false)
+}
+
+// like setTimeout, but again every ms after that
+fn set_interval(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    schedule(interp, args, "setInterval", true)
+}
+
+fn schedule(interp: &mut Interpreter, args: &[Value], native: &str, repeat: bool) -> Result<Value, RuntimeError> {
+    match args {
+        [callee @ (Value::Native(_) | Value::RecordType(_) | Value::Class(_) | Value::Method(_)), Value::NUMBER(ms), rest @ ..] if *ms >= 0.0 && ms.is_finite() => {
+            let delay = Duration::from_secs_f64(ms / 1000.0);
+            let every = if repeat { Some(delay) } else { None };
+            let id = interp.timers.schedule(delay, every, callee.clone(), rest.to_vec());
+            Ok(Value::NUMBER(id as f64))
+        }
+        _ => Err(RuntimeError {
+            line: 0,
+            message: format!("{}() takes a function to call and a delay in milliseconds", native),
+        }),
+    }
+}
+
+// true if there was a timer with that id still waiting
+fn clear_timer(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let cancelled = match args {
+        [Value::NUMBER(id)] => interp.timers.cancel(*id as u64),
+        _ => false,
+    };
+    Ok(Value::BOOLEAN(cancelled))
+}
+
+// spawn(source) runs `source` on another thread, in an interpreter of its own, and returns the
+// worker's id.  The two only talk through send and recv
+fn spawn(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    match args {
+        [Value::STRING(source)] => {
+            let id = interp.workers.spawn(source.to_string(), interp.config.clone(), interp.builtins.clone(), interp.features.clone())?;
+            Ok(Value::NUMBER(id as f64))
+        }
+        _ => Err(RuntimeError {
+            line: 0,
+            message: "spawn() takes the source of the script to run".to_string(),
+        }),
+    }
+}
+
+// send(id, value) to a worker, or send(value) from a worker back to whoever spawned it.  False
+// when the other side has finished
+fn send(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    let sent = match args {
+        [Value::NUMBER(id), value] => interp.workers.send(*id as u64, value.clone())?,
+        [value] => parent(interp, "send")?.send(value.clone()),
+        _ => {
+            return Err(RuntimeError {
+                line: 0,
+                message: "send() takes a worker id and a value, or just the value in a worker".to_string(),
+            })
+        }
+    };
+    Ok(Value::BOOLEAN(sent))
+}
+
+// recv(id) from a worker, or recv() in a worker from whoever spawned it.  Waits for the next
+// value, and is nil once the other side has finished
+fn recv(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    match args {
+        [Value::NUMBER(id)] => interp.workers.recv(*id as u64),
+        [] => Ok(parent(interp, "recv")?.recv()),
+        _ => Err(RuntimeError {
+            line: 0,
+            message: "recv() takes a worker id, or nothing in a worker".to_string(),
+        }),
+    }
+}
+
+// join(id) waits for a worker to finish, for the value its script ended with
+fn join(interp: &mut Interpreter, args: &[Value]) -> Result<Value, RuntimeError> {
+    match args {
+        [Value::NUMBER(id)] => interp.workers.join(*id as u64),
+        _ => Err(RuntimeError {
+            line: 0,
+            message: "join() takes a worker id".to_string(),
+        }),
+    }
+}
+
+fn parent<'a>(interp: &'a Interpreter, native: &str) -> Result<&'a Parent, RuntimeError> {
+    interp.parent.as_ref().ok_or_else(|| RuntimeError {
+        line: 0,
+        message: format!("{}() needs a worker id outside a spawned script", native),
+    })
+}
+
+// Only a script with the random capability gets a different sequence on every run
+pub(super) fn seed(from_host: bool) -> u64 {
+    if !from_host {
+        return 0x9E37_79B9_7F4A_7C15;
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    // xorshift gets stuck on zero
+    (now.as_nanos() as u64) | 1
+}
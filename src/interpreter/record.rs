@@ -0,0 +1,69 @@
+use std::fmt;
+use std::sync::Arc;
+use crate::parser::Value;
+
+// What `record Point { x, y }` declares.  Calling it builds a Point out of one value per field,
+// in the order they're listed
+#[derive(Debug, PartialEq)]
+pub struct RecordType {
+    name: String,
+    fields: Vec<String>,
+}
+
+// A value built by calling a record type.  Fields can be read but not assigned, and two records
+// are equal when their types and every one of their fields are
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    kind: Arc<RecordType>,
+    values: Vec<Value>,
+}
+
+impl RecordType {
+    pub(super) fn new(name: &str, fields: &[String]) -> Self {
+        Self { name: name.to_string(), fields: fields.to_vec() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+}
+
+impl Record {
+    // one value per field of `kind`, checked by the caller
+    pub(super) fn new(kind: Arc<RecordType>, values: Vec<Value>) -> Self {
+        Self { kind, values }
+    }
+
+    pub fn kind(&self) -> &RecordType {
+        &self.kind
+    }
+
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        let index = self.kind.fields.iter().position(|name| name == field)?;
+        self.values.get(index)
+    }
+
+    // every field with its value, in declaration order
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.kind.fields.iter().map(String::as_str).zip(self.values.iter())
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {{", self.kind.name)?;
+        for (index, (name, value)) in self.fields().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            write!(f, "{}{}: {}", separator, name, value)?;
+        }
+        if self.values.is_empty() {
+            write!(f, "}}")
+        } else {
+            write!(f, " }}")
+        }
+    }
+}
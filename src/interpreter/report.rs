@@ -0,0 +1,55 @@
+use std::fmt;
+use std::time::Duration;
+use crate::parser::Value;
+
+// What the last call to `Interpreter::start` cost, so a host can bill or cut off scripts.
+// `steps` counts every statement executed and expression evaluated, `allocations` counts the
+// new strings built by concatenation or read from the host.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecutionReport {
+    pub steps: u64,
+    // just the statements out of `steps`
+    pub statements: u64,
+    // natives and record types alike
+    pub calls: u64,
+    pub max_call_depth: usize,
+    // how many scopes deep the environment got, 0 when nothing left the globals
+    pub max_scope_depth: usize,
+    pub allocations: u64,
+    // lines written by print, a value with newlines in it counts each of them
+    pub printed_lines: u64,
+    pub duration: Duration,
+}
+
+// What `Interpreter::eval` hands back for a program that ran to the end.  `value` is the
+// program's result: the value of its last statement if that's an expression, block or if, and
+// None when it ends in a declaration, print or loop.  Whatever print wrote is output, not the
+// result, and only shows up here as a count.  A program that called exit(code) stopped there,
+// it has `exit_code` and no value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgramOutcome {
+    pub value: Option<Value>,
+    pub printed_lines: u64,
+    pub duration: Duration,
+    pub exit_code: Option<i32>,
+}
+
+impl fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "steps: {}, max call depth: {}, allocations: {}, printed lines: {}, duration: {:?}",
+            self.steps, self.max_call_depth, self.allocations, self.printed_lines, self.duration
+        )
+    }
+}
+
+impl ExecutionReport {
+    // The counts a person reading after a run cares about, a line each, for the CLI's --summary
+    pub fn summary(&self) -> String {
+        format!(
+            "statements executed: {}\nfunctions called: {}\nallocations: {}\npeak environment depth: {}",
+            self.statements, self.calls, self.allocations, self.max_scope_depth
+        )
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::Instant;
+use crate::parser::{Stmt, Value};
+use super::{Interpreter, ProgramOutcome, RuntimeError, ScopeGuard};
+
+// Where a program run with Interpreter::eval_resumable has got to
+#[derive(Debug, PartialEq)]
+pub enum RunState {
+    // out of steps for now, Interpreter::resume carries on
+    Paused,
+    Finished(ProgramOutcome),
+}
+
+// what a paused program needs to carry on: its tree, the next statement to run and the value of
+// the last one
+pub(super) struct Paused {
+    stmts: Vec<Stmt>,
+    next: usize,
+    value: Value,
+    // where the script's deferred expressions start
+    deferred: usize,
+}
+
+impl Interpreter {
+    // Interpreter::eval a slice at a time.  The program runs until it has taken `budget` steps,
+    // then hands back RunState::Paused and leaves everything where it was for `resume`.  The
+    // interpreter counts as running until the program finishes, and reset gives up on it.
+    //
+    // A statement always runs to the end, so it only pauses between the statements at the top of
+    // the program, a long loop goes over its budget rather than stopping halfway
+    pub fn eval_resumable(&mut self, stmts: Vec<Stmt>, budget: u64) -> Result<RunState, RuntimeError> {
+        if self.running {
+            return Err(RuntimeError {
+                line: 0,
+                message: "Interpreter is already running".to_string(),
+            });
+        }
+        self.running = true;
+
+        self.report = Default::default();
+        self.next_yield = 0;
+        self.exit_code = None;
+        self.cancel.clear();
+        self.paused = Some(Paused { stmts, next: 0, value: Value::Null, deferred: self.deferred.len() });
+        self.resume(budget)
+    }
+
+    // Carry on with the paused program for another `budget` steps
+    pub fn resume(&mut self, budget: u64) -> Result<RunState, RuntimeError> {
+        let mut paused = self.paused.take().ok_or(RuntimeError {
+            line: 0,
+            message: "Nothing to resume, no program is paused".to_string(),
+        })?;
+        let started = Instant::now();
+        // at least a statement each time, or it would never get anywhere
+        let until = self.report.steps.saturating_add(budget.max(1));
+
+        let mut result = Ok(std::mem::replace(&mut paused.value, Value::Null));
+        let mut script = ScopeGuard::resume(self, paused.deferred);
+        while let (Some(stmt), Ok(_)) = (paused.stmts.get(paused.next), &result) {
+            if script.report.steps >= until {
+                script.suspend();
+                paused.value = result?;
+                self.report.duration += started.elapsed();
+                self.paused = Some(paused);
+                return Ok(RunState::Paused);
+            }
+
+            result = script.execute(stmt);
+            paused.next += 1;
+        }
+        let result = script.finish(result);
+
+        self.report.duration += started.elapsed();
+        self.running = false;
+        let value = self.exited(result)?;
+        Ok(RunState::Finished(self.outcome(&paused.stmts, value)))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        Parser::new(Scanner::new(source.to_owned()).collect()).parse()
+    }
+
+    #[test]
+    fn it_pauses_and_resumes_between_statements() {
+        let mut interp = Interpreter::new();
+        let stmts = parse("var a = 1; defer a = 0; a = a + 1; a = a * 10; a");
+
+        assert_eq!(interp.eval_resumable(stmts, 1), Ok(RunState::Paused));
+        assert!(interp.is_paused());
+        assert_eq!(interp.resolve("a"), Ok(Value::NUMBER(1.0)));
+        // nothing else gets to run in the meantime
+        assert_eq!(interp.eval(parse("1")), Err(RuntimeError { line: 0, message: "Interpreter is already running".to_string() }));
+
+        let mut pauses = 1;
+        let outcome = loop {
+            match interp.resume(1) {
+                Ok(RunState::Paused) => pauses += 1,
+                Ok(RunState::Finished(outcome)) => break outcome,
+                Err(err) => panic!("{}", err),
+            }
+        };
+        assert_eq!(outcome.value, Some(Value::NUMBER(20.0)));
+        assert_eq!(pauses, 4);
+        // the defer waited for the end of the program, not the end of a slice
+        assert_eq!(interp.resolve("a"), Ok(Value::NUMBER(0.0)));
+        assert!(!interp.is_paused());
+
+        assert_eq!(interp.resume(1), Err(RuntimeError { line: 0, message: "Nothing to resume, no program is paused".to_string() }));
+        let outcome = interp.eval_resumable(parse("var b = 2; b + a"), 1000);
+        assert_eq!(outcome.map(|state| matches!(state, RunState::Finished(ProgramOutcome { value: Some(Value::NUMBER(n)), .. }) if n == 2.0)), Ok(true));
+    }
+}
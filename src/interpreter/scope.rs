@@ -0,0 +1,114 @@
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use super::{Environment, Interpreter, InterpreterResult, RuntimeError};
+
+// The interpreter while it's inside something that has to be undone on the way out: a block's
+// environment, a call's depth, whatever was deferred in between.  All of it is put back on drop,
+// so every exit (an error through `?` included) leaves the interpreter the way it was found.  Use
+// it like the interpreter itself, it derefs to one.
+pub(super) struct ScopeGuard<'a> {
+    interp: &'a mut Interpreter,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    call: bool,
+    // how many deferred expressions belong to scopes further out
+    deferred: usize,
+}
+
+impl<'a> ScopeGuard<'a> {
+    // a new innermost environment for the block's declarations
+    pub(super) fn block(interp: &'a mut Interpreter) -> Self {
+        // unable to have mutable copy as we descend down the tree :(
+        let inner = Rc::new(RefCell::new(Environment::new_with_scope(&interp.environment)));
+        let enclosing = std::mem::replace(&mut interp.environment, inner);
+        let depth = interp.environment.borrow().depth();
+        interp.report.max_scope_depth = interp.report.max_scope_depth.max(depth);
+        let deferred = interp.deferred.len();
+        Self { interp, enclosing: Some(enclosing), call: false, deferred }
+    }
+
+    // `environment` in place of the current one until the scope is left, for a call whose body
+    // doesn't run in the scope it was called from
+    pub(super) fn frame(interp: &'a mut Interpreter, environment: Environment) -> Self {
+        let enclosing = std::mem::replace(&mut interp.environment, Rc::new(RefCell::new(environment)));
+        let deferred = interp.deferred.len();
+        Self { interp, enclosing: Some(enclosing), call: false, deferred }
+    }
+
+    // a block that declares nothing still owns what it defers, it just doesn't need an
+    // environment for it
+    pub(super) fn deferring(interp: &'a mut Interpreter) -> Self {
+        let deferred = interp.deferred.len();
+        Self { interp, enclosing: None, call: false, deferred }
+    }
+
+    // the script scope of a paused program again, owning what it had deferred since `deferred`
+    pub(super) fn resume(interp: &'a mut Interpreter, deferred: usize) -> Self {
+        Self { interp, enclosing: None, call: false, deferred }
+    }
+
+    // Leave the scope without leaving it, for a program that pauses: what it deferred stays put
+    // until it's resumed.  Only makes sense for a scope without an environment or a call to undo
+    pub(super) fn suspend(self) -> usize {
+        debug_assert!(self.enclosing.is_none() && !self.call);
+        let deferred = self.deferred;
+        std::mem::forget(self);
+        deferred
+    }
+
+    // one call deeper, for as long as the callee runs, unless that's deeper than the config allows
+    pub(super) fn call(interp: &'a mut Interpreter, line: usize) -> Result<Self, RuntimeError> {
+        if interp.config.max_call_depth.is_some_and(|max| interp.call_depth >= max) {
+            return Err(RuntimeError {
+                line,
+                message: "stack overflow".to_string(),
+            });
+        }
+        interp.call_depth += 1;
+        interp.report.calls += 1;
+        interp.report.max_call_depth = interp.report.max_call_depth.max(interp.call_depth);
+        let deferred = interp.deferred.len();
+        Ok(Self { interp, enclosing: None, call: true, deferred })
+    }
+
+    // Leave the scope the way it finished, running what it deferred last first while its
+    // variables are still around.  They all run whatever happens, but only the first error is
+    // kept, and one the scope itself ran into comes before any of theirs
+    pub(super) fn finish(self, mut result: InterpreterResult) -> InterpreterResult {
+        while self.interp.deferred.len() > self.deferred {
+            let expr = self.interp.deferred.pop().unwrap();
+            if let (Ok(_), Err(err)) = (&result, self.interp.evaluate(&expr)) {
+                result = Err(err);
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for ScopeGuard<'_> {
+    fn drop(&mut self) {
+        // only left over when the scope was never finished
+        self.interp.deferred.truncate(self.deferred);
+        if let Some(enclosing) = self.enclosing.take() {
+            self.interp.environment = enclosing;
+        }
+        if self.call {
+            self.interp.call_depth -= 1;
+        }
+    }
+}
+
+impl Deref for ScopeGuard<'_> {
+    type Target = Interpreter;
+
+    fn deref(&self) -> &Interpreter {
+        self.interp
+    }
+}
+
+impl DerefMut for ScopeGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Interpreter {
+        self.interp
+    }
+}
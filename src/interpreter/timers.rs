@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+use crate::parser::Value;
+
+// What setTimeout and setInterval leave for after the program, see Interpreter::run_until_idle
+#[derive(Default)]
+pub(super) struct Timers {
+    pending: Vec<Timer>,
+    // ids start at 1, so a script can use 0 for "no timer"
+    last_id: u64,
+}
+
+pub(super) struct Timer {
+    id: u64,
+    due: Instant,
+    // how often it comes back, None for a timeout that fires once
+    every: Option<Duration>,
+    pub(super) callee: Value,
+    pub(super) args: Vec<Value>,
+}
+
+impl Timers {
+    pub(super) fn schedule(&mut self, delay: Duration, every: Option<Duration>, callee: Value, args: Vec<Value>) -> u64 {
+        self.last_id += 1;
+        let due = Instant::now() + delay;
+        self.pending.push(Timer { id: self.last_id, due, every, callee, args });
+        self.last_id
+    }
+
+    // false when there was no such timer, or it already fired
+    pub(super) fn cancel(&mut self, id: u64) -> bool {
+        let before = self.pending.len();
+        self.pending.retain(|timer| timer.id != id);
+        self.pending.len() != before
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    // Wait for the timer due soonest and hand it over, the first one scheduled on a tie.  An
+    // interval is put straight back for its next time, so clearing it from its own callback works
+    pub(super) fn next(&mut self) -> Option<Timer> {
+        let index = (0..self.pending.len()).min_by_key(|&index| (self.pending[index].due, self.pending[index].id))?;
+        let timer = self.pending.remove(index);
+
+        let now = Instant::now();
+        if timer.due > now {
+            std::thread::sleep(timer.due - now);
+        }
+
+        if let Some(every) = timer.every {
+            // counted from when it was due rather than when it ran, so a slow callback doesn't
+            // make it drift
+            self.pending.push(Timer {
+                id: timer.id,
+                due: timer.due + every,
+                every: timer.every,
+                callee: timer.callee.clone(),
+                args: timer.args.clone(),
+            });
+        }
+
+        Some(timer)
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+use crate::parser::Value;
+
+// Everything a script observes that could differ between two runs (the clock, random numbers,
+// stdin) flows through the interpreter's Trace.  Recording keeps each value in order; replaying
+// hands the same values back so a flaky run can be reproduced exactly.
+#[derive(Debug, PartialEq)]
+pub enum Trace {
+    Off,
+    Record(Vec<Value>),
+    Replay(VecDeque<Value>),
+}
+
+// One value per line: N <number>, S <escaped string>, B <bool> or nil
+pub fn write_trace(values: &[Value]) -> String {
+    let mut st = String::new();
+    for value in values {
+        match value {
+            // {:?} round trips every f64 exactly
+            Value::NUMBER(n) => st.push_str(&format!("N {:?}", n)),
+            Value::STRING(s) => st.push_str(&format!("S {}", s.escape_default())),
+            Value::BOOLEAN(b) => st.push_str(&format!("B {}", b)),
+            _ => st.push_str("nil"),
+        }
+        st.push('\n');
+    }
+    st
+}
+
+pub fn read_trace(source: &str) -> Result<Vec<Value>, String> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let value = match line.split_at(line.len().min(2)) {
+                ("N ", n) => n.parse().ok().map(Value::NUMBER),
+                ("S ", s) => unescape(s).map(|s| Value::STRING(s.into())),
+                ("B ", b) => b.parse().ok().map(Value::BOOLEAN),
+                _ if line == "nil" => Some(Value::Null),
+                _ => None,
+            };
+            value.ok_or_else(|| format!("Invalid trace entry on line {}: {}", i + 1, line))
+        })
+        .collect()
+}
+
+// the inverse of str::escape_default
+fn unescape(st: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = st.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            'u' => {
+                let hex: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                out.push(std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_round_trips_values() {
+        let values = vec![
+            Value::NUMBER(0.1 + 0.2),
+            Value::NUMBER(-0.0),
+            Value::STRING("line one\n\t\"quoted\" é ☃".into()),
+            Value::STRING("".into()),
+            Value::BOOLEAN(true),
+            Value::Null,
+        ];
+        assert_eq!(read_trace(&write_trace(&values)), Ok(values));
+    }
+
+    #[test]
+    fn it_rejects_garbage() {
+        assert_eq!(read_trace("N 1\nX 2"), Err("Invalid trace entry on line 2: X 2".to_string()));
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use crate::parser::{Parser, Value};
+use super::{Interpreter, InterpreterConfig, RuntimeError};
+
+// A script running on a thread of its own, started by spawn(source).  It shares nothing with
+// the one that started it, values only go back and forth through send and recv
+struct Worker {
+    to: Sender<Value>,
+    from: Receiver<Value>,
+    thread: JoinHandle<Result<Option<Value>, RuntimeError>>,
+}
+
+// the workers a script has spawned and not joined yet, by id
+#[derive(Default)]
+pub(super) struct Workers {
+    running: HashMap<u64, Worker>,
+    last_id: u64,
+}
+
+// a worker's end of the channels to whoever spawned it
+pub(super) struct Parent {
+    to: Sender<Value>,
+    from: Receiver<Value>,
+}
+
+impl Workers {
+    // Start `source` in a fresh interpreter with the same config, natives and features as the
+    // spawning one.  It prints to stdout and stderr whatever the spawning one prints to, a writer
+    // can't be shared.  Its thread has the stack the config says scripts get
+    pub(super) fn spawn(&mut self, source: String, config: InterpreterConfig, builtins: HashMap<String, Value>, features: HashSet<String>) -> Result<u64, RuntimeError> {
+        let (to_worker, from_parent) = mpsc::channel();
+        let (to_parent, from_worker) = mpsc::channel();
+
+        let stack_size = config.stack_size;
+        let thread = thread::Builder::new().stack_size(stack_size).spawn(move || {
+            let mut interp = Interpreter::with_config(config);
+            interp.builtins = builtins;
+            interp.features = features;
+            // its source didn't come from the spawner's file
+            interp.builtins.insert("__file__".to_string(), Value::Null);
+            interp.parent = Some(Parent { to: to_parent, from: from_parent });
+
+            let mut parser = Parser::scanned(interp.scanner(source).scan()).with_stack_size(stack_size);
+            if let Some(error) = parser.scan_errors().first() {
+                return Err(RuntimeError { line: error.line, message: error.message.clone() });
+            }
+            interp.eval(parser.parse()).map(|outcome| outcome.value)
+        });
+        let thread = thread.map_err(|err| RuntimeError {
+            line: 0,
+            message: format!("Couldn't start a worker: {}", err),
+        })?;
+
+        self.last_id += 1;
+        self.running.insert(self.last_id, Worker { to: to_worker, from: from_worker, thread });
+        Ok(self.last_id)
+    }
+
+    // false once the worker has finished and won't read it
+    pub(super) fn send(&self, id: u64, value: Value) -> Result<bool, RuntimeError> {
+        Ok(self.get(id)?.to.send(value).is_ok())
+    }
+
+    // Wait for the worker's next value, nil once it has finished without sending one
+    pub(super) fn recv(&self, id: u64) -> Result<Value, RuntimeError> {
+        Ok(self.get(id)?.from.recv().unwrap_or(Value::Null))
+    }
+
+    // Wait for the worker to finish, for the value its script ended with.  Its error is the
+    // joiner's error
+    pub(super) fn join(&mut self, id: u64) -> Result<Value, RuntimeError> {
+        let worker = self.running.remove(&id).ok_or_else(|| unknown(id))?;
+        // a worker waiting on recv gets nil rather than waiting forever
+        drop(worker.to);
+
+        match worker.thread.join() {
+            Ok(result) => result.map(|value| value.unwrap_or(Value::Null)),
+            Err(_) => Err(RuntimeError {
+                line: 0,
+                message: format!("Worker {} crashed", id),
+            }),
+        }
+    }
+
+    fn get(&self, id: u64) -> Result<&Worker, RuntimeError> {
+        self.running.get(&id).ok_or_else(|| unknown(id))
+    }
+}
+
+impl Parent {
+    pub(super) fn send(&self, value: Value) -> bool {
+        self.to.send(value).is_ok()
+    }
+
+    // nil once the spawning script has joined or dropped this worker
+    pub(super) fn recv(&self) -> Value {
+        self.from.recv().unwrap_or(Value::Null)
+    }
+}
+
+fn unknown(id: u64) -> RuntimeError {
+    RuntimeError {
+        line: 0,
+        message: format!("No worker {}, it was never spawned or has been joined", id),
+    }
+}
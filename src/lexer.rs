@@ -1,7 +1,14 @@
-extern crate regex;
-use regex::Regex;
-use std::fmt;
+use alloc::borrow::ToOwned;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use crate::parser::OPERATORS;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum LexemeKind {
     // Single-character tokens.
@@ -27,6 +34,9 @@ pub enum LexemeKind {
     GreaterEqual,
     Less,
     LessEqual,
+    QuestionDot,
+    QuestionQuestion,
+    Pipe,
 
     // Literals.
     IDENTIFIER(String),
@@ -35,21 +45,26 @@ pub enum LexemeKind {
 
     // Keywords.
     AND,
+    AWAIT,
     CLASS,
+    DEFER,
     ELSE,
     FALSE,
     FUN,
     FOR,
     IF,
+    IS,
     NIL,
     OR,
     PRINT,
+    RECORD,
     RETURN,
     SUPER,
     THIS,
     TRUE,
     VAR,
     WHILE,
+    WITH,
 
     UNEXPECTED(String),
 
@@ -57,13 +72,13 @@ pub enum LexemeKind {
 }
 
 impl LexemeKind {
-    pub fn to_string(&self) -> String {
+    pub fn source_text(&self) -> String {
         match self {
             Self::LeftParen => "(".to_owned(),
             Self::RightParen => ")".to_owned(),
             Self::LeftBrace => "{".to_owned(),
             Self::RightBrace => "}".to_owned(),
-            Self::Comma => ".".to_owned(),
+            Self::Comma => ",".to_owned(),
             Self::Dot => ".".to_owned(),
             Self::Minus => "-".to_owned(),
             Self::Plus => "+".to_owned(),
@@ -71,33 +86,41 @@ impl LexemeKind {
             Self::Slash => "/".to_owned(),
             Self::Star => "*".to_owned(),
             Self::Bang => "!".to_owned(),
-            Self::BangEqual => "!!".to_owned(),
+            Self::BangEqual => "!=".to_owned(),
             Self::Equal => "=".to_owned(),
             Self::EqualEqual => "==".to_owned(),
             Self::Greater => ">".to_owned(),
             Self::GreaterEqual => ">=".to_owned(),
             Self::Less => "<".to_owned(),
             Self::LessEqual => "<=".to_owned(),
+            Self::QuestionDot => "?.".to_owned(),
+            Self::QuestionQuestion => "??".to_owned(),
+            Self::Pipe => "|>".to_owned(),
             Self::Whitespace => " ".to_owned(),
-            Self::IDENTIFIER(i) => i.to_owned(),
-            Self::STRING(s) => format!("\"{}\"", s),
+            Self::IDENTIFIER(i) => escape_identifier(i),
+            Self::STRING(s) => quote_string(s),
             Self::NUMBER(n) => n.to_string(),
             Self::AND => "and".to_owned(),
+            Self::AWAIT => "await".to_owned(),
             Self::CLASS => "class".to_owned(),
+            Self::DEFER => "defer".to_owned(),
             Self::ELSE => "else".to_owned(),
             Self::FALSE => "false".to_owned(),
             Self::FUN => "fun".to_owned(),
             Self::FOR => "for".to_owned(),
             Self::IF => "if".to_owned(),
+            Self::IS => "is".to_owned(),
             Self::NIL => "nil".to_owned(),
             Self::OR => "or".to_owned(),
             Self::PRINT => "print".to_owned(),
+            Self::RECORD => "record".to_owned(),
             Self::RETURN => "return".to_owned(),
             Self::SUPER => "super".to_owned(),
             Self::THIS => "this".to_owned(),
             Self::TRUE => "true".to_owned(),
             Self::VAR => "var".to_owned(),
             Self::WHILE => "while".to_owned(),
+            Self::WITH => "with".to_owned(),
             Self::EOF => "<EOF>".to_owned(),
             Self::UNEXPECTED(st) => st.clone(),
         }
@@ -110,15 +133,124 @@ impl fmt::Display for LexemeKind {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+// Source text that carries no meaning for the program but that tooling wants to keep: the
+// formatter needs to put comments back where the user wrote them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Token {
     pub line: usize,
     pub lexeme: LexemeKind,
+    // only filled in by a lossless Scanner, along with `text`, the token exactly as written
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+    pub text: String,
 }
 
 impl Token {
     pub fn new(lexeme: LexemeKind, line: usize) -> Self {
-        Self { lexeme, line }
+        Self { lexeme, line, leading: vec![], trailing: vec![], text: String::new() }
+    }
+}
+
+// The source a lossless Scanner read, character for character.  Tools that rewrite a script
+// change the tokens they care about and print the rest back untouched
+pub fn print(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        for trivia in token.leading.iter() {
+            out.push_str(&trivia.text);
+        }
+        out.push_str(&token.text);
+        for trivia in token.trailing.iter() {
+            out.push_str(&trivia.text);
+        }
+    }
+
+    out
+}
+
+// Where each lossless token's own text sits in the source, as byte ranges with the trivia either
+// side left out.  Pair with a LineIndex to get at lines and columns
+pub fn spans(tokens: &[Token]) -> Vec<Range<usize>> {
+    let mut offset = 0;
+    let mut spans = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        offset += token.leading.iter().map(|trivia| trivia.text.len()).sum::<usize>();
+        spans.push(offset..offset + token.text.len());
+        offset += token.text.len();
+        offset += token.trailing.iter().map(|trivia| trivia.text.len()).sum::<usize>();
+    }
+
+    spans
+}
+
+// Raised for source the scanner could not make sense of.  The offending text is still handed to
+// the parser as an UNEXPECTED token so the token stream stays complete, but the caller gets the
+// full list of problems up front from `Scanner::scan` rather than discovering them one at a time.
+#[derive(Debug, PartialEq)]
+pub struct ScanError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [line: {}]", self.message, self.line)
+    }
+}
+
+// Extra spellings for keywords, so an embedder can offer `function` for `fun`, or keywords in the
+// user's own language, without forking the scanner.  The usual spellings keep working alongside.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Keywords {
+    aliases: BTreeMap<String, LexemeKind>,
+}
+
+impl Keywords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Make `alias` another way of writing `keyword`.  The alias has to be something that would
+    // otherwise scan as an identifier, and can't already mean something else.  Scripts lose it as
+    // a variable name, so embedders should pick words their users' scripts don't use.
+    pub fn alias(&mut self, alias: &str, keyword: &str) -> Result<(), String> {
+        let lexeme = match keyword_lexeme(keyword) {
+            Some(lexeme) => lexeme,
+            None => return Err(format!("\"{}\" isn't a keyword", keyword)),
+        };
+
+        if !is_identifier(alias) {
+            return Err(format!("\"{}\" can't be a keyword, it isn't shaped like a name", alias));
+        }
+        if is_keyword(alias) {
+            return Err(format!("\"{}\" is already a keyword", alias));
+        }
+
+        match self.aliases.get(alias) {
+            Some(existing) if *existing != lexeme => {
+                Err(format!("\"{}\" already stands for \"{}\"", alias, existing.source_text()))
+            }
+            _ => {
+                self.aliases.insert(alias.to_string(), lexeme);
+                Ok(())
+            }
+        }
+    }
+
+    fn get(&self, word: &str) -> Option<LexemeKind> {
+        self.aliases.get(word).cloned()
     }
 }
 
@@ -126,6 +258,13 @@ pub struct Scanner {
     cursor: usize,
     chars: Vec<char>,
     line: usize,
+    errors: Vec<ScanError>,
+    lossless: bool,
+    keywords: Keywords,
+    ignore_keyword_case: bool,
+    // only with implicit_semicolons, the rest of the tokens with the semicolons put in
+    pending: Option<VecDeque<Token>>,
+    implicit_semicolons: bool,
 }
 
 // Lexer - group raw substrings into lexemes.  This is a higher representation than the raw source.
@@ -136,9 +275,54 @@ impl Scanner {
             chars: source.chars().collect(),
             cursor: 0,
             line: 0,
+            errors: vec![],
+            lossless: false,
+            keywords: Keywords::new(),
+            ignore_keyword_case: false,
+            pending: None,
+            implicit_semicolons: false,
+        }
+    }
+
+    // Also recognise the aliases in `keywords`.  Tokens come out as the keyword they stand for,
+    // a lossless scan keeps the alias as their text.
+    pub fn with_keywords(self, keywords: Keywords) -> Self {
+        Self { keywords, ..self }
+    }
+
+    // PRINT and Print are print too, for scripts coming from languages that don't care.  Only
+    // keywords (aliases included): `Total` and `total` are still two different variables.
+    pub fn ignoring_keyword_case(self) -> Self {
+        Self { ignore_keyword_case: true, ..self }
+    }
+
+    // A newline ends the statement when what's before it could, so quick scripts and the REPL
+    // can leave semicolons off without a line running on into the next, see insert_semicolons
+    pub fn implicit_semicolons(self) -> Self {
+        Self { implicit_semicolons: true, ..self }
+    }
+
+    // Instead of Whitespace tokens and dropped comments, attach both to the neighbouring tokens.
+    // A token owns the trivia before it (leading) and whatever follows it on the same line
+    // (trailing).  Trivia after the last token hangs off a final EOF token.
+    pub fn lossless(source: String) -> Self {
+        Self {
+            lossless: true,
+            ..Self::new(source)
         }
     }
 
+    // Run the scanner to the end, returning every token along with every error encountered
+    pub fn scan(mut self) -> (Vec<Token>, Vec<ScanError>) {
+        let tokens = self.by_ref().collect();
+        (tokens, self.errors)
+    }
+
+    fn error(&mut self, message: String, text: String) -> LexemeKind {
+        self.errors.push(ScanError { line: self.line, message });
+        LexemeKind::UNEXPECTED(text)
+    }
+
     fn current_char(&self) -> Option<&char> {
         self.chars.get(self.cursor)
     }
@@ -151,38 +335,157 @@ impl Scanner {
         self.cursor >= self.chars.len()
     }
 
-    fn number_boundary(&mut self) -> f64 {
+    // 1.5, 2e9 or 2.5e-3.  1_000_000 and 0xFF_FF can have underscores between digits to make them easier to read.
+    // They're dropped before the number is parsed, and one anywhere else is an error rather
+    // than a guess at what was meant
+    fn number_boundary(&mut self) -> LexemeKind {
+        if self.current_char() == Some(&'0') && matches!(self.peek_next(), Some('x') | Some('X')) {
+            return self.hex_boundary();
+        }
+
         let mut buffer = String::new();
         while self.current_char().is_some() {
             let c = self.current_char().unwrap();
             match *c {
-                add if is_number(add) || add == '.' => {
+                add if is_number(add) || add == '.' || add == '_' => {
                     buffer.push(add.to_owned());
                     self.cursor += 1;
                 }
                 _ => break,
             }
         }
+        // 2.5e-3.  An e straight after the digits is always the start of an exponent, so 1e or
+        // 1e+ with no digits after is an error rather than a 1 and an identifier
+        if let Some(&e) = self.current_char().filter(|&&c| c == 'e' || c == 'E') {
+            let sign = matches!(self.peek_next(), Some('+') | Some('-')) as usize;
+            buffer.push(e);
+            buffer.extend(&self.chars[self.cursor + 1..self.cursor + 1 + sign]);
+            self.cursor += 1 + sign;
+            if !self.current_char().is_some_and(|&c| is_number(c)) {
+                return self.error(format!("Malformed exponent: {}", buffer), buffer);
+            }
+            while let Some(&c) = self.current_char().filter(|&&c| is_number(c) || c == '_') {
+                buffer.push(c);
+                self.cursor += 1;
+            }
+        }
 
-        buffer.parse().unwrap()
+        if !separated(&buffer, is_number) {
+            return self.error(format!("Misplaced _ in number: {}", buffer), buffer);
+        }
+        match buffer.replace('_', "").parse::<f64>() {
+            // 1e999 would quietly be Infinity, say so instead, Infinity is there by name
+            Ok(num) if num.is_infinite() => self.error(format!("Number too big: {}", buffer), buffer),
+            Ok(num) => LexemeKind::NUMBER(num),
+            // 1.2.3
+            Err(_) => self.error(format!("Invalid number: {}", buffer), buffer),
+        }
+    }
+
+    // 0xff, whole numbers only
+    fn hex_boundary(&mut self) -> LexemeKind {
+        // first was 0x, the digits start after it
+        let mut buffer: String = self.chars[self.cursor..self.cursor + 2].iter().collect();
+        self.cursor += 2;
+        while let Some(&c) = self.current_char() {
+            if !(c.is_ascii_hexdigit() || c == '_') {
+                break;
+            }
+            buffer.push(c);
+            self.cursor += 1;
+        }
+
+        let digits = &buffer[2..];
+        if !separated(digits, |c| c.is_ascii_hexdigit()) {
+            return self.error(format!("Misplaced _ in number: {}", buffer), buffer);
+        }
+        match u64::from_str_radix(&digits.replace('_', ""), 16) {
+            Ok(num) => LexemeKind::NUMBER(num as f64),
+            // 0x, or more than fits
+            Err(_) => self.error(format!("Invalid number: {}", buffer), buffer),
+        }
     }
 
-    fn word_boundary(&mut self) -> String {
+    fn word_boundary(&mut self) -> LexemeKind {
         // first was ". next char is potentially the word
         self.cursor += 1;
         let mut buffer = String::new();
-        while self.peek_next().is_some() {
-            let c = self.current_char().unwrap();
+        while let Some(c) = self.current_char() {
             match *c {
-                '"' => break,
+                // leave the cursor on the closing quote, next() steps over it
+                '"' => return LexemeKind::STRING(buffer),
                 add => {
+                    if add == '\n' {
+                        self.line += 1;
+                    }
                     buffer.push(add.to_owned());
                     self.cursor += 1;
                 }
             }
         }
 
-        buffer
+        self.error("Unterminated string".to_string(), format!("\"{}", buffer))
+    }
+
+    // """like this""", which can have a " in it.  Nothing inside is escaped and newlines stay as
+    // they are, so it's mostly for text pasted in whole
+    fn triple_quoted_boundary(&mut self) -> LexemeKind {
+        // first was """, the string starts after it
+        self.cursor += 3;
+        let mut buffer = String::new();
+        while let Some(&c) = self.current_char() {
+            if self.chars[self.cursor..].starts_with(&['"', '"', '"']) {
+                // leave the cursor on the last closing quote, next() steps over it
+                self.cursor += 2;
+                return LexemeKind::STRING(buffer);
+            }
+            if c == '\n' {
+                self.line += 1;
+            }
+            buffer.push(c);
+            self.cursor += 1;
+        }
+
+        self.error("Unterminated string".to_string(), format!("\"\"\"{}", buffer))
+    }
+
+    // r"..." or r"""...""".  A plain string doesn't process escapes either, so today this is the
+    // same string, but the r says the backslashes are meant to be backslashes whatever plain
+    // strings grow to do with them
+    fn raw_string_boundary(&mut self) -> LexemeKind {
+        // first was r, the quotes start after it
+        self.cursor += 1;
+        self.string_boundary()
+    }
+
+    fn string_boundary(&mut self) -> LexemeKind {
+        if self.chars[self.cursor..].starts_with(&['"', '"', '"']) {
+            self.triple_quoted_boundary()
+        } else {
+            self.word_boundary()
+        }
+    }
+
+    // `class` is the variable called class.  For hosts whose globals happen to be called the same
+    // as a keyword; the backticks aren't part of the name.
+    fn raw_identifier_boundary(&mut self) -> LexemeKind {
+        // first was `, the name starts after it
+        self.cursor += 1;
+        let mut buffer = String::new();
+        while let Some(&c) = self.current_char() {
+            match c {
+                // leave the cursor on the closing backtick, next() steps over it
+                '`' if is_identifier(&buffer) => return LexemeKind::IDENTIFIER(buffer),
+                '`' => return self.error(format!("Invalid name: `{}`", buffer), format!("`{}`", buffer)),
+                '\n' => break,
+                add => {
+                    buffer.push(add);
+                    self.cursor += 1;
+                }
+            }
+        }
+
+        self.error("Unterminated `".to_string(), format!("`{}", buffer))
     }
 
     fn identifier_boundary(&mut self) -> LexemeKind {
@@ -198,32 +501,83 @@ impl Scanner {
             }
         }
 
-        match buffer.as_str() {
-            "and" => LexemeKind::AND,
-            "class" => LexemeKind::CLASS,
-            "else" => LexemeKind::ELSE,
-            "false" => LexemeKind::FALSE,
-            "for" => LexemeKind::FOR,
-            "fun" => LexemeKind::FUN,
-            "if" => LexemeKind::IF,
-            "nil" => LexemeKind::NIL,
-            "or" => LexemeKind::OR,
-            "print" => LexemeKind::PRINT,
-            "return" => LexemeKind::RETURN,
-            "super" => LexemeKind::SUPER,
-            "this" => LexemeKind::THIS,
-            "true" => LexemeKind::TRUE,
-            "var" => LexemeKind::VAR,
-            "while" => LexemeKind::WHILE,
-            _ => LexemeKind::IDENTIFIER(buffer),
+        match self.keyword(&buffer) {
+            Some(lexeme) => lexeme,
+            None => LexemeKind::IDENTIFIER(buffer),
         }
     }
-}
 
-impl Iterator for Scanner {
-    type Item = Token;
+    fn keyword(&self, word: &str) -> Option<LexemeKind> {
+        let find = |word: &str| keyword_lexeme(word).or_else(|| self.keywords.get(word));
+        match find(word) {
+            None if self.ignore_keyword_case => find(&word.to_ascii_lowercase()),
+            found => found,
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    // a run of whitespace or a single comment, stopping short of a newline for trailing trivia
+    fn trivia_boundary(&mut self, trailing: bool) -> Option<Trivia> {
+        let c = *self.current_char()?;
+        let mut buffer = String::new();
+
+        if c == '/' && self.peek_next() == Some(&'/') {
+            while let Some(&c) = self.current_char() {
+                if c == '\n' {
+                    break;
+                }
+                buffer.push(c);
+                self.cursor += 1;
+            }
+
+            return Some(Trivia { kind: TriviaKind::Comment, text: buffer });
+        }
+
+        while let Some(&c) = self.current_char() {
+            if !c.is_whitespace() || (trailing && c == '\n') {
+                break;
+            }
+            if c == '\n' {
+                self.line += 1;
+            }
+            buffer.push(c);
+            self.cursor += 1;
+        }
+
+        if buffer.is_empty() {
+            None
+        } else {
+            Some(Trivia { kind: TriviaKind::Whitespace, text: buffer })
+        }
+    }
+
+    fn next_lossless(&mut self) -> Option<Token> {
+        let mut leading = vec![];
+        while let Some(trivia) = self.trivia_boundary(false) {
+            leading.push(trivia);
+        }
+
+        let start = self.cursor;
+        let mut token = if self.is_finished() {
+            if leading.is_empty() {
+                return None;
+            }
+            Token::new(LexemeKind::EOF, self.line)
+        } else {
+            self.next_token()?
+        };
+
+        // an unterminated string steps over a closing quote that isn't there
+        self.cursor = self.cursor.min(self.chars.len());
+        token.text = self.chars[start..self.cursor].iter().collect();
+        token.leading = leading;
+        while let Some(trivia) = self.trivia_boundary(true) {
+            token.trailing.push(trivia);
+        }
+
+        Some(token)
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
         if self.is_finished() {
             return None;
         }
@@ -231,8 +585,12 @@ impl Iterator for Scanner {
         let c = self.chars[self.cursor];
 
         if is_number(c) {
-            let num = self.number_boundary();
-            return Some(Token::new(LexemeKind::NUMBER(num), self.line));
+            let lexeme = self.number_boundary();
+            return Some(Token::new(lexeme, self.line));
+        } else if c == 'r' && self.peek_next() == Some(&'"') {
+            let lexeme = self.raw_string_boundary();
+            self.cursor += 1;
+            return Some(Token::new(lexeme, self.line));
         } else if is_valid_ident(c) {
             let lexeme = self.identifier_boundary();
             return Some(Token::new(lexeme, self.line));
@@ -297,31 +655,43 @@ impl Iterator for Scanner {
                     self.line,
                 ))
             }
+            // a ? on its own means nothing yet
+            '?' => match self.peek_next() {
+                Some('.') => {
+                    self.cursor += 1;
+                    Some(Token::new(LexemeKind::QuestionDot, self.line))
+                }
+                Some('?') => {
+                    self.cursor += 1;
+                    Some(Token::new(LexemeKind::QuestionQuestion, self.line))
+                }
+                _ => {
+                    let lexeme = self.error(format!("Unexpected character: {}", c), c.to_string());
+                    Some(Token::new(lexeme, self.line))
+                }
+            },
+            // likewise a | without the >
+            '|' => match self.peek_next() {
+                Some('>') => {
+                    self.cursor += 1;
+                    Some(Token::new(LexemeKind::Pipe, self.line))
+                }
+                _ => {
+                    let lexeme = self.error(format!("Unexpected character: {}", c), c.to_string());
+                    Some(Token::new(lexeme, self.line))
+                }
+            },
             '/' => {
                 let next = self.peek_next();
                 if next == Some(&'/') {
-                    self.cursor += 1;
-                    let mut done = false;
-                    while !done {
-                        if self.is_finished() {
-                            done = true;
-                        } else {
-                            let next = self.peek_next();
-                            if next != Some(&'\n') {
-                                if self.is_finished() {
-                                    done = true;
-                                } else {
-                                    self.cursor += 1;
-                                }
-                            } else {
-                                done = true;
-                            }
-                        }
+                    // stop on the newline so it is still scanned as whitespace
+                    while self.current_char().is_some() && self.current_char() != Some(&'\n') {
+                        self.cursor += 1;
                     }
 
                     // We aren't capturing tokens because the point of this is to execute the
                     // program and not faithfully represent every character (lossless)
-                    self.next()
+                    return self.next_token();
                 } else {
                     Some(Token::new(LexemeKind::Slash, self.line))
                 }
@@ -334,14 +704,19 @@ impl Iterator for Scanner {
                 Some(Token::new(LexemeKind::Whitespace, self.line))
             }
             '"' => {
-                let word = self.word_boundary();
-                Some(Token::new(LexemeKind::STRING(word), self.line))
+                let lexeme = self.string_boundary();
+                Some(Token::new(lexeme, self.line))
+            }
+            '`' => {
+                let lexeme = self.raw_identifier_boundary();
+                Some(Token::new(lexeme, self.line))
             }
             _ => {
                 if self.is_finished() {
                     Some(Token::new(LexemeKind::EOF, self.line))
                 } else {
-                    Some(Token::new(LexemeKind::UNEXPECTED(c.to_string()), self.line))
+                    let lexeme = self.error(format!("Unexpected character: {}", c), c.to_string());
+                    Some(Token::new(lexeme, self.line))
                 }
             }
         };
@@ -351,13 +726,174 @@ impl Iterator for Scanner {
     }
 }
 
+impl Iterator for Scanner {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.implicit_semicolons {
+            // where a semicolon goes depends on the token after the newline, so this can't be
+            // done a token at a time
+            if self.pending.is_none() {
+                self.implicit_semicolons = false;
+                let tokens = self.by_ref().collect();
+                self.pending = Some(insert_semicolons(tokens).into());
+                self.implicit_semicolons = true;
+            }
+            return self.pending.as_mut().and_then(VecDeque::pop_front);
+        }
+
+        if self.lossless {
+            self.next_lossless()
+        } else {
+            self.next_token()
+        }
+    }
+}
+
+// Semicolons where newlines end statements, the pass behind Scanner::implicit_semicolons.  It's
+// Go's rule more than JavaScript's: a newline after something a statement can end on (a name, a
+// literal, or the ) closing a call or grouping) is a semicolon.  Except
+//   inside parentheses, so arguments and conditions can go over lines
+//   after the ) of an if, while or with, which has its body still to come
+//   when the next line can only carry on the one before: it starts with `.`, `?.`, `|>`, `else`,
+//     a brace, a ), a comma, or an operator that can't start a statement.  `-`, `+` and `(`
+//     can, so `a = b` then `-c` on the next line are two statements where JavaScript has one
+// Lossless tokens stay lossless, the semicolons are put in with no text
+pub fn insert_semicolons(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::with_capacity(tokens.len());
+    // one for each open (, true when it's an if, while or with's condition
+    let mut parens: Vec<bool> = vec![];
+    // the last token that wasn't whitespace: whether it can end a statement, and its line
+    let (mut ends, mut line) = (false, 0);
+    let mut newline = false;
+    let mut condition_next = false;
+
+    for token in tokens {
+        if token.lexeme == LexemeKind::Whitespace {
+            // a newline's Whitespace is already on the next line
+            newline |= token.line > line;
+            out.push(token);
+            continue;
+        }
+
+        newline |= token.leading.iter().any(|trivia| trivia.text.contains('\n'));
+        if ends && newline && parens.is_empty() && !continues(&token.lexeme) {
+            // straight after the token it ends, ahead of the whitespace
+            let at = out.iter().rposition(|token| token.lexeme != LexemeKind::Whitespace).map_or(0, |i| i + 1);
+            out.insert(at, Token::new(LexemeKind::Semicolon, line));
+        }
+
+        ends = match &token.lexeme {
+            LexemeKind::IDENTIFIER(_)
+            | LexemeKind::STRING(_)
+            | LexemeKind::NUMBER(_)
+            | LexemeKind::TRUE
+            | LexemeKind::FALSE
+            | LexemeKind::NIL
+            | LexemeKind::THIS => true,
+            LexemeKind::RightParen => !parens.pop().unwrap_or(false),
+            LexemeKind::LeftParen => {
+                parens.push(condition_next);
+                false
+            }
+            _ => false,
+        };
+        condition_next = matches!(token.lexeme, LexemeKind::IF | LexemeKind::WHILE | LexemeKind::WITH);
+        newline = token.trailing.iter().any(|trivia| trivia.text.contains('\n'));
+        line = token.line;
+        out.push(token);
+    }
+
+    out
+}
+
+// whether a line starting with `lexeme` can only be the rest of the line before
+fn continues(lexeme: &LexemeKind) -> bool {
+    match lexeme {
+        LexemeKind::LeftParen | LexemeKind::Minus | LexemeKind::Plus => false,
+        LexemeKind::ELSE
+        | LexemeKind::RightParen
+        | LexemeKind::RightBrace
+        | LexemeKind::LeftBrace
+        | LexemeKind::Comma
+        | LexemeKind::Semicolon => true,
+        lexeme => OPERATORS.iter().any(|(operator, _, _)| operator == lexeme),
+    }
+}
+
+fn keyword_lexeme(word: &str) -> Option<LexemeKind> {
+    let lexeme = match word {
+        "and" => LexemeKind::AND,
+        "await" => LexemeKind::AWAIT,
+        "class" => LexemeKind::CLASS,
+        "defer" => LexemeKind::DEFER,
+        "else" => LexemeKind::ELSE,
+        "false" => LexemeKind::FALSE,
+        "for" => LexemeKind::FOR,
+        "fun" => LexemeKind::FUN,
+        "if" => LexemeKind::IF,
+        "is" => LexemeKind::IS,
+        "nil" => LexemeKind::NIL,
+        "or" => LexemeKind::OR,
+        "print" => LexemeKind::PRINT,
+        "record" => LexemeKind::RECORD,
+        "return" => LexemeKind::RETURN,
+        "super" => LexemeKind::SUPER,
+        "this" => LexemeKind::THIS,
+        "true" => LexemeKind::TRUE,
+        "var" => LexemeKind::VAR,
+        "while" => LexemeKind::WHILE,
+        "with" => LexemeKind::WITH,
+        _ => return None,
+    };
+
+    Some(lexeme)
+}
+
+pub fn is_keyword(word: &str) -> bool {
+    keyword_lexeme(word).is_some()
+}
+
+// `name` as it has to be written in source to scan back as that name
+pub fn escape_identifier(name: &str) -> String {
+    if is_keyword(name) {
+        format!("`{}`", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+// `text` as a string literal that scans back as the same text.  There are no escapes, so one
+// with a " in it has to be triple quoted, and one that ends in " can't be written at all; it
+// comes out as the closest there is
+pub fn quote_string(text: &str) -> String {
+    if text.contains('"') {
+        format!("\"\"\"{}\"\"\"", text)
+    } else {
+        format!("\"{}\"", text)
+    }
+}
+
+// whether `word` scans as a single identifier (or keyword), without backticks
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(is_valid_ident) && chars.all(|c| is_valid_ident(c) || is_number(c))
+}
+
+// every _ in `number` sits between two digits
+fn separated(number: &str, is_digit: impl Fn(char) -> bool) -> bool {
+    let chars: Vec<char> = number.chars().collect();
+    chars.iter().enumerate().all(|(i, &c)| {
+        c != '_' || (i > 0 && is_digit(chars[i - 1]) && chars.get(i + 1).is_some_and(|&next| is_digit(next)))
+    })
+}
+
 fn is_number(c: char) -> bool {
-    c >= '0' && c <= '9'
+    c.is_ascii_digit()
 }
 
 fn is_valid_ident(c: char) -> bool {
-    let re = Regex::new(r"[a-zA-Z_]").unwrap();
-    re.is_match(&c.to_string())
+    c.is_ascii_alphabetic() || c == '_'
 }
 
 #[cfg(test)]
@@ -399,6 +935,19 @@ mod tests {
         assert_eq!(sc.next(), None);
     }
 
+    #[test]
+    fn it_handles_comments_before_newline() {
+        let tokens: Vec<Token> = Scanner::new("// one\n// two\na".to_owned()).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(LexemeKind::Whitespace, 1),
+                Token::new(LexemeKind::Whitespace, 2),
+                Token::new(LexemeKind::IDENTIFIER("a".to_string()), 2),
+            ]
+        );
+    }
+
     #[test]
     fn it_handles_comparisons() {
         let mut sc = Scanner::new(">= <= != () ==".to_owned());
@@ -415,6 +964,30 @@ mod tests {
         assert_eq!(sc.next(), None);
     }
 
+    #[test]
+    fn it_handles_pipes() {
+        let mut sc = Scanner::new("a|>b |".to_owned());
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::IDENTIFIER("a".to_owned()), 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::Pipe, 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::IDENTIFIER("b".to_owned()), 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::Whitespace, 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::UNEXPECTED("|".to_owned()), 0));
+        assert_eq!(sc.next(), None);
+    }
+
+    #[test]
+    fn it_handles_question_marks() {
+        let mut sc = Scanner::new("a?.b??c ?".to_owned());
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::IDENTIFIER("a".to_owned()), 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::QuestionDot, 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::IDENTIFIER("b".to_owned()), 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::QuestionQuestion, 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::IDENTIFIER("c".to_owned()), 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::Whitespace, 0));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::UNEXPECTED("?".to_owned()), 0));
+        assert_eq!(sc.next(), None);
+    }
+
     #[test]
     fn it_handles_strings() {
         let mut sc = Scanner::new("\"bar\" ".to_owned());
@@ -426,6 +999,35 @@ mod tests {
         assert_eq!(sc.next(), None);
     }
 
+    #[test]
+    fn it_handles_triple_quoted_and_raw_strings() {
+        let mut sc = Scanner::new("\"\"\"say \"hi\"\nand\\n\"\"\" r\"C:\\dir\" r\"\"\"a\"\"\" \"\" x".to_owned());
+        // the line is where the string ends, like a plain string that goes over lines
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::STRING("say \"hi\"\nand\\n".to_string()), 1));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::Whitespace, 1));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::STRING("C:\\dir".to_string()), 1));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::Whitespace, 1));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::STRING("a".to_string()), 1));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::Whitespace, 1));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::STRING("".to_string()), 1));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::Whitespace, 1));
+        assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::IDENTIFIER("x".to_owned()), 1));
+        assert_eq!(sc.next(), None);
+
+        // r on its own is still a name
+        let tokens: Vec<LexemeKind> = Scanner::new("r (r)".to_owned()).map(|token| token.lexeme).collect();
+        assert_eq!(tokens[0], LexemeKind::IDENTIFIER("r".to_owned()));
+        assert_eq!(tokens[3], LexemeKind::IDENTIFIER("r".to_owned()));
+
+        let source = "print r\"a\" + \"\"\"b\n\"c\"\"\";";
+        assert_eq!(print(&Scanner::lossless(source.to_owned()).collect::<Vec<_>>()), source);
+        assert_eq!(LexemeKind::STRING("a \"b\"c".to_owned()).source_text(), "\"\"\"a \"b\"c\"\"\"");
+
+        let (_, errors) = Scanner::new("\"\"\"a\n\"\"".to_owned()).scan();
+        assert_eq!(errors, vec![ScanError { line: 1, message: "Unterminated string".to_owned() }]);
+        assert_eq!(print(&Scanner::lossless("r\"a".to_owned()).collect::<Vec<_>>()), "r\"a");
+    }
+
     #[test]
     fn it_handles_combo_strings() {
         let mut sc = Scanner::new("\"foo\" = \"bar\" ".to_owned());
@@ -521,6 +1123,152 @@ andd
         assert_eq!(sc.next(), None);
     }
 
+    #[test]
+    fn it_collects_scan_errors() {
+        let (tokens, errors) = Scanner::new("1 · 2\n\"foo".to_owned()).scan();
+        assert_eq!(tokens[2], Token::new(LexemeKind::UNEXPECTED("·".to_string()), 0));
+        assert_eq!(tokens[4], Token::new(LexemeKind::NUMBER(2.0), 0));
+        assert_eq!(tokens[6], Token::new(LexemeKind::UNEXPECTED("\"foo".to_string()), 1));
+        assert_eq!(
+            errors,
+            vec![
+                ScanError { line: 0, message: "Unexpected character: ·".to_string() },
+                ScanError { line: 1, message: "Unterminated string".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_errors_invalid_number() {
+        let (tokens, errors) = Scanner::new("1.2.3".to_owned()).scan();
+        assert_eq!(tokens, vec![Token::new(LexemeKind::UNEXPECTED("1.2.3".to_string()), 0)]);
+        assert_eq!(errors, vec![ScanError { line: 0, message: "Invalid number: 1.2.3".to_string() }]);
+    }
+
+    #[test]
+    fn it_handles_exponents() {
+        let numbers = |source: &str| {
+            Scanner::new(source.to_owned()).map(|token| token.lexeme).filter(|lexeme| *lexeme != LexemeKind::Whitespace).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            numbers("1e9 2.5e-3 1E+2 1_0e1_0"),
+            vec![LexemeKind::NUMBER(1e9), LexemeKind::NUMBER(2.5e-3), LexemeKind::NUMBER(100.0), LexemeKind::NUMBER(10e10)]
+        );
+        let (_, errors) = Scanner::new("1e999".to_owned()).scan();
+        assert_eq!(errors, vec![ScanError { line: 0, message: "Number too big: 1e999".to_string() }]);
+
+        // an e with no digits after it is still an exponent, just a broken one
+        let (tokens, errors) = Scanner::new("1e 1e+ 2E-x".to_owned()).scan();
+        let errors: Vec<String> = errors.into_iter().map(|error| error.message).collect();
+        assert_eq!(errors, vec!["Malformed exponent: 1e", "Malformed exponent: 1e+", "Malformed exponent: 2E-"]);
+        assert_eq!(tokens.iter().filter(|token| matches!(token.lexeme, LexemeKind::UNEXPECTED(_))).count(), 3);
+        assert!(tokens.iter().any(|token| token.lexeme == LexemeKind::IDENTIFIER("x".to_owned())));
+    }
+
+    #[test]
+    fn it_handles_numeric_separators() {
+        let numbers = |source: &str| {
+            Scanner::new(source.to_owned()).map(|token| token.lexeme).filter(|lexeme| *lexeme != LexemeKind::Whitespace).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            numbers("1_000_000 1_0.2_5 0xFF_FF 0x10"),
+            vec![LexemeKind::NUMBER(1000000.0), LexemeKind::NUMBER(10.25), LexemeKind::NUMBER(65535.0), LexemeKind::NUMBER(16.0)]
+        );
+        // a _ in front is a name
+        assert_eq!(numbers("_1"), vec![LexemeKind::IDENTIFIER("_1".to_owned())]);
+
+        let (_, errors) = Scanner::new("1__0 1_ 1_.5 1._5 0x_F 0x 0xFF_".to_owned()).scan();
+        let errors: Vec<String> = errors.into_iter().map(|error| error.message).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "Misplaced _ in number: 1__0",
+                "Misplaced _ in number: 1_",
+                "Misplaced _ in number: 1_.5",
+                "Misplaced _ in number: 1._5",
+                "Misplaced _ in number: 0x_F",
+                "Invalid number: 0x",
+                "Misplaced _ in number: 0xFF_",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_has_no_scan_errors() {
+        let (tokens, errors) = Scanner::new("var a = \"foo\";".to_owned()).scan();
+        assert_eq!(tokens.len(), 8);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn it_prints_lossless_tokens_back() {
+        let source = "// numbers keep their digits
+var a = 1.50 , b != 2;  // and odd spacing stays
+  { print(\"x\"); }
+
+";
+        let tokens: Vec<Token> = Scanner::lossless(source.to_owned()).collect();
+        assert_eq!(print(&tokens), source);
+    }
+
+    #[test]
+    fn it_attaches_trivia() {
+        let source = "// leading
+var a = 1; // trailing
+print(a);
+";
+        let tokens: Vec<Token> = Scanner::lossless(source.to_owned()).collect();
+        let lexemes: Vec<LexemeKind> = tokens.iter().map(|t| t.lexeme.clone()).collect();
+        assert!(!lexemes.contains(&LexemeKind::Whitespace));
+        assert_eq!(lexemes.len(), 11);
+
+        assert_eq!(tokens[0].lexeme, LexemeKind::VAR);
+        assert_eq!(tokens[0].line, 1);
+        assert_eq!(
+            tokens[0].leading,
+            vec![
+                Trivia { kind: TriviaKind::Comment, text: "// leading".to_string() },
+                Trivia { kind: TriviaKind::Whitespace, text: "\n".to_string() },
+            ]
+        );
+        assert_eq!(tokens[0].trailing, vec![Trivia { kind: TriviaKind::Whitespace, text: " ".to_string() }]);
+
+        assert_eq!(tokens[4].lexeme, LexemeKind::Semicolon);
+        assert_eq!(
+            tokens[4].trailing,
+            vec![
+                Trivia { kind: TriviaKind::Whitespace, text: " ".to_string() },
+                Trivia { kind: TriviaKind::Comment, text: "// trailing".to_string() },
+            ]
+        );
+
+        assert_eq!(tokens[5].lexeme, LexemeKind::PRINT);
+        assert_eq!(tokens[5].line, 2);
+        assert_eq!(tokens[5].leading, vec![Trivia { kind: TriviaKind::Whitespace, text: "\n".to_string() }]);
+        assert_eq!(tokens[9].lexeme, LexemeKind::Semicolon);
+        assert_eq!(tokens[9].trailing, vec![]);
+        assert_eq!(tokens[10].lexeme, LexemeKind::EOF);
+        assert_eq!(tokens[10].leading, vec![Trivia { kind: TriviaKind::Whitespace, text: "\n".to_string() }]);
+    }
+
+    #[test]
+    fn it_attaches_trailing_trivia_to_eof() {
+        let tokens: Vec<Token> = Scanner::lossless("a\n// end".to_owned()).collect();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].lexeme, LexemeKind::EOF);
+        assert_eq!(tokens[1].line, 1);
+        assert_eq!(
+            tokens[1].leading,
+            vec![
+                Trivia { kind: TriviaKind::Whitespace, text: "\n".to_string() },
+                Trivia { kind: TriviaKind::Comment, text: "// end".to_string() },
+            ]
+        );
+
+        let tokens: Vec<Token> = Scanner::lossless("a".to_owned()).collect();
+        assert_eq!(tokens, vec![Token { text: "a".to_string(), ..Token::new(LexemeKind::IDENTIFIER("a".to_string()), 0) }]);
+    }
+
     #[test]
     fn it_handles_keywords() {
         let source = "print(\"foo\")";
@@ -546,4 +1294,94 @@ andd
         assert_eq!(sc.next().unwrap(), Token::new(LexemeKind::IDENTIFIER("foo".to_string()), 0));
         assert_eq!(sc.next(), None);
     }
+
+    #[test]
+    fn it_scans_keyword_aliases() {
+        let mut keywords = Keywords::new();
+        keywords.alias("function", "fun").unwrap();
+        keywords.alias("si", "if").unwrap();
+        keywords.alias("si", "if").unwrap();
+
+        let tokens: Vec<LexemeKind> = Scanner::new("si fun function functions".to_owned())
+            .with_keywords(keywords.clone())
+            .map(|token| token.lexeme)
+            .filter(|lexeme| *lexeme != LexemeKind::Whitespace)
+            .collect();
+        assert_eq!(tokens, vec![LexemeKind::IF, LexemeKind::FUN, LexemeKind::FUN, LexemeKind::IDENTIFIER("functions".to_owned())]);
+
+        let tokens: Vec<Token> = Scanner::lossless("si (a) b".to_owned()).with_keywords(keywords.clone()).collect();
+        assert_eq!(tokens[0].lexeme, LexemeKind::IF);
+        assert_eq!(print(&tokens), "si (a) b");
+
+        assert_eq!(keywords.alias("si", "while"), Err("\"si\" already stands for \"if\"".to_owned()));
+        assert_eq!(keywords.alias("when", "unless"), Err("\"unless\" isn't a keyword".to_owned()));
+        assert_eq!(keywords.alias("while", "if"), Err("\"while\" is already a keyword".to_owned()));
+        assert_eq!(keywords.alias("2if", "if"), Err("\"2if\" can't be a keyword, it isn't shaped like a name".to_owned()));
+        assert_eq!(keywords.alias("", "if"), Err("\"\" can't be a keyword, it isn't shaped like a name".to_owned()));
+    }
+
+    #[test]
+    fn it_scans_raw_identifiers() {
+        let tokens: Vec<LexemeKind> = Scanner::new("`class` `a1`".to_owned()).map(|token| token.lexeme).collect();
+        assert_eq!(
+            tokens,
+            vec![LexemeKind::IDENTIFIER("class".to_owned()), LexemeKind::Whitespace, LexemeKind::IDENTIFIER("a1".to_owned())]
+        );
+        assert_eq!(LexemeKind::IDENTIFIER("class".to_owned()).source_text(), "`class`");
+        assert_eq!(LexemeKind::IDENTIFIER("klass".to_owned()).source_text(), "klass");
+
+        let tokens: Vec<Token> = Scanner::lossless("print(`while`);".to_owned()).collect();
+        assert_eq!(tokens[2].text, "`while`");
+        assert_eq!(print(&tokens), "print(`while`);");
+
+        let (_, errors) = Scanner::new("`a b` `1a` `".to_owned()).scan();
+        let errors: Vec<String> = errors.into_iter().map(|error| error.message).collect();
+        assert_eq!(errors, vec!["Invalid name: `a b`", "Invalid name: `1a`", "Unterminated `"]);
+    }
+
+    #[test]
+    fn it_inserts_implicit_semicolons() {
+        let scan = |source: &str| {
+            let tokens = Scanner::new(source.to_owned()).implicit_semicolons().filter(|token| token.lexeme != LexemeKind::Whitespace);
+            tokens.map(|token| token.lexeme.source_text()).collect::<Vec<_>>().join(" ")
+        };
+
+        assert_eq!(scan("var a = 1\nprint(a)\n"), "var a = 1 ; print ( a )");
+        // the newline doesn't make it two statements when the first can't end there
+        assert_eq!(scan("a = 1 +\n2"), "a = 1 + 2");
+        assert_eq!(scan("a = b\n  .c\n  |> f"), "a = b . c |> f");
+        assert_eq!(scan("a = b\n(c)"), "a = b ; ( c )");
+        assert_eq!(scan("f(a,\n  b)"), "f ( a , b )");
+        assert_eq!(scan("if (a)\nprint(b)\nelse\nprint(c)"), "if ( a ) print ( b ) else print ( c )");
+        assert_eq!(scan("while (a)\n{\n  a = a - 1\n}"), "while ( a ) { a = a - 1 }");
+        assert_eq!(scan("record P {\n  x,\n  y\n}"), "record P { x , y }");
+        // a comment on the end of the line is still the end of it
+        assert_eq!(scan("a = 1 // one\nb = 2"), "a = 1 ; b = 2");
+        assert_eq!(scan("a = \"\"\"x\ny\"\"\" b"), "a = \"x\ny\" b");
+
+        let source = "var a = 1 // one\nprint(a)";
+        let tokens: Vec<Token> = Scanner::lossless(source.to_owned()).implicit_semicolons().collect();
+        assert_eq!(tokens.iter().filter(|token| token.lexeme == LexemeKind::Semicolon).count(), 1);
+        assert_eq!(print(&tokens), source);
+    }
+
+    #[test]
+    fn it_can_ignore_keyword_case() {
+        let scan = |scanner: Scanner| {
+            scanner.map(|token| token.lexeme).filter(|lexeme| *lexeme != LexemeKind::Whitespace).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            scan(Scanner::new("PRINT Var Total".to_owned()).ignoring_keyword_case()),
+            vec![LexemeKind::PRINT, LexemeKind::VAR, LexemeKind::IDENTIFIER("Total".to_owned())]
+        );
+        assert_eq!(
+            scan(Scanner::new("PRINT".to_owned())),
+            vec![LexemeKind::IDENTIFIER("PRINT".to_owned())]
+        );
+
+        let mut keywords = Keywords::new();
+        keywords.alias("function", "fun").unwrap();
+        assert_eq!(scan(Scanner::new("Function".to_owned()).with_keywords(keywords).ignoring_keyword_case()), vec![LexemeKind::FUN]);
+    }
 }
@@ -0,0 +1,39 @@
+// Without std only the front end is built: lexing and parsing need nothing more than an allocator
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "core")]
+pub mod lexer;
+#[cfg(feature = "core")]
+pub mod line_index;
+#[cfg(feature = "core")]
+pub mod parser;
+#[cfg(feature = "std")]
+pub mod codegen;
+#[cfg(feature = "std")]
+pub mod resolver;
+#[cfg(feature = "std")]
+pub mod interpreter;
+#[cfg(feature = "core")]
+pub mod visitor;
+#[cfg(feature = "core")]
+pub mod values;
+#[cfg(feature = "std")]
+pub mod dap;
+#[cfg(feature = "std")]
+pub mod doc;
+#[cfg(feature = "std")]
+pub mod grammar;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod minify;
+#[cfg(feature = "std")]
+pub mod outline;
+#[cfg(feature = "std")]
+pub mod fix;
+#[cfg(feature = "std")]
+pub mod rename;
+#[cfg(feature = "std")]
+pub mod symbols;
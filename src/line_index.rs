@@ -0,0 +1,120 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Converts between byte offsets into a source and line/column positions.  Built once per file,
+// after which each lookup is a binary search over where the lines start.  Lines count from 0 like
+// the scanner's, and columns count from 0 in the encoding asked for: UTF-8 bytes for anything
+// working on Rust strings, UTF-16 code units for editors that think in those (LSP does).
+pub struct LineIndex {
+    source: String,
+    // byte offset of the first character of every line
+    line_starts: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(offset, _)| offset + 1));
+
+        Self { source: source.into(), line_starts }
+    }
+
+    pub fn lines(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // None past the end of the source or inside a multi-byte character
+    pub fn line_col(&self, offset: usize, encoding: Encoding) -> Option<LineCol> {
+        if !self.source.is_char_boundary(offset) {
+            return None;
+        }
+
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let before = &self.source[self.line_starts[line]..offset];
+        let column = match encoding {
+            Encoding::Utf8 => before.len(),
+            Encoding::Utf16 => before.encode_utf16().count(),
+        };
+
+        Some(LineCol { line, column })
+    }
+
+    // None for a line that doesn't exist or a column past its end (or inside a character)
+    pub fn offset(&self, position: LineCol, encoding: Encoding) -> Option<usize> {
+        let start = *self.line_starts.get(position.line)?;
+        let end = self.line_starts.get(position.line + 1).map(|next| next - 1).unwrap_or(self.source.len());
+        let line = &self.source[start..end];
+
+        let column = match encoding {
+            Encoding::Utf8 => position.column,
+            Encoding::Utf16 => {
+                let mut units = 0;
+                let mut bytes = 0;
+                for c in line.chars() {
+                    if units >= position.column {
+                        break;
+                    }
+                    units += c.len_utf16();
+                    bytes += c.len_utf8();
+                }
+                if units != position.column {
+                    return None;
+                }
+                bytes
+            }
+        };
+
+        if column <= line.len() && line.is_char_boundary(column) {
+            Some(start + column)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_offsets_both_ways() {
+        let index = LineIndex::new("var a = 1;\nprint(a);\n");
+        assert_eq!(index.lines(), 3);
+        assert_eq!(index.line_col(0, Encoding::Utf8), Some(LineCol { line: 0, column: 0 }));
+        assert_eq!(index.line_col(10, Encoding::Utf8), Some(LineCol { line: 0, column: 10 }));
+        assert_eq!(index.line_col(11, Encoding::Utf8), Some(LineCol { line: 1, column: 0 }));
+        assert_eq!(index.line_col(17, Encoding::Utf8), Some(LineCol { line: 1, column: 6 }));
+        assert_eq!(index.line_col(21, Encoding::Utf8), Some(LineCol { line: 2, column: 0 }));
+        assert_eq!(index.line_col(22, Encoding::Utf8), None);
+
+        assert_eq!(index.offset(LineCol { line: 1, column: 6 }, Encoding::Utf8), Some(17));
+        assert_eq!(index.offset(LineCol { line: 1, column: 10 }, Encoding::Utf8), None);
+        assert_eq!(index.offset(LineCol { line: 3, column: 0 }, Encoding::Utf8), None);
+    }
+
+    #[test]
+    fn it_counts_utf16_columns() {
+        // é is 2 bytes and 1 UTF-16 unit, 😀 is 4 bytes and 2 units
+        let index = LineIndex::new("a\n\"é😀\" + b");
+        let b = "a\n\"é😀\" + ".len();
+        assert_eq!(index.line_col(b, Encoding::Utf8), Some(LineCol { line: 1, column: 11 }));
+        assert_eq!(index.line_col(b, Encoding::Utf16), Some(LineCol { line: 1, column: 8 }));
+        assert_eq!(index.offset(LineCol { line: 1, column: 8 }, Encoding::Utf16), Some(b));
+        assert_eq!(index.line_col(b - 5, Encoding::Utf8), None);
+        // halfway through the 😀
+        assert_eq!(index.offset(LineCol { line: 1, column: 3 }, Encoding::Utf16), None);
+    }
+}
@@ -1,64 +1,599 @@
-mod lexer;
-mod parser;
-mod interpreter;
-mod visitor;
-
-use lexer::Scanner;
-use parser::Parser;
-use interpreter::Interpreter;
+use tree_walk::codegen;
+use tree_walk::dap;
+use tree_walk::doc;
+use tree_walk::fix;
+use tree_walk::grammar;
+use tree_walk::metrics;
+use tree_walk::minify;
+use tree_walk::outline;
+use tree_walk::rename;
+use tree_walk::symbols::SymbolTable;
+use tree_walk::lexer::Scanner;
+use tree_walk::parser::{cache::DiskCache, Parser, Stmt};
+use tree_walk::resolver;
+use tree_walk::interpreter::{self, CancelToken, Capabilities, Interpreter};
+use tree_walk::values::{self, NumberFormat};
 
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::RefCell;
 use std::env;
 use std::fmt;
 use std::fs;
-use std::io::{self, Write};
+use std::io;
+#[cfg(feature = "repl")]
+use std::io::Write;
+use std::panic;
 use std::path;
 use std::process;
+use std::sync::OnceLock;
+use std::thread;
 
 type TWResult<T> = Result<T, Box<dyn std::error::Error>>;
 
+// Settings collected from --flags, applied to every Interpreter the CLI creates
+#[derive(Default)]
+struct Options {
+    number_format: NumberFormat,
+    // trace files for the clock, random and readLine values a run observed
+    record: Option<String>,
+    replay: Option<String>,
+    // scripts run from the command line are trusted unless --sandbox says otherwise
+    sandbox: bool,
+    // print an ExecutionReport to stderr after each run
+    stats: bool,
+    // print what the run did, statements, calls and the like, to stderr after each run
+    summary: bool,
+    // check the script for undefined names instead of running it
+    verify: bool,
+    // list what the script declares instead of running it
+    outline: bool,
+    // print the script translated to this language instead of running it
+    emit: Option<String>,
+    // reuse parsed trees from the on-disk cache
+    cache: bool,
+    // list the references to the variable at script:line:column instead of running it
+    refs: bool,
+    // apply the safe quick fixes to the script in place instead of running it
+    fix: bool,
+    // PRINT and Print scan as print
+    ignore_keyword_case: bool,
+    // a newline ends a statement that could end there
+    implicit_semicolons: bool,
+    // run scripts under the console debugger, stopping for writes to the --watch=name variables
+    // and at the --break=line breakpoints
+    debug: bool,
+    watches: Vec<String>,
+    breakpoints: Vec<(usize, Option<String>)>,
+}
+
+thread_local! {
+    // what the CLI is in the middle of, e.g. "parsing script.lox", for the panic diagnostic
+    static PROCESSING: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn processing(what: String) {
+    PROCESSING.with(|p| *p.borrow_mut() = what);
+}
+
+// what Ctrl-C cancels, the one interpreter running scripts.  A signal handler can't take
+// arguments, so it has to be somewhere static
+static CTRL_C: OnceLock<CancelToken> = OnceLock::new();
+
+// Ctrl-C stops the script rather than the whole process, so there's an "Interrupted" error saying
+// where it got to, and the REPL gets to carry on with the next line
+#[cfg(unix)]
+fn handle_ctrl_c(interp: &Interpreter) {
+    const SIGINT: i32 = 2;
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    extern "C" fn on_sigint(_signum: i32) {
+        if let Some(token) = CTRL_C.get() {
+            token.cancel();
+        }
+    }
+
+    if CTRL_C.set(interp.cancel_token()).is_ok() {
+        // SAFETY: the handler only does an atomic store
+        unsafe {
+            signal(SIGINT, on_sigint);
+        }
+    }
+}
+
+// no signals to catch, Ctrl-C just ends the process
+#[cfg(not(unix))]
+fn handle_ctrl_c(_interp: &Interpreter) {}
+
 fn main() -> TWResult<()> {
-    let args: Vec<String> = env::args().skip(1).collect();
+    // a panic is a bug in here, not in the script.  Say so instead of dumping a backtrace on
+    // someone who just wanted to run their program
+    panic::set_hook(Box::new(|info| {
+        eprintln!("internal interpreter error, please report");
+        PROCESSING.with(|p| {
+            if !p.borrow().is_empty() {
+                eprintln!("  while {}", p.borrow());
+            }
+        });
+        let message = info.payload_as_str().unwrap_or("no message");
+        match info.location() {
+            Some(location) => eprintln!("  {} (at {})", message, location),
+            None => eprintln!("  {}", message),
+        }
+        // RUST_BACKTRACE still works for whoever ends up fixing it
+        let backtrace = Backtrace::capture();
+        if backtrace.status() == BacktraceStatus::Captured {
+            eprintln!("{}", backtrace);
+        }
+    }));
+
+    // everything runs on a thread of its own, so scripts get the stack --stack-size asks for.  An
+    // error can't cross back as it is, it's printed the way returning it from main would
+    let cli = thread::Builder::new().stack_size(stack_size()).spawn(|| cli().map_err(|err| format!("{:?}", err)))?;
+    match cli.join() {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+        // EX_SOFTWARE
+        Err(_) => process::exit(70),
+    }
+}
+
+// What scripts run on unless --stack-size=MiB says otherwise, plenty for nesting that would
+// overflow the usual 8MB
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// Read ahead of the other flags, there has to be a thread with the stack before anything runs
+fn stack_size() -> usize {
+    env::args()
+        .find_map(|arg| arg.strip_prefix("--stack-size=").and_then(|mib| mib.parse::<usize>().ok()))
+        .map_or(STACK_SIZE, |mib| mib * 1024 * 1024)
+}
+
+fn cli() -> TWResult<()> {
+    let mut options = Options::default();
+    let mut args: Vec<String> = vec![];
+
+    for arg in env::args().skip(1) {
+        if let Some(digits) = arg.strip_prefix("--precision=") {
+            options.number_format.significant_digits = Some(digits.parse()?);
+        } else if let Some(target) = arg.strip_prefix("--emit=") {
+            options.emit = Some(target.to_string());
+        } else if arg == "--ignore-keyword-case" {
+            options.ignore_keyword_case = true;
+        } else if arg == "--implicit-semicolons" {
+            options.implicit_semicolons = true;
+        } else if arg == "--fix" {
+            options.fix = true;
+        } else if arg == "--refs" {
+            options.refs = true;
+        } else if arg == "--cache" {
+            options.cache = true;
+        } else if arg == "--verify" {
+            options.verify = true;
+        } else if arg == "--outline" {
+            options.outline = true;
+        } else if arg == "--stats" {
+            options.stats = true;
+        } else if arg == "--summary" {
+            options.summary = true;
+        } else if arg == "--debug" {
+            options.debug = true;
+        } else if let Some(name) = arg.strip_prefix("--watch=") {
+            options.debug = true;
+            options.watches.push(name.to_string());
+        } else if let Some(spec) = arg.strip_prefix("--break=") {
+            options.debug = true;
+            match interpreter::parse_breakpoint(spec) {
+                Some(breakpoint) => options.breakpoints.push(breakpoint),
+                None => {
+                    eprintln!("Expected --break=[script:]line [if condition], got {}", arg);
+                    process::exit(64);
+                }
+            }
+        } else if arg.starts_with("--stack-size=") {
+            // already read, see stack_size
+        } else if arg == "--sandbox" {
+            options.sandbox = true;
+        } else if let Some(path) = arg.strip_prefix("--record=") {
+            options.record = Some(path.to_string());
+        } else if let Some(path) = arg.strip_prefix("--replay=") {
+            options.replay = Some(path.to_string());
+        } else {
+            args.push(arg);
+        }
+    }
+
+    match args.first().map(String::as_str) {
+        Some("doc") => return run_doc(&args[1..]),
+        Some("metrics") => return run_metrics(&args[1..]),
+        Some("grammar") => return run_grammar(&args[1..]),
+        Some("minify") if args.len() == 2 => return run_minify(&args[1]),
+        Some("rename") if args.len() == 4 => return run_rename(&args[1], &args[2], &args[3]),
+        Some("dap") if args.len() == 1 => return run_dap(),
+        _ => {}
+    }
 
     match args.len() {
-        0 => run_prompt(),
-        1 => run_file(&args[0]),
+        1 if options.verify => verify_file(&args[0]),
+        1 if options.outline => outline_file(&args[0]),
+        1 if options.refs => find_references(&args[0]),
+        1 if options.fix => fix_file(&args[0]),
+        1 if options.emit.is_some() => emit_file(&args[0], options.emit.as_deref().unwrap()),
+        #[cfg(feature = "repl")]
+        0 if !options.verify && !options.outline && !options.refs && !options.fix && options.emit.is_none() => run_prompt(&options),
+        1 => run_file(&args[0], &options),
+        _ => {
+            eprintln!("Usage: tree-walk [--precision=digits] [--sandbox] [--ignore-keyword-case] [--implicit-semicolons] [--stats] [--summary] [--debug] [--watch=name] [--break=line] [--cache] [--stack-size=MiB] [--record=trace | --replay=trace] [script]");
+            eprintln!("       tree-walk --verify script");
+            eprintln!("       tree-walk --outline script");
+            eprintln!("       tree-walk --refs script:line:column");
+            eprintln!("       tree-walk --fix script");
+            eprintln!("       tree-walk --emit=js|rust script");
+            eprintln!("       tree-walk doc [--json] script...");
+            eprintln!("       tree-walk metrics script...");
+            eprintln!("       tree-walk grammar [--format=ebnf|railroad-html]");
+            eprintln!("       tree-walk minify script");
+            eprintln!("       tree-walk rename old new script[:line]");
+            eprintln!("       tree-walk dap");
+            process::exit(64);
+        }
+    }
+}
+
+fn run_doc(args: &[String]) -> TWResult<()> {
+    let json = args.iter().any(|a| a == "--json");
+    let files: Vec<&String> = args.iter().filter(|a| *a != "--json").collect();
+    if files.is_empty() {
+        eprintln!("Usage: tree-walk doc [--json] script...");
+        process::exit(64);
+    }
+
+    for file in files {
+        processing(format!("documenting {}", file));
+        let items = doc::extract(fs::read_to_string(file)?);
+        if json {
+            println!("{}", doc::to_json(file, &items));
+        } else {
+            print!("{}", doc::to_markdown(file, &items));
+        }
+    }
+
+    Ok(())
+}
+
+fn run_metrics(files: &[String]) -> TWResult<()> {
+    if files.is_empty() {
+        eprintln!("Usage: tree-walk metrics script...");
+        process::exit(64);
+    }
+
+    for file in files {
+        processing(format!("measuring {}", file));
+        let stmts = parse_reporting(file)?;
+
+        println!("{}", file);
+        for unit in metrics::measure(&stmts) {
+            println!("  {}", unit);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_grammar(args: &[String]) -> TWResult<()> {
+    let rules = grammar::rules();
+    match args {
+        [] => print!("{}", grammar::to_ebnf(&rules)),
+        [format] if format == "--format=ebnf" => print!("{}", grammar::to_ebnf(&rules)),
+        [format] if format == "--format=railroad-html" => print!("{}", grammar::to_railroad_html(&rules)),
+        _ => {
+            eprintln!("Usage: tree-walk grammar [--format=ebnf|railroad-html]");
+            process::exit(64);
+        }
+    }
+
+    Ok(())
+}
+
+// the debugger for an editor, speaking the Debug Adapter Protocol over stdin and stdout
+fn run_dap() -> TWResult<()> {
+    dap::serve(io::BufReader::new(io::stdin()), io::stdout())?;
+    Ok(())
+}
+
+fn run_minify(file: &str) -> TWResult<()> {
+    processing(format!("minifying {}", file));
+    match minify::minify(fs::read_to_string(file)?) {
+        Ok(source) => println!("{}", source),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(65);
+        }
+    }
+
+    Ok(())
+}
+
+// file.lox:12 picks the variable mentioned on line 12 when several share the name
+fn run_rename(old: &str, new: &str, target: &str) -> TWResult<()> {
+    let (file, line) = match target.rsplit_once(':') {
+        Some((file, line)) if line.parse::<usize>().is_ok() => (file, Some(line.parse()?)),
+        _ => (target, None),
+    };
+
+    processing(format!("renaming {} in {}", old, file));
+    match rename::rename(fs::read_to_string(file)?, old, new, line) {
+        Ok(source) => print!("{}", source),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(65);
+        }
+    }
+
+    Ok(())
+}
+
+// One reference per line as script:line:column, the same numbering the position was given in
+fn find_references(target: &str) -> TWResult<()> {
+    let (file, line, column) = match parse_position(target) {
+        Some(position) => position,
+        None => {
+            eprintln!("Usage: tree-walk --refs script:line:column");
+            process::exit(64);
+        }
+    };
+
+    processing(format!("finding references in {}", file));
+    let table = match SymbolTable::new(fs::read_to_string(file)?) {
+        Ok(table) => table,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(65);
+        }
+    };
+
+    let symbol = match table.symbol_at(line, column) {
+        Some(symbol) => symbol,
+        None => {
+            eprintln!("No variable at {}:{}:{}", file, line, column);
+            process::exit(65);
+        }
+    };
+
+    for reference in table.references(symbol) {
+        let kind = if reference.declaration { " (declaration)" } else { "" };
+        println!("{}:{}:{}{}", file, reference.line, reference.column, kind);
+    }
+
+    Ok(())
+}
+
+// script.lox:3:14
+fn parse_position(target: &str) -> Option<(&str, usize, usize)> {
+    let (rest, column) = target.rsplit_once(':')?;
+    let (file, line) = rest.rsplit_once(':')?;
+    Some((file, line.parse().ok()?, column.parse().ok()?))
+}
+
+fn fix_file(filename: &str) -> TWResult<()> {
+    processing(format!("fixing {}", filename));
+    let mut source = fs::read_to_string(filename)?;
+
+    // a fix can uncover another (print x parses once it has parentheses), so go round again.  A
+    // few passes is plenty, more would mean a fix that doesn't fix anything
+    for _ in 0..5 {
+        let fixes = match fix::fixes(&source) {
+            Ok(fixes) => fixes,
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(65);
+            }
+        };
+
+        let safe: Vec<&fix::Fix> = fixes.iter().filter(|fix| fix.safe).collect();
+        if safe.is_empty() {
+            for suggestion in fixes.iter() {
+                eprintln!("suggestion: {} [line: {}]", suggestion.message, suggestion.line);
+            }
+            break;
+        }
+
+        for fixed in safe.iter() {
+            eprintln!("fixed: {} [line: {}]", fixed.message, fixed.line);
+        }
+        source = fix::apply(&source, &safe);
+    }
+
+    fs::write(filename, source)?;
+    Ok(())
+}
+
+fn emit_file(filename: &str, target: &str) -> TWResult<()> {
+    processing(format!("translating {}", filename));
+    // a scan error fails the translation with the rest, from its error node
+    let stmts = Parser::scanned(Scanner::new(fs::read_to_string(filename)?).scan()).with_stack_size(stack_size()).parse();
+
+    let emitted = match target {
+        "js" => codegen::js::emit(&stmts),
+        "rust" => codegen::rust::emit(&stmts),
         _ => {
-            eprintln!("Usage: tree-walk [script]");
+            eprintln!("Unknown --emit target: {}", target);
             process::exit(64);
         }
+    };
+
+    match emitted {
+        Ok(source) => print!("{}", source),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(65);
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_file(filename: &str) -> TWResult<()> {
+    processing(format!("verifying {}", filename));
+    // a scan error is an error node in the tree, which the resolver reports along with the rest
+    let stmts = Parser::scanned(Scanner::new(fs::read_to_string(filename)?).scan()).with_stack_size(stack_size()).parse();
+    let resolution = resolver::resolve(&stmts, &interpreter::natives());
+    for error in &resolution.errors {
+        eprintln!("{}", error);
+    }
+    for warning in &resolution.warnings {
+        eprintln!("{}", warning);
     }
+
+    // warnings are worth reading but don't fail verification
+    let count = resolution.errors.len();
+    if count > 0 {
+        eprintln!("{}: {} problem{} found", filename, count, if count == 1 { "" } else { "s" });
+        process::exit(65);
+    }
+
+    Ok(())
 }
 
-fn run_prompt() -> TWResult<()> {
-    loop {
+fn outline_file(filename: &str) -> TWResult<()> {
+    processing(format!("outlining {}", filename));
+    for symbol in outline::outline(&parse_reporting(filename)?) {
+        println!("{}", symbol);
+    }
+
+    Ok(())
+}
+
+// Scan and parse a script for a tool that makes what it can of a broken one.  What the scan found
+// wrong goes to stderr, and the tree has an error node wherever it was
+fn parse_reporting(filename: &str) -> TWResult<Vec<Stmt>> {
+    let mut parser = Parser::scanned(Scanner::new(fs::read_to_string(filename)?).scan()).with_stack_size(stack_size());
+    for error in parser.scan_errors() {
+        eprintln!("{}", error);
+    }
+
+    Ok(parser.parse())
+}
+
+#[cfg(feature = "repl")]
+fn run_prompt(options: &Options) -> TWResult<()> {
+    // one interpreter for the whole session, so a line can use what earlier ones defined
+    let mut interp = interpreter(options)?;
+    for input in 1.. {
         print!("> ");
         io::stdout().flush()?;
 
         let mut line = String::new();
         io::stdin().read_line(&mut line)?;
 
-        if line.len() == 0 {
+        if line.is_empty() {
             break;
         }
 
-        run(line)?;
+        processing(format!("running REPL input {}: {}", input, line.trim()));
+        run(line, &mut interp, options, true)?;
+        // the next line starts from the global scope whatever this one got up to
+        interp.reset();
     }
 
     Ok(())
 }
 
-fn run_file<P: AsRef<path::Path> + fmt::Display>(filename: P) -> TWResult<()> {
-    run(fs::read_to_string(filename)?)
+fn run_file<P: AsRef<path::Path> + fmt::Display>(filename: P, options: &Options) -> TWResult<()> {
+    processing(format!("running {}", filename));
+    let mut interp = interpreter(options)?;
+    interp.set_file(&filename.to_string());
+    let source = fs::read_to_string(filename)?;
+    if options.debug {
+        // stderr, so the prompt doesn't end up in whatever reads the script's output
+        interp.set_debugger(interpreter::Console::new(&source, io::BufReader::new(io::stdin()), io::stderr()));
+        for name in &options.watches {
+            interp.watch(name);
+        }
+        for (line, condition) in &options.breakpoints {
+            interp.set_breakpoint(*line, condition.as_deref());
+        }
+    }
+    if !run(source, &mut interp, options, false)? {
+        // the source could not be scanned
+        process::exit(65);
+    }
+    if interp.cancel_token().is_cancelled() {
+        // what a shell expects of a process stopped by SIGINT
+        process::exit(130);
+    }
+
+    Ok(())
 }
 
-fn run(source: String) -> TWResult<()> {
-    let tokens = Scanner::new(source).collect();
+fn interpreter(options: &Options) -> TWResult<Interpreter> {
+    let capabilities = if options.sandbox { Capabilities::none() } else { Capabilities::all() };
+    let mut interp = Interpreter::builder()
+        .capabilities(capabilities)
+        .case_insensitive_keywords(options.ignore_keyword_case)
+        .implicit_semicolons(options.implicit_semicolons)
+        .number_format(options.number_format.clone())
+        .stack_size(stack_size())
+        .build();
+    if let Some(path) = &options.replay {
+        interp.replay(interpreter::read_trace(&fs::read_to_string(path)?)?);
+    } else if options.record.is_some() {
+        interp.record();
+    }
+    handle_ctrl_c(&interp);
+
+    Ok(interp)
+}
+
+fn run(source: String, interp: &mut Interpreter, options: &Options, echo: bool) -> TWResult<bool> {
+    // the cache scans the usual way, and only knows the source it was given
+    let cached = match DiskCache::default_dir() {
+        Some(dir) if options.cache && !options.ignore_keyword_case && !options.implicit_semicolons => DiskCache::new(dir).get_or_parse(&source),
+        _ => None,
+    };
+
+    let stmts = match cached {
+        Some(stmts) => stmts,
+        None => {
+            let mut parser = Parser::scanned(interp.scanner(source).scan()).with_stack_size(stack_size());
+            if !parser.scan_errors().is_empty() {
+                for error in parser.scan_errors() {
+                    eprintln!("{}", error);
+                }
+                return Ok(false);
+            }
+
+            parser.parse()
+        }
+    };
+    // only echo bare expressions, print has already written its value
+    let echo = echo && matches!(stmts.last(), Some(Stmt::Expr(_)));
 
-    let mut parser = Parser::new(tokens); // vec![token1, token2]
-    let stmts = parser.parse();
-    let mut interp = Interpreter::new();
     let res = interp.start(stmts);
-    eprintln!("{:?}", res);
+    // then whatever it left on timers
+    let res = res.and_then(|value| interp.run_until_idle().map(|_| value));
+    if options.stats {
+        eprintln!("{}", interp.report());
+    }
+    if options.summary {
+        eprintln!("{}", interp.report().summary());
+    }
 
-    Ok(())
+    if let Some(path) = &options.record {
+        fs::write(path, interpreter::write_trace(interp.recorded()))?;
+    }
+    // the REPL too, exit() is how a session ends as well
+    if let Some(code) = interp.exit_code() {
+        process::exit(code);
+    }
+
+    match res {
+        Ok(value) if echo => println!("{}", values::pretty(&value, values::DEFAULT_DEPTH, &options.number_format)),
+        res => eprintln!("{:?}", res),
+    }
+
+    Ok(true)
 }
@@ -0,0 +1,227 @@
+use std::fmt;
+use std::sync::Arc;
+use crate::lexer::LexemeKind;
+use crate::parser::{Expr, Function, NodeId, Stmt, Value};
+use crate::visitor::{ExpressionVisitor, StatementVisitor};
+
+// Size and shape of one unit of code.  There are no functions yet, so the only unit is the script
+// itself; each function will get its own entry once they exist.
+//   statements  every statement, however deeply nested. Blocks themselves don't count
+//   max_depth   how many if/while are wrapped around the most deeply nested statement
+//   complexity  cyclomatic complexity: 1 + every if, while, and, or
+#[derive(Debug, PartialEq)]
+pub struct Metrics {
+    pub name: String,
+    pub line: usize,
+    pub statements: usize,
+    pub max_depth: usize,
+    pub complexity: usize,
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} [line: {}] statements: {}, max depth: {}, complexity: {}",
+            self.name, self.line, self.statements, self.max_depth, self.complexity
+        )
+    }
+}
+
+pub fn measure(stmts: &[Stmt]) -> Vec<Metrics> {
+    let mut counter = Counter {
+        metrics: Metrics { name: "<script>".to_string(), line: 0, statements: 0, max_depth: 0, complexity: 1 },
+        depth: 0,
+    };
+
+    for stmt in stmts {
+        counter.statement(stmt);
+    }
+
+    vec![counter.metrics]
+}
+
+struct Counter {
+    metrics: Metrics,
+    depth: usize,
+}
+
+impl Counter {
+    fn statement(&mut self, stmt: &Stmt) {
+        if !matches!(stmt, Stmt::Block(_)) {
+            self.metrics.statements += 1;
+            self.metrics.max_depth = self.metrics.max_depth.max(self.depth);
+        }
+        stmt.accept(self);
+    }
+
+    // the body of an if or while
+    fn nested(&mut self, stmt: &Stmt) {
+        self.depth += 1;
+        self.statement(stmt);
+        self.depth -= 1;
+    }
+}
+
+impl ExpressionVisitor<()> for Counter {
+    fn visit_assign(&mut self, _name: &str, expr: &Expr, _line: &usize) {
+        expr.accept(self);
+    }
+
+    fn visit_binary(&mut self, left: &Expr, _operator: &LexemeKind, right: &Expr) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], _line: &usize) {
+        callee.accept(self);
+        for arg in arguments {
+            arg.accept(self);
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Expr, _operator: &LexemeKind, right: &Expr) {
+        // short circuiting is a branch
+        self.metrics.complexity += 1;
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_literal(&mut self, _val: &Value) {}
+
+    fn visit_unary(&mut self, _operator: &LexemeKind, right: &Expr) {
+        right.accept(self);
+    }
+
+    fn visit_grouping(&mut self, val: &Expr) {
+        val.accept(self);
+    }
+
+    fn visit_variable(&mut self, _ident: &str, _id: NodeId, _line: &usize) {}
+
+    fn visit_this(&mut self, _line: &usize) {}
+
+    fn visit_get(&mut self, object: &Expr, _name: &str, _optional: &bool, _line: &usize) {
+        object.accept(self);
+    }
+
+    fn visit_set(&mut self, object: &Expr, _name: &str, value: &Expr, _line: &usize) {
+        object.accept(self);
+        value.accept(self);
+    }
+
+    fn visit_is(&mut self, value: &Expr, _type_name: &str, _line: &usize) {
+        value.accept(self);
+    }
+
+    fn visit_await(&mut self, expr: &Expr, _line: &usize) {
+        expr.accept(self);
+    }
+
+    fn visit_if_expr(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>, _line: &usize) {
+        StatementVisitor::visit_if(self, condition, then_branch, else_branch);
+    }
+
+    fn visit_error(&mut self, _line: &usize, _message: &str) {}
+}
+
+impl StatementVisitor<()> for Counter {
+    fn visit_block(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) {
+        self.metrics.complexity += 1;
+        condition.accept(self);
+        self.nested(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.nested(else_branch);
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, _line: &usize) {
+        self.metrics.complexity += 1;
+        condition.accept(self);
+        self.nested(body);
+    }
+
+    fn visit_variable_def(&mut self, _ident: &str, expr: &Option<Expr>, _line: &usize) {
+        if let Some(expr) = expr {
+            expr.accept(self);
+        }
+    }
+
+    fn visit_record(&mut self, _name: &str, _fields: &[String], _line: &usize) {}
+
+    // there's no way to call a method yet, so they're still part of the script's unit
+    fn visit_class(&mut self, _name: &str, methods: &Arc<[Function]>, _line: &usize) {
+        for method in methods.iter() {
+            for stmt in &method.body {
+                self.statement(stmt);
+            }
+        }
+    }
+
+    fn visit_print(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            expr.accept(self);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        expr.accept(self);
+    }
+
+    fn visit_with(&mut self, _ident: &str, init: &Expr, body: &Stmt, _line: &usize) {
+        // not a branch, the body always runs
+        init.accept(self);
+        self.statement(body);
+    }
+
+    fn visit_defer(&mut self, expr: &Expr, _line: &usize) {
+        expr.accept(self);
+    }
+
+    fn visit_error(&mut self, _line: &usize, _message: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    fn script(source: &str) -> Metrics {
+        let tokens = Scanner::new(source.to_owned()).collect();
+        measure(&Parser::new(tokens).parse()).remove(0)
+    }
+
+    #[test]
+    fn it_measures_straight_line_code() {
+        let metrics = script("var a = 1; print(a); { a = 2; }");
+        assert_eq!(metrics.statements, 3);
+        assert_eq!(metrics.max_depth, 0);
+        assert_eq!(metrics.complexity, 1);
+    }
+
+    #[test]
+    fn it_measures_branches() {
+        let metrics = script("
+var a = 1;
+while (a < 3) {
+    if (a == 1 and true) {
+        print(a);
+    } else {
+        a = a + 1;
+    }
+    a = a + 1;
+}
+");
+        assert_eq!(metrics.statements, 6);
+        assert_eq!(metrics.max_depth, 2);
+        // while, if, and
+        assert_eq!(metrics.complexity, 4);
+    }
+}
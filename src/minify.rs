@@ -0,0 +1,395 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use crate::lexer::{self, LexemeKind, Scanner};
+use crate::parser::{Expr, Function, NodeId, Parser, Stmt, Value, TYPE_NAMES};
+use crate::visitor::{ExpressionVisitor, StatementVisitor};
+
+// Compact source that runs the same as `source`.  Comments and whitespace go, and every variable
+// declared inside a block gets a short name.  Globals keep theirs since a host or another script
+// may know them by name.
+//
+// Scoping follows the resolver: a local is renamed from its declaration to the end of its block,
+// so a read before the declaration still refers to whatever was visible outside.
+pub fn minify(source: String) -> Result<String, String> {
+    let scan = Scanner::new(source).scan();
+    if let Some(error) = scan.1.first() {
+        return Err(error.to_string());
+    }
+
+    // a short name must not collide with anything the script mentions
+    let taken = scan
+        .0
+        .iter()
+        .filter_map(|token| match &token.lexeme {
+            LexemeKind::IDENTIFIER(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let stmts = Parser::scanned(scan).parse();
+    let mut minifier = Minifier {
+        scopes: vec![],
+        taken,
+        next_name: 0,
+        error: None,
+    };
+
+    let out: String = stmts.iter().map(|stmt| stmt.accept(&mut minifier)).collect();
+
+    match minifier.error {
+        Some(error) => Err(error),
+        None => Ok(out),
+    }
+}
+
+struct Minifier {
+    // original -> short name, for each block we are inside of
+    scopes: Vec<HashMap<String, String>>,
+    taken: HashSet<String>,
+    next_name: usize,
+    error: Option<String>,
+}
+
+impl Minifier {
+    fn name(&self, name: &str) -> String {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .unwrap_or_else(|| lexer::escape_identifier(name))
+    }
+
+    // a, b, ... z, aa, ab, ... skipping keywords and anything the script already uses
+    fn fresh_name(&mut self) -> String {
+        loop {
+            let mut n = self.next_name;
+            self.next_name += 1;
+
+            let mut name = String::new();
+            loop {
+                name.insert(0, (b'a' + (n % 26) as u8) as char);
+                if n < 26 {
+                    break;
+                }
+                n = n / 26 - 1;
+            }
+
+            if !lexer::is_keyword(&name) && !self.taken.contains(&name) {
+                return name;
+            }
+        }
+    }
+}
+
+// a keyword next to a name or number needs a space between them, `-` next to `-` does too
+fn join(left: &str, right: &str) -> String {
+    let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '"');
+    let needs_space = (word(left.chars().last()) && word(right.chars().next()))
+        || (left.ends_with('-') && right.starts_with('-'));
+
+    if needs_space {
+        format!("{} {}", left, right)
+    } else {
+        format!("{}{}", left, right)
+    }
+}
+
+fn literal(val: &Value) -> String {
+    match val {
+        Value::BOOLEAN(b) => b.to_string(),
+        Value::STRING(s) => lexer::quote_string(s),
+        // there's no literal for these, only the globals.  -Infinity reads back as - Infinity
+        Value::NUMBER(n) if n.is_nan() => "NaN".to_string(),
+        Value::NUMBER(n) if n.is_infinite() => if *n > 0.0 { "Infinity" } else { "-Infinity" }.to_string(),
+        // Display for f64 never switches to exponents, so 1e300 would be 301 characters
+        Value::NUMBER(n) => {
+            let (plain, exponent) = (n.to_string(), format!("{:e}", n));
+            if exponent.len() < plain.len() {
+                exponent
+            } else {
+                plain
+            }
+        }
+        Value::Native(native) => native.name.to_string(),
+        Value::Module(module) => module.name().to_string(),
+        Value::RecordType(kind) => kind.name().to_string(),
+        // only ever folded in by a host, there's no source that spells one
+        Value::Record(record) => record.to_string(),
+        Value::Class(class) => class.name().to_string(),
+        Value::Instance(instance) => instance.to_string(),
+        Value::Method(method) => method.to_string(),
+        // there's no nil literal to write, but an empty grouping reads back as one
+        Value::Null => "()".to_string(),
+    }
+}
+
+impl ExpressionVisitor<String> for Minifier {
+    fn visit_assign(&mut self, name: &str, expr: &Expr, _line: &usize) -> String {
+        format!("{}={}", self.name(name), expr.accept(self))
+    }
+
+    fn visit_binary(&mut self, left: &Expr, operator: &LexemeKind, right: &Expr) -> String {
+        let left = join(&left.accept(self), &operator.source_text());
+        join(&left, &right.accept(self))
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], _line: &usize) -> String {
+        let args: Vec<String> = arguments.iter().map(|arg| arg.accept(self)).collect();
+        format!("{}({})", callee.accept(self), args.join(","))
+    }
+
+    fn visit_logical(&mut self, left: &Expr, operator: &LexemeKind, right: &Expr) -> String {
+        self.visit_binary(left, operator, right)
+    }
+
+    fn visit_literal(&mut self, val: &Value) -> String {
+        literal(val)
+    }
+
+    fn visit_unary(&mut self, operator: &LexemeKind, right: &Expr) -> String {
+        join(&operator.source_text(), &right.accept(self))
+    }
+
+    fn visit_grouping(&mut self, val: &Expr) -> String {
+        format!("({})", val.accept(self))
+    }
+
+    fn visit_variable(&mut self, ident: &str, _id: NodeId, _line: &usize) -> String {
+        self.name(ident)
+    }
+
+    fn visit_this(&mut self, _line: &usize) -> String {
+        "this".to_string()
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &str, optional: &bool, _line: &usize) -> String {
+        let dot = if *optional { "?." } else { "." };
+        format!("{}{}{}", object.accept(self), dot, lexer::escape_identifier(name))
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &str, value: &Expr, _line: &usize) -> String {
+        format!("{}.{}={}", object.accept(self), lexer::escape_identifier(name), value.accept(self))
+    }
+
+    fn visit_is(&mut self, value: &Expr, type_name: &str, _line: &usize) -> String {
+        // a record's type is a variable like any other, the built in ones aren't
+        let type_name = if TYPE_NAMES.contains(&type_name) { type_name.to_string() } else { self.name(type_name) };
+        join(&join(&value.accept(self), "is"), &type_name)
+    }
+
+    fn visit_await(&mut self, expr: &Expr, _line: &usize) -> String {
+        join("await", &expr.accept(self))
+    }
+
+    fn visit_if_expr(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>, _line: &usize) -> String {
+        StatementVisitor::visit_if(self, condition, then_branch, else_branch)
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) -> String {
+        self.error.get_or_insert_with(|| format!("{} [line: {}]", message, line));
+        String::new()
+    }
+}
+
+impl StatementVisitor<String> for Minifier {
+    fn visit_block(&mut self, stmts: &[Stmt]) -> String {
+        self.scopes.push(HashMap::new());
+        let body: String = stmts.iter().map(|stmt| stmt.accept(self)).collect();
+        self.scopes.pop();
+
+        format!("{{{}}}", body)
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) -> String {
+        let mut out = format!("if({})", condition.accept(self));
+        out.push_str(&then_branch.accept(self));
+        if let Some(else_branch) = else_branch {
+            out = join(&join(&out, "else"), &else_branch.accept(self));
+        }
+
+        out
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, _line: &usize) -> String {
+        format!("while({}){}", condition.accept(self), body.accept(self))
+    }
+
+    fn visit_variable_def(&mut self, ident: &str, expr: &Option<Expr>, _line: &usize) -> String {
+        // the initializer still sees the outer binding: var a = a;
+        let init = expr.as_ref().map(|expr| expr.accept(self));
+
+        let name = if self.scopes.is_empty() {
+            lexer::escape_identifier(ident)
+        } else {
+            let short = self.fresh_name();
+            self.scopes.last_mut().unwrap().insert(ident.to_string(), short.clone());
+            short
+        };
+
+        match init {
+            Some(init) => format!("var {}={};", name, init),
+            None => format!("var {};", name),
+        }
+    }
+
+    fn visit_record(&mut self, name: &str, fields: &[String], _line: &usize) -> String {
+        // fields are looked up by name, so only the record's own name can shrink
+        let name = if self.scopes.is_empty() {
+            lexer::escape_identifier(name)
+        } else {
+            let short = self.fresh_name();
+            self.scopes.last_mut().unwrap().insert(name.to_string(), short.clone());
+            short
+        };
+        let fields: Vec<String> = fields.iter().map(|field| lexer::escape_identifier(field)).collect();
+
+        format!("record {}{{{}}}", name, fields.join(","))
+    }
+
+    fn visit_class(&mut self, name: &str, methods: &Arc<[Function]>, _line: &usize) -> String {
+        // method names are looked up on the instance, so like fields they stay as they are
+        let name = if self.scopes.is_empty() {
+            lexer::escape_identifier(name)
+        } else {
+            let short = self.fresh_name();
+            self.scopes.last_mut().unwrap().insert(name.to_string(), short.clone());
+            short
+        };
+
+        let mut out = format!("class {}{{", name);
+        for method in methods.iter() {
+            self.scopes.push(HashMap::new());
+            let params: Vec<String> = method
+                .params
+                .iter()
+                .map(|param| {
+                    let short = self.fresh_name();
+                    self.scopes.last_mut().unwrap().insert(param.to_string(), short.clone());
+                    short
+                })
+                .collect();
+            let body: String = method.body.iter().map(|stmt| stmt.accept(self)).collect();
+            self.scopes.pop();
+
+            out.push_str(&format!("{}({}){{{}}}", lexer::escape_identifier(&method.name), params.join(","), body));
+        }
+        out.push('}');
+        out
+    }
+
+    fn visit_print(&mut self, exprs: &[Expr]) -> String {
+        let exprs: Vec<String> = exprs.iter().map(|expr| expr.accept(self)).collect();
+        format!("print({});", exprs.join(","))
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) -> String {
+        format!("{};", expr.accept(self))
+    }
+
+    fn visit_with(&mut self, ident: &str, init: &Expr, body: &Stmt, _line: &usize) -> String {
+        let init = init.accept(self);
+
+        // the resource is only ever in scope around the body
+        let name = self.fresh_name();
+        self.scopes.push(HashMap::from([(ident.to_string(), name.clone())]));
+        let body = body.accept(self);
+        self.scopes.pop();
+
+        format!("with(var {}={}){}", name, init, body)
+    }
+
+    fn visit_defer(&mut self, expr: &Expr, _line: &usize) -> String {
+        format!("defer {};", expr.accept(self))
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) -> String {
+        self.error.get_or_insert_with(|| format!("{} [line: {}]", message, line));
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn it_strips_whitespace_and_comments() {
+        let res = minify("
+// the answer
+var answer = 40 + 2;
+print(answer);
+if (answer == 42 and true) {
+    print(\"yes\");
+} else print(-answer);
+".to_string());
+        assert_eq!(res, Ok("var answer=40+2;print(answer);if(answer==42 and true){print(\"yes\");}else print(-answer);".to_string()));
+        assert_eq!(minify("print(1 != 2);".to_string()), Ok("print(1!=2);".to_string()));
+    }
+
+    #[test]
+    fn it_shortens_locals() {
+        let res = minify("
+var total = 0;
+{
+    var step = 2;
+    var a = step;
+    {
+        var step = total;
+        total = step + a;
+    }
+}
+".to_string());
+        // `a` is already a name in the script, so short names start at b
+        assert_eq!(res, Ok("var total=0;{var b=2;var c=b;{var d=total;total=d+c;}}".to_string()));
+    }
+
+    #[test]
+    fn it_keeps_keyword_names_escaped() {
+        let res = minify("var `class` = 1;\n{ var `if` = `class`; print(`if`); }".to_string());
+        assert_eq!(res, Ok("var `class`=1;{var a=`class`;print(a);}".to_string()));
+    }
+
+    #[test]
+    fn it_keeps_quotes_in_strings() {
+        let res = minify("print(\"\"\"say \"hi\" \"\"\" + r\"a\\b\");".to_string());
+        assert_eq!(res, Ok("print(\"\"\"say \"hi\" \"\"\"+\"a\\b\");".to_string()));
+    }
+
+    #[test]
+    fn it_writes_numbers_short() {
+        let res = minify("var a = 1e300 + 2.5e-3 + 1_000;".to_string());
+        assert_eq!(res, Ok("var a=1e300+0.0025+1e3;".to_string()));
+    }
+
+    #[test]
+    fn it_runs_the_same() {
+        let source = "
+var n = 0;
+var out = 0;
+while (n < 5) {
+    var doubled = n * 2;
+    out = out + doubled;
+    n = n + 1;
+}
+out;
+";
+        // the value of the last statement
+        let run = |source: String| {
+            let tokens = Scanner::new(source).collect();
+            Interpreter::new().start(Parser::new(tokens).parse())
+        };
+
+        let minified = minify(source.to_string()).unwrap();
+        assert!(minified.len() < source.len());
+        assert_eq!(run(minified), run(source.to_string()));
+        assert_eq!(run(source.to_string()), Ok(Value::NUMBER(20.0)));
+    }
+
+    #[test]
+    fn it_refuses_broken_source() {
+        assert!(minify("var a = 1.2.3;".to_string()).is_err());
+        assert!(minify("print(1".to_string()).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use crate::parser::Stmt;
+
+// One thing a script declares, the way an editor lists a document's structure.  `kind` is var,
+// record, field, class or method, and there'll be fun once the parser knows it.  Lines count
+// from 0
+#[derive(Debug, PartialEq)]
+pub struct SymbolInfo {
+    pub kind: &'static str,
+    pub name: String,
+    pub line: usize,
+    // what's declared inside it, a record's fields or a class's methods
+    pub children: Vec<SymbolInfo>,
+}
+
+impl fmt::Display for SymbolInfo {
+    // one line each, children indented under their parent
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} [line: {}]", self.kind, self.name, self.line)?;
+        for child in &self.children {
+            for line in child.to_string().lines() {
+                write!(f, "\n  {}", line)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The script's top level declarations in the order they're written.  Blocks, ifs and loops are
+// scopes of their own rather than symbols, so what's declared in them is left out
+pub fn outline(stmts: &[Stmt]) -> Vec<SymbolInfo> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::VariableDef { ident, line, .. } => Some(SymbolInfo { kind: "var", name: ident.clone(), line: *line, children: vec![] }),
+            Stmt::Record { name, fields, line } => Some(SymbolInfo {
+                kind: "record",
+                name: name.clone(),
+                line: *line,
+                // fields are on the record's line as far as the tree knows
+                children: fields
+                    .iter()
+                    .map(|field| SymbolInfo { kind: "field", name: field.clone(), line: *line, children: vec![] })
+                    .collect(),
+            }),
+            Stmt::Class { name, methods, line } => Some(SymbolInfo {
+                kind: "class",
+                name: name.clone(),
+                line: *line,
+                children: methods
+                    .iter()
+                    .map(|method| SymbolInfo { kind: "method", name: method.name.clone(), line: method.line, children: vec![] })
+                    .collect(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    #[test]
+    fn it_outlines_top_level_declarations() {
+        let source = "var a = 1;\nrecord Point { x, y }\n{ var hidden = 2; }\nvar b;\nclass Greeter {\n  hello() {}\n}";
+        let symbols = outline(&Parser::new(Scanner::new(source.to_owned()).collect()).parse());
+
+        let listed: Vec<String> = symbols.iter().map(|symbol| symbol.to_string()).collect();
+        assert_eq!(
+            listed,
+            vec![
+                "var a [line: 0]",
+                "record Point [line: 1]\n  field x [line: 1]\n  field y [line: 1]",
+                "var b [line: 3]",
+                "class Greeter [line: 4]\n  method hello [line: 5]",
+            ]
+        );
+    }
+}
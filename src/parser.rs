@@ -1,32 +1,61 @@
+#[cfg(feature = "std")]
+pub mod cache;
 pub(crate) mod expression;
+pub(crate) mod precedence;
 pub(crate) mod statement;
 
-use crate::lexer::{LexemeKind, Token};
-pub use expression::{Expr, Value};
-pub use statement::Stmt;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use crate::lexer::{LexemeKind, ScanError, Token};
+pub use expression::{Expr, NodeId, Value, TYPE_NAMES};
+pub use precedence::{Associativity, Precedence, OPERATORS, PREFIX_OPERATORS};
+pub use statement::{Function, Stmt};
 
 #[derive(Debug)]
-pub(crate) struct Parser {
+pub struct Parser {
     tokens: Vec<Token>,
+    // what the scan made of the UNEXPECTED tokens, one each and in the same order.  Along with
+    // how many of them come before the token last looked up, so the next lookup, which is nearly
+    // always further on, only counts the ones in between
+    scan_errors: Vec<ScanError>,
+    unexpected_before: Cell<(usize, usize)>,
     pub cursor: usize,
+    // where parse_with_fuel has to stop, and whether anything tried to look past it
+    fuel_end: Option<usize>,
+    starved: Cell<bool>,
+    // how many operands and statements deep we are, and how deep the stack has room for
+    depth: usize,
+    max_depth: usize,
 }
 
+// Roughly what one more level of nesting, ((1)) over (1), costs the parser in stack.  That's a
+// debug build's worth with room to spare, a release build gets by on far less
+pub const STACK_PER_LEVEL: usize = 16 * 1024;
+// what a parse assumes it has to work with unless it's told otherwise, a spawned thread's default
+pub const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+// only the interpreter walks trees this way
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
 pub(crate) fn debug_tree(ast: &Stmt) -> String {
     let mut st = String::new();
-    st.push_str("(");
+    st.push('(');
     if let Stmt::Expr(Expr::Binary {
         left,
         operator,
         right,
     }) = ast
     {
-        let op = operator.to_string();
+        let op = operator.source_text();
         st.push_str(&op);
-        st.push_str(" ");
+        st.push(' ');
 
         let l = &(*left).debug();
         st.push_str(l);
-        st.push_str(" ");
+        st.push(' ');
 
         let r = &(*right).debug();
         st.push_str(r);
@@ -34,31 +63,106 @@ pub(crate) fn debug_tree(ast: &Stmt) -> String {
         // println!("Not an expression");
     }
 
-    st.push_str(")");
+    st.push(')');
     st
 }
 
+// x |> f is f(x), and x |> f(a) is f(x, a), the way a chain of transformations reads best.  A
+// call that hands back the function to pipe into needs parens, x |> (make()) is make()(x)
+fn piped(argument: Expr, callee: Expr, line: usize) -> Expr {
+    match callee {
+        Expr::Call { callee, mut arguments, line } => {
+            arguments.insert(0, argument);
+            Expr::Call { callee, arguments, line }
+        }
+        callee => Expr::Call { callee: Box::new(callee), arguments: vec![argument], line },
+    }
+}
+
 impl Parser {
-    pub(crate) fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, cursor: 0 }
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            scan_errors: vec![],
+            unexpected_before: Cell::new((0, 0)),
+            cursor: 0,
+            fuel_end: None,
+            starved: Cell::new(false),
+            depth: 0,
+            max_depth: DEFAULT_STACK_SIZE / STACK_PER_LEVEL,
+        }
+    }
+
+    // How much stack the thread doing the parse has, in bytes.  Anything nested deeper than that
+    // has room for is an error in the tree rather than a crash
+    pub fn with_stack_size(mut self, bytes: usize) -> Self {
+        self.max_depth = (bytes / STACK_PER_LEVEL).max(1);
+        self
+    }
+
+    // What Scanner::scan hands back, so an error node where the scan went wrong says what it
+    // found wrong rather than just which text it couldn't parse
+    pub fn scanned((tokens, scan_errors): (Vec<Token>, Vec<ScanError>)) -> Self {
+        Self { scan_errors, ..Self::new(tokens) }
+    }
+
+    pub fn scan_errors(&self) -> &[ScanError] {
+        &self.scan_errors
     }
 
     // ultimately, we execute a list of statements
-    pub(crate) fn parse(&mut self) -> Vec<Stmt> {
+    pub fn parse(&mut self) -> Vec<Stmt> {
         let mut stmts = Vec::new();
         while !self.at_end() {
+            let start = self.cursor;
+            self.starved.set(false);
             let res = statement::parse(self);
+            // out of fuel part way through, it's the next parse's to finish
+            if self.starved.get() {
+                self.cursor = start;
+                break;
+            }
 
+            // whatever couldn't be parsed is an error in the tree rather than a gap in it, and is
+            // stepped over so the statements after it are still there for tooling
+            let stmt = res.unwrap_or_else(|| Stmt::Error { line: self.line(), message: "Expected a statement".to_string() });
+            if self.cursor == start {
+                self.cursor += 1;
+            }
             self.eat_whitespace();
 
-            stmts.push(res.unwrap());
+            stmts.push(stmt);
         }
 
         stmts
     }
 
+    // Parse no further than `fuel` tokens on, so an editor can keep a pathological file from
+    // holding up typing.  What's handed back is every statement that fit whole, along with the
+    // token after it the parser peeks at to see it has ended.  The cursor is left at the start
+    // of the one that didn't: at_end() says whether there's more, and another parse, with fuel
+    // or without, carries on from there.  A statement longer than `fuel` never fits, leave that
+    // to a parse without
+    pub fn parse_with_fuel(&mut self, fuel: usize) -> Vec<Stmt> {
+        self.fuel_end = Some(self.cursor + fuel);
+        let stmts = self.parse();
+        self.fuel_end = None;
+        stmts
+    }
+
+    // How tightly `kind` binds between or after operands and which way it groups, None if it
+    // isn't that kind of operator.  A - or + ahead of an operand is Precedence::Unary instead
+    pub fn precedence_of(kind: LexemeKind) -> Option<(Precedence, Associativity)> {
+        precedence::lookup(&kind)
+    }
+
     pub fn at_end(&self) -> bool {
-        self.peek_kind() == Some(LexemeKind::EOF) || self.peek_kind() == None
+        self.peek_kind() == Some(LexemeKind::EOF) || self.peek_kind().is_none()
+    }
+
+    // the line of what's next, or of the last token once there's nothing left, for errors
+    fn line(&self) -> usize {
+        self.tokens.get(self.cursor).or_else(|| self.tokens.last()).map_or(0, |token| token.line)
     }
 
     fn last_token(&self) -> Option<&Token> {
@@ -66,12 +170,15 @@ impl Parser {
     }
 
     fn peek(&self) -> Option<&Token> {
+        if self.fuel_end.is_some_and(|end| self.cursor >= end) {
+            self.starved.set(true);
+            return None;
+        }
         self.tokens.get(self.cursor)
     }
 
     fn peek_kind(&self) -> Option<LexemeKind> {
-        self.peek()
-            .and_then(|Token { lexeme, .. }| Some(lexeme.clone()))
+        self.peek().map(|Token { lexeme, .. }| lexeme.clone())
     }
 
     fn expect(&mut self, kind: LexemeKind) -> Result<(), Option<Expr>> {
@@ -84,7 +191,7 @@ impl Parser {
         }
 
         // no token
-        Err(self.error(0, &format!("Unexpected token")))
+        Err(self.error(0, "Unexpected token"))
     }
 
     fn at(&self, kind: LexemeKind) -> bool {
@@ -100,10 +207,34 @@ impl Parser {
         }
     }
 
+    // what the scan said about the token at `index`, if it's UNEXPECTED and the scan's errors
+    // were handed over
+    fn scan_error(&self, index: usize) -> Option<&ScanError> {
+        if !matches!(self.tokens.get(index)?.lexeme, LexemeKind::UNEXPECTED(_)) {
+            return None;
+        }
+        // a parse with fuel can go back and start a statement over, count again from the top then
+        let (from, mut earlier) = match self.unexpected_before.get() {
+            (from, earlier) if from <= index => (from, earlier),
+            _ => (0, 0),
+        };
+        earlier += self.tokens[from..index].iter().filter(|token| matches!(token.lexeme, LexemeKind::UNEXPECTED(_))).count();
+        self.unexpected_before.set((index, earlier));
+        self.scan_errors.get(earlier)
+    }
+
     fn error(&self, line: usize, msg: &str) -> Option<Expr> {
         Some(Expr::Error { line, message: msg.to_string() })
     }
 
+    // at an operator on `level` of the precedence table
+    fn at_level(&self, level: Precedence) -> bool {
+        match self.peek_kind() {
+            Some(kind) => matches!(precedence::lookup(&kind), Some((precedence, _)) if precedence == level),
+            None => false,
+        }
+    }
+
     fn is_equal(&self, kinds: Vec<LexemeKind>) -> bool {
         let res = kinds.iter().find(|&k| self.at(k.clone()));
         res.is_some()
@@ -115,24 +246,25 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Option<Expr> {
-        let mut expr = self.or();
+        let mut expr = self.binary(Precedence::Pipe);
 
         self.eat_whitespace();
 
-        while self.is_equal(vec![LexemeKind::Equal]) {
+        while self.at_level(Precedence::Assignment) {
             self.cursor += 1; // EQUAL
 
             self.eat_whitespace();
 
-            if let Some(Expr::Variable(st)) = expr {
+            if let Some(Expr::Variable { name, line, .. }) = expr {
                 // this came from fn primary()
                 // recursive call in case a = b = 1;
                 let right = self.assignment();
                 match right {
                     Some(r) => {
                         expr = Some(Expr::Assign {
-                            name: st,
+                            name,
                             expr: Box::new(r),
+                            line,
                         });
 
                         let _ = self.expect(LexemeKind::Semicolon);
@@ -142,6 +274,19 @@ impl Parser {
                         expr = self.error(last_token.line, "Unfinished right hand assignment expression");
                     }
                 }
+            } else if let Some(Expr::Get { object, name, optional: false, line }) = expr {
+                // obj.x = 1, the same again for a field
+                match self.assignment() {
+                    Some(value) => {
+                        expr = Some(Expr::Set { object, name, value: Box::new(value), line });
+
+                        let _ = self.expect(LexemeKind::Semicolon);
+                    }
+                    None => {
+                        let last_token = self.last_token().unwrap();
+                        expr = self.error(last_token.line, "Unfinished right hand assignment expression");
+                    }
+                }
             } else {
                 let last_token = self.last_token().unwrap();
                 expr = self.error(last_token.line, "Invalid left hand assignment expression");
@@ -151,152 +296,153 @@ impl Parser {
         expr
     }
 
-    fn or(&mut self) -> Option<Expr> {
-        let mut expr = self.and();
+    // An operand and whatever operators follow it, as long as they bind at least as tightly as
+    // `min`.  Each operator's right hand side is parsed the same way, starting a level tighter
+    // for one that groups to the left, so a - b - c is (a - b) - c but a + b * c keeps b * c
+    // together.  Which operator sits on which level is all in the precedence table
+    fn binary(&mut self, min: Precedence) -> Option<Expr> {
+        let mut expr = self.unary();
 
         self.eat_whitespace();
 
-        while self.is_equal(vec![LexemeKind::OR]) {
-            let operator = self.peek_kind().unwrap();
+        while let Some((operator, precedence, associativity)) = self.infix(min) {
+            let line = self.peek().unwrap().line;
             self.cursor += 1;
-            let right = self.and();
-            expr = Some(Expr::Logical {
-                left: Box::new(expr.unwrap()),
-                operator,
-                right: Box::new(right.unwrap()),
-            });
-        }
-
-        expr
-    }
 
-    fn and(&mut self) -> Option<Expr> {
-        let mut expr = self.equality();
+            let left = match expr {
+                Some(left) => Box::new(left),
+                None => return self.error(line, &format!("Expected an expression before {}", operator.source_text())),
+            };
+
+            // the right of an is names a type, it isn't an expression
+            if operator == LexemeKind::IS {
+                self.eat_whitespace();
+                match self.peek_kind() {
+                    Some(LexemeKind::IDENTIFIER(type_name)) => {
+                        self.cursor += 1;
+                        expr = Some(Expr::Is { value: left, type_name, line });
+                    }
+                    _ => return self.error(line, "Expected a type name after is"),
+                }
 
-        self.eat_whitespace();
+                self.eat_whitespace();
+                continue;
+            }
 
-        while self.is_equal(vec![LexemeKind::AND]) {
-            let operator = self.peek_kind().unwrap();
-            self.cursor += 1;
-            let right = self.equality();
-            expr = Some(Expr::Logical {
-                left: Box::new(expr.unwrap()),
-                operator,
-                right: Box::new(right.unwrap()),
+            let next = match associativity {
+                Associativity::Left => precedence.tighter(),
+                Associativity::Right => precedence,
+            };
+            // 1 + at the end of the file, or of what's been typed so far
+            let right = match self.binary(next) {
+                Some(right) => Box::new(right),
+                None => return self.error(line, &format!("Expected an expression after {}", operator.source_text())),
+            };
+            expr = Some(match precedence {
+                Precedence::Pipe => piped(*left, *right, line),
+                // a ?? b, and and or only evaluate their right when they need it
+                Precedence::Coalesce | Precedence::Or | Precedence::And => Expr::Logical { left, operator, right },
+                _ => Expr::Binary { left, operator, right },
             });
         }
 
         expr
     }
 
-    fn equality(&mut self) -> Option<Expr> {
-        let mut expr = self.comparison();
-
-        self.eat_whitespace();
-
-        while self.is_equal(vec![LexemeKind::BangEqual, LexemeKind::EqualEqual]) {
-            let operator = self.peek_kind().unwrap();
-            self.cursor += 1;
-            let right = self.comparison();
-            expr = Some(Expr::Binary {
-                left: Box::new(expr.unwrap()),
-                operator,
-                right: Box::new(right.unwrap()),
-            })
+    // The operator at the cursor if it goes between two operands and binds at least as tightly
+    // as `min`.  Assignment has a rule of its own, and calls and property reads are parsed
+    // along with their operand
+    fn infix(&self, min: Precedence) -> Option<(LexemeKind, Precedence, Associativity)> {
+        let kind = self.peek_kind()?;
+        match precedence::lookup(&kind)? {
+            (precedence, associativity) if precedence >= min && precedence > Precedence::Assignment && precedence < Precedence::Unary => {
+                Some((kind, precedence, associativity))
+            }
+            _ => None,
         }
-
-        expr
     }
 
-    fn comparison(&mut self) -> Option<Expr> {
-        let mut expr = self.term();
-
-        self.eat_whitespace();
-
-        while self.is_equal(vec![
-            LexemeKind::Greater,
-            LexemeKind::GreaterEqual,
-            LexemeKind::Less,
-            LexemeKind::LessEqual,
-        ]) {
-            let operator = self.peek_kind().unwrap();
-
-            self.cursor += 1;
-
-            let right = self.term();
-            expr = Some(Expr::Binary {
-                left: Box::new(expr.unwrap()),
-                operator,
-                right: Box::new(right.unwrap()),
-            })
+    // One level further in, or, when that's deeper than the stack has room for, the line of
+    // whatever is there once it's been stepped over
+    pub(crate) fn enter(&mut self) -> Result<(), usize> {
+        if self.depth >= self.max_depth {
+            let line = self.line();
+            self.skip_nested();
+            return Err(line);
         }
 
-        expr
+        self.depth += 1;
+        Ok(())
     }
 
-    fn term(&mut self) -> Option<Expr> {
-        let mut expr = self.factor();
-
-        self.eat_whitespace();
-
-        while self.is_equal(vec![LexemeKind::Minus, LexemeKind::Plus]) {
-            let operator = self.peek_kind().unwrap();
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
+    }
 
+    // Step over the operand or statement at the cursor without parsing it.  That's up to the
+    // bracket it's inside of closing, or the end of its statement, so the levels it's nested in
+    // still find their closing brackets and carry on from there
+    fn skip_nested(&mut self) {
+        let mut open = 0;
+        while let Some(kind) = self.peek_kind() {
+            match kind {
+                LexemeKind::EOF => break,
+                LexemeKind::LeftParen | LexemeKind::LeftBrace => open += 1,
+                LexemeKind::RightParen | LexemeKind::RightBrace | LexemeKind::Semicolon if open == 0 => break,
+                LexemeKind::RightParen | LexemeKind::RightBrace => open -= 1,
+                _ => {}
+            }
             self.cursor += 1;
-
-            let right = self.factor();
-            expr = Some(Expr::Binary {
-                left: Box::new(expr.unwrap()), // 1
-                operator, // +
-                right: Box::new(right.unwrap()), // 1
-            })
         }
-
-        expr
     }
 
-    fn factor(&mut self) -> Option<Expr> {
-        let mut expr = self.unary();
-
-        self.eat_whitespace();
-
-        while self.is_equal(vec![LexemeKind::Slash, LexemeKind::Star]) {
-            let operator = self.peek_kind().unwrap();
-            self.cursor += 1;
-            let right = self.unary();
-            expr = Some(Expr::Binary {
-                left: Box::new(expr.unwrap()),
-                operator,
-                right: Box::new(right.unwrap()),
-            })
+    fn unary(&mut self) -> Option<Expr> {
+        if let Err(line) = self.enter() {
+            return self.error(line, "Expression nested too deeply");
         }
 
-        expr
+        let res = self.operand();
+        self.leave();
+        res
     }
 
-    fn unary(&mut self) -> Option<Expr> {
+    // a prefix operator and what it applies to, or a call, or something simpler
+    fn operand(&mut self) -> Option<Expr> {
         let mut res = None;
 
         self.eat_whitespace();
 
-        while self.is_equal(vec![LexemeKind::Bang, LexemeKind::Minus, LexemeKind::Plus]) {
-            let operator = self.peek_kind().unwrap();
+        // as tight as ! and -, so `await f() + 1` adds to what f's future finishes with
+        if self.at(LexemeKind::AWAIT) {
+            let line = self.peek().unwrap().line;
+            self.cursor += 1;
+            return match self.unary() {
+                Some(expr) => Some(Expr::Await { expr: Box::new(expr), line }),
+                None => self.error(line, "Expected something to wait for after await"),
+            };
+        }
+
+        while self.is_equal(PREFIX_OPERATORS.to_vec()) {
+            let (operator, line) = self.peek().map(|token| (token.lexeme.clone(), token.line)).unwrap();
 
             self.cursor += 1;
 
-            let new = self.unary();
+            let new = match self.unary() {
+                Some(new) => new,
+                None => return self.error(line, &format!("Expected an expression after {}", operator.source_text())),
+            };
             match res {
                 Some(Expr::Unary { operator, right }) => {
                     res = Some(Expr::Binary {
                         left: right,
                         operator: operator.clone(),
-                        right: Box::new(new.unwrap()),
+                        right: Box::new(new),
                     });
                 },
                 _ => {
                     res = Some(Expr::Unary {
                         operator,
-                        right: Box::new(new.unwrap()),
+                        right: Box::new(new),
                     });
                 }
             }
@@ -305,22 +451,99 @@ impl Parser {
         if res.is_some() {
             res
         } else {
-            let res = self.primary();
+            let res = self.call();
             let token = self.tokens.get(self.cursor);
-            if let Some(Token { lexeme: LexemeKind::UNEXPECTED(l), line }) = token {
+            if let Some(Token { lexeme: LexemeKind::UNEXPECTED(l), line, .. }) = token {
+                let line = *line;
+                let message = match self.scan_error(self.cursor) {
+                    Some(error) => error.message.clone(),
+                    None => format!("Parsing error at {}", l),
+                };
                 self.cursor += 1;
-                self.error(*line, &format!("Parsing error at {}", l))
+                self.error(line, &message)
             } else {
                 res
             }
         }
     }
 
+    fn call(&mut self) -> Option<Expr> {
+        let mut expr = self.primary();
+
+        self.eat_whitespace();
+
+        // clock() or a chain like make()() or fs.read() or fs?.read()
+        while expr.is_some() && self.at_level(Precedence::Call) {
+            let line = self.peek().unwrap().line;
+            self.cursor += 1;
+
+            let dot = self.tokens[self.cursor - 1].lexeme.clone();
+            if dot != LexemeKind::LeftParen {
+                self.eat_whitespace();
+                match self.peek_kind() {
+                    Some(LexemeKind::IDENTIFIER(name)) => {
+                        self.cursor += 1;
+                        let optional = dot == LexemeKind::QuestionDot;
+                        expr = Some(Expr::Get { object: Box::new(expr.unwrap()), name, optional, line });
+                    }
+                    _ => return self.error(line, &format!("Expected a name after {}", dot.source_text())),
+                }
+
+                self.eat_whitespace();
+                continue;
+            }
+
+            let arguments = match self.arguments() {
+                Ok(arguments) => arguments,
+                Err(error) => return error,
+            };
+
+            expr = Some(Expr::Call {
+                callee: Box::new(expr.unwrap()),
+                arguments,
+                line,
+            });
+
+            self.eat_whitespace();
+        }
+
+        expr
+    }
+
+    // comma separated expressions up to and including the closing paren
+    fn arguments(&mut self) -> Result<Vec<Expr>, Option<Expr>> {
+        let mut arguments = vec![];
+
+        self.eat_whitespace();
+
+        if !self.at(LexemeKind::RightParen) {
+            loop {
+                match self.expression() {
+                    Some(arg) => arguments.push(arg),
+                    None => {
+                        let line = self.last_token().map_or(0, |t| t.line);
+                        return Err(self.error(line, "Unfinished argument list"));
+                    }
+                }
+
+                self.eat_whitespace();
+
+                if !self.at(LexemeKind::Comma) {
+                    break;
+                }
+                self.cursor += 1;
+                self.eat_whitespace();
+            }
+        }
+
+        self.expect(LexemeKind::RightParen)?;
+
+        Ok(arguments)
+    }
+
     fn primary(&mut self) -> Option<Expr> {
         // first check if we have something to look at
-        if self.peek_kind() == None {
-            return None;
-        }
+        self.peek_kind()?;
 
         let token = self.tokens.get(self.cursor).unwrap();
         match &token.lexeme {
@@ -334,7 +557,7 @@ impl Parser {
             }
             LexemeKind::STRING(st) => {
                 self.cursor += 1;
-                Some(Expr::Literal(Value::STRING(st.to_string())))
+                Some(Expr::Literal(Value::STRING(st.as_str().into())))
             }
             LexemeKind::NUMBER(num) => {
                 self.cursor += 1;
@@ -343,16 +566,34 @@ impl Parser {
             LexemeKind::IDENTIFIER(st) => {
                 self.cursor += 1;
                 // this will be used by the fn assignment
-                Some(Expr::Variable(st.to_string()))
+                Some(Expr::variable(st, token.line))
+            }
+            LexemeKind::THIS => {
+                self.cursor += 1;
+                Some(Expr::This { line: token.line })
+            }
+            LexemeKind::IF => {
+                let line = token.line;
+                self.cursor += 1;
+
+                // the same if as the statement, only somewhere a value is wanted
+                match statement::if_statement(self)? {
+                    Stmt::If { condition, then_branch, else_branch } => Some(Expr::If {
+                        condition: Box::new(condition),
+                        then_branch,
+                        else_branch,
+                        line,
+                    }),
+                    _ => unreachable!(),
+                }
             }
             LexemeKind::LeftParen => {
                 self.cursor += 1;
 
-                // empty print stmt - print()
+                // () is nothing at all, which is nil
                 if self.peek_kind() == Some(LexemeKind::RightParen) {
-                    return Some(Expr::Grouping(
-                        Box::new(Expr::Literal(Value::STRING("".to_string()))),
-                    ));
+                    self.cursor += 1;
+                    return Some(Expr::Literal(Value::Null));
                 }
 
                 let expr = self.expression();
@@ -363,14 +604,23 @@ impl Parser {
                         let last_token = self.last_token().unwrap();
                         self.error(last_token.line, &format!("~~Parsing error at {}", last_token.lexeme))
                     }
-                    ex => Some(Expr::Grouping(
-                        Box::new(ex.unwrap())
-                    )),
+                    Some(ex) => {
+                        self.eat_whitespace();
+                        if let Err(error) = self.expect(LexemeKind::RightParen) {
+                            return error;
+                        }
+                        Some(Expr::Grouping(Box::new(ex)))
+                    }
                 }
             }
             m => {
+                let message = match self.scan_error(self.cursor) {
+                    Some(error) => error.message.clone(),
+                    None => format!("Parsing error at {}", m),
+                };
+                let line = token.line;
                 self.cursor += 1;
-                self.error(token.line, &format!("Parsing error at {}", m))
+                self.error(line, &message)
             }
         }
     }
@@ -384,7 +634,7 @@ mod test {
     #[test]
     fn it_handles_binary() {
         let tokens = Scanner::new("1+1".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Binary {
@@ -395,7 +645,7 @@ mod test {
         );
 
         let tokens = Scanner::new("1 == 1".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Binary {
@@ -409,7 +659,7 @@ mod test {
     #[test]
     fn it_handles_co() {
         let tokens = Scanner::new("1 >= 2".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Binary {
@@ -420,7 +670,7 @@ mod test {
         );
 
         let tokens = Scanner::new("1 <= 2".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Binary {
@@ -431,10 +681,49 @@ mod test {
         );
     }
 
+    // the tree with every operator's operands in parens, so the grouping shows
+    fn grouped(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary { left, operator, right } | Expr::Logical { left, operator, right } => {
+                format!("({} {} {})", grouped(left), operator.source_text(), grouped(right))
+            }
+            Expr::Is { value, type_name, .. } => format!("({} is {})", grouped(value), type_name),
+            Expr::Unary { operator, right } => format!("({}{})", operator.source_text(), grouped(right)),
+            Expr::Variable { name, .. } => name.to_string(),
+            Expr::Call { callee, arguments, .. } => {
+                let arguments: Vec<String> = arguments.iter().map(grouped).collect();
+                format!("{}({})", grouped(callee), arguments.join(", "))
+            }
+            Expr::Get { object, name, .. } => format!("{}.{}", grouped(object), name),
+            Expr::Set { object, name, value, .. } => format!("({}.{} = {})", grouped(object), name, grouped(value)),
+            Expr::Grouping(expr) => grouped(expr),
+            expr => expr.debug(),
+        }
+    }
+
+    #[test]
+    fn it_groups_by_precedence() {
+        let parse = |source: &str| match Parser::new(Scanner::new(source.to_owned()).collect()).parse().pop() {
+            Some(Stmt::Expr(expr)) => grouped(&expr),
+            stmt => panic!("not an expression: {:?}", stmt),
+        };
+
+        assert_eq!(parse("a - b - c"), "((a - b) - c)");
+        assert_eq!(parse("a + b * c - d / e"), "((a + (b * c)) - (d / e))");
+        assert_eq!(parse("a ?? b or c and d == e < f + g * -h"), "(a ?? (b or (c and (d == (e < (f + (g * (-h))))))))");
+        assert_eq!(parse("a * b == c is Number or d"), "(((a * b) == (c is Number)) or d)");
+        assert_eq!(parse("a < b == c > d"), "((a < b) == (c > d))");
+
+        assert_eq!(parse("a |> f |> g"), "g(f(a))");
+        assert_eq!(parse("a + b |> f(c) |> m.g"), "m.g(f((a + b), c))");
+        assert_eq!(parse("a |> (make())"), "make()(a)");
+        assert_eq!(parse("a ?? b |> f"), "f((a ?? b))");
+    }
+
     #[test]
     fn it_handles_unary() {
         let tokens = Scanner::new("-1".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Unary {
@@ -444,7 +733,7 @@ mod test {
         );
 
         let tokens = Scanner::new("+1".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Unary {
@@ -457,7 +746,7 @@ mod test {
     #[test]
     fn it_errors_keyword() {
         let tokens = Scanner::new("and".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Error { line: 0, message: "Parsing error at AND".to_string() })
@@ -467,17 +756,17 @@ mod test {
     #[test]
     fn not_expression() {
         let tokens = Scanner::new("a".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
-            Stmt::Expr(Expr::Variable("a".to_string()))
+            Stmt::Expr(Expr::variable("a", 0))
         );
     }
 
     #[test]
     fn it_works_parenthesized_expression() {
         let tokens = Scanner::new("(1+1)".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Grouping(
@@ -490,10 +779,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_parses_empty_parens_as_nil() {
+        let tokens = Scanner::new("var x = (); print(x); print(1, f(2));".to_owned()).collect();
+        assert_eq!(
+            Parser::new(tokens).parse(),
+            vec![
+                Stmt::VariableDef { ident: "x".to_string(), expr: Some(Expr::Literal(Value::Null)), line: 0 },
+                Stmt::Print(vec![Expr::variable("x", 0)]),
+                Stmt::Print(vec![
+                    Expr::Literal(Value::NUMBER(1.0)),
+                    Expr::Call {
+                        callee: Box::new(Expr::variable("f", 0)),
+                        arguments: vec![Expr::Literal(Value::NUMBER(2.0))],
+                        line: 0,
+                    },
+                ]),
+            ]
+        );
+    }
+
     #[test]
     fn it_works_plus_plus() {
         let tokens = Scanner::new("+1+1".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Binary {
@@ -507,37 +816,37 @@ mod test {
     #[test]
     fn variables_semicolon() {
         let tokens = Scanner::new("var a;".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
-            Stmt::VariableDef { ident: "a".to_string(), expr: None}
+            Stmt::VariableDef { ident: "a".to_string(), expr: None, line: 0 }
         );
     }
 
     #[test]
     fn variables_no_semicolon() {
         let tokens = Scanner::new("var a".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
-            Stmt::VariableDef { ident: "a".to_string(), expr: None}
+            Stmt::VariableDef { ident: "a".to_string(), expr: None, line: 0 }
         );
     }
 
     #[test]
     fn assignment() {
         let tokens = Scanner::new("a = 2;".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
-            Stmt::Expr(Expr::Assign { name: "a".to_string(), expr: Box::new(Expr::Literal(Value::NUMBER(2.0))) })
+            Stmt::Expr(Expr::Assign { name: "a".to_string(), expr: Box::new(Expr::Literal(Value::NUMBER(2.0))), line: 0 })
         );
     }
 
     #[test]
     fn multiple_assignment() {
         let tokens = Scanner::new("a = b = 2;".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(
@@ -546,18 +855,32 @@ mod test {
                     expr: Box::new(
                         Expr::Assign {
                             name: "b".to_string(),
-                            expr: Box::new(Expr::Literal(Value::NUMBER(2.0)))
+                            expr: Box::new(Expr::Literal(Value::NUMBER(2.0))),
+                            line: 0,
                         }
-                    )
+                    ),
+                    line: 0,
                 }
             )
         );
     }
 
+    #[test]
+    fn field_assignment() {
+        let tokens = Scanner::new("a.b.c = d.e = f;\na?.b = 1;".to_owned()).collect();
+        let mut ast = Parser::new(tokens).parse().into_iter().map(|stmt| match stmt {
+            Stmt::Expr(expr) => grouped(&expr),
+            stmt => panic!("not an expression: {:?}", stmt),
+        });
+        assert_eq!(ast.next().unwrap(), "(a.b.c = (d.e = f))");
+        // there's nothing to set a field on when a ?. turned up nil
+        assert_eq!(ast.next().unwrap(), "Invalid left hand assignment expression");
+    }
+
     #[test]
     fn logical_and() {
         let tokens = Scanner::new("a = 2 and 5;".to_owned()).collect();
-        let ast = Parser::new(tokens).parse().into_iter().nth(0).unwrap();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
         assert_eq!(
             ast,
             Stmt::Expr(Expr::Assign {
@@ -566,8 +889,187 @@ mod test {
                     left: Box::new(Expr::Literal(Value::NUMBER(2.0))),
                     operator: LexemeKind::AND,
                     right: Box::new(Expr::Literal(Value::NUMBER(5.0))),
-                })
+                }),
+                line: 0,
             })
         );
     }
+
+    #[test]
+    fn it_parses_calls() {
+        let tokens = Scanner::new("clock()".to_owned()).collect();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
+        assert_eq!(
+            ast,
+            Stmt::Expr(Expr::Call {
+                callee: Box::new(Expr::variable("clock", 0)),
+                arguments: vec![],
+                line: 0,
+            })
+        );
+
+        let tokens = Scanner::new("add(1, 2 * 3)(4)".to_owned()).collect();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
+        assert_eq!(
+            ast,
+            Stmt::Expr(Expr::Call {
+                callee: Box::new(Expr::Call {
+                    callee: Box::new(Expr::variable("add", 0)),
+                    arguments: vec![
+                        Expr::Literal(Value::NUMBER(1.0)),
+                        Expr::Binary {
+                            left: Box::new(Expr::Literal(Value::NUMBER(2.0))),
+                            operator: LexemeKind::Star,
+                            right: Box::new(Expr::Literal(Value::NUMBER(3.0))),
+                        },
+                    ],
+                    line: 0,
+                }),
+                arguments: vec![Expr::Literal(Value::NUMBER(4.0))],
+                line: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn it_errors_unfinished_call() {
+        let tokens = Scanner::new("clock(1".to_owned()).collect();
+        let ast = Parser::new(tokens).parse().into_iter().next().unwrap();
+        assert_eq!(ast, Stmt::Expr(Expr::Error { line: 0, message: "Unexpected token".to_string() }));
+    }
+
+    #[test]
+    fn it_parses_lossless_tokens() {
+        let source = "var a = 1; // one\n// two\na = a + 1;";
+        let tokens = Scanner::new(source.to_owned()).collect();
+        let lossless = Scanner::lossless(source.to_owned()).collect();
+        assert_eq!(Parser::new(tokens).parse(), Parser::new(lossless).parse());
+    }
+
+    #[test]
+    fn it_puts_scan_errors_in_the_tree() {
+        let source = "var a = 1.2.3;\nvar b = · + 1;";
+        let stmts = Parser::scanned(Scanner::new(source.to_owned()).scan()).parse();
+        let errors: Vec<Option<&Expr>> = stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::VariableDef { expr, .. } => expr.as_ref(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(errors[0], Some(&Expr::Error { line: 0, message: "Invalid number: 1.2.3".to_string() }));
+        assert!(matches!(errors[1], Some(Expr::Binary { left, .. }) if **left == Expr::Error { line: 1, message: "Unexpected character: ·".to_string() }));
+
+        // each error to its own token, however many there are and however the parse gets there
+        let many: String = (0..50).map(|i| format!("var v{} = {}e;\n", i, i)).collect();
+        let messages = |stmts: Vec<Stmt>| -> Vec<String> {
+            stmts
+                .into_iter()
+                .map(|stmt| match stmt {
+                    Stmt::VariableDef { expr: Some(Expr::Error { message, .. }), .. } => message,
+                    stmt => panic!("{:?}", stmt),
+                })
+                .collect()
+        };
+        let expected: Vec<String> = (0..50).map(|i| format!("Malformed exponent: {}e", i)).collect();
+        assert_eq!(messages(Parser::scanned(Scanner::new(many.clone()).scan()).parse()), expected);
+        let mut parser = Parser::scanned(Scanner::new(many).scan());
+        let mut stmts = vec![];
+        while !parser.at_end() {
+            stmts.extend(parser.parse_with_fuel(16));
+        }
+        assert_eq!(messages(stmts), expected);
+
+        // without them it can only say what it couldn't parse
+        let stmts = Parser::new(Scanner::new(source.to_owned()).collect()).parse();
+        assert!(matches!(&stmts[0], Stmt::VariableDef { expr: Some(Expr::Error { message, .. }), .. } if message.starts_with("Parsing error at")));
+    }
+
+    #[test]
+    fn it_expects_an_operand_after_an_operator_at_the_end() {
+        let parse = |source: &str| match Parser::new(Scanner::new(source.to_owned()).collect()).parse().pop() {
+            Some(Stmt::Expr(Expr::Error { message, .. })) | Some(Stmt::VariableDef { expr: Some(Expr::Error { message, .. }), .. }) => message,
+            stmt => panic!("not an error: {:?}", stmt),
+        };
+
+        assert_eq!(parse("1 +"), "Expected an expression after +");
+        assert_eq!(parse("1 |>"), "Expected an expression after |>");
+        assert_eq!(parse("1 ??"), "Expected an expression after ??");
+        assert_eq!(parse("1 * 2 -"), "Expected an expression after -");
+        assert_eq!(parse("var x = 1 +"), "Expected an expression after +");
+        assert_eq!(parse("!"), "Expected an expression after !");
+        assert_eq!(parse("-"), "Expected an expression after -");
+
+        // only the operand that's missing is an error, the operator it's the operand of is still there
+        let stmts = Parser::new(Scanner::new("1 + !".to_owned()).collect()).parse();
+        assert!(matches!(&stmts[0], Stmt::Expr(Expr::Binary { right, .. }) if matches!(**right, Expr::Error { .. })));
+    }
+
+    #[test]
+    fn it_stops_short_of_nesting_deeper_than_the_stack_goes() {
+        let parens = format!("var a = {}1{};\nvar b = 2;", "(".repeat(10_000), ")".repeat(10_000));
+        let stmts = Parser::new(Scanner::new(parens).collect()).parse();
+        assert_eq!(stmts.len(), 2);
+        let mut expr = match &stmts[0] {
+            Stmt::VariableDef { expr: Some(expr), .. } => expr,
+            stmt => panic!("{:?}", stmt),
+        };
+        let mut depth = 0;
+        while let Expr::Grouping(inner) = expr {
+            expr = inner;
+            depth += 1;
+        }
+        assert_eq!(expr, &Expr::Error { line: 0, message: "Expression nested too deeply".to_string() });
+        assert!(depth < DEFAULT_STACK_SIZE / STACK_PER_LEVEL);
+        assert!(matches!(&stmts[1], Stmt::VariableDef { ident, .. } if ident == "b"));
+
+        // blocks go just as deep, and with more stack it goes deeper
+        let blocks = format!("{}print(1);{}", "{".repeat(10_000), "}".repeat(10_000));
+        assert!(format!("{:?}", Parser::new(Scanner::new(blocks).collect()).parse()).contains("Statement nested too deeply"));
+        let shallow = format!("{}1{};", "(".repeat(150), ")".repeat(150));
+        let stmts = Parser::new(Scanner::new(shallow.clone()).collect()).parse();
+        assert!(format!("{:?}", stmts).contains("nested too deeply"));
+        let stmts = Parser::new(Scanner::new(shallow).collect()).with_stack_size(8 * DEFAULT_STACK_SIZE).parse();
+        assert!(!format!("{:?}", stmts).contains("nested too deeply"));
+    }
+
+    #[test]
+    fn it_never_gives_two_variables_the_same_id() {
+        let ids = |source: &str| match Parser::new(Scanner::new(source.to_owned()).collect()).parse().pop() {
+            Some(Stmt::Expr(Expr::Binary { left, right, .. })) => match (*left, *right) {
+                (Expr::Variable { id: l, .. }, Expr::Variable { id: r, .. }) => [l.get(), r.get()],
+                other => panic!("{:?}", other),
+            },
+            stmt => panic!("{:?}", stmt),
+        };
+
+        let first = ids("a + a");
+        let second = ids("a + a");
+        assert_ne!(first[0], first[1]);
+        assert!(!second.contains(&first[0]) && !second.contains(&first[1]));
+        // though the trees are the same for all that
+        let parse = |source: &str| Parser::new(Scanner::new(source.to_owned()).collect()).parse();
+        assert_eq!(parse("a + a"), parse("a + a"));
+    }
+
+    #[test]
+    fn it_parses_on_another_thread() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Expr>();
+        assert_send_sync::<Stmt>();
+        assert_send_sync::<Token>();
+        assert_send_sync::<Value>();
+
+        let ast = std::thread::spawn(|| {
+            let tokens = Scanner::new("var a = \"shared\";".to_owned()).collect();
+            Parser::new(tokens).parse()
+        })
+        .join()
+        .unwrap();
+
+        let shared = std::sync::Arc::new(ast);
+        let other = shared.clone();
+        let len = std::thread::spawn(move || other.len()).join().unwrap();
+        assert_eq!(len, shared.len());
+    }
 }
@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use crate::lexer::{LexemeKind, Scanner};
+use super::{Expr, Function, NodeId, Parser, Stmt, Value};
+
+// Parsed trees keyed by a hash of the source they came from, so unchanged files skip scanning
+// and parsing.  Sources that don't scan cleanly are never cached; the caller needs those errors.
+
+// Bump whenever Expr, Stmt or the encoding below changes, old entries then just stop matching
+const FORMAT_VERSION: u8 = 3;
+const MAGIC: &[u8] = b"TWAST";
+
+// FNV-1a, chosen over DefaultHasher because disk entries have to hash the same across builds
+pub fn hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Scan and parse, or None if the source has scan errors
+fn parse(source: &str) -> Option<Vec<Stmt>> {
+    let mut parser = Parser::scanned(Scanner::new(source.to_string()).scan());
+    if !parser.scan_errors().is_empty() {
+        return None;
+    }
+
+    Some(parser.parse())
+}
+
+// For embedders that run the same scripts over and over in one process, on any thread
+#[derive(Default)]
+pub struct MemoryCache {
+    entries: HashMap<u64, Arc<Vec<Stmt>>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_or_parse(&mut self, source: &str) -> Option<Arc<Vec<Stmt>>> {
+        let key = hash(source);
+        if let Some(stmts) = self.entries.get(&key) {
+            return Some(stmts.clone());
+        }
+
+        let stmts = Arc::new(parse(source)?);
+        self.entries.insert(key, stmts.clone());
+        Some(stmts)
+    }
+}
+
+// Serialized trees in files named after the source hash
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    // $XDG_CACHE_HOME/tree-walk, falling back to ~/.cache/tree-walk
+    pub fn default_dir() -> Option<PathBuf> {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|cache| cache.join("tree-walk"))
+    }
+
+    fn path(&self, source: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.ast", hash(source)))
+    }
+
+    // A stale, corrupt or unwritable entry is only a miss, never an error
+    pub fn get_or_parse(&self, source: &str) -> Option<Vec<Stmt>> {
+        let path = self.path(source);
+        if let Some(stmts) = fs::read(&path).ok().and_then(|bytes| deserialize(&bytes)) {
+            return Some(stmts);
+        }
+
+        let stmts = parse(source)?;
+        let _ = fs::create_dir_all(&self.dir).and_then(|_| fs::write(&path, serialize(&stmts)));
+        Some(stmts)
+    }
+}
+
+// Operators are stored as their index in here
+const OPERATORS: [LexemeKind; 14] = [
+    LexemeKind::Minus,
+    LexemeKind::Plus,
+    LexemeKind::Slash,
+    LexemeKind::Star,
+    LexemeKind::Bang,
+    LexemeKind::BangEqual,
+    LexemeKind::EqualEqual,
+    LexemeKind::Greater,
+    LexemeKind::GreaterEqual,
+    LexemeKind::Less,
+    LexemeKind::LessEqual,
+    LexemeKind::AND,
+    LexemeKind::OR,
+    LexemeKind::QuestionQuestion,
+];
+
+pub fn serialize(stmts: &[Stmt]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(FORMAT_VERSION);
+    write_len(&mut out, stmts.len());
+    for stmt in stmts {
+        write_stmt(&mut out, stmt);
+    }
+    out
+}
+
+fn write_len(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u64).to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, st: &str) {
+    write_len(out, st.len());
+    out.extend_from_slice(st.as_bytes());
+}
+
+fn write_stmt(out: &mut Vec<u8>, stmt: &Stmt) {
+    match stmt {
+        Stmt::Block(stmts) => {
+            out.push(0);
+            write_len(out, stmts.len());
+            stmts.iter().for_each(|stmt| write_stmt(out, stmt));
+        }
+        Stmt::If { condition, then_branch, else_branch } => {
+            out.push(1);
+            write_if(out, condition, then_branch, else_branch);
+        }
+        Stmt::While { condition, body, line } => {
+            out.push(2);
+            write_expr(out, condition);
+            write_stmt(out, body);
+            write_len(out, *line);
+        }
+        Stmt::VariableDef { ident, expr, line } => {
+            out.push(3);
+            write_str(out, ident);
+            write_optional(out, expr);
+            write_len(out, *line);
+        }
+        Stmt::Record { name, fields, line } => {
+            out.push(9);
+            write_str(out, name);
+            write_len(out, fields.len());
+            fields.iter().for_each(|field| write_str(out, field));
+            write_len(out, *line);
+        }
+        Stmt::Class { name, methods, line } => {
+            out.push(10);
+            write_str(out, name);
+            write_len(out, methods.len());
+            for method in methods.iter() {
+                write_str(out, &method.name);
+                write_len(out, method.params.len());
+                method.params.iter().for_each(|param| write_str(out, param));
+                write_len(out, method.body.len());
+                method.body.iter().for_each(|stmt| write_stmt(out, stmt));
+                write_len(out, method.line);
+            }
+            write_len(out, *line);
+        }
+        Stmt::Print(exprs) => {
+            out.push(4);
+            write_len(out, exprs.len());
+            exprs.iter().for_each(|expr| write_expr(out, expr));
+        }
+        Stmt::Expr(expr) => {
+            out.push(5);
+            write_expr(out, expr);
+        }
+        Stmt::Error { line, message } => {
+            out.push(6);
+            write_len(out, *line);
+            write_str(out, message);
+        }
+        Stmt::With { ident, init, body, line } => {
+            out.push(8);
+            write_str(out, ident);
+            write_expr(out, init);
+            write_stmt(out, body);
+            write_len(out, *line);
+        }
+        Stmt::Defer { expr, line } => {
+            out.push(7);
+            write_expr(out, expr);
+            write_len(out, *line);
+        }
+    }
+}
+
+// the statement and the expression are written the same, bar the tag
+fn write_if(out: &mut Vec<u8>, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) {
+    write_expr(out, condition);
+    write_stmt(out, then_branch);
+    match else_branch {
+        Some(else_branch) => {
+            out.push(1);
+            write_stmt(out, else_branch);
+        }
+        None => out.push(0),
+    }
+}
+
+fn write_optional(out: &mut Vec<u8>, expr: &Option<Expr>) {
+    match expr {
+        Some(expr) => {
+            out.push(1);
+            write_expr(out, expr);
+        }
+        None => out.push(0),
+    }
+}
+
+fn write_operator(out: &mut Vec<u8>, op: &LexemeKind) {
+    // the parser only builds Binary, Logical and Unary from these
+    out.push(OPERATORS.iter().position(|known| known == op).unwrap() as u8);
+}
+
+fn write_expr(out: &mut Vec<u8>, expr: &Expr) {
+    match expr {
+        Expr::Assign { name, expr, line } => {
+            out.push(0);
+            write_str(out, name);
+            write_expr(out, expr);
+            write_len(out, *line);
+        }
+        Expr::Binary { left, operator, right } => {
+            out.push(1);
+            write_expr(out, left);
+            write_operator(out, operator);
+            write_expr(out, right);
+        }
+        Expr::Call { callee, arguments, line } => {
+            out.push(2);
+            write_expr(out, callee);
+            write_len(out, arguments.len());
+            arguments.iter().for_each(|arg| write_expr(out, arg));
+            write_len(out, *line);
+        }
+        Expr::Logical { left, operator, right } => {
+            out.push(3);
+            write_expr(out, left);
+            write_operator(out, operator);
+            write_expr(out, right);
+        }
+        Expr::Unary { operator, right } => {
+            out.push(4);
+            write_operator(out, operator);
+            write_expr(out, right);
+        }
+        Expr::Grouping(expr) => {
+            out.push(5);
+            write_expr(out, expr);
+        }
+        Expr::Literal(value) => {
+            out.push(6);
+            match value {
+                Value::BOOLEAN(b) => out.extend_from_slice(&[0, *b as u8]),
+                Value::STRING(st) => {
+                    out.push(1);
+                    write_str(out, st);
+                }
+                Value::NUMBER(n) => {
+                    out.push(2);
+                    out.extend_from_slice(&n.to_bits().to_le_bytes());
+                }
+                // natives, records and classes are never literals in source
+                Value::Null
+                | Value::Native(_)
+                | Value::Module(_)
+                | Value::RecordType(_)
+                | Value::Record(_)
+                | Value::Class(_)
+                | Value::Instance(_)
+                | Value::Method(_) => out.push(3),
+            }
+        }
+        Expr::Variable { name, line, .. } => {
+            out.push(7);
+            write_str(out, name);
+            write_len(out, *line);
+        }
+        Expr::This { line } => {
+            out.push(15);
+            write_len(out, *line);
+        }
+        Expr::Error { line, message } => {
+            out.push(8);
+            write_len(out, *line);
+            write_str(out, message);
+        }
+        Expr::Get { object, name, optional, line } => {
+            out.push(if *optional { 11 } else { 10 });
+            write_expr(out, object);
+            write_str(out, name);
+            write_len(out, *line);
+        }
+        Expr::Set { object, name, value, line } => {
+            out.push(14);
+            write_expr(out, object);
+            write_str(out, name);
+            write_expr(out, value);
+            write_len(out, *line);
+        }
+        Expr::Is { value, type_name, line } => {
+            out.push(12);
+            write_expr(out, value);
+            write_str(out, type_name);
+            write_len(out, *line);
+        }
+        Expr::Await { expr, line } => {
+            out.push(13);
+            write_expr(out, expr);
+            write_len(out, *line);
+        }
+        Expr::If { condition, then_branch, else_branch, line } => {
+            out.push(9);
+            write_if(out, condition, then_branch, else_branch);
+            write_len(out, *line);
+        }
+    }
+}
+
+// None for anything that isn't exactly what `serialize` of this version writes
+pub fn deserialize(bytes: &[u8]) -> Option<Vec<Stmt>> {
+    let mut reader = Reader { bytes, cursor: 0 };
+    if reader.take(MAGIC.len())? != MAGIC || reader.byte()? != FORMAT_VERSION {
+        return None;
+    }
+
+    let stmts = reader.list(Reader::stmt)?;
+    if reader.cursor != bytes.len() {
+        return None;
+    }
+    Some(stmts)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.cursor..self.cursor.checked_add(n)?)?;
+        self.cursor += n;
+        Some(slice)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Some(u64::from_le_bytes(buf))
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        self.u64().map(|n| n as usize)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn list<T>(&mut self, item: fn(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let len = self.len()?;
+        // every item is at least a byte, don't trust a corrupt length to size the Vec
+        let mut items = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            items.push(item(self)?);
+        }
+        Some(items)
+    }
+
+    fn optional(&mut self) -> Option<Option<Expr>> {
+        match self.byte()? {
+            0 => Some(None),
+            1 => Some(Some(self.expr()?)),
+            _ => None,
+        }
+    }
+
+    fn operator(&mut self) -> Option<LexemeKind> {
+        OPERATORS.get(self.byte()? as usize).cloned()
+    }
+
+    fn stmt(&mut self) -> Option<Stmt> {
+        Some(match self.byte()? {
+            0 => Stmt::Block(self.list(Reader::stmt)?),
+            1 => {
+                let (condition, then_branch, else_branch) = self.if_parts()?;
+                Stmt::If { condition, then_branch, else_branch }
+            }
+            2 => Stmt::While { condition: self.expr()?, body: Box::new(self.stmt()?), line: self.len()? },
+            3 => Stmt::VariableDef { ident: self.string()?, expr: self.optional()?, line: self.len()? },
+            4 => Stmt::Print(self.list(Reader::expr)?),
+            5 => Stmt::Expr(self.expr()?),
+            6 => Stmt::Error { line: self.len()?, message: self.string()? },
+            7 => Stmt::Defer { expr: self.expr()?, line: self.len()? },
+            9 => Stmt::Record { name: self.string()?, fields: self.list(Reader::string)?, line: self.len()? },
+            10 => Stmt::Class { name: self.string()?, methods: self.list(Reader::function)?.into(), line: self.len()? },
+            8 => Stmt::With { ident: self.string()?, init: self.expr()?, body: Box::new(self.stmt()?), line: self.len()? },
+            _ => return None,
+        })
+    }
+
+    fn expr(&mut self) -> Option<Expr> {
+        Some(match self.byte()? {
+            0 => Expr::Assign { name: self.string()?, expr: Box::new(self.expr()?), line: self.len()? },
+            1 => Expr::Binary { left: Box::new(self.expr()?), operator: self.operator()?, right: Box::new(self.expr()?) },
+            2 => Expr::Call { callee: Box::new(self.expr()?), arguments: self.list(Reader::expr)?, line: self.len()? },
+            3 => Expr::Logical { left: Box::new(self.expr()?), operator: self.operator()?, right: Box::new(self.expr()?) },
+            4 => Expr::Unary { operator: self.operator()?, right: Box::new(self.expr()?) },
+            5 => Expr::Grouping(Box::new(self.expr()?)),
+            6 => Expr::Literal(match self.byte()? {
+                0 => Value::BOOLEAN(self.byte()? != 0),
+                1 => Value::STRING(self.string()?.into()),
+                2 => Value::NUMBER(f64::from_bits(self.u64()?)),
+                3 => Value::Null,
+                _ => return None,
+            }),
+            // ids are only unique within a process, a node read back gets a new one
+            7 => Expr::Variable { name: self.string()?, line: self.len()?, id: NodeId::next() },
+            8 => Expr::Error { line: self.len()?, message: self.string()? },
+            9 => {
+                let (condition, then_branch, else_branch) = self.if_parts()?;
+                Expr::If { condition: Box::new(condition), then_branch, else_branch, line: self.len()? }
+            }
+            10 => Expr::Get { object: Box::new(self.expr()?), name: self.string()?, optional: false, line: self.len()? },
+            12 => Expr::Is { value: Box::new(self.expr()?), type_name: self.string()?, line: self.len()? },
+            11 => Expr::Get { object: Box::new(self.expr()?), name: self.string()?, optional: true, line: self.len()? },
+            13 => Expr::Await { expr: Box::new(self.expr()?), line: self.len()? },
+            15 => Expr::This { line: self.len()? },
+            14 => Expr::Set { object: Box::new(self.expr()?), name: self.string()?, value: Box::new(self.expr()?), line: self.len()? },
+            _ => return None,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn function(&mut self) -> Option<Function> {
+        Some(Function { name: self.string()?, params: self.list(Reader::string)?, body: self.list(Reader::stmt)?, line: self.len()? })
+    }
+
+    fn if_parts(&mut self) -> Option<(Expr, Box<Stmt>, Box<Option<Stmt>>)> {
+        let condition = self.expr()?;
+        let then_branch = Box::new(self.stmt()?);
+        let else_branch = match self.byte()? {
+            0 => None,
+            1 => Some(self.stmt()?),
+            _ => return None,
+        };
+
+        Some((condition, then_branch, Box::new(else_branch)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+var a = \"hi\";
+var n = -1.5;
+while (n < 3 and !false) {
+    if (n == 0) print(a + \"!\"); else n = (n + 1) * 2;
+    clock(1, n);
+    defer n = 0;
+}
+var b = if (a) { 1 } else 2;
+fs.read(b)(a);
+with (var f = open(a)) print(f);
+var c = fs?.read(a).b ?? b ?? \"none\";
+print(c is String == a + 1 is Number);
+var d = await fs.fetch(c) + 1;
+record Point { x, y, }
+print(Point(1, 2).x is Point);
+class Counter { add(n, by) { n = n + by; } reset() {} }
+print(1";
+
+    #[test]
+    fn it_round_trips_trees() {
+        let stmts = parse(SOURCE).unwrap();
+        let bytes = serialize(&stmts);
+        assert_eq!(deserialize(&bytes), Some(stmts));
+    }
+
+    #[test]
+    fn it_rejects_other_versions_and_garbage() {
+        let mut bytes = serialize(&parse("var a = 1;").unwrap());
+        assert_eq!(deserialize(&bytes[..bytes.len() - 1]), None);
+
+        bytes[MAGIC.len()] = FORMAT_VERSION + 1;
+        assert_eq!(deserialize(&bytes), None);
+        assert_eq!(deserialize(b"nonsense"), None);
+    }
+
+    #[test]
+    fn it_caches_in_memory() {
+        let mut cache = MemoryCache::new();
+        let first = cache.get_or_parse("var a = 1;").unwrap();
+        let second = cache.get_or_parse("var a = 1;").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(cache.get_or_parse("var a = 1.2.3;").is_none());
+    }
+
+    #[test]
+    fn it_caches_on_disk() {
+        let dir = env::temp_dir().join(format!("tree-walk-cache-test-{}", std::process::id()));
+        let cache = DiskCache::new(dir.clone());
+
+        let parsed = cache.get_or_parse("print(1);").unwrap();
+        assert!(cache.path("print(1);").exists());
+        assert_eq!(cache.get_or_parse("print(1);"), Some(parsed));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -1,30 +1,112 @@
-use std::fmt;
-use crate::lexer::{LexemeKind, Token};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use crate::interpreter::{Class, Instance, Method, NativeFunction, NativeModule, Record, RecordType};
+use crate::lexer::LexemeKind;
+use crate::values::lox_format;
+use super::statement::Stmt;
 use crate::visitor::ExpressionVisitor;
 
-#[derive(Debug, PartialEq)]
+// Which node of which tree a variable read is.  The parser hands out a new one for every
+// Variable it makes, never the same one twice however many trees come and go, so the
+// interpreter can key what it learns about a read on it.  A clone is the same node.  Two trees
+// are equal when they say the same thing, whichever parse they came out of, so ids don't take
+// part in comparing them
+#[derive(Clone, Copy, Debug)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    pub fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        NodeId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+impl PartialEq for NodeId {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Expr {
     Assign {
         name: String,
         expr: Box<Expr>,
+        line: usize,
     },
     Binary {
         left: Box<Expr>,
         operator: LexemeKind,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+        line: usize,
+    },
     Literal(Value),
     Logical {
         left: Box<Expr>,
         operator: LexemeKind,
         right: Box<Expr>,
     },
-    Variable(String),
+    Variable {
+        name: String,
+        line: usize,
+        id: NodeId,
+    },
+    // the instance a method was called on
+    This {
+        line: usize,
+    },
     Unary {
         operator: LexemeKind,
         right: Box<Expr>,
     },
     Grouping(Box<Expr>),
+    // fs.readFile, a member of a native module.  fs?.readFile when optional, which is nil rather
+    // than an error if fs is nil, and so is the rest of the chain it starts
+    Get {
+        object: Box<Expr>,
+        name: String,
+        optional: bool,
+        line: usize,
+    },
+    // obj.x = value, a field of an instance
+    Set {
+        object: Box<Expr>,
+        name: String,
+        value: Box<Expr>,
+        line: usize,
+    },
+    // x is Number, true when the value is of the type by that name
+    Is {
+        value: Box<Expr>,
+        type_name: String,
+        line: usize,
+    },
+    // await fetch(url), only something an AsyncInterpreter can run
+    Await {
+        expr: Box<Expr>,
+        line: usize,
+    },
+    // var x = if (c) { 1 } else { 2 };
+    If {
+        condition: Box<Expr>,
+        then_branch: Box<Stmt>,
+        else_branch: Box<Option<Stmt>>,
+        line: usize,
+    },
     Error {
         line: usize,
         message: String,
@@ -33,40 +115,108 @@ pub enum Expr {
 
 // a single element tuple struct over a generic type will not work.
 // arms in parser will return different types for T
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     BOOLEAN(bool),
-    STRING(String),
+    // strings are immutable, so copies of a value share one allocation. Arc rather than Rc keeps
+    // literals, and so the whole tree, Send + Sync
+    STRING(Arc<str>),
     NUMBER(f64),
+    // natives only exist once there's an interpreter to call them
+    #[cfg(feature = "std")]
+    Native(NativeFunction),
+    #[cfg(feature = "std")]
+    Module(Arc<NativeModule>),
+    // the type a record declaration makes, which builds records when called
+    #[cfg(feature = "std")]
+    RecordType(Arc<RecordType>),
+    #[cfg(feature = "std")]
+    Record(Arc<Record>),
+    // a class, which makes instances when called
+    #[cfg(feature = "std")]
+    Class(Arc<Class>),
+    #[cfg(feature = "std")]
+    Instance(Arc<Instance>),
+    // a method looked up on an instance, which `this` is when it's called
+    #[cfg(feature = "std")]
+    Method(Arc<Method>),
     Null,
 }
 
+// What `is` can test for, one name per kind of value
+// (a record is also its own type's name, and an instance its class's)
+pub const TYPE_NAMES: [&str; 8] = ["Boolean", "String", "Number", "Function", "Module", "Record", "Instance", "Nil"];
+
 impl Value {
-    pub fn to_string(&self) -> String {
+    pub fn type_name(&self) -> &'static str {
         match self {
-            Self::BOOLEAN(b) => b.to_string(),
-            Self::NUMBER(n) => n.to_string(),
-            Self::STRING(ref s) => format!("\"{}\"", s),
-            Self::Null => "nil".to_owned(),
+            Self::BOOLEAN(_) => "Boolean",
+            Self::STRING(_) => "String",
+            Self::NUMBER(_) => "Number",
+            #[cfg(feature = "std")]
+            Self::Native(_) => "Function",
+            #[cfg(feature = "std")]
+            Self::Module(_) => "Module",
+            #[cfg(feature = "std")]
+            Self::RecordType(_) => "Function",
+            #[cfg(feature = "std")]
+            Self::Record(_) => "Record",
+            #[cfg(feature = "std")]
+            Self::Class(_) => "Function",
+            #[cfg(feature = "std")]
+            Self::Instance(_) => "Instance",
+            #[cfg(feature = "std")]
+            Self::Method(_) => "Function",
+            Self::Null => "Nil",
         }
     }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+        match self {
+            Self::BOOLEAN(b) => write!(f, "{}", b),
+            Self::NUMBER(n) => write!(f, "{}", lox_format(*n)),
+            Self::STRING(ref s) => write!(f, "\"{}\"", s),
+            #[cfg(feature = "std")]
+            Self::Native(_) => write!(f, "<native fn>"),
+            #[cfg(feature = "std")]
+            Self::Module(module) => write!(f, "<module {}>", module.name()),
+            #[cfg(feature = "std")]
+            Self::RecordType(kind) => write!(f, "<record {}>", kind.name()),
+            #[cfg(feature = "std")]
+            Self::Record(record) => write!(f, "{}", record),
+            #[cfg(feature = "std")]
+            Self::Class(class) => write!(f, "<class {}>", class.name()),
+            #[cfg(feature = "std")]
+            Self::Instance(instance) => write!(f, "{}", instance),
+            #[cfg(feature = "std")]
+            Self::Method(method) => write!(f, "{}", method),
+            Self::Null => write!(f, "nil"),
+        }
     }
 }
 
+// only the interpreter walks trees this way
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
 impl Expr {
+    // a read of `name`, a node of its own
+    pub fn variable(name: &str, line: usize) -> Expr {
+        Expr::Variable { name: name.to_string(), line, id: NodeId::next() }
+    }
+
     pub(crate) fn accept<T>(&self, visitor: &mut dyn ExpressionVisitor<T>) -> T {
         match self {
-            Expr::Assign { name, expr } => {
-                visitor.visit_assign(name, expr)
+            Expr::Assign { name, expr, line } => {
+                visitor.visit_assign(name, expr, line)
             }
             Expr::Binary { operator, left, right } => {
                 visitor.visit_binary(left, operator, right)
             }
+            Expr::Call { callee, arguments, line } => {
+                visitor.visit_call(callee, arguments, line)
+            }
             Expr::Logical { operator, left, right } => {
                 visitor.visit_logical(left, operator, right)
             }
@@ -79,8 +229,26 @@ impl Expr {
             Expr::Literal(v) => {
                 visitor.visit_literal(v)
             }
-            Expr::Variable(v) => {
-                visitor.visit_variable(v)
+            Expr::Variable { name, line, id } => {
+                visitor.visit_variable(name, *id, line)
+            }
+            Expr::This { line } => {
+                visitor.visit_this(line)
+            }
+            Expr::Get { object, name, optional, line } => {
+                visitor.visit_get(object, name, optional, line)
+            }
+            Expr::Set { object, name, value, line } => {
+                visitor.visit_set(object, name, value, line)
+            }
+            Expr::Is { value, type_name, line } => {
+                visitor.visit_is(value, type_name, line)
+            }
+            Expr::Await { expr, line } => {
+                visitor.visit_await(expr, line)
+            }
+            Expr::If { condition, then_branch, else_branch, line } => {
+                visitor.visit_if_expr(condition, then_branch, else_branch, line)
             }
             Expr::Error { line, message } => {
                 visitor.visit_error(line, message)
@@ -88,50 +256,81 @@ impl Expr {
         }
     }
 
+    // The line the expression starts on, as near as the tree knows.  Literals don't keep theirs,
+    // so one on its own has none
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn line(&self) -> Option<usize> {
+        match self {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => left.line().or_else(|| right.line()),
+            Expr::Unary { right, .. } => right.line(),
+            Expr::Grouping(expr) => expr.line(),
+            Expr::Literal(_) => None,
+            Expr::Call { callee, line, .. } => callee.line().or(Some(*line)),
+            Expr::Get { object, line, .. } | Expr::Set { object, line, .. } => object.line().or(Some(*line)),
+            Expr::Assign { line, .. }
+            | Expr::Variable { line, .. }
+            | Expr::This { line }
+            | Expr::Is { line, .. }
+            | Expr::Await { line, .. }
+            | Expr::If { line, .. }
+            | Expr::Error { line, .. } => Some(*line),
+        }
+    }
+
     pub(crate) fn debug(&self) -> String {
         match self {
-            Expr::Assign { name, expr} => {
+            Expr::Assign { name, expr, .. } => {
                 let mut st = String::new();
-                st.push_str("(");
+                st.push('(');
 
                 let op = name.to_string();
                 st.push_str(&op);
-                st.push_str(" ");
+                st.push(' ');
 
                 let l = &expr.debug();
                 st.push_str(l);
-                st.push_str(" ");
+                st.push(' ');
 
                 st
             },
             Expr::Binary { operator, left, right } => {
                 let mut st = String::new();
-                st.push_str("(");
+                st.push('(');
 
-                let op = operator.to_string();
+                let op = operator.source_text();
                 st.push_str(&op);
-                st.push_str(" ");
+                st.push(' ');
 
                 let l = &left.debug();
                 st.push_str(l);
-                st.push_str(" ");
+                st.push(' ');
 
                 let r = &right.debug();
                 st.push_str(r);
 
                 st
             },
+            Expr::Call { callee, arguments, .. } => {
+                let mut st = callee.debug();
+                st.push('(');
+
+                let args: Vec<String> = arguments.iter().map(|a| a.debug()).collect();
+                st.push_str(&args.join(", "));
+                st.push(')');
+
+                st
+            },
             Expr::Logical { operator, left, right } => {
                 let mut st = String::new();
-                st.push_str("(");
+                st.push('(');
 
-                let op = operator.to_string();
+                let op = operator.source_text();
                 st.push_str(&op);
-                st.push_str(" ");
+                st.push(' ');
 
                 let l = &left.debug();
                 st.push_str(l);
-                st.push_str(" ");
+                st.push(' ');
 
                 let r = &right.debug();
                 st.push_str(r);
@@ -144,6 +343,12 @@ impl Expr {
                     Value::BOOLEAN(false) => "true".to_string(),
                     Value::STRING(st) => st.to_string(),
                     Value::NUMBER(n) => n.to_string(),
+                    #[cfg(feature = "std")]
+                    Value::Native(native) => native.name.to_string(),
+                    #[cfg(feature = "std")]
+                    Value::Module(module) => module.name().to_string(),
+                    #[cfg(feature = "std")]
+                    Value::RecordType(_) | Value::Record(_) | Value::Class(_) | Value::Instance(_) | Value::Method(_) => v.to_string(),
                     Value::Null => "".to_string(),
                 }
             }
@@ -151,21 +356,51 @@ impl Expr {
                 let mut st = String::new();
                 st.push_str("( ");
 
-                let op = operator.to_string();
+                let op = operator.source_text();
                 st.push_str(&op);
-                st.push_str(" ");
+                st.push(' ');
 
                 let r = &right.debug();
                 st.push_str(r);
-                st.push_str(" ");
+                st.push(' ');
 
                 st
             },
             Expr::Grouping(value) => {
                 value.debug()
             },
-            Expr::Variable(st) => {
-                st.to_string()
+            Expr::Variable { name, .. } => {
+                name.to_string()
+            },
+            Expr::This { .. } => {
+                "this".to_string()
+            },
+            Expr::Get { object, name, optional, .. } => {
+                let mut st = object.debug();
+                if *optional {
+                    st.push('?');
+                }
+                st.push('.');
+                st.push_str(name);
+
+                st
+            },
+            Expr::Set { object, name, value, .. } => {
+                format!("(= {}.{} {})", object.debug(), name, value.debug())
+            },
+            Expr::Is { value, type_name, .. } => {
+                format!("(is {} {})", value.debug(), type_name)
+            },
+            Expr::Await { expr, .. } => {
+                format!("(await {})", expr.debug())
+            },
+            Expr::If { condition, .. } => {
+                let mut st = String::new();
+                st.push_str("(if ");
+                st.push_str(&condition.debug());
+                st.push(' ');
+
+                st
             },
             Expr::Error { message, .. } => message.to_string()
         }
@@ -0,0 +1,95 @@
+use crate::lexer::LexemeKind;
+
+// How tightly an operator binds, loosest first, so comparing two says which one groups first:
+// a + b * c is a + (b * c) because Factor > Term.  The prefix operators (-, +, ! and await) are
+// all Unary, and calls and property reads are Call
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Precedence {
+    Assignment,
+    Pipe,
+    Coalesce,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+}
+
+impl Precedence {
+    // the next level up, Call is as tight as it gets
+    pub fn tighter(self) -> Self {
+        match self {
+            Self::Assignment => Self::Pipe,
+            Self::Pipe => Self::Coalesce,
+            Self::Coalesce => Self::Or,
+            Self::Or => Self::And,
+            Self::And => Self::Equality,
+            Self::Equality => Self::Comparison,
+            Self::Comparison => Self::Term,
+            Self::Term => Self::Factor,
+            Self::Factor => Self::Unary,
+            Self::Unary | Self::Call => Self::Call,
+        }
+    }
+}
+
+// Which way a run of operators on the same level groups, a - b - c is (a - b) - c but
+// a = b = c is a = (b = c)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+// Every operator that goes between or after operands, and the one place the grammar's
+// precedence is written down.  The parser works from it, anything else that needs to know how
+// an expression groups should too, see Parser::precedence_of
+pub const OPERATORS: [(LexemeKind, Precedence, Associativity); 19] = [
+    (LexemeKind::Equal, Precedence::Assignment, Associativity::Right),
+    (LexemeKind::Pipe, Precedence::Pipe, Associativity::Left),
+    (LexemeKind::QuestionQuestion, Precedence::Coalesce, Associativity::Left),
+    (LexemeKind::OR, Precedence::Or, Associativity::Left),
+    (LexemeKind::AND, Precedence::And, Associativity::Left),
+    (LexemeKind::BangEqual, Precedence::Equality, Associativity::Left),
+    (LexemeKind::EqualEqual, Precedence::Equality, Associativity::Left),
+    (LexemeKind::Greater, Precedence::Comparison, Associativity::Left),
+    (LexemeKind::GreaterEqual, Precedence::Comparison, Associativity::Left),
+    (LexemeKind::Less, Precedence::Comparison, Associativity::Left),
+    (LexemeKind::LessEqual, Precedence::Comparison, Associativity::Left),
+    (LexemeKind::IS, Precedence::Comparison, Associativity::Left),
+    (LexemeKind::Minus, Precedence::Term, Associativity::Left),
+    (LexemeKind::Plus, Precedence::Term, Associativity::Left),
+    (LexemeKind::Slash, Precedence::Factor, Associativity::Left),
+    (LexemeKind::Star, Precedence::Factor, Associativity::Left),
+    (LexemeKind::LeftParen, Precedence::Call, Associativity::Left),
+    (LexemeKind::Dot, Precedence::Call, Associativity::Left),
+    (LexemeKind::QuestionDot, Precedence::Call, Associativity::Left),
+];
+
+// The operators that go in front of their operand, all of them Unary.  await is one too, but
+// it's only allowed where an AsyncInterpreter can wait and gets a rule of its own
+pub const PREFIX_OPERATORS: [LexemeKind; 3] = [LexemeKind::Bang, LexemeKind::Minus, LexemeKind::Plus];
+
+pub(super) fn lookup(kind: &LexemeKind) -> Option<(Precedence, Associativity)> {
+    OPERATORS
+        .iter()
+        .find(|(operator, _, _)| operator == kind)
+        .map(|(_, precedence, associativity)| (*precedence, *associativity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_lists_operators_loosest_first() {
+        assert!(OPERATORS.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert_eq!(lookup(&LexemeKind::Star), Some((Precedence::Factor, Associativity::Left)));
+        assert_eq!(lookup(&LexemeKind::Equal), Some((Precedence::Assignment, Associativity::Right)));
+        assert_eq!(lookup(&LexemeKind::Bang), None);
+        assert!(Precedence::Factor > Precedence::Term);
+    }
+}
@@ -1,11 +1,17 @@
-use crate::lexer::LexemeKind;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::lexer::{LexemeKind, Token};
 use super::expression::Expr;
 use super::Parser;
 use crate::visitor::StatementVisitor;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Stmt {
-    Block(Box<Vec<Stmt>>),
+    Block(Vec<Stmt>),
     If {
         condition: Expr,
         then_branch: Box<Stmt>,
@@ -14,19 +20,58 @@ pub enum Stmt {
     While {
         condition: Expr,
         body: Box<Stmt>,
+        line: usize,
     },
     VariableDef {
         ident: String,
         expr: Option<Expr>,
+        line: usize,
+    },
+    // record Point { x, y }
+    Record {
+        name: String,
+        fields: Vec<String>,
+        line: usize,
+    },
+    // class Counter { increment(by) { ... } }
+    Class {
+        name: String,
+        // shared with every class the declaration makes, running it doesn't copy the bodies
+        methods: Arc<[Function]>,
+        line: usize,
     },
-    Print(Option<Expr>),
+    // print(a, b)
+    Print(Vec<Expr>),
     Expr(Expr),
+    // with (var ident = init) body, then close(ident) however body ends
+    With {
+        ident: String,
+        init: Expr,
+        body: Box<Stmt>,
+        line: usize,
+    },
+    // runs `expr` on the way out of the enclosing block
+    Defer {
+        expr: Expr,
+        line: usize,
+    },
     Error {
         line: usize,
         message: String,
     }
 }
 
+// A method, name(params) { body }.  There's nowhere else to declare a function yet
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+    pub line: usize,
+}
+
+// only the interpreter walks trees this way
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
 impl Stmt {
     pub(crate) fn accept<T>(&self, visitor: &mut dyn StatementVisitor<T>) -> T {
         match self {
@@ -36,28 +81,84 @@ impl Stmt {
             Stmt::If { condition, then_branch, else_branch } => {
                 visitor.visit_if(condition, then_branch, else_branch)
             }
-            Stmt::While { condition, body } => {
-                visitor.visit_while(condition, body)
+            Stmt::While { condition, body, line } => {
+                visitor.visit_while(condition, body, line)
+            }
+            Stmt::VariableDef { ident, expr, line } => {
+                visitor.visit_variable_def(ident, expr, line)
             }
-            Stmt::VariableDef { ident, expr } => {
-                visitor.visit_variable_def(ident, expr)
+            Stmt::Record { name, fields, line } => {
+                visitor.visit_record(name, fields, line)
             }
-            Stmt::Print(expr) => {
-                visitor.visit_print(expr)
+            Stmt::Class { name, methods, line } => {
+                visitor.visit_class(name, methods, line)
+            }
+            Stmt::Print(exprs) => {
+                visitor.visit_print(exprs)
             }
             Stmt::Expr(expr) => {
                 visitor.visit_expr(expr)
             }
+            Stmt::With { ident, init, body, line } => {
+                visitor.visit_with(ident, init, body, line)
+            }
+            Stmt::Defer { expr, line } => {
+                visitor.visit_defer(expr, line)
+            }
             Stmt::Error { line, message } => {
                 visitor.visit_error(line, message)
             }
         }
     }
+
+    // What line a breakpoint has to be on to stop here.  A block has none of its own, its
+    // statements stop for themselves
+    pub(crate) fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Block(_) => None,
+            Stmt::If { condition, .. } => condition.line(),
+            Stmt::Print(exprs) => exprs.iter().find_map(Expr::line),
+            Stmt::Expr(expr) => expr.line(),
+            Stmt::While { line, .. }
+            | Stmt::VariableDef { line, .. }
+            | Stmt::Record { line, .. }
+            | Stmt::Class { line, .. }
+            | Stmt::With { line, .. }
+            | Stmt::Defer { line, .. }
+            | Stmt::Error { line, .. } => Some(*line),
+        }
+    }
+
+    // Statements that add a name to the scope they run in.  The branch of an if or the body of a
+    // while runs in that scope too unless it's a block of its own, `if (a) var x = 1;` declares x
+    // where the if is
+    pub(crate) fn is_declaration(&self) -> bool {
+        match self {
+            Stmt::VariableDef { .. } | Stmt::Record { .. } | Stmt::Class { .. } => true,
+            Stmt::If { then_branch, else_branch, .. } => {
+                then_branch.is_declaration() || else_branch.as_ref().as_ref().is_some_and(Stmt::is_declaration)
+            }
+            Stmt::While { body, .. } => body.is_declaration(),
+            _ => false,
+        }
+    }
 }
 
 pub(crate) fn parse(p: &mut Parser) -> Option<Stmt> {
     p.eat_whitespace();
 
+    // blocks in blocks in blocks go as deep as parens do, and are kept to the same limit
+    if let Err(line) = p.enter() {
+        return Some(Stmt::Error { line, message: "Statement nested too deeply".to_string() });
+    }
+
+    let stmt = nested(p);
+    p.leave();
+    stmt
+}
+
+fn nested(p: &mut Parser) -> Option<Stmt> {
+
     if p.at(LexemeKind::VAR) {
         p.cursor += 1;
         // ultimately, this is what our program is made up of
@@ -68,6 +169,15 @@ pub(crate) fn parse(p: &mut Parser) -> Option<Stmt> {
     } else if p.at(LexemeKind::WHILE) {
         p.cursor += 1;
         while_statement(p)
+    } else if p.at(LexemeKind::RECORD) {
+        p.cursor += 1;
+        record_statement(p)
+    } else if p.at(LexemeKind::CLASS) {
+        p.cursor += 1;
+        class_statement(p)
+    } else if p.at(LexemeKind::WITH) {
+        p.cursor += 1;
+        with_statement(p)
     } else if p.at(LexemeKind::LeftBrace) {
         p.cursor += 1;
 
@@ -77,40 +187,217 @@ pub(crate) fn parse(p: &mut Parser) -> Option<Stmt> {
     }
 }
 
-fn if_statement(p: &mut Parser) -> Option<Stmt> {
-    p.eat_whitespace();
+// the next statement, or an error in its place when there isn't one, so a broken file still
+// parses to a whole tree for the tools that work on one
+fn parse_or_error(p: &mut Parser, message: &str) -> Stmt {
+    match parse(p) {
+        Some(stmt) => stmt,
+        None => Stmt::Error { line: p.line(), message: message.to_string() },
+    }
+}
 
-    let _ = p.expect(LexemeKind::LeftParen);
+// (condition) after if or while.  A missing paren is an error where the condition would be, and
+// the rest of the statement is parsed all the same so what comes after isn't thrown off
+fn condition(p: &mut Parser, keyword: &str) -> Expr {
     p.eat_whitespace();
-    let condition = p.expression()?;
+    let line = p.line();
+    let open = p.expect(LexemeKind::LeftParen);
     p.eat_whitespace();
-    let _ = p.expect(LexemeKind::RightParen);
+    let condition = p.expression();
+    p.eat_whitespace();
+    let close_line = p.line();
+    let close = p.expect(LexemeKind::RightParen);
+
+    match (open, condition, close) {
+        (Err(_), _, _) => Expr::Error { line, message: format!("Expected ( after {}", keyword) },
+        (_, None, _) => Expr::Error { line: close_line, message: format!("Expected a condition after {}", keyword) },
+        (_, Some(_), Err(_)) => Expr::Error { line: close_line, message: format!("Expected ) after the {} condition", keyword) },
+        (_, Some(condition), Ok(_)) => condition,
+    }
+}
 
-    let then_branch = parse(p).unwrap();
+pub(super) fn if_statement(p: &mut Parser) -> Option<Stmt> {
+    let condition = condition(p, "if");
+
+    let then_branch = parse_or_error(p, "Expected a statement after if");
     p.eat_whitespace();
 
     let mut else_branch = None;
     if p.at(LexemeKind::ELSE) {
         p.cursor += 1;
         p.eat_whitespace();
-        else_branch = parse(p);
+        else_branch = Some(parse_or_error(p, "Expected a statement after else"));
     }
 
     Some(Stmt::If { condition, then_branch: Box::new(then_branch), else_branch: Box::new(else_branch) })
 }
 
 fn while_statement(p: &mut Parser) -> Option<Stmt> {
+    let line = p.last_token().unwrap().line; // WHILE
+    let condition = condition(p, "while");
+
+    let body = parse_or_error(p, "Expected a statement after while");
+
+    Some(Stmt::While { condition, body: Box::new(body), line })
+}
+
+// record Point { x, y }
+fn record_statement(p: &mut Parser) -> Option<Stmt> {
+    let line = p.last_token().unwrap().line; // RECORD
+    p.eat_whitespace();
+
+    let name = match p.peek_kind() {
+        Some(LexemeKind::IDENTIFIER(name)) => name,
+        _ => return Some(Stmt::Error { line, message: "Expected a name after record".to_string() }),
+    };
+    p.cursor += 1;
+    p.eat_whitespace();
+    if p.expect(LexemeKind::LeftBrace).is_err() {
+        return Some(Stmt::Error { line, message: format!("Expected {{ after record {}", name) });
+    }
+
+    let mut fields: Vec<String> = vec![];
+    loop {
+        p.eat_whitespace();
+        if p.at(LexemeKind::RightBrace) {
+            p.cursor += 1;
+            break;
+        }
+
+        match p.peek_kind() {
+            Some(LexemeKind::IDENTIFIER(field)) if fields.contains(&field) => {
+                return Some(Stmt::Error { line, message: format!("Field \"{}\" is listed twice in record {}", field, name) });
+            }
+            Some(LexemeKind::IDENTIFIER(field)) => {
+                p.cursor += 1;
+                fields.push(field);
+            }
+            _ => return Some(Stmt::Error { line, message: format!("Expected a field name in record {}", name) }),
+        }
+
+        // a trailing comma is fine
+        p.eat_whitespace();
+        if p.at(LexemeKind::Comma) {
+            p.cursor += 1;
+        } else if !p.at(LexemeKind::RightBrace) {
+            return Some(Stmt::Error { line, message: format!("Expected , or }} between the fields of record {}", name) });
+        }
+    }
+
+    Some(Stmt::Record { name, fields, line })
+}
+
+// class Counter { increment(by) { ... } }
+fn class_statement(p: &mut Parser) -> Option<Stmt> {
+    let line = p.last_token().unwrap().line; // CLASS
+    p.eat_whitespace();
+
+    let name = match p.peek_kind() {
+        Some(LexemeKind::IDENTIFIER(name)) => name,
+        _ => return Some(Stmt::Error { line, message: "Expected a name after class".to_string() }),
+    };
+    p.cursor += 1;
+    p.eat_whitespace();
+    if p.expect(LexemeKind::LeftBrace).is_err() {
+        return Some(Stmt::Error { line, message: format!("Expected {{ after class {}", name) });
+    }
+
+    let mut methods: Vec<Function> = vec![];
+    loop {
+        p.eat_whitespace();
+        if p.at(LexemeKind::RightBrace) {
+            p.cursor += 1;
+            break;
+        }
+
+        let method = match method(p, &name) {
+            Ok(method) => method,
+            Err(message) => return Some(Stmt::Error { line: p.line(), message }),
+        };
+        if methods.iter().any(|other| other.name == method.name) {
+            return Some(Stmt::Error { line: method.line, message: format!("Method \"{}\" is declared twice in class {}", method.name, name) });
+        }
+        methods.push(method);
+    }
+
+    Some(Stmt::Class { name, methods: methods.into(), line })
+}
+
+// name(params) { body }, inside class `class`
+fn method(p: &mut Parser, class: &str) -> Result<Function, String> {
+    let (name, line) = match p.peek() {
+        Some(Token { lexeme: LexemeKind::IDENTIFIER(name), line, .. }) => (name.clone(), *line),
+        _ => return Err(format!("Expected a method name or }} in class {}", class)),
+    };
+    p.cursor += 1;
+    p.eat_whitespace();
+    if p.expect(LexemeKind::LeftParen).is_err() {
+        return Err(format!("Expected ( after {}", name));
+    }
+
+    let mut params: Vec<String> = vec![];
+    loop {
+        p.eat_whitespace();
+        if p.at(LexemeKind::RightParen) {
+            p.cursor += 1;
+            break;
+        }
+
+        match p.peek_kind() {
+            Some(LexemeKind::IDENTIFIER(param)) if params.contains(&param) => {
+                return Err(format!("Parameter \"{}\" is listed twice in {}", param, name));
+            }
+            Some(LexemeKind::IDENTIFIER(param)) => {
+                p.cursor += 1;
+                params.push(param);
+            }
+            _ => return Err(format!("Expected a parameter name in {}", name)),
+        }
+
+        p.eat_whitespace();
+        if p.at(LexemeKind::Comma) {
+            p.cursor += 1;
+        } else if !p.at(LexemeKind::RightParen) {
+            return Err(format!("Expected , or ) between the parameters of {}", name));
+        }
+    }
+
+    p.eat_whitespace();
+    if p.expect(LexemeKind::LeftBrace).is_err() {
+        return Err(format!("Expected {{ before the body of {}", name));
+    }
+    let body = match block(p) {
+        Some(Stmt::Block(body)) => body,
+        _ => vec![],
+    };
+
+    Ok(Function { name, params, body, line })
+}
+
+// with (var f = open("x")) { ... }
+fn with_statement(p: &mut Parser) -> Option<Stmt> {
+    let line = p.last_token().unwrap().line; // WITH
     p.eat_whitespace();
 
     let _ = p.expect(LexemeKind::LeftParen);
     p.eat_whitespace();
-    let condition = p.expression()?;
+    if p.expect(LexemeKind::VAR).is_err() {
+        return Some(Stmt::Error { line, message: "Expected var after with (".to_string() });
+    }
+    p.eat_whitespace();
+    let (ident, init) = match p.expression() {
+        Some(Expr::Assign { name, expr, .. }) => (name, *expr),
+        _ => return Some(Stmt::Error { line, message: "with needs a value to hold, var name = value".to_string() }),
+    };
     p.eat_whitespace();
-    let _ = p.expect(LexemeKind::RightParen);
+    let init = match p.expect(LexemeKind::RightParen) {
+        Ok(_) => init,
+        Err(_) => Expr::Error { line: p.line(), message: format!("Expected ) after with (var {} = ...", ident) },
+    };
 
-    let body = parse(p);
+    let body = parse_or_error(p, "Expected a statement after with");
 
-    Some(Stmt::While { condition, body: Box::new(body.unwrap()) })
+    Some(Stmt::With { ident, init, body: Box::new(body), line })
 }
 
 fn block(p: &mut Parser) -> Option<Stmt> {
@@ -118,77 +405,95 @@ fn block(p: &mut Parser) -> Option<Stmt> {
 
     p.eat_whitespace();
 
-    while p.at(LexemeKind::RightBrace) == false {
-        let res = parse(p);
-        v.push(res.unwrap());
+    while !p.at(LexemeKind::RightBrace) {
+        if p.at_end() {
+            v.push(Stmt::Error { line: p.line(), message: "Expected } at the end of the block".to_string() });
+            return Some(Stmt::Block(v));
+        }
+
+        let start = p.cursor;
+        v.push(parse_or_error(p, "Expected a statement"));
+        // step over what couldn't be parsed at all
+        if p.cursor == start {
+            p.cursor += 1;
+        }
 
         p.eat_whitespace();
     }
 
-    p.eat_whitespace();
-
     p.cursor += 1; // RightBrace
 
-    Some(Stmt::Block(Box::new(v)))
+    Some(Stmt::Block(v))
 }
 
 pub(crate) fn statement(p: &mut Parser) -> Option<Stmt> {
     if p.at(LexemeKind::PRINT) {
         p.cursor += 1; // PRINT
         print_stmt(p)
+    } else if p.at(LexemeKind::DEFER) {
+        p.cursor += 1; // DEFER
+        defer_stmt(p)
     } else {
         // fallthrough to expression
         let expr = p.expression()?;
+
+        // semicolon optional, assignment has already consumed its own
+        if p.at(LexemeKind::Semicolon) {
+            p.cursor += 1;
+        }
+
         Some(Stmt::Expr(expr))
     }
 }
 
 fn declaration_stmt(p: &mut Parser) -> Option<Stmt> {
     // var x = 1+1;
+    let line = p.last_token().unwrap().line; // VAR
     p.eat_whitespace();
 
     match p.expression() {
-        Some(Expr::Assign { name, expr }) => {
-            let stmt = Some(Stmt::VariableDef { ident: name, expr: Some(*expr) });
-            // assert!(p.at(LexemeKind::Semicolon));
-            p.cursor += 1;
-            stmt
+        Some(Expr::Assign { name, expr, .. }) => {
+            // assignment has already consumed the semicolon
+            Some(Stmt::VariableDef { ident: name, expr: Some(*expr), line })
         }
-        Some(Expr::Variable(name)) => {
-            let stmt = Some(Stmt::VariableDef { ident: name, expr: None });
-            // assert!(p.at(LexemeKind::Semicolon));
-            p.cursor += 1;
-            stmt
+        Some(Expr::Variable { name, .. }) => {
+            if p.at(LexemeKind::Semicolon) {
+                p.cursor += 1;
+            }
+            Some(Stmt::VariableDef { ident: name, expr: None, line })
         }
-        _ => Some(Stmt::Error { line: 0, message: "Unfinished right hand assignment".to_string() })
+        _ => Some(Stmt::Error { line, message: "Unfinished right hand assignment".to_string() })
     }
 }
 
-fn print_stmt(p: &mut Parser) -> Option<Stmt> {
-    p.cursor += 1; // LeftParen
+// defer close(file);
+fn defer_stmt(p: &mut Parser) -> Option<Stmt> {
+    let line = p.last_token().unwrap().line; // DEFER
+    p.eat_whitespace();
 
-    match p.peek_kind() {
-        Some(LexemeKind::RightParen) => {
-            p.cursor += 1; // RightParen
-            // print();
-            Some(Stmt::Print(None))
-        }
-        _ => {
-            let expr = p.expression();
+    let expr = p.expression()?;
+    if p.at(LexemeKind::Semicolon) {
+        p.cursor += 1;
+    }
 
-            if let Ok(()) = p.expect(LexemeKind::RightParen) {
-                p.cursor += 1; // RightParen
+    Some(Stmt::Defer { expr, line })
+}
 
-                // semicolon optional
-                if let Ok(_) = p.expect(LexemeKind::Semicolon) {
-                   p.cursor += 1;
-                }
+fn print_stmt(p: &mut Parser) -> Option<Stmt> {
+    let line = p.last_token().unwrap().line; // PRINT
+    p.cursor += 1; // LeftParen
 
-                Some(Stmt::Print(expr))
-            } else {
-                Some(Stmt::Error { line: 0, message: "Unfinished print statement".to_string() })
+    // print(a, b) puts a space between them, print() prints nothing at all
+    match p.arguments() {
+        Ok(exprs) => {
+            // semicolon optional
+            if p.at(LexemeKind::Semicolon) {
+                p.cursor += 1;
             }
+
+            Some(Stmt::Print(exprs))
         }
+        Err(_) => Some(Stmt::Error { line, message: "Unfinished print statement".to_string() }),
     }
 }
 
@@ -205,7 +510,100 @@ mod tests {
         let res = parse(&mut p);
         assert_eq!(
             res,
-            Some(Stmt::Print(Some(Expr::Literal(Value::NUMBER(1.0)))))
+            Some(Stmt::Print(vec![Expr::Literal(Value::NUMBER(1.0))]))
+        );
+    }
+
+    #[test]
+    fn it_stops_when_out_of_fuel() {
+        let source = "var a = 1;\nvar b = 2;\nprint(a + b);";
+        let whole = Parser::new(Scanner::new(source.to_owned()).collect()).parse();
+
+        let mut p = Parser::new(Scanner::new(source.to_owned()).collect());
+        // enough for the first declaration, and the token after it that says it has ended, but only
+        // part of the second
+        let first = p.parse_with_fuel(10);
+        assert_eq!(first, whole[..1]);
+        assert!(!p.at_end());
+        // not enough for the next one at all
+        assert_eq!(p.parse_with_fuel(2), vec![]);
+
+        let rest = p.parse();
+        assert!(p.at_end());
+        assert_eq!([first, rest].concat(), whole);
+    }
+
+    #[test]
+    fn it_keeps_a_whole_tree_through_errors() {
+        let tokens = Scanner::new("var a = 1;\nif (a print(a);\n{ var b = 2;\nwhile (".to_owned()).collect();
+        let error = |line, message: &str| Stmt::Error { line, message: message.to_string() };
+        assert_eq!(
+            Parser::new(tokens).parse(),
+            vec![
+                Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(1.0))), line: 0 },
+                Stmt::If {
+                    condition: Expr::Error { line: 1, message: "Expected ) after the if condition".to_string() },
+                    then_branch: Box::new(Stmt::Print(vec![Expr::variable("a", 1)])),
+                    else_branch: Box::new(None),
+                },
+                // the file stops part way into the block
+                Stmt::Block(vec![
+                    Stmt::VariableDef { ident: "b".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))), line: 2 },
+                    Stmt::While {
+                        condition: Expr::Error { line: 3, message: "Expected a condition after while".to_string() },
+                        body: Box::new(error(3, "Expected a statement after while")),
+                        line: 3,
+                    },
+                    error(3, "Expected } at the end of the block"),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_parses_classes() {
+        let tokens = Scanner::new("class Counter {\n  add(n, by) { print(n + by); }\n  reset() {}\n}".to_owned()).collect();
+        let mut p = Parser::new(tokens);
+        let add = Function {
+            name: "add".to_string(),
+            params: vec!["n".to_string(), "by".to_string()],
+            body: vec![Stmt::Print(vec![Expr::Binary {
+                left: Box::new(Expr::variable("n", 1)),
+                operator: LexemeKind::Plus,
+                right: Box::new(Expr::variable("by", 1)),
+            }])],
+            line: 1,
+        };
+        let reset = Function { name: "reset".to_string(), params: vec![], body: vec![], line: 2 };
+        assert_eq!(parse(&mut p), Some(Stmt::Class { name: "Counter".to_string(), methods: vec![add, reset].into(), line: 0 }));
+
+        let error = |source: &str| parse(&mut Parser::new(Scanner::new(source.to_owned()).collect()));
+        assert_eq!(error("class { }"), Some(Stmt::Error { line: 0, message: "Expected a name after class".to_string() }));
+        assert_eq!(error("class A { a(x, x) {} }"), Some(Stmt::Error { line: 0, message: "Parameter \"x\" is listed twice in a".to_string() }));
+        assert_eq!(error("class A { a() {} a() {} }"), Some(Stmt::Error { line: 0, message: "Method \"a\" is declared twice in class A".to_string() }));
+    }
+
+    #[test]
+    fn it_parses_defer() {
+        let tokens = Scanner::new("defer close(f);\ndefer x = 1;".to_owned()).collect();
+        let mut p = Parser::new(tokens);
+        assert_eq!(
+            parse(&mut p),
+            Some(Stmt::Defer {
+                expr: Expr::Call {
+                    callee: Box::new(Expr::variable("close", 0)),
+                    arguments: vec![Expr::variable("f", 0)],
+                    line: 0,
+                },
+                line: 0,
+            })
+        );
+        assert_eq!(
+            parse(&mut p),
+            Some(Stmt::Defer {
+                expr: Expr::Assign { name: "x".to_string(), expr: Box::new(Expr::Literal(Value::NUMBER(1.0))), line: 1 },
+                line: 1,
+            })
         );
     }
 
@@ -216,7 +614,7 @@ mod tests {
         let res = parse(&mut p);
         assert_eq!(
             res,
-            Some(Stmt::Print(Some(Expr::Literal(Value::STRING("foo".to_string())))))
+            Some(Stmt::Print(vec![Expr::Literal(Value::STRING("foo".into()))]))
         );
     }
 
@@ -227,7 +625,7 @@ mod tests {
         let res = parse(&mut p);
         assert_eq!(
             res,
-            Some(Stmt::Print(None))
+            Some(Stmt::Print(vec![]))
         );
     }
 
@@ -238,11 +636,11 @@ mod tests {
         let res = parse(&mut p);
         assert_eq!(
             res,
-            Some(Stmt::Print(Some(Expr::Binary {
+            Some(Stmt::Print(vec![Expr::Binary {
                 left: Box::new(Expr::Literal(Value::NUMBER(8.0))),
                 operator: LexemeKind::Star,
                 right: Box::new(Expr::Literal(Value::NUMBER(8.0))),
-            })))
+            }]))
         );
 
         let tokens = Scanner::new("print(8 * 8)".to_owned()).collect();
@@ -250,11 +648,11 @@ mod tests {
         let res = parse(&mut p);
         assert_eq!(
             res,
-            Some(Stmt::Print(Some(Expr::Binary {
+            Some(Stmt::Print(vec![Expr::Binary {
                 left: Box::new(Expr::Literal(Value::NUMBER(8.0))),
                 operator: LexemeKind::Star,
                 right: Box::new(Expr::Literal(Value::NUMBER(8.0))),
-            })))
+            }]))
         );
 
         let tokens = Scanner::new("print(8 *  8)".to_owned()).collect();
@@ -262,11 +660,11 @@ mod tests {
         let res = parse(&mut p);
         assert_eq!(
             res,
-            Some(Stmt::Print(Some(Expr::Binary {
+            Some(Stmt::Print(vec![Expr::Binary {
                 left: Box::new(Expr::Literal(Value::NUMBER(8.0))),
                 operator: LexemeKind::Star,
                 right: Box::new(Expr::Literal(Value::NUMBER(8.0))),
-            })))
+            }]))
         );
     }
 
@@ -291,12 +689,12 @@ mod tests {
         let tokens = Scanner::new("var a;".to_owned()).collect();
         let mut p = Parser::new(tokens);
         let res = parse(&mut p);
-        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: None }));
+        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: None, line: 0 }));
 
         let tokens = Scanner::new("var  a;".to_owned()).collect();
         let mut p = Parser::new(tokens);
         let res = parse(&mut p);
-        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: None }));
+        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: None, line: 0 }));
     }
 
     #[test]
@@ -304,12 +702,12 @@ mod tests {
         let tokens = Scanner::new("var a = \"foo\";".to_owned()).collect();
         let mut p = Parser::new(tokens);
         let res = parse(&mut p);
-        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::STRING("foo".to_string()))) }));
+        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::STRING("foo".into()))), line: 0 }));
 
         let tokens = Scanner::new("var a  =  \"foo\";".to_owned()).collect();
         let mut p = Parser::new(tokens);
         let res = parse(&mut p);
-        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::STRING("foo".to_string()))) }));
+        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::STRING("foo".into()))), line: 0 }));
 
         let tokens = Scanner::new("var a  = 2*8;".to_owned()).collect();
         let mut p = Parser::new(tokens);
@@ -322,7 +720,8 @@ mod tests {
                     left: Box::new(Expr::Literal(Value::NUMBER(2.0))),
                     operator: LexemeKind::Star,
                     right: Box::new(Expr::Literal(Value::NUMBER(8.0))),
-                })
+                }),
+                line: 0,
             })
         );
     }
@@ -333,7 +732,7 @@ mod tests {
 print(a);".to_owned()).collect();
         let mut p = Parser::new(tokens);
         let res = parse(&mut p);
-        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0)))}));
+        assert_eq!(res, Some(Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))), line: 0 }));
     }
 
     #[test]
@@ -361,14 +760,10 @@ print(a);".to_owned()).collect();
         assert_eq!(
             res,
             Some(
-                Stmt::Block(
-                    Box::new(
-                        vec![
-                            Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))) },
-                            Stmt::Print(Some(Expr::Variable("a".to_string()))),
-                        ]
-                    )
-                )
+                Stmt::Block(vec![
+                    Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))), line: 0 },
+                    Stmt::Print(vec![Expr::variable("a", 0)]),
+                ])
             )
         );
     }
@@ -383,14 +778,10 @@ print(a);".to_owned()).collect();
         assert_eq!(
             res,
             Some(
-                Stmt::Block(
-                    Box::new(
-                        vec![
-                            Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))) },
-                            Stmt::Print(Some(Expr::Variable("a".to_string()))),
-                        ]
-                    )
-                )
+                Stmt::Block(vec![
+                    Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))), line: 1 },
+                    Stmt::Print(vec![Expr::variable("a", 2)]),
+                ])
             )
         );
     }
@@ -408,10 +799,10 @@ print(a);".to_owned()).collect();
             Some(
                 Stmt::If {
                     condition: Expr::Literal(Value::BOOLEAN(true)),
-                    then_branch: Box::new(Stmt::Block(Box::new(vec![
-                        Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))) },
-                        Stmt::Print(Some(Expr::Variable("a".to_string()))),
-                    ]))),
+                    then_branch: Box::new(Stmt::Block(vec![
+                        Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))), line: 1 },
+                        Stmt::Print(vec![Expr::variable("a", 2)]),
+                    ])),
                     else_branch: Box::new(None),
                 }
             )
@@ -428,7 +819,7 @@ print(a);".to_owned()).collect();
             Some(
                 Stmt::If {
                     condition: Expr::Literal(Value::BOOLEAN(true)),
-                    then_branch: Box::new(Stmt::Print(Some(Expr::Literal(Value::NUMBER(2.0))))),
+                    then_branch: Box::new(Stmt::Print(vec![Expr::Literal(Value::NUMBER(2.0))])),
                     else_branch: Box::new(None),
                 }
             )
@@ -452,14 +843,14 @@ if (true) {
             Some(
                 Stmt::If {
                     condition: Expr::Literal(Value::BOOLEAN(true)),
-                    then_branch: Box::new(Stmt::Block(Box::new(vec![
-                        Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))) },
-                        Stmt::Print(Some(Expr::Variable("a".to_string()))),
+                    then_branch: Box::new(Stmt::Block(vec![
+                        Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))), line: 2 },
+                        Stmt::Print(vec![Expr::variable("a", 3)]),
+                    ])),
+                    else_branch: Box::new(Some(Stmt::Block(vec![
+                        Stmt::VariableDef { ident: "b".to_string(), expr: Some(Expr::Literal(Value::NUMBER(3.0))), line: 5 },
+                        Stmt::Print(vec![Expr::variable("b", 6)]),
                     ]))),
-                    else_branch: Box::new(Some(Stmt::Block(Box::new(vec![
-                        Stmt::VariableDef { ident: "b".to_string(), expr: Some(Expr::Literal(Value::NUMBER(3.0))) },
-                        Stmt::Print(Some(Expr::Variable("b".to_string()))),
-                    ])))),
                 }
             )
         );
@@ -479,10 +870,11 @@ if (true) {
             Some(
                 Stmt::While {
                     condition: Expr::Literal(Value::BOOLEAN(true)),
-                    body: Box::new(Stmt::Block(Box::new(vec![
-                        Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))) },
-                        Stmt::Print(Some(Expr::Variable("a".to_string()))),
-                    ]))),
+                    body: Box::new(Stmt::Block(vec![
+                        Stmt::VariableDef { ident: "a".to_string(), expr: Some(Expr::Literal(Value::NUMBER(2.0))), line: 2 },
+                        Stmt::Print(vec![Expr::variable("a", 3)]),
+                    ])),
+                    line: 1,
                 }
             )
         );
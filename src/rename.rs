@@ -0,0 +1,129 @@
+use crate::lexer::{LexemeKind, Scanner, Token};
+use crate::symbols::SymbolTable;
+
+// `source` with the variable called `old` renamed to `new`, everywhere it is referred to and
+// nowhere else: another variable that happens to share the name keeps it.  Comments and layout
+// come through untouched.
+//
+// If more than one variable is called `old`, `line` picks the one mentioned on that line, using
+// the same line numbers as every other diagnostic.  A rename that would make some name refer to a
+// different variable than before (`new` shadowing, or being shadowed by, something) is refused.
+pub fn rename(source: String, old: &str, new: &str, line: Option<usize>) -> Result<String, String> {
+    let valid = match Scanner::new(new.to_string()).collect::<Vec<Token>>().as_slice() {
+        [Token { lexeme: LexemeKind::IDENTIFIER(name), .. }] => name == new,
+        _ => false,
+    };
+    if !valid {
+        return Err(format!("\"{}\" can't be used as a variable name", new));
+    }
+
+    let mut table = SymbolTable::new(source)?;
+    let symbols = table.symbols();
+
+    let mut bindings: Vec<usize> = symbols
+        .iter()
+        .filter(|symbol| symbol.name == old && line.is_none_or(|line| symbol.line == line))
+        .filter_map(|symbol| symbol.binding)
+        .collect();
+    bindings.sort_unstable();
+    bindings.dedup();
+
+    let binding = match bindings.as_slice() {
+        [binding] => *binding,
+        [] => {
+            let on_line = line.map(|line| format!(" on line {}", line)).unwrap_or_default();
+            return Err(format!("No variable called \"{}\" is declared{}", old, on_line));
+        }
+        _ => {
+            let lines: Vec<String> = symbols
+                .iter()
+                .filter(|symbol| symbol.declaration && symbol.name == old)
+                .map(|symbol| symbol.line.to_string())
+                .collect();
+            return Err(format!(
+                "More than one variable is called \"{}\" (declared on lines {}), give the line of the one to rename",
+                old,
+                lines.join(", ")
+            ));
+        }
+    };
+
+    let before: Vec<Option<usize>> = symbols.iter().map(|symbol| symbol.binding).collect();
+    let renamed: Vec<usize> = (0..symbols.len()).filter(|&index| before[index] == Some(binding)).collect();
+    let out = table.print_renamed(&renamed, new);
+
+    // every name has to refer to what it did before, otherwise `new` collided with something
+    let after = SymbolTable::new(out.clone())?;
+    for (binding, symbol) in before.iter().zip(after.symbols()) {
+        if *binding != symbol.binding {
+            return Err(format!(
+                "Renaming \"{}\" to \"{}\" would change what \"{}\" refers to on line {}",
+                old, new, symbol.name, symbol.line
+            ));
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_renames_within_scope() {
+        let source = "
+var count = 0; // how many
+{
+    var count = 10;
+    count = count + 1;
+}
+count = count  +  1;
+";
+        let res = rename(source.to_string(), "count", "total", Some(1));
+        assert_eq!(res, Ok("
+var total = 0; // how many
+{
+    var count = 10;
+    count = count + 1;
+}
+total = total  +  1;
+".to_string()));
+
+        let res = rename(source.to_string(), "count", "inner", Some(4));
+        assert_eq!(res, Ok("
+var count = 0; // how many
+{
+    var inner = 10;
+    inner = inner + 1;
+}
+count = count  +  1;
+".to_string()));
+    }
+
+    #[test]
+    fn it_needs_a_line_when_the_name_is_ambiguous() {
+        let res = rename("var a = 1;\n{ var a = 2; }".to_string(), "a", "b", None);
+        assert_eq!(res, Err("More than one variable is called \"a\" (declared on lines 0, 1), give the line of the one to rename".to_string()));
+
+        assert_eq!(rename("var a = 1;".to_string(), "b", "c", None), Err("No variable called \"b\" is declared".to_string()));
+        assert_eq!(rename("clock();".to_string(), "clock", "c", None), Err("No variable called \"clock\" is declared".to_string()));
+    }
+
+    #[test]
+    fn it_refuses_renames_that_change_meaning() {
+        let source = "
+var a = 1;
+{
+    var b = 2;
+    print(a);
+}
+";
+        let res = rename(source.to_string(), "a", "b", None);
+        assert_eq!(res, Err("Renaming \"a\" to \"b\" would change what \"b\" refers to on line 4".to_string()));
+
+        assert!(rename("var a = 1; var b = 2;".to_string(), "a", "b", None).is_err());
+        assert_eq!(rename("var a = 1;".to_string(), "a", "while", None), Err("\"while\" can't be used as a variable name".to_string()));
+        assert_eq!(rename("var a = 1;".to_string(), "a", "a b", None), Err("\"a b\" can't be used as a variable name".to_string()));
+    }
+}
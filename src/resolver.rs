@@ -0,0 +1,592 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use crate::interpreter::{self, NativeFunction};
+use crate::lexer::LexemeKind;
+use crate::parser::{Expr, Function, NodeId, Stmt, Value, TYPE_NAMES};
+use crate::visitor::{ExpressionVisitor, StatementVisitor};
+
+// Something found by walking the tree before running it
+#[derive(Debug, PartialEq)]
+pub struct ResolveError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [line: {}]", self.message, self.line)
+    }
+}
+
+// Code that will run but can't be doing what was meant.  `note` explains which construct is
+// responsible.
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    pub line: usize,
+    pub message: String,
+    pub note: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "warning: {} [line: {}]\n  note: {}", self.message, self.line, self.note)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Resolution {
+    pub errors: Vec<ResolveError>,
+    pub warnings: Vec<Warning>,
+}
+
+// Every read or assignment of a name that won't be defined when it runs, plus any parse errors
+// along the way.  `natives` are what the host provides up front.
+//
+// This follows the interpreter exactly: a name exists from the statement that declares it to the
+// end of its block, and `var a;` without an initializer declares nothing.
+//
+// Also warns about loops that can never run and calls made only to throw the result away.
+pub fn resolve(stmts: &[Stmt], natives: &[NativeFunction]) -> Resolution {
+    let mut resolver = Resolver {
+        scopes: vec![
+            natives.iter().map(|native| native.name).chain(interpreter::GLOBALS).map(str::to_string).collect(),
+            HashSet::new(),
+        ],
+        must_use: natives.iter().filter(|native| native.must_use).map(|native| native.name).collect(),
+        resolution: Resolution::default(),
+    };
+
+    for stmt in stmts {
+        stmt.accept(&mut resolver);
+    }
+
+    resolver.resolution
+}
+
+struct Resolver {
+    // natives first, then globals, innermost last
+    scopes: Vec<HashSet<String>>,
+    must_use: HashSet<&'static str>,
+    resolution: Resolution,
+}
+
+impl Resolver {
+    fn check(&mut self, name: &str, line: usize) {
+        if !self.scopes.iter().any(|scope| scope.contains(name)) {
+            self.resolution.errors.push(ResolveError {
+                line,
+                message: format!("Variable \"{}\" does not exist", name),
+            });
+        }
+    }
+
+    fn warn(&mut self, line: usize, message: String, note: String) {
+        self.resolution.warnings.push(Warning { line, message, note });
+    }
+
+    // the native a call resolves to, unless a script variable has taken its name
+    fn native_callee<'e>(&self, callee: &'e Expr) -> Option<&'e str> {
+        match callee {
+            Expr::Variable { name, .. } if !self.scopes[1..].iter().any(|scope| scope.contains(name)) => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+}
+
+// false and nil written out, possibly in parentheses
+fn always_falsy(expr: &Expr) -> bool {
+    match expr {
+        Expr::Grouping(inner) => always_falsy(inner),
+        Expr::Literal(val) => matches!(val, Value::BOOLEAN(false) | Value::Null),
+        _ => false,
+    }
+}
+
+impl ExpressionVisitor<()> for Resolver {
+    fn visit_assign(&mut self, name: &str, expr: &Expr, line: &usize) {
+        expr.accept(self);
+        self.check(name, *line);
+    }
+
+    fn visit_binary(&mut self, left: &Expr, _operator: &LexemeKind, right: &Expr) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], _line: &usize) {
+        callee.accept(self);
+        for arg in arguments {
+            arg.accept(self);
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Expr, _operator: &LexemeKind, right: &Expr) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_literal(&mut self, _val: &Value) {}
+
+    fn visit_unary(&mut self, _operator: &LexemeKind, right: &Expr) {
+        right.accept(self);
+    }
+
+    fn visit_grouping(&mut self, val: &Expr) {
+        val.accept(self);
+    }
+
+    fn visit_variable(&mut self, ident: &str, _id: NodeId, line: &usize) {
+        self.check(ident, *line);
+    }
+
+    fn visit_this(&mut self, line: &usize) {
+        // methods declare it along with their parameters
+        if !self.scopes.iter().any(|scope| scope.contains("this")) {
+            self.resolution.errors.push(ResolveError { line: *line, message: "Can't use this outside of a method".to_string() });
+        }
+    }
+
+    fn visit_get(&mut self, object: &Expr, _name: &str, _optional: &bool, _line: &usize) {
+        object.accept(self);
+    }
+
+    fn visit_set(&mut self, object: &Expr, _name: &str, value: &Expr, _line: &usize) {
+        object.accept(self);
+        value.accept(self);
+    }
+
+    fn visit_is(&mut self, value: &Expr, type_name: &str, line: &usize) {
+        value.accept(self);
+        if !TYPE_NAMES.contains(&type_name) {
+            self.check(type_name, *line);
+        }
+    }
+
+    fn visit_await(&mut self, expr: &Expr, _line: &usize) {
+        expr.accept(self);
+    }
+
+    fn visit_if_expr(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>, _line: &usize) {
+        StatementVisitor::visit_if(self, condition, then_branch, else_branch);
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) {
+        self.resolution.errors.push(ResolveError { line: *line, message: message.to_string() });
+    }
+}
+
+impl StatementVisitor<()> for Resolver {
+    fn visit_block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashSet::new());
+        for stmt in stmts {
+            stmt.accept(self);
+        }
+        self.scopes.pop();
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) {
+        condition.accept(self);
+        then_branch.accept(self);
+        if let Some(else_branch) = else_branch {
+            else_branch.accept(self);
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, line: &usize) {
+        condition.accept(self);
+        if always_falsy(condition) {
+            self.warn(
+                *line,
+                "Loop body is unreachable".to_string(),
+                format!("the condition of the while on line {} is always false", line),
+            );
+        }
+        body.accept(self);
+    }
+
+    fn visit_variable_def(&mut self, ident: &str, expr: &Option<Expr>, _line: &usize) {
+        if let Some(expr) = expr {
+            expr.accept(self);
+            self.scopes.last_mut().unwrap().insert(ident.to_string());
+        }
+    }
+
+    fn visit_record(&mut self, name: &str, _fields: &[String], _line: &usize) {
+        self.scopes.last_mut().unwrap().insert(name.to_string());
+    }
+
+    // the class is declared before its methods, so they can refer to it
+    fn visit_class(&mut self, name: &str, methods: &Arc<[Function]>, _line: &usize) {
+        self.scopes.last_mut().unwrap().insert(name.to_string());
+        for method in methods.iter() {
+            let mut scope: HashSet<String> = method.params.iter().cloned().collect();
+            scope.insert("this".to_string());
+            self.scopes.push(scope);
+            for stmt in &method.body {
+                stmt.accept(self);
+            }
+            self.scopes.pop();
+        }
+    }
+
+    fn visit_print(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            expr.accept(self);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        expr.accept(self);
+
+        if let Expr::Call { callee, line, .. } = expr {
+            if let Some(name) = self.native_callee(callee).filter(|name| self.must_use.contains(name)) {
+                self.warn(
+                    *line,
+                    format!("Result of {}() is unused", name),
+                    format!("{}() does nothing but return a value, assign it or remove the call", name),
+                );
+            }
+        }
+    }
+
+    fn visit_with(&mut self, ident: &str, init: &Expr, body: &Stmt, _line: &usize) {
+        init.accept(self);
+        self.scopes.push(HashSet::from([ident.to_string()]));
+        body.accept(self);
+        self.scopes.pop();
+    }
+
+    fn visit_defer(&mut self, expr: &Expr, _line: &usize) {
+        expr.accept(self);
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) {
+        self.resolution.errors.push(ResolveError { line: *line, message: message.to_string() });
+    }
+}
+
+// One mention of a variable.  `binding` tells apart variables that share a name: declarations
+// are numbered in the order they appear, and every mention carries the number of the one it
+// refers to.  None for a name nothing in the script declares, a native or a mistake.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occurrence {
+    pub name: String,
+    pub binding: Option<usize>,
+    pub declaration: bool,
+}
+
+// Every mention of a variable in the order they appear in the source, so they line up with the
+// IDENTIFIER tokens.  Scoping is the same as `resolve`, except `var a;` counts as declaring `a`:
+// it names a variable even if the interpreter won't create it, and renaming it should follow
+// along.  The first parse error stops it, there is no telling what a broken tree refers to.
+pub fn occurrences(stmts: &[Stmt]) -> Result<Vec<Occurrence>, ResolveError> {
+    let mut binder = Binder {
+        scopes: vec![HashMap::new()],
+        declarations: 0,
+        occurrences: vec![],
+        error: None,
+    };
+
+    for stmt in stmts {
+        stmt.accept(&mut binder);
+    }
+
+    match binder.error {
+        Some(error) => Err(error),
+        None => Ok(binder.occurrences),
+    }
+}
+
+struct Binder {
+    // name -> binding, globals first, innermost last
+    scopes: Vec<HashMap<String, usize>>,
+    declarations: usize,
+    occurrences: Vec<Occurrence>,
+    error: Option<ResolveError>,
+}
+
+impl Binder {
+    // declaring a name twice in one scope reuses the variable
+    fn bind(&mut self, name: &str) -> usize {
+        let scope = self.scopes.last_mut().unwrap();
+        match scope.get(name) {
+            Some(binding) => *binding,
+            None => {
+                self.declarations += 1;
+                scope.insert(name.to_string(), self.declarations - 1);
+                self.declarations - 1
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &str) {
+        let binding = self.bind(name);
+        self.occurrences.push(Occurrence { name: name.to_string(), binding: Some(binding), declaration: true });
+    }
+
+    fn mention(&mut self, name: &str) {
+        let binding = self.scopes.iter().rev().find_map(|scope| scope.get(name)).copied();
+        self.occurrences.push(Occurrence { name: name.to_string(), binding, declaration: false });
+    }
+
+    fn error(&mut self, line: usize, message: &str) {
+        self.error.get_or_insert_with(|| ResolveError { line, message: message.to_string() });
+    }
+}
+
+impl ExpressionVisitor<()> for Binder {
+    fn visit_assign(&mut self, name: &str, expr: &Expr, _line: &usize) {
+        self.mention(name);
+        expr.accept(self);
+    }
+
+    fn visit_binary(&mut self, left: &Expr, _operator: &LexemeKind, right: &Expr) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], _line: &usize) {
+        callee.accept(self);
+        for arg in arguments {
+            arg.accept(self);
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Expr, _operator: &LexemeKind, right: &Expr) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_literal(&mut self, _val: &Value) {}
+
+    fn visit_unary(&mut self, _operator: &LexemeKind, right: &Expr) {
+        right.accept(self);
+    }
+
+    fn visit_grouping(&mut self, val: &Expr) {
+        val.accept(self);
+    }
+
+    fn visit_variable(&mut self, ident: &str, _id: NodeId, _line: &usize) {
+        self.mention(ident);
+    }
+
+    // not an IDENTIFIER, so nothing to line up with
+    fn visit_this(&mut self, _line: &usize) {}
+
+    fn visit_get(&mut self, object: &Expr, _name: &str, _optional: &bool, _line: &usize) {
+        object.accept(self);
+    }
+
+    fn visit_set(&mut self, object: &Expr, _name: &str, value: &Expr, _line: &usize) {
+        object.accept(self);
+        value.accept(self);
+    }
+
+    fn visit_is(&mut self, value: &Expr, type_name: &str, _line: &usize) {
+        value.accept(self);
+        // the built in type names aren't variables, whatever the script calls its own
+        if TYPE_NAMES.contains(&type_name) {
+            self.occurrences.push(Occurrence { name: type_name.to_string(), binding: None, declaration: false });
+        } else {
+            self.mention(type_name);
+        }
+    }
+
+    fn visit_await(&mut self, expr: &Expr, _line: &usize) {
+        expr.accept(self);
+    }
+
+    fn visit_if_expr(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>, _line: &usize) {
+        StatementVisitor::visit_if(self, condition, then_branch, else_branch);
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) {
+        self.error(*line, message);
+    }
+}
+
+impl StatementVisitor<()> for Binder {
+    fn visit_block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            stmt.accept(self);
+        }
+        self.scopes.pop();
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) {
+        condition.accept(self);
+        then_branch.accept(self);
+        if let Some(else_branch) = else_branch {
+            else_branch.accept(self);
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, _line: &usize) {
+        condition.accept(self);
+        body.accept(self);
+    }
+
+    fn visit_variable_def(&mut self, ident: &str, expr: &Option<Expr>, _line: &usize) {
+        // the name comes first in the source, but the initializer still sees the outer binding:
+        // var a = a;
+        let index = self.occurrences.len();
+        self.occurrences.push(Occurrence { name: ident.to_string(), binding: None, declaration: true });
+        if let Some(expr) = expr {
+            expr.accept(self);
+        }
+
+        self.occurrences[index].binding = Some(self.bind(ident));
+    }
+
+    fn visit_record(&mut self, name: &str, fields: &[String], _line: &usize) {
+        self.declare(name);
+        // fields aren't variables, but they're written as names and have to line up with the
+        // tokens like everything else
+        for field in fields {
+            self.occurrences.push(Occurrence { name: field.to_string(), binding: None, declaration: false });
+        }
+    }
+
+    fn visit_class(&mut self, name: &str, methods: &Arc<[Function]>, _line: &usize) {
+        self.declare(name);
+        for method in methods.iter() {
+            // a method's name is looked up on the instance, like a field
+            self.occurrences.push(Occurrence { name: method.name.to_string(), binding: None, declaration: false });
+            self.scopes.push(HashMap::new());
+            for param in &method.params {
+                self.declare(param);
+            }
+            for stmt in &method.body {
+                stmt.accept(self);
+            }
+            self.scopes.pop();
+        }
+    }
+
+    fn visit_print(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            expr.accept(self);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        expr.accept(self);
+    }
+
+    fn visit_with(&mut self, ident: &str, init: &Expr, body: &Stmt, _line: &usize) {
+        // the name comes first here too, but the value is worked out in the enclosing scope
+        let index = self.occurrences.len();
+        self.occurrences.push(Occurrence { name: ident.to_string(), binding: None, declaration: true });
+        init.accept(self);
+
+        self.declarations += 1;
+        self.occurrences[index].binding = Some(self.declarations - 1);
+        self.scopes.push(HashMap::from([(ident.to_string(), self.declarations - 1)]));
+        body.accept(self);
+        self.scopes.pop();
+    }
+
+    fn visit_defer(&mut self, expr: &Expr, _line: &usize) {
+        expr.accept(self);
+    }
+
+    fn visit_error(&mut self, line: &usize, message: &str) {
+        self.error(*line, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Scanner;
+    use crate::parser::Parser;
+
+    fn resolution(source: &str) -> Resolution {
+        let tokens = Scanner::new(source.to_owned()).collect();
+        resolve(&Parser::new(tokens).parse(), &crate::interpreter::natives())
+    }
+
+    fn errors(source: &str) -> Vec<ResolveError> {
+        resolution(source).errors
+    }
+
+    #[test]
+    fn it_resolves_defined_names() {
+        assert_eq!(errors("var a = 1; print(a + clock());"), vec![]);
+        assert_eq!(errors("var a = 1; { var b = a; b = 2; }"), vec![]);
+    }
+
+    #[test]
+    fn it_reports_undefined_names() {
+        let res = errors("
+var total = 0;
+{
+    var inner = 1;
+}
+print(totl);
+inner = 2;
+var x;
+print(x);
+");
+        assert_eq!(res, vec![
+            ResolveError { line: 5, message: "Variable \"totl\" does not exist".to_string() },
+            ResolveError { line: 6, message: "Variable \"inner\" does not exist".to_string() },
+            ResolveError { line: 8, message: "Variable \"x\" does not exist".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn it_reports_names_used_before_declaration() {
+        let res = errors("print(a); var a = a;");
+        assert_eq!(res.len(), 2);
+    }
+
+    #[test]
+    fn it_warns_about_loops_that_never_run() {
+        let res = resolution("
+var a = 1;
+while (false) {
+    a = 2;
+}
+while (false) a = 3;
+while (a) a = false;
+");
+        assert_eq!(res.errors, vec![]);
+        assert_eq!(res.warnings.iter().map(|w| w.line).collect::<Vec<_>>(), vec![2, 5]);
+        assert_eq!(res.warnings[0].note, "the condition of the while on line 2 is always false");
+    }
+
+    #[test]
+    fn it_warns_about_unused_results() {
+        let res = resolution("clock(); readLine(); var a = random();");
+        assert_eq!(res.errors, vec![]);
+        assert_eq!(res.warnings, vec![Warning {
+            line: 0,
+            message: "Result of clock() is unused".to_string(),
+            note: "clock() does nothing but return a value, assign it or remove the call".to_string(),
+        }]);
+
+        // once shadowed it's just a variable
+        assert_eq!(resolution("var clock = 1; clock();").warnings, vec![]);
+    }
+
+    #[test]
+    fn it_binds_occurrences_to_declarations() {
+        let tokens = Scanner::new("var a = 1; { var a = a; a = 2; } a; clock();".to_owned()).collect();
+        let occurrences = occurrences(&Parser::new(tokens).parse()).unwrap();
+        let bindings: Vec<_> = occurrences.iter().map(|o| (o.name.as_str(), o.binding, o.declaration)).collect();
+        assert_eq!(bindings, vec![
+            ("a", Some(0), true),
+            ("a", Some(1), true),
+            ("a", Some(0), false),
+            ("a", Some(1), false),
+            ("a", Some(0), false),
+            ("clock", None, false),
+        ]);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::lexer::{self, LexemeKind, Scanner, Token};
+use crate::line_index::{Encoding, LineIndex};
+use crate::parser::Parser;
+use crate::resolver;
+
+// Every variable mentioned in a script and where, for the questions an editor asks: what's under
+// the cursor, where else is it used.  Built from the lossless tokens, so positions are exact and
+// rename can print the script back from them.
+pub struct SymbolTable {
+    tokens: Vec<Token>,
+    symbols: Vec<Symbol>,
+}
+
+// A mention of a variable.  Lines and columns count from 0, columns in bytes (see LineIndex)
+#[derive(Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    // the declaration it refers to, see resolver::Occurrence
+    pub binding: Option<usize>,
+    pub declaration: bool,
+    pub line: usize,
+    pub column: usize,
+    // index of the IDENTIFIER token it was written as
+    pub(crate) token: usize,
+}
+
+impl SymbolTable {
+    pub fn new(source: String) -> Result<Self, String> {
+        let index = LineIndex::new(&source);
+        let (tokens, errors) = Scanner::lossless(source.clone()).scan();
+        if let Some(error) = errors.first() {
+            return Err(error.to_string());
+        }
+
+        let stmts = Parser::scanned(Scanner::lossless(source).scan()).parse();
+        let occurrences = resolver::occurrences(&stmts).map_err(|error| error.to_string())?;
+
+        let mut identifiers = vec![];
+        for (token, span) in lexer::spans(&tokens).into_iter().enumerate() {
+            // the name after a dot is a module member, not a variable
+            let member = token > 0 && matches!(tokens[token - 1].lexeme, LexemeKind::Dot | LexemeKind::QuestionDot);
+            if let (LexemeKind::IDENTIFIER(_), false) = (&tokens[token].lexeme, member) {
+                // spans come from the same source, so they always land on a character
+                let at = index.line_col(span.start, Encoding::Utf8).unwrap();
+                identifiers.push((token, at.line, at.column));
+            }
+        }
+
+        let lined_up = identifiers.len() == occurrences.len()
+            && identifiers.iter().zip(occurrences.iter()).all(|((index, ..), occurrence)| tokens[*index].text == occurrence.name);
+        if !lined_up {
+            return Err("Couldn't match up the names in the source with the script".to_string());
+        }
+
+        let symbols = identifiers
+            .into_iter()
+            .zip(occurrences)
+            .map(|((token, line, column), occurrence)| Symbol {
+                name: occurrence.name,
+                binding: occurrence.binding,
+                declaration: occurrence.declaration,
+                line,
+                column,
+                token,
+            })
+            .collect();
+
+        Ok(Self { tokens, symbols })
+    }
+
+    // in the order they appear
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    // The symbol whose name covers this position, if any
+    pub fn symbol_at(&self, line: usize, column: usize) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| {
+            symbol.line == line && (symbol.column..symbol.column + symbol.name.len()).contains(&column)
+        })
+    }
+
+    // Every mention of the variable `symbol` refers to, declarations included.  Names nothing
+    // declares (natives) are matched by name instead.
+    pub fn references(&self, symbol: &Symbol) -> Vec<&Symbol> {
+        self.symbols
+            .iter()
+            .filter(|other| match symbol.binding {
+                Some(_) => other.binding == symbol.binding,
+                None => other.binding.is_none() && other.name == symbol.name,
+            })
+            .collect()
+    }
+
+    // The script back, with `symbols` written as `name` instead
+    pub(crate) fn print_renamed(&mut self, symbols: &[usize], name: &str) -> String {
+        for &index in symbols {
+            let token = &mut self.tokens[self.symbols[index].token];
+            token.lexeme = LexemeKind::IDENTIFIER(name.to_string());
+            token.text = name.to_string();
+        }
+
+        lexer::print(&self.tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_references() {
+        let table = SymbolTable::new("
+var a = 1;
+{ var a = 2;
+  print(a); }
+a = a + clock() + clock();
+a = fs?.a(a);
+".to_string()).unwrap();
+
+        let at = |line, column| table.symbol_at(line, column).map(|symbol| (symbol.line, symbol.column));
+        assert_eq!(at(1, 4), Some((1, 4)));
+        assert_eq!(at(3, 8), Some((3, 8)));
+        assert_eq!(at(1, 5), None);
+        assert_eq!(at(4, 12), Some((4, 8)));
+
+        let refs = |line, column| {
+            let symbol = table.symbol_at(line, column).unwrap();
+            table.references(symbol).iter().map(|symbol| (symbol.line, symbol.column, symbol.declaration)).collect::<Vec<_>>()
+        };
+        assert_eq!(refs(4, 0), vec![(1, 4, true), (4, 0, false), (4, 4, false), (5, 0, false), (5, 10, false)]);
+        assert_eq!(at(5, 8), None);
+        assert_eq!(refs(3, 8), vec![(2, 6, true), (3, 8, false)]);
+        assert_eq!(refs(4, 8), vec![(4, 8, false), (4, 18, false)]);
+    }
+}
@@ -0,0 +1,332 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::parser::Value;
+
+// The reference Lox implementation prints numbers with Java's Double.toString and then drops a
+// trailing ".0".  Output comparison suites are written against that, so this reproduces it
+// exactly and without consulting the locale:
+//   integers print without a fraction     1, -0, 100
+//   scientific notation outside 1e-3..1e7 1.0E7, 1.5E-5
+//   non-finite values use Java's names    NaN, Infinity, -Infinity
+pub fn lox_format(n: f64) -> String {
+    NumberFormat::default().format(n)
+}
+
+// How numbers are turned into text.  The default is the Lox reference format above; the
+// interpreter carries one of these so an embedder can ask for fewer digits or move the points
+// where scientific notation kicks in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumberFormat {
+    // round to this many significant digits, dropping trailing zeros
+    pub significant_digits: Option<usize>,
+    // magnitudes at or above this are written as 1.5E21
+    pub scientific_above: Option<f64>,
+    // non-zero magnitudes below this are written as 1.5E-7
+    pub scientific_below: Option<f64>,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            significant_digits: None,
+            scientific_above: Some(1e7),
+            scientific_below: Some(1e-3),
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn format(&self, n: f64) -> String {
+        if n.is_nan() {
+            return "NaN".to_owned();
+        } else if n.is_infinite() {
+            return if n > 0.0 { "Infinity" } else { "-Infinity" }.to_owned();
+        }
+
+        let magnitude = n.abs();
+        let scientific = magnitude != 0.0
+            && (self.scientific_above.is_some_and(|t| magnitude >= t)
+                || self.scientific_below.is_some_and(|t| magnitude < t));
+
+        match (scientific, self.significant_digits) {
+            (true, Some(digits)) => scientific_notation(&format!("{:.*e}", digits.max(1) - 1, n)),
+            (true, None) => scientific_notation(&format!("{:e}", n)),
+            (false, Some(digits)) => {
+                if magnitude == 0.0 {
+                    return n.to_string();
+                }
+                // let {:e} do the rounding, it knows where the first digit is without log10 (which
+                // isn't in core).  1.23e5 reads back as 123000
+                let rounded = format!("{:.*e}", digits.max(1) - 1, n);
+                let exponent: i32 = rounded[rounded.find('e').unwrap() + 1..].parse().unwrap();
+                // digits to keep after the decimal point, negative when rounding into the integer part
+                let decimals = digits.max(1) as i32 - 1 - exponent;
+                if decimals >= 0 {
+                    trim_fraction(&format!("{:.*}", decimals as usize, n)).to_string()
+                } else {
+                    rounded.parse::<f64>().unwrap().to_string()
+                }
+            }
+            // shortest digits that round trip, which is also what Java picks. -0.0 prints as -0
+            (false, None) => n.to_string(),
+        }
+    }
+}
+
+// Rust's 1.500e3 -> Java's 1.5E3.  Java always keeps one digit after the point: 1e7 -> 1.0E7
+fn scientific_notation(st: &str) -> String {
+    let (mantissa, exponent) = st.split_at(st.find('e').unwrap());
+    let mantissa = trim_fraction(mantissa);
+    let point = if mantissa.contains('.') { "" } else { ".0" };
+    format!("{}{}E{}", mantissa, point, &exponent[1..])
+}
+
+fn trim_fraction(st: &str) -> &str {
+    if st.contains('.') {
+        st.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        st
+    }
+}
+
+// How deep `print` and the REPL echo will descend into nested values before eliding the rest.
+pub const DEFAULT_DEPTH: usize = 8;
+
+// A compound value that would run past this many columns gets a line per field instead.  There
+// are none without std
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+const WIDTH: usize = 80;
+
+// Human readable rendering used by print and the REPL echo.  Unlike Display, this spans lines: a
+// record or an instance goes on one line if it fits in WIDTH columns, and otherwise puts each
+// field on a line of its own, indented two spaces further than the value.  Below `max_depth`
+// levels it gives up and prints "..." in place of whatever is there.  An instance's fields can be
+// assigned anything, itself included (a.self = a), so one found inside itself prints as "<cycle>"
+// rather than going round forever.
+pub fn pretty(value: &Value, max_depth: usize, numbers: &NumberFormat) -> String {
+    let mut printer = Printer { max_depth, numbers, inside: Vec::new() };
+    printer.value(value, 0)
+}
+
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+struct Printer<'a> {
+    max_depth: usize,
+    numbers: &'a NumberFormat,
+    // the compound values being printed, outermost first, by address
+    inside: Vec<usize>,
+}
+
+impl Printer<'_> {
+    fn value(&mut self, value: &Value, depth: usize) -> String {
+        if depth > self.max_depth {
+            return "...".to_owned();
+        }
+
+        match value {
+            Value::NUMBER(n) => self.numbers.format(*n),
+            #[cfg(feature = "std")]
+            Value::Native(_) | Value::Module(_) | Value::RecordType(_) | Value::Class(_) | Value::Method(_) => value.to_string(),
+            // <A instance> { x: 1 }, or just <A instance> until it has fields
+            #[cfg(feature = "std")]
+            Value::Instance(instance) => match instance.fields() {
+                fields if fields.is_empty() => value.to_string(),
+                fields => self.compound(alloc::sync::Arc::as_ptr(instance) as usize, &value.to_string(), fields, depth),
+            },
+            #[cfg(feature = "std")]
+            Value::Record(record) => {
+                let address = alloc::sync::Arc::as_ptr(record) as usize;
+                let fields = record.fields().map(|(name, field)| (name.to_string(), field.clone())).collect();
+                self.compound(address, record.kind().name(), fields, depth)
+            }
+            Value::BOOLEAN(_) | Value::STRING(_) | Value::Null => value.to_string(),
+        }
+    }
+
+    // `name { field: value, ... }` on one line or over several
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    fn compound(&mut self, address: usize, name: &str, fields: Vec<(String, Value)>, depth: usize) -> String {
+        if self.inside.contains(&address) {
+            return "<cycle>".to_owned();
+        }
+        if fields.is_empty() {
+            return format!("{} {{}}", name);
+        }
+
+        self.inside.push(address);
+        let fields: Vec<String> = fields.iter().map(|(field, value)| format!("{}: {}", field, self.value(value, depth + 1))).collect();
+        self.inside.pop();
+
+        let flat = format!("{} {{ {} }}", name, fields.join(", "));
+        if !flat.contains('\n') && depth * 2 + flat.len() <= WIDTH {
+            return flat;
+        }
+
+        let mut out = format!("{} {{", name);
+        for (index, field) in fields.iter().enumerate() {
+            out.push_str("\n  ");
+            // a field that spans lines itself moves over with it
+            out.push_str(&field.replace('\n', "\n  "));
+            if index + 1 < fields.len() {
+                out.push(',');
+            }
+        }
+        out.push_str("\n}");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_prints_scalars() {
+        assert_eq!(pretty(&Value::NUMBER(1.5), DEFAULT_DEPTH, &NumberFormat::default()), "1.5");
+        assert_eq!(pretty(&Value::BOOLEAN(true), DEFAULT_DEPTH, &NumberFormat::default()), "true");
+        assert_eq!(pretty(&Value::STRING("foo".into()), DEFAULT_DEPTH, &NumberFormat::default()), "\"foo\"");
+        assert_eq!(pretty(&Value::Null, DEFAULT_DEPTH, &NumberFormat::default()), "nil");
+    }
+
+    #[test]
+    fn it_prints_scalars_at_zero_depth() {
+        assert_eq!(pretty(&Value::NUMBER(2.0), 0, &NumberFormat::default()), "2");
+    }
+
+    // what the last statement of `source` is worth
+    #[cfg(feature = "std")]
+    fn evaluate(source: &str) -> Value {
+        use crate::interpreter::Interpreter;
+        use crate::lexer::Scanner;
+        use crate::parser::Parser;
+        let stmts = Parser::new(Scanner::new(source.to_owned()).collect()).parse();
+        Interpreter::new().eval(stmts).unwrap().value.unwrap()
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn it_indents_records_that_dont_fit_on_a_line() {
+        let numbers = NumberFormat::default();
+        let short = evaluate("record P { a, b }\nP(1, P(2, true));");
+        assert_eq!(pretty(&short, DEFAULT_DEPTH, &numbers), "P { a: 1, b: P { a: 2, b: true } }");
+        assert_eq!(pretty(&evaluate("record E {}\nE();"), DEFAULT_DEPTH, &numbers), "E {}");
+
+        let long = evaluate("record Pair { left, right }\nPair(\"a fairly long string on the left\", Pair(\"and another one down here\", \"that pushes it past the width\"));");
+        assert_eq!(
+            pretty(&long, DEFAULT_DEPTH, &numbers),
+            "Pair {\n  left: \"a fairly long string on the left\",\n  right: Pair {\n    left: \"and another one down here\",\n    right: \"that pushes it past the width\"\n  }\n}"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn it_prints_instance_fields_and_cycles() {
+        let numbers = NumberFormat::default();
+        assert_eq!(pretty(&evaluate("class A {}\nA();"), DEFAULT_DEPTH, &numbers), "<A instance>");
+
+        let a = evaluate("class A {}\nclass B {}\nvar a = A();\na.y = 2;\na.x = B();\na.x.back = a;\na.self = a;\na;");
+        assert_eq!(pretty(&a, DEFAULT_DEPTH, &numbers), "<A instance> { self: <cycle>, x: <B instance> { back: <cycle> }, y: 2 }");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn it_elides_whatever_is_too_deep() {
+        let nested = evaluate("record P { a, b }\nP(1, P(2, P(3, true)));");
+        assert_eq!(pretty(&nested, 2, &NumberFormat::default()), "P { a: 1, b: P { a: 2, b: P { a: ..., b: ... } } }");
+        assert_eq!(pretty(&nested, 0, &NumberFormat::default()), "P { a: ..., b: ... }");
+    }
+
+    #[test]
+    fn it_formats_numbers_by_default() {
+        let numbers = NumberFormat::default();
+        assert_eq!(numbers.format(1.0), "1");
+        assert_eq!(numbers.format(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(numbers.format(1e21), "1.0E21");
+    }
+
+    #[test]
+    fn it_formats_significant_digits() {
+        let numbers = NumberFormat { significant_digits: Some(3), ..NumberFormat::default() };
+        assert_eq!(numbers.format(0.1 + 0.2), "0.3");
+        assert_eq!(numbers.format(1.23456), "1.23");
+        assert_eq!(numbers.format(-2.0 / 3.0), "-0.667");
+        assert_eq!(numbers.format(123456.0), "123000");
+        assert_eq!(numbers.format(9.996), "10");
+        assert_eq!(numbers.format(99960.0), "100000");
+        assert_eq!(numbers.format(0.0), "0");
+    }
+
+    #[test]
+    fn it_formats_scientific_thresholds() {
+        let numbers = NumberFormat {
+            scientific_above: Some(1e9),
+            scientific_below: Some(1e-4),
+            ..NumberFormat::default()
+        };
+        assert_eq!(numbers.format(1e21), "1.0E21");
+        assert_eq!(numbers.format(0.00001), "1.0E-5");
+        assert_eq!(numbers.format(12345678.0), "12345678");
+        assert_eq!(numbers.format(0.0), "0");
+
+        let numbers = NumberFormat { significant_digits: Some(2), ..numbers };
+        assert_eq!(numbers.format(123456789012.0), "1.2E11");
+        assert_eq!(numbers.format(-0.0000150), "-1.5E-5");
+
+        let numbers = NumberFormat { scientific_above: None, scientific_below: None, significant_digits: None };
+        assert_eq!(numbers.format(1e21), "1000000000000000000000");
+    }
+
+    #[test]
+    fn lox_format_integers() {
+        assert_eq!(lox_format(0.0), "0");
+        assert_eq!(lox_format(1.0), "1");
+        assert_eq!(lox_format(-1.0), "-1");
+        assert_eq!(lox_format(100.0), "100");
+        assert_eq!(lox_format(9999999.0), "9999999");
+        assert_eq!(lox_format(-9999999.0), "-9999999");
+    }
+
+    #[test]
+    fn lox_format_negative_zero() {
+        assert_eq!(lox_format(-0.0), "-0");
+        assert_eq!(lox_format(-2.0 * 0.0), "-0");
+    }
+
+    #[test]
+    fn lox_format_fractions() {
+        assert_eq!(lox_format(1.5), "1.5");
+        assert_eq!(lox_format(-1.5), "-1.5");
+        assert_eq!(lox_format(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(lox_format(1.0 / 3.0), "0.3333333333333333");
+        assert_eq!(lox_format(0.001), "0.001");
+        assert_eq!(lox_format(123.456), "123.456");
+    }
+
+    #[test]
+    fn lox_format_scientific() {
+        assert_eq!(lox_format(1e7), "1.0E7");
+        assert_eq!(lox_format(-1e7), "-1.0E7");
+        assert_eq!(lox_format(12345678.0), "1.2345678E7");
+        assert_eq!(lox_format(1e21), "1.0E21");
+        assert_eq!(lox_format(0.0009), "9.0E-4");
+        assert_eq!(lox_format(0.000123), "1.23E-4");
+        assert_eq!(lox_format(-1.5e-10), "-1.5E-10");
+        assert_eq!(lox_format(f64::MAX), "1.7976931348623157E308");
+        assert_eq!(lox_format(f64::MIN_POSITIVE), "2.2250738585072014E-308");
+    }
+
+    #[test]
+    fn lox_format_non_finite() {
+        assert_eq!(lox_format(f64::NAN), "NaN");
+        assert_eq!(lox_format(f64::INFINITY), "Infinity");
+        assert_eq!(lox_format(f64::NEG_INFINITY), "-Infinity");
+        assert_eq!(lox_format(1.0 / 0.0), "Infinity");
+    }
+
+    #[test]
+    fn it_prints_with_number_format() {
+        let numbers = NumberFormat { significant_digits: Some(2), ..NumberFormat::default() };
+        assert_eq!(pretty(&Value::NUMBER(1.0 / 3.0), DEFAULT_DEPTH, &numbers), "0.33");
+    }
+}
@@ -1,5 +1,7 @@
+use alloc::string::String;
+use alloc::sync::Arc;
 use crate::lexer::LexemeKind;
-use crate::parser::{Expr, Stmt, Value};
+use crate::parser::{Expr, Function, NodeId, Stmt, Value};
 
 // Dynamic dispatch
 // This has a higher runtime cost due to vtable lookups.
@@ -7,22 +9,34 @@ use crate::parser::{Expr, Stmt, Value};
 // generic type T.
 // Everything is behind a  reference because we pass around
 pub trait ExpressionVisitor<T> {
-    fn visit_assign(&mut self, name: &str, expr: &Expr) -> T;
+    fn visit_assign(&mut self, name: &str, expr: &Expr, line: &usize) -> T;
     fn visit_binary(&mut self, left: &Expr, operator: &LexemeKind, right: &Expr) -> T;
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], line: &usize) -> T;
     fn visit_logical(&mut self, left: &Expr, operator: &LexemeKind, right: &Expr) -> T;
     fn visit_literal(&mut self, val: &Value) -> T;
     fn visit_unary(&mut self, operator: &LexemeKind, right: &Expr) -> T;
     fn visit_grouping(&mut self, val: &Expr) -> T;
-    fn visit_variable(&mut self, ident: &str) -> T;
+    fn visit_variable(&mut self, ident: &str, id: NodeId, line: &usize) -> T;
+    fn visit_get(&mut self, object: &Expr, name: &str, optional: &bool, line: &usize) -> T;
+    fn visit_this(&mut self, line: &usize) -> T;
+    fn visit_set(&mut self, object: &Expr, name: &str, value: &Expr, line: &usize) -> T;
+    fn visit_is(&mut self, value: &Expr, type_name: &str, line: &usize) -> T;
+    fn visit_await(&mut self, expr: &Expr, line: &usize) -> T;
+    // `if` used as a value, the branches are statements (usually blocks) whose value it takes
+    fn visit_if_expr(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>, line: &usize) -> T;
     fn visit_error(&mut self, line: &usize, message: &str) -> T;
 }
 
 pub trait StatementVisitor<T> {
-    fn visit_block(&mut self, stmts: &Vec<Stmt>) -> T;
+    fn visit_block(&mut self, stmts: &[Stmt]) -> T;
     fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: &Option<Stmt>) -> T;
-    fn visit_while(&mut self, condition: &Expr, body: &Stmt) -> T;
-    fn visit_variable_def(&mut self, ident: &str, expr: &Option<Expr>) -> T;
-    fn visit_print(&mut self, expr: &Option<Expr>) -> T;
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, line: &usize) -> T;
+    fn visit_variable_def(&mut self, ident: &str, expr: &Option<Expr>, line: &usize) -> T;
+    fn visit_record(&mut self, name: &str, fields: &[String], line: &usize) -> T;
+    fn visit_class(&mut self, name: &str, methods: &Arc<[Function]>, line: &usize) -> T;
+    fn visit_print(&mut self, exprs: &[Expr]) -> T;
     fn visit_expr(&mut self, expr: &Expr) -> T;
+    fn visit_with(&mut self, ident: &str, init: &Expr, body: &Stmt, line: &usize) -> T;
+    fn visit_defer(&mut self, expr: &Expr, line: &usize) -> T;
     fn visit_error(&mut self, line: &usize, message: &str) -> T;
 }